@@ -0,0 +1,147 @@
+//! Receive Throughput Benchmark
+//!
+//! # Design
+//! A network stack that never blocks delivers a stream of small QoS 0 messages in TCP segment
+//! sized chunks, as a broker forwarding a burst of telemetry would. The time taken to handle all
+//! messages is measured once using `Minimq::poll()`, which handles a single message per call, and
+//! once using `Minimq::poll_all()`, which drains the socket within a single call.
+//!
+//! Run using `cargo bench --bench receive`.
+use std::time::{Duration, Instant};
+
+use minimq::{
+    embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, TcpClientStack},
+    Minimq,
+};
+
+/// The number of messages received per measurement.
+const MESSAGES: usize = 100_000;
+
+/// The maximum amount of data returned by a single read.
+const SEGMENT_SIZE: usize = 1460;
+
+/// A network stack that plays back a fixed stream of data from the broker.
+struct StreamStack {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl StreamStack {
+    fn new() -> Self {
+        // A successful CONNACK without properties.
+        let mut data = vec![0x20, 0x03, 0x00, 0x00, 0x00];
+
+        let topic = b"bench/data";
+        let payload = [0xA5; 32];
+        for _ in 0..MESSAGES {
+            data.push(0x30);
+            data.push((2 + topic.len() + 1 + payload.len()) as u8);
+            data.extend_from_slice(&(topic.len() as u16).to_be_bytes());
+            data.extend_from_slice(topic);
+            data.push(0x00);
+            data.extend_from_slice(&payload);
+        }
+
+        Self { data, position: 0 }
+    }
+}
+
+impl TcpClientStack for StreamStack {
+    type TcpSocket = ();
+    type Error = ();
+
+    fn socket(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn connect(&mut self, _: &mut (), _: SocketAddr) -> nb::Result<(), ()> {
+        Ok(())
+    }
+
+    fn is_connected(&mut self, _: &()) -> Result<bool, ()> {
+        Ok(true)
+    }
+
+    fn send(&mut self, _: &mut (), buffer: &[u8]) -> nb::Result<usize, ()> {
+        Ok(buffer.len())
+    }
+
+    fn receive(&mut self, _: &mut (), buffer: &mut [u8]) -> nb::Result<usize, ()> {
+        let remaining = &self.data[self.position..];
+        if remaining.is_empty() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let length = remaining.len().min(buffer.len()).min(SEGMENT_SIZE);
+        buffer[..length].copy_from_slice(&remaining[..length]);
+        self.position += length;
+        Ok(length)
+    }
+
+    fn close(&mut self, _: ()) -> Result<(), ()> {
+        Ok(())
+    }
+}
+
+type Client = Minimq<StreamStack, std_embedded_time::StandardClock, 1024, 16>;
+
+fn client() -> Client {
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    Minimq::new(
+        localhost,
+        "bench",
+        StreamStack::new(),
+        std_embedded_time::StandardClock::default(),
+    )
+    .unwrap()
+}
+
+/// Measure the time taken to receive all messages.
+///
+/// # Args
+/// * `poll` - A function that polls the client once and returns the number of handled messages.
+fn measure(mut poll: impl FnMut(&mut Client) -> usize) -> Duration {
+    let mut mqtt = client();
+    let mut received = 0;
+
+    let start = Instant::now();
+    while received < MESSAGES {
+        received += poll(&mut mqtt);
+    }
+
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:>10}: {:>8.2} ms, {:>10.0} messages/s",
+        name,
+        elapsed.as_secs_f64() * 1e3,
+        MESSAGES as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let single = measure(|mqtt| {
+        mqtt.poll(|_, _, payload, _| {
+            std::hint::black_box(payload);
+        })
+        .unwrap()
+        .map_or(0, |_| 1)
+    });
+
+    let drained = measure(|mqtt| {
+        mqtt.poll_all(|_, _, payload, _| {
+            std::hint::black_box(payload);
+        })
+        .unwrap()
+    });
+
+    report("poll", single);
+    report("poll_all", drained);
+    println!(
+        "{:>10}: {:.2}x",
+        "speedup",
+        single.as_secs_f64() / drained.as_secs_f64()
+    );
+}
@@ -0,0 +1,176 @@
+#ifndef MINIMQ_H
+#define MINIMQ_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit manually. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// The maximum size of messages sent and received by a client.
+#define MINIMQ_MESSAGE_SIZE 1024
+
+// The maximum number of messages that may be in flight.
+#define MINIMQ_MESSAGE_COUNT 16
+
+// The status of a call to the C API.
+typedef enum MinimqStatus {
+  // The call succeeded.
+  MINIMQ_STATUS_OK = 0,
+  // An argument was invalid, e.g. a null pointer or a string that is not valid UTF-8.
+  MINIMQ_STATUS_INVALID_ARGUMENT,
+  // The client is not connected to the broker.
+  MINIMQ_STATUS_NOT_CONNECTED,
+  // The request cannot be handled at the moment and may be retried later.
+  MINIMQ_STATUS_NOT_READY,
+  // A packet does not fit into a message buffer.
+  MINIMQ_STATUS_BUFFER_TOO_SMALL,
+  // A network callback reported an error.
+  MINIMQ_STATUS_NETWORK,
+  // The broker violated the MQTT protocol or refused a request.
+  MINIMQ_STATUS_PROTOCOL,
+  // Any other error occurred.
+  MINIMQ_STATUS_FAILED,
+} MinimqStatus;
+
+// An MQTT client used through the C API.
+typedef struct MinimqClient MinimqClient;
+
+// The callbacks implementing the network connection and clock of a client.
+//
+// # Note
+// All callbacks receive `context` as their first argument.
+typedef struct MinimqNetwork {
+  // An opaque pointer passed to every callback.
+  void *context;
+  // Connect to the broker at an IPv4 address in host byte order. Returns zero once connected, a
+  // positive value while the connection is in progress, or a negative value on failure.
+  int32_t (*connect)(void*, uint32_t, uint16_t);
+  // Send data to the broker. Returns the number of bytes accepted, which may be zero, or a
+  // negative value on failure.
+  ptrdiff_t (*send)(void*, const uint8_t*, size_t);
+  // Receive data from the broker. Returns the number of bytes received, which is zero if no
+  // data is available, or a negative value on failure.
+  ptrdiff_t (*receive)(void*, uint8_t*, size_t);
+  // Close the connection to the broker.
+  void (*close)(void*);
+  // Get the current time in milliseconds.
+  uint32_t (*now_ms)(void*);
+} MinimqNetwork;
+
+// The callback receiving messages. It is passed the context provided at registration, the topic
+// and the payload of the message. The topic is not null-terminated.
+typedef void (*MinimqMessageCallback)(void*, const uint8_t*, size_t, const uint8_t*, size_t);
+
+// Get the size of the storage required for a client in bytes.
+size_t minimq_client_size(void);
+
+// Get the alignment of the storage required for a client in bytes.
+size_t minimq_client_align(void);
+
+// Initialize a client in the provided storage.
+//
+// # Args
+// * `storage` - The memory to store the client in.
+// * `size` - The size of the storage in bytes. It must be at least `minimq_client_size()`.
+// * `broker` - The IPv4 address of the broker in host byte order.
+// * `client_id` - The null-terminated client identifier.
+// * `network` - The callbacks implementing the network connection and the clock. All callbacks
+//   must be provided. The table is copied into the client.
+//
+// # Returns
+// The initialized client, or null if an argument was invalid or the storage is not aligned to
+// `minimq_client_align()`.
+//
+// # Safety
+// `storage` must be valid for writes of `size` bytes for as long as the client is used, and
+// `client_id` and `network` must be null or valid pointers.
+MinimqClient *minimq_init(void *storage,
+                          size_t size,
+                          uint32_t broker,
+                          const char *client_id,
+                          const MinimqNetwork *network);
+
+// Release a client. The storage of the client may be reused afterwards.
+//
+// # Args
+// * `client` - The client to release.
+//
+// # Safety
+// `client` must be null or a client returned by `minimq_init()` that was not released yet.
+void minimq_deinit(MinimqClient *client);
+
+// Register the callback receiving messages.
+//
+// # Args
+// * `client` - The client to receive messages with.
+// * `callback` - The function to call for every received message. If null, messages are dropped.
+// * `context` - An opaque pointer passed to the callback.
+//
+// # Safety
+// `client` must be null or a valid client.
+void minimq_set_message_callback(MinimqClient *client,
+                                 MinimqMessageCallback callback,
+                                 void *context);
+
+// Check for received messages and maintain the connection to the broker.
+//
+// # Note
+// The registered callback is called for at most one message per call.
+//
+// # Args
+// * `client` - The client to poll.
+//
+// # Safety
+// `client` must be null or a valid client.
+MinimqStatus minimq_poll(MinimqClient *client);
+
+// Determine if the client is connected to the broker.
+//
+// # Args
+// * `client` - The client to check.
+//
+// # Safety
+// `client` must be null or a valid client.
+bool minimq_is_connected(MinimqClient *client);
+
+// Subscribe to a topic.
+//
+// # Args
+// * `client` - The client to subscribe with.
+// * `topic` - The null-terminated topic filter to subscribe to.
+//
+// # Safety
+// `client` must be null or a valid client, and `topic` must be null or a valid string.
+MinimqStatus minimq_subscribe(MinimqClient *client, const char *topic);
+
+// Determine if any subscriptions are waiting for acknowledgement by the broker.
+//
+// # Args
+// * `client` - The client to check.
+//
+// # Safety
+// `client` must be null or a valid client.
+bool minimq_subscriptions_pending(MinimqClient *client);
+
+// Publish a message.
+//
+// # Args
+// * `client` - The client to publish with.
+// * `topic` - The null-terminated topic to publish the message to.
+// * `payload` - The payload of the message. May be null if `length` is zero.
+// * `length` - The length of the payload in bytes.
+// * `qos` - The quality-of-service level of the message, which is 0 or 1.
+// * `retain` - Specifies whether the message should be retained by the broker.
+//
+// # Safety
+// `client` must be null or a valid client, `topic` must be null or a valid string, and `payload`
+// must be valid for reads of `length` bytes.
+MinimqStatus minimq_publish(MinimqClient *client,
+                            const char *topic,
+                            const uint8_t *payload,
+                            size_t length,
+                            uint8_t qos,
+                            bool retain);
+
+#endif /* MINIMQ_H */
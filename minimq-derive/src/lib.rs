@@ -0,0 +1,121 @@
+//! # MiniMQ Derive
+//! Provides derive macros for the `minimq` MQTT5 client.
+//!
+//! # Design
+//! `#[derive(Telemetry)]` implements `minimq::Telemetry` for a struct with named fields. Every field
+//! is published to a topic consisting of the prefix provided to `publish_telemetry()` and the name
+//! of the field, e.g. `sensor/temperature`. Field values are serialized using their `Display`
+//! implementation.
+//!
+//! Fields may be configured using the `telemetry` attribute:
+//! * `#[telemetry(skip)]` - The field is not published.
+//! * `#[telemetry(rename = "name")]` - The field is published using a different name.
+//!
+//! This crate is re-exported by `minimq` when the `derive` feature is enabled.
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Lit, Meta, NestedMeta};
+
+/// Derive `minimq::Telemetry` for a struct with named fields.
+///
+/// # Note
+/// All published fields must implement `core::fmt::Display`.
+#[proc_macro_derive(Telemetry, attributes(telemetry))]
+pub fn derive_telemetry(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match telemetry(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn telemetry(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "Telemetry can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "Telemetry can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut publications = Vec::new();
+    for field in fields.iter() {
+        // Note(unwrap): Named fields always have an identifier.
+        let ident = field.ident.as_ref().unwrap();
+        let mut name = ident.to_string();
+        let mut skip = false;
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("telemetry"))
+        {
+            let list = match attr.parse_meta()? {
+                Meta::List(list) => list,
+                meta => return Err(Error::new_spanned(meta, "Expected `telemetry(...)`")),
+            };
+
+            for nested in list.nested.iter() {
+                match nested {
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => skip = true,
+                    NestedMeta::Meta(Meta::NameValue(value)) if value.path.is_ident("rename") => {
+                        match &value.lit {
+                            Lit::Str(lit) => name = lit.value(),
+                            lit => return Err(Error::new_spanned(lit, "Expected a string")),
+                        }
+                    }
+                    nested => {
+                        return Err(Error::new_spanned(
+                            nested,
+                            "Unknown telemetry attribute, expected `skip` or `rename`",
+                        ))
+                    }
+                }
+            }
+        }
+
+        if name.is_empty() || name.contains(['/', '+', '#']) {
+            return Err(Error::new_spanned(
+                field,
+                format!("`{}` is not a valid topic level", name),
+            ));
+        }
+
+        if !skip {
+            publications.push(quote! {
+                ::minimq::telemetry::publish_field(client, prefix, #name, &self.#ident)?;
+            });
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::minimq::Telemetry for #ident #ty_generics #where_clause {
+            fn publish_telemetry<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>(
+                &self,
+                client: &mut ::minimq::mqtt_client::MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+                prefix: &str,
+            ) -> ::core::result::Result<(), ::minimq::Error<TcpStack::Error>>
+            where
+                TcpStack: ::minimq::embedded_nal::TcpClientStack,
+                Clock: ::minimq::embedded_time::Clock,
+            {
+                #(#publications)*
+                ::core::result::Result::Ok(())
+            }
+        }
+    })
+}
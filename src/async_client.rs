@@ -0,0 +1,284 @@
+//! Asynchronous Client
+//!
+//! # Design
+//! Firmware built on an async executor, such as embassy or RTIC, prefers to `await` the completion
+//! of a request instead of spinning a `poll()` loop. The `AsyncClient` wraps the `Minimq` client and
+//! provides futures to connect, publish, subscribe and receive messages. Every future drives the
+//! client while it is waiting, so the connection with the broker is serviced by whichever
+//! operation the application is currently awaiting.
+//!
+//! Messages may arrive while the application awaits an operation other than `receive()`. These are
+//! passed to the message handler that was provided when the client was constructed, so that no
+//! message is lost.
+//!
+//! # Note
+//! The client communicates over the blocking-free embedded-nal traits, which do not provide a
+//! means to wake a task once the network stack has data available. The client is not built on
+//! `embedded-nal-async`. Instead, the client waits on a `Timer` provided by the application while
+//! no data is received, until the next deadline of the client (see `MqttClient::next_deadline()`)
+//! or for at most the poll interval, whichever comes first. This allows the executor to sleep
+//! instead of polling the network continuously. While data is being received, the client only
+//! yields to other tasks before continuing.
+use crate::{mqtt_client::MqttClient, Error, Minimq, Property, QoS, Retain};
+
+use core::{
+    convert::TryFrom,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use embedded_nal::TcpClientStack;
+use embedded_time::duration::Milliseconds;
+
+/// The default interval at which the network is polled while no data is received.
+const DEFAULT_POLL_INTERVAL: Milliseconds<u32> = Milliseconds(10);
+
+/// A timer of the async executor, e.g. `embassy_time::Timer`.
+pub trait Timer {
+    /// The future that completes once the delay has passed.
+    type Delay: Future<Output = ()>;
+
+    /// Wait for a duration.
+    ///
+    /// # Args
+    /// * `duration` - The duration to wait for.
+    fn delay(&mut self, duration: Milliseconds<u32>) -> Self::Delay;
+}
+
+/// A handler for messages that arrive while awaiting an operation other than `receive()`.
+pub type MessageHandler<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize> =
+    for<'a> fn(
+        &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+        &'a str,
+        &[u8],
+        &[Property<'a>],
+    );
+
+/// A future that is pending once to allow other tasks to run.
+///
+/// # Note
+/// The task is woken right away, so this is only used while data is being received.
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+/// An MQTT client providing asynchronous operations.
+pub struct AsyncClient<TcpStack, Clock, Delay, const MSG_SIZE: usize, const MSG_COUNT: usize>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+    Delay: Timer,
+{
+    mqtt: Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+    handler: MessageHandler<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+    timer: Delay,
+    poll_interval: Milliseconds<u32>,
+}
+
+impl<TcpStack, Clock, Delay, const MSG_SIZE: usize, const MSG_COUNT: usize>
+    AsyncClient<TcpStack, Clock, Delay, MSG_SIZE, MSG_COUNT>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+    Delay: Timer,
+    u32: TryFrom<Clock::T>,
+{
+    /// Construct an asynchronous MQTT client.
+    ///
+    /// # Args
+    /// * `mqtt` - The client to drive.
+    /// * `timer` - The timer to wait on while no data is received.
+    /// * `handler` - The handler for messages that arrive while awaiting an operation other than
+    ///   `receive()`.
+    pub fn new(
+        mqtt: Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+        timer: Delay,
+        handler: MessageHandler<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+    ) -> Self {
+        Self {
+            mqtt,
+            handler,
+            timer,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+
+    /// Configure the interval at which the network is polled while no data is received.
+    ///
+    /// # Note
+    /// The network stack cannot wake the client once data arrives, so this bounds the latency of
+    /// received data. The client is polled earlier if one of its timers expires. The default is 10
+    /// milliseconds.
+    ///
+    /// # Args
+    /// * `interval` - The maximum duration to wait before polling the network again.
+    pub fn set_poll_interval(&mut self, interval: Milliseconds<u32>) {
+        self.poll_interval = interval;
+    }
+
+    /// Get a reference to the underlying client.
+    pub fn inner(&self) -> &Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT> {
+        &self.mqtt
+    }
+
+    /// Get a mutable reference to the underlying client.
+    pub fn inner_mut(&mut self) -> &mut Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT> {
+        &mut self.mqtt
+    }
+
+    /// Consume the asynchronous client and return the underlying client.
+    pub fn into_inner(self) -> Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT> {
+        self.mqtt
+    }
+
+    /// Service the connection once, passing any received message to the message handler.
+    async fn progress(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        let handler = self.handler;
+        let summary = self.mqtt.poll_with_summary(handler)?;
+
+        if summary.bytes_received > 0 || summary.pending {
+            // Further data is likely available right away.
+            yield_now().await;
+            Ok(())
+        } else {
+            self.idle().await
+        }
+    }
+
+    /// Wait until the network needs to be polled again.
+    async fn idle(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        // A deadline that already passed indicates that the client is waiting on the network,
+        // which cannot notify the client once it is ready.
+        let delay = match self.mqtt.client.time_until_deadline()? {
+            Some(remaining) if remaining > Milliseconds(0u32) && remaining < self.poll_interval => {
+                remaining
+            }
+            _ => self.poll_interval,
+        };
+
+        self.timer.delay(delay).await;
+        Ok(())
+    }
+
+    /// Wait until the connection with the broker is established.
+    pub async fn connect(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        while !self.mqtt.client.is_connected() {
+            self.progress().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Publish a message over MQTT.
+    ///
+    /// # Note
+    /// The future waits until the client is connected and the message can be published. It
+    /// resolves once the message was handed to the client, not once it was acknowledged. See
+    /// `MqttClient::publish()`.
+    ///
+    /// # Args
+    /// * `topic` - The topic to publish the message to.
+    /// * `data` - The data to transmit as the message contents.
+    /// * `qos` - The desired quality-of-service level of the message.
+    /// * `retain` - Specifies whether the message should be retained by the broker.
+    /// * `properties` - A list of properties to associate with the message being published. May be
+    ///   empty.
+    pub async fn publish(
+        &mut self,
+        topic: &str,
+        data: &[u8],
+        qos: QoS,
+        retain: Retain,
+        properties: &[Property<'_>],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        loop {
+            if self.mqtt.client.is_connected() {
+                match self
+                    .mqtt
+                    .client
+                    .publish(topic, data, qos, retain, properties)
+                {
+                    Err(Error::NotReady) => {}
+                    result => return result,
+                }
+            }
+
+            self.progress().await?;
+        }
+    }
+
+    /// Subscribe to a topic.
+    ///
+    /// # Note
+    /// The future waits until the client is connected and resolves once the broker acknowledged
+    /// all pending subscriptions. See `MqttClient::subscribe()`.
+    ///
+    /// # Args
+    /// * `topic` - The topic to subscribe to.
+    /// * `properties` - A list of properties to attach to the subscription request. May be empty.
+    pub async fn subscribe(
+        &mut self,
+        topic: &str,
+        properties: &[Property<'_>],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        loop {
+            if self.mqtt.client.is_connected() {
+                match self.mqtt.client.subscribe(topic, properties) {
+                    Err(Error::NotReady) => {}
+                    result => break result?,
+                }
+            }
+
+            self.progress().await?;
+        }
+
+        while self.mqtt.client.subscriptions_pending() {
+            self.progress().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait for a message to be received.
+    ///
+    /// # Args
+    /// * `f` - A closure to process the received message. See `Minimq::poll()`.
+    ///
+    /// # Returns
+    /// The value returned by the closure.
+    pub async fn receive<F, R>(&mut self, mut f: F) -> Result<R, Error<TcpStack::Error>>
+    where
+        for<'a> F: FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+            &'a str,
+            &[u8],
+            &[Property<'a>],
+        ) -> R,
+    {
+        loop {
+            if let Some(result) = self.mqtt.poll_one(&mut f)? {
+                return Ok(result);
+            }
+
+            self.idle().await?;
+        }
+    }
+}
@@ -0,0 +1,180 @@
+//! Client Builder
+//!
+//! # Design
+//! `Minimq::new()` only takes the arguments that every client requires. All other options are
+//! configured on the client before it connects, which `MinimqBuilder` collects in one place:
+//!
+//! ```ignore
+//! let mut mqtt: Minimq<_, _, 256, 16> = MinimqBuilder::new(broker, stack, clock)
+//!     .client_id("sensor")
+//!     .port(8883)
+//!     .keepalive_interval(30)
+//!     .will("sensor/status", b"offline", QoS::AtLeastOnce, Retain::Retained, &[])
+//!     .build()?;
+//! ```
+//!
+//! Options are validated once the client is built, so new options may be added without changing
+//! the signature of the constructor.
+use crate::{mqtt_client::AuthHandler, Error, Minimq, Property, QoS, Retain};
+use embedded_nal::{IpAddr, TcpClientStack};
+
+/// The will message of a client under construction.
+struct WillOptions<'a> {
+    topic: &'a str,
+    data: &'a [u8],
+    qos: QoS,
+    retain: Retain,
+    properties: &'a [Property<'a>],
+}
+
+/// A builder collecting the options of a `Minimq` client.
+pub struct MinimqBuilder<'a, TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    broker: IpAddr,
+    network_stack: TcpStack,
+    clock: Clock,
+    client_id: &'a str,
+    port: Option<u16>,
+    keepalive_interval: Option<u16>,
+    session_expiry: Option<u32>,
+    clean_start: bool,
+    topic_alias_maximum: u16,
+    will: Option<WillOptions<'a>>,
+    authentication: Option<(&'static str, AuthHandler)>,
+}
+
+impl<'a, TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+    MinimqBuilder<'a, TcpStack, Clock, MSG_SIZE, MSG_COUNT>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    /// Start building a new MQTT interface.
+    ///
+    /// # Args
+    /// * `broker` - The IP address of the broker to connect to.
+    /// * `network_stack` - The network stack to use for communication.
+    /// * `clock` - The clock to use for managing MQTT state timing.
+    pub fn new(broker: IpAddr, network_stack: TcpStack, clock: Clock) -> Self {
+        Self {
+            broker,
+            network_stack,
+            clock,
+            client_id: "",
+            port: None,
+            keepalive_interval: None,
+            session_expiry: None,
+            clean_start: false,
+            topic_alias_maximum: 0,
+            will: None,
+            authentication: None,
+        }
+    }
+
+    /// Use a client ID for communicating with the broker. By default, the broker assigns one.
+    pub fn client_id(mut self, client_id: &'a str) -> Self {
+        self.client_id = client_id;
+        self
+    }
+
+    /// Connect to the broker on a TCP port other than the standard MQTT port 1883.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port.replace(port);
+        self
+    }
+
+    /// Request a keep-alive interval in seconds. See `MqttClient::set_keepalive_interval()`.
+    pub fn keepalive_interval(mut self, interval_seconds: u16) -> Self {
+        self.keepalive_interval.replace(interval_seconds);
+        self
+    }
+
+    /// Request a session expiry interval in seconds. See `MqttClient::set_session_expiry()`.
+    pub fn session_expiry(mut self, interval_seconds: u32) -> Self {
+        self.session_expiry.replace(interval_seconds);
+        self
+    }
+
+    /// Discard the session whenever connecting. See `MqttClient::set_clean_start()`.
+    pub fn clean_start(mut self, clean_start: bool) -> Self {
+        self.clean_start = clean_start;
+        self
+    }
+
+    /// Allow the broker to use topic aliases. See `MqttClient::set_topic_alias_maximum()`.
+    pub fn topic_alias_maximum(mut self, maximum: u16) -> Self {
+        self.topic_alias_maximum = maximum;
+        self
+    }
+
+    /// Specify the will message. See `MqttClient::set_will()`.
+    pub fn will(
+        mut self,
+        topic: &'a str,
+        data: &'a [u8],
+        qos: QoS,
+        retain: Retain,
+        properties: &'a [Property<'a>],
+    ) -> Self {
+        self.will.replace(WillOptions {
+            topic,
+            data,
+            qos,
+            retain,
+            properties,
+        });
+        self
+    }
+
+    /// Use enhanced authentication. See `MqttClient::set_authentication()`.
+    pub fn authentication(mut self, method: &'static str, handler: AuthHandler) -> Self {
+        self.authentication.replace((method, handler));
+        self
+    }
+
+    /// Construct the MQTT interface.
+    ///
+    /// # Returns
+    /// The configured client, or an error if an option is invalid, e.g. if the client ID is too
+    /// long.
+    pub fn build(
+        self,
+    ) -> Result<Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT>, Error<TcpStack::Error>> {
+        let mut mqtt = Minimq::new(self.broker, self.client_id, self.network_stack, self.clock)?;
+        let client = &mut mqtt.client;
+
+        if let Some(port) = self.port {
+            client.set_broker_port(port);
+        }
+
+        if let Some(interval) = self.keepalive_interval {
+            client.set_keepalive_interval(interval)?;
+        }
+
+        if let Some(interval) = self.session_expiry {
+            client.set_session_expiry(interval)?;
+        }
+
+        client.set_clean_start(self.clean_start)?;
+        client.set_topic_alias_maximum(self.topic_alias_maximum)?;
+
+        if let Some(will) = self.will {
+            client.set_will(
+                will.topic,
+                will.data,
+                will.qos,
+                will.retain,
+                will.properties,
+            )?;
+        }
+
+        if let Some((method, handler)) = self.authentication {
+            client.set_authentication(method, handler);
+        }
+
+        Ok(mqtt)
+    }
+}
@@ -0,0 +1,136 @@
+//! Payload Compression
+//!
+//! # Design
+//! Payloads of published messages that exceed a configurable size are compressed using the
+//! heatshrink algorithm, which is designed for embedded systems and has no allocation
+//! requirements. Compressed messages are tagged with a `content-encoding: heatshrink` user
+//! property, so receivers can determine whether the payload has to be decompressed. A payload is
+//! only sent compressed if compression actually reduces its size.
+//!
+//! Received messages carrying the tag are decompressed before they are passed to the `poll()`
+//! handler, and the tag is removed from the properties provided to the handler. If a payload
+//! cannot be decompressed (e.g. because it does not fit into `MSG_SIZE` bytes), the message is
+//! passed to the handler unchanged with the tag still present.
+//!
+//! # Note
+//! The default heatshrink configuration (a window size of 2^11 bytes and a lookahead of 2^4 bytes)
+//! is used. Other receivers of compressed messages must use the same configuration.
+use crate::{warn, Property};
+use heapless::Vec;
+
+/// The name of the user property used to tag compressed payloads.
+const CONTENT_ENCODING: &str = "content-encoding";
+
+/// The value of the tag for payloads compressed with heatshrink.
+const HEATSHRINK: &str = "heatshrink";
+
+/// The user property that compressed messages are tagged with.
+pub(crate) const COMPRESSED: Property<'static> =
+    Property::UserProperty(CONTENT_ENCODING, HEATSHRINK);
+
+/// Compress a payload.
+///
+/// # Args
+/// * `payload` - The payload to compress.
+/// * `dest` - The location to store the compressed payload into.
+///
+/// # Returns
+/// The compressed payload if it is smaller than the original payload.
+pub(crate) fn compress<'a>(payload: &[u8], dest: &'a mut [u8]) -> Option<&'a [u8]> {
+    let compressed = heatshrink::encode(payload, dest, &heatshrink::Config::default()).ok()?;
+
+    if compressed.len() < payload.len() {
+        Some(compressed)
+    } else {
+        None
+    }
+}
+
+/// Decompress the payload of a received message if it is tagged as compressed.
+///
+/// # Args
+/// * `payload` - The received payload.
+/// * `properties` - The properties of the received message. The compression tag is removed if
+///   the payload was decompressed.
+/// * `dest` - The location to store the decompressed payload into.
+///
+/// # Returns
+/// The decompressed payload, or the received payload if it was not decompressed.
+pub(crate) fn decompress<'a, const N: usize>(
+    payload: &'a [u8],
+    properties: &mut Vec<Property<'_>, N>,
+    dest: &'a mut [u8],
+) -> &'a [u8] {
+    let index = match properties
+        .iter()
+        .position(|property| *property == COMPRESSED)
+    {
+        Some(index) => index,
+        None => return payload,
+    };
+
+    match heatshrink::decode(payload, dest, &heatshrink::Config::default()) {
+        Ok(decompressed) => {
+            properties.remove(index);
+            decompressed
+        }
+        Err(_) => {
+            warn!("Failed to decompress received payload");
+            payload
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress, COMPRESSED};
+    use crate::Property;
+    use heapless::Vec;
+
+    const PAYLOAD: &[u8] =
+        br#"{"temperature": 20.5, "humidity": 40.5, "temperature_setpoint": 20.0}"#;
+
+    #[test]
+    fn compression_round_trip() {
+        let mut compressed = [0; 128];
+        let compressed = compress(PAYLOAD, &mut compressed).unwrap();
+        assert!(compressed.len() < PAYLOAD.len());
+
+        let mut properties: Vec<Property, 2> = Vec::new();
+        properties.push(Property::ContentType("json")).unwrap();
+        properties.push(COMPRESSED).unwrap();
+
+        let mut decompressed = [0; 128];
+        let payload = decompress(compressed, &mut properties, &mut decompressed);
+        assert_eq!(payload, PAYLOAD);
+        assert_eq!(properties, [Property::ContentType("json")]);
+    }
+
+    #[test]
+    fn incompressible_payload() {
+        let mut compressed = [0; 128];
+        assert!(compress(&[0x12, 0x34, 0x56], &mut compressed).is_none());
+    }
+
+    #[test]
+    fn uncompressed_payload_is_unchanged() {
+        let mut properties: Vec<Property, 2> = Vec::new();
+        let mut decompressed = [0; 128];
+        let payload = decompress(PAYLOAD, &mut properties, &mut decompressed);
+        assert_eq!(payload, PAYLOAD);
+    }
+
+    #[test]
+    fn oversized_payload_is_not_decompressed() {
+        let mut compressed = [0; 128];
+        let compressed = compress(PAYLOAD, &mut compressed).unwrap();
+
+        let mut properties: Vec<Property, 2> = Vec::new();
+        properties.push(COMPRESSED).unwrap();
+
+        let mut decompressed = [0; 16];
+        let payload = decompress(compressed, &mut properties, &mut decompressed);
+        assert_eq!(payload, compressed);
+        assert_eq!(properties, [COMPRESSED]);
+    }
+}
@@ -1,6 +1,9 @@
-use crate::{de::PacketReader, message_types::MessageType, Property, ProtocolError as Error};
+use crate::{
+    de::{PacketReader, Properties},
+    message_types::MessageType,
+    ProtocolError as Error, QoS, Retain,
+};
 use bit_field::BitField;
-use heapless::Vec;
 
 #[derive(Debug)]
 pub struct ConnAck<'a> {
@@ -11,7 +14,7 @@ pub struct ConnAck<'a> {
     pub reason_code: u8,
 
     /// A list of properties associated with the connection.
-    pub properties: Vec<Property<'a>, 8>,
+    pub properties: Properties<'a>,
 }
 
 #[derive(Debug)]
@@ -19,14 +22,27 @@ pub struct Pub<'a> {
     /// The topic that the message was received on.
     pub topic: &'a str,
 
+    /// The quality-of-service level that the message was delivered with.
+    pub qos: QoS,
+
+    /// Indicates whether the message was retained by the broker before being delivered.
+    pub retain: Retain,
+
+    /// Indicates true if the message may have been delivered before.
+    pub duplicate: bool,
+
+    /// The packet identifier of the message. Only present for QoS levels above `AtMostOnce`.
+    pub packet_id: Option<u16>,
+
     /// The properties transmitted with the publish data.
-    pub properties: Vec<Property<'a>, 8>,
+    pub properties: Properties<'a>,
 
     /// The payload of the message.
     pub payload: &'a [u8],
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct PubAck<'a> {
     /// Packet identifier
     pub packet_identifier: u16,
@@ -35,19 +51,52 @@ pub struct PubAck<'a> {
     pub reason: u8,
 
     /// The properties transmitted with the publish data.
-    pub properties: Vec<Property<'a>, 8>,
+    pub properties: Properties<'a>,
 }
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct SubAck<'a> {
     /// The identifier that the acknowledge is assocaited with.
     pub packet_identifier: u16,
 
-    /// The success status of the subscription request.
-    pub reason_code: u8,
+    /// The success status of each topic filter of the subscription request, in the order that
+    /// the filters were requested.
+    pub reason_codes: &'a [u8],
 
     /// A list of properties associated with the subscription.
-    pub properties: Vec<Property<'a>, 8>,
+    pub properties: Properties<'a>,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct UnsubAck<'a> {
+    /// The identifier that the acknowledge is associated with.
+    pub packet_identifier: u16,
+
+    /// The success status of the unsubscribe request.
+    pub reason_code: u8,
+
+    /// A list of properties associated with the acknowledgement.
+    pub properties: Properties<'a>,
+}
+
+#[derive(Debug)]
+pub struct Disconnect<'a> {
+    /// The reason that the server closed the connection.
+    pub reason_code: u8,
+
+    /// A list of properties associated with the disconnection.
+    pub properties: Properties<'a>,
+}
+
+#[derive(Debug)]
+pub struct Auth<'a> {
+    /// The status of the authentication exchange.
+    pub reason_code: u8,
+
+    /// A list of properties associated with the authentication, such as the challenge.
+    pub properties: Properties<'a>,
 }
 
 #[derive(Debug)]
@@ -55,8 +104,14 @@ pub enum ReceivedPacket<'a> {
     ConnAck(ConnAck<'a>),
     Publish(Pub<'a>),
     PubAck(PubAck<'a>),
+    PubRec(PubAck<'a>),
+    PubComp(PubAck<'a>),
+    PubRel(PubAck<'a>),
     SubAck(SubAck<'a>),
+    UnsubAck(UnsubAck<'a>),
     PingResp,
+    Disconnect(Disconnect<'a>),
+    Auth(Auth<'a>),
 }
 
 impl<'a> ReceivedPacket<'a> {
@@ -67,55 +122,129 @@ impl<'a> ReceivedPacket<'a> {
     ///
     /// # Returns
     /// A packet describing the received content.
-    pub(crate) fn parse_message<'reader: 'a, const T: usize>(
-        packet_reader: &'reader PacketReader<T>,
+    pub(crate) fn parse_message<'reader: 'a>(
+        packet_reader: &'reader PacketReader,
     ) -> Result<ReceivedPacket<'a>, Error> {
         let (message_type, flags, remaining_length) = packet_reader.read_fixed_header()?;
 
         // Validate packet length.
         if remaining_length != packet_reader.len()? {
-            return Err(Error::MalformedPacket);
+            return Err(packet_reader.malformed(
+                1,
+                "remaining length matching the packet",
+                Some(remaining_length as u32),
+            ));
         }
 
         match message_type {
             MessageType::ConnAck => {
                 if flags != 0 {
-                    return Err(Error::MalformedPacket);
+                    return Err(packet_reader.malformed(0, "no flags", Some(flags as u32)));
                 }
 
                 Ok(ReceivedPacket::ConnAck(parse_connack(packet_reader)?))
             }
 
-            MessageType::Publish => Ok(ReceivedPacket::Publish(parse_publish(packet_reader)?)),
+            MessageType::Publish => Ok(ReceivedPacket::Publish(parse_publish(
+                packet_reader,
+                flags,
+            )?)),
 
             MessageType::PubAck => Ok(ReceivedPacket::PubAck(parse_puback(packet_reader)?)),
 
+            // PUBREC and PUBCOMP packets share the layout of the PUBACK.
+            MessageType::PubRec | MessageType::PubComp => {
+                if flags != 0 {
+                    return Err(packet_reader.malformed(0, "no flags", Some(flags as u32)));
+                }
+
+                let ack = parse_puback(packet_reader)?;
+                Ok(match message_type {
+                    MessageType::PubRec => ReceivedPacket::PubRec(ack),
+                    _ => ReceivedPacket::PubComp(ack),
+                })
+            }
+
+            // PUBREL packets share the layout of the PUBACK, but have a reserved flag set.
+            MessageType::PubRel => {
+                if flags != 0b0010 {
+                    return Err(packet_reader.malformed(0, "flags of 0b0010", Some(flags as u32)));
+                }
+
+                Ok(ReceivedPacket::PubRel(parse_puback(packet_reader)?))
+            }
+
             MessageType::SubAck => {
                 if flags != 0 {
-                    return Err(Error::MalformedPacket);
+                    return Err(packet_reader.malformed(0, "no flags", Some(flags as u32)));
                 }
 
                 Ok(ReceivedPacket::SubAck(parse_suback(packet_reader)?))
             }
 
+            MessageType::UnsubAck => {
+                if flags != 0 {
+                    return Err(packet_reader.malformed(0, "no flags", Some(flags as u32)));
+                }
+
+                Ok(ReceivedPacket::UnsubAck(parse_unsuback(packet_reader)?))
+            }
+
             MessageType::PingResp => {
-                if flags != 0 || remaining_length != 0 {
-                    return Err(Error::MalformedPacket);
+                if flags != 0 {
+                    return Err(packet_reader.malformed(0, "no flags", Some(flags as u32)));
+                }
+
+                if remaining_length != 0 {
+                    return Err(packet_reader.malformed(
+                        1,
+                        "no remaining length",
+                        Some(remaining_length as u32),
+                    ));
                 }
 
                 Ok(ReceivedPacket::PingResp)
             }
 
+            MessageType::Disconnect => {
+                if flags != 0 {
+                    return Err(packet_reader.malformed(0, "no flags", Some(flags as u32)));
+                }
+
+                let (reason_code, properties) = parse_reason(packet_reader)?;
+                Ok(ReceivedPacket::Disconnect(Disconnect {
+                    reason_code,
+                    properties,
+                }))
+            }
+
+            MessageType::Auth => {
+                if flags != 0 {
+                    return Err(packet_reader.malformed(0, "no flags", Some(flags as u32)));
+                }
+
+                let (reason_code, properties) = parse_reason(packet_reader)?;
+                Ok(ReceivedPacket::Auth(Auth {
+                    reason_code,
+                    properties,
+                }))
+            }
+
             _ => Err(Error::UnsupportedPacket),
         }
     }
 }
 
-fn parse_connack<const T: usize>(p: &PacketReader<T>) -> Result<ConnAck, Error> {
+fn parse_connack(p: &PacketReader) -> Result<ConnAck<'_>, Error> {
     // Read the connect acknowledgement flags.
+    let offset = p.offset();
     let flags = p.read_u8()?;
     if flags != 0 && flags != 1 {
-        return Err(Error::MalformedPacket);
+        return Err(p.malformed(
+            offset,
+            "session present acknowledge flag",
+            Some(flags as u32),
+        ));
     }
 
     let reason_code = p.read_u8()?;
@@ -132,11 +261,29 @@ fn parse_connack<const T: usize>(p: &PacketReader<T>) -> Result<ConnAck, Error>
     })
 }
 
-fn parse_publish<'a, 'reader: 'a, const T: usize>(
-    p: &'reader PacketReader<T>,
-) -> Result<Pub<'a>, Error> {
+fn parse_publish<'a, 'reader: 'a>(p: &'reader PacketReader, flags: u8) -> Result<Pub<'a>, Error> {
+    let qos = match flags.get_bits(1..=2) {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        2 => QoS::ExactlyOnce,
+        qos => return Err(p.malformed(0, "QoS 0, 1 or 2", Some(qos as u32))),
+    };
+
+    let retain = if flags.get_bit(0) {
+        Retain::Retained
+    } else {
+        Retain::NotRetained
+    };
+
     let topic = p.read_utf8_string()?;
 
+    // The packet identifier is only present for QoS levels above `AtMostOnce`.
+    let packet_id = if qos != QoS::AtMostOnce {
+        Some(p.read_u16()?)
+    } else {
+        None
+    };
+
     let properties = p.read_properties()?;
     // TODO: Validate properties associated with this message.
 
@@ -144,14 +291,16 @@ fn parse_publish<'a, 'reader: 'a, const T: usize>(
 
     Ok(Pub {
         topic,
+        qos,
+        retain,
+        duplicate: flags.get_bit(3),
+        packet_id,
         properties,
         payload,
     })
 }
 
-fn parse_puback<'a, 'reader: 'a, const T: usize>(
-    p: &'reader PacketReader<T>,
-) -> Result<PubAck<'a>, Error> {
+fn parse_puback<'a, 'reader: 'a>(p: &'reader PacketReader) -> Result<PubAck<'a>, Error> {
     let id = p.read_u16()?;
     // If length = 4 -> 1 byte fixed header, 1 byte remaining length, 2 bytes variable header
     // variable header has packet identifier only with no properties and default success 0x00 reason code
@@ -159,21 +308,31 @@ fn parse_puback<'a, 'reader: 'a, const T: usize>(
         return Ok(PubAck {
             packet_identifier: id,
             reason: 0x00,
-            properties: Vec::new(),
+            properties: Properties::new(),
         });
     }
 
     let reason = p.read_u8()?;
+
+    // If length = 5, the reason code is not followed by any properties.
+    if p.packet_length() == Ok(5) {
+        return Ok(PubAck {
+            packet_identifier: id,
+            reason,
+            properties: Properties::new(),
+        });
+    }
+
     let properties = p.read_properties()?;
 
     Ok(PubAck {
         packet_identifier: id,
-        reason: reason,
+        reason,
         properties,
     })
 }
 
-fn parse_suback<const T: usize>(p: &PacketReader<T>) -> Result<SubAck, Error> {
+fn parse_suback<'a, 'reader: 'a>(p: &'reader PacketReader) -> Result<SubAck<'a>, Error> {
     // Read the variable length header.
     let id = p.read_u16()?;
 
@@ -181,19 +340,55 @@ fn parse_suback<const T: usize>(p: &PacketReader<T>) -> Result<SubAck, Error> {
     let properties = p.read_properties()?;
     // TODO: Validate properties associated with this message.
 
-    // Read the final payload, which contains the reason code.
-    let reason_code = p.read_u8()?;
+    // Read the final payload, which contains a reason code for each requested topic filter.
+    let reason_codes = p.payload()?;
+    if reason_codes.is_empty() {
+        return Err(p.malformed(p.offset(), "at least one reason code", None));
+    }
 
     Ok(SubAck {
+        packet_identifier: id,
+        reason_codes,
+        properties,
+    })
+}
+
+fn parse_unsuback(p: &PacketReader) -> Result<UnsubAck<'_>, Error> {
+    let id = p.read_u16()?;
+    let properties = p.read_properties()?;
+
+    // The payload contains a reason code for the single unsubscribed topic filter.
+    let reason_code = p.read_u8()?;
+
+    Ok(UnsubAck {
         packet_identifier: id,
         reason_code,
         properties,
     })
 }
 
+/// Parse the reason code and properties of a DISCONNECT or AUTH packet.
+fn parse_reason(p: &PacketReader) -> Result<(u8, Properties<'_>), Error> {
+    // The reason code and properties may be omitted if the reason code is 0x00.
+    if p.len()? == 0 {
+        return Ok((0, Properties::new()));
+    }
+
+    let reason_code = p.read_u8()?;
+
+    let properties = if p.len()? > 0 {
+        p.read_properties()?
+    } else {
+        Properties::new()
+    };
+
+    Ok((reason_code, properties))
+}
+
 #[cfg(test)]
 mod test {
     use super::{PacketReader, ReceivedPacket};
+    use crate::{Property, ProtocolError, QoS, Retain};
 
     #[test]
     fn deserialize_good_connack() {
@@ -205,8 +400,8 @@ mod test {
                   // No payload.
         ];
 
-        let mut reader = PacketReader::<32>::from_serialized(&mut serialized_connack);
-        let connack = ReceivedPacket::parse_message(&mut reader).unwrap();
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_connack);
+        let connack = ReceivedPacket::parse_message(&reader).unwrap();
         match connack {
             ReceivedPacket::ConnAck(conn_ack) => {
                 assert_eq!(conn_ack.reason_code, 0);
@@ -226,11 +421,151 @@ mod test {
             0x05, // Payload
         ];
 
-        let mut reader = PacketReader::<32>::from_serialized(&mut serialized_publish);
-        let publish = ReceivedPacket::parse_message(&mut reader).unwrap();
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_publish);
+        let publish = ReceivedPacket::parse_message(&reader).unwrap();
+        match publish {
+            ReceivedPacket::Publish(pub_info) => {
+                assert_eq!(pub_info.topic, "A");
+                assert_eq!(pub_info.packet_id, None);
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
+    #[test]
+    fn deserialize_good_publish_qos1() {
+        let mut serialized_publish: [u8; 9] = [
+            0x32, // Publish, QoS 1
+            0x07, // Remaining length
+            0x00, 0x01, // Topic length (1)
+            0x41, // Topic name: 'A'
+            0x00, 0x07, // Packet identifier
+            0x00, // Properties length
+            0x05, // Payload
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_publish);
+        let publish = ReceivedPacket::parse_message(&reader).unwrap();
         match publish {
             ReceivedPacket::Publish(pub_info) => {
                 assert_eq!(pub_info.topic, "A");
+                assert_eq!(pub_info.qos, QoS::AtLeastOnce);
+                assert_eq!(pub_info.packet_id, Some(7));
+                assert_eq!(pub_info.payload, [0x05]);
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
+    #[test]
+    fn deserialize_good_publish_flags() {
+        let mut serialized_publish: [u8; 9] = [
+            0x3B, // Publish, DUP, QoS 1, retained
+            0x07, // Remaining length
+            0x00, 0x01, // Topic length (1)
+            0x41, // Topic name: 'A'
+            0x00, 0x07, // Packet identifier
+            0x00, // Properties length
+            0x05, // Payload
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_publish);
+        let publish = ReceivedPacket::parse_message(&reader).unwrap();
+        match publish {
+            ReceivedPacket::Publish(pub_info) => {
+                assert_eq!(pub_info.qos, QoS::AtLeastOnce);
+                assert_eq!(pub_info.retain, Retain::Retained);
+                assert!(pub_info.duplicate);
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
+    #[test]
+    fn deserialize_good_publish_content_type() {
+        let mut serialized_publish: [u8; 17] = [
+            0x30, // Publish, no QoS
+            0x0F, // Remaining length
+            0x00, 0x01, // Topic length (1)
+            0x41, // Topic name: 'A'
+            0x09, // Properties length
+            0x01, 0x01, // Payload format indicator (UTF-8)
+            0x03, 0x00, 0x04, b'j', b's', b'o', b'n', // Content type: json
+            b'{', b'}', // Payload
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_publish);
+        let publish = ReceivedPacket::parse_message(&reader).unwrap();
+        match publish {
+            ReceivedPacket::Publish(pub_info) => {
+                assert_eq!(
+                    pub_info.properties,
+                    [
+                        Property::PayloadFormatIndicator(1),
+                        Property::ContentType("json")
+                    ]
+                );
+                assert_eq!(pub_info.payload, b"{}");
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
+    #[test]
+    fn deserialize_good_publish_subscription_identifier() {
+        let mut serialized_publish: [u8; 11] = [
+            0x30, // Publish, no QoS
+            0x09, // Remaining length
+            0x00, 0x01, // Topic length (1)
+            0x41, // Topic name: 'A'
+            0x04, // Properties length
+            0x0B, 0x80, 0x80, 0x01, // Subscription identifier (16384)
+            0x05, // Payload
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_publish);
+        let publish = ReceivedPacket::parse_message(&reader).unwrap();
+        match publish {
+            ReceivedPacket::Publish(pub_info) => {
+                assert_eq!(
+                    pub_info.properties,
+                    [Property::SubscriptionIdentifier(16384)]
+                );
+                assert_eq!(pub_info.payload, [0x05]);
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
+    #[test]
+    fn deserialize_good_publish_user_properties() {
+        let mut serialized_publish: heapless::Vec<u8, 128> = heapless::Vec::from_slice(&[
+            0x30, // Publish, no QoS
+            0x4B, // Remaining length
+            0x00, 0x01, // Topic length (1)
+            0x41, // Topic name: 'A'
+            0x46, // Properties length
+        ])
+        .unwrap();
+
+        // Ten user properties `k: v`.
+        for _ in 0..10 {
+            serialized_publish
+                .extend_from_slice(&[0x26, 0x00, 0x01, b'k', 0x00, 0x01, b'v'])
+                .unwrap();
+        }
+        serialized_publish.push(0x05).unwrap(); // Payload
+
+        let reader = PacketReader::<[u8; 128]>::from_serialized(&mut serialized_publish);
+        let publish = ReceivedPacket::parse_message(&reader).unwrap();
+        match publish {
+            ReceivedPacket::Publish(pub_info) => {
+                assert_eq!(pub_info.properties.len(), 10);
+                assert!(pub_info
+                    .properties
+                    .iter()
+                    .all(|property| *property == Property::UserProperty("k", "v")));
+                assert_eq!(pub_info.payload, [0x05]);
             }
             _ => panic!("Invalid message"),
         }
@@ -246,8 +581,8 @@ mod test {
             0x00, // Properties length
         ];
 
-        let mut reader = PacketReader::<32>::from_serialized(&mut serialized_suback);
-        let puback = ReceivedPacket::parse_message(&mut reader).unwrap();
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_suback);
+        let puback = ReceivedPacket::parse_message(&reader).unwrap();
         match puback {
             ReceivedPacket::PubAck(pub_ack) => {
                 assert_eq!(pub_ack.reason, 0x10);
@@ -266,8 +601,8 @@ mod test {
             0x00, 0x06, // Identifier
         ];
 
-        let mut reader = PacketReader::<32>::from_serialized(&mut serialized_suback);
-        let puback = ReceivedPacket::parse_message(&mut reader).unwrap();
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_suback);
+        let puback = ReceivedPacket::parse_message(&reader).unwrap();
         match puback {
             ReceivedPacket::PubAck(pub_ack) => {
                 assert_eq!(pub_ack.reason, 0x00);
@@ -278,6 +613,138 @@ mod test {
         }
     }
 
+    #[test]
+    fn deserialize_good_puback_without_properties() {
+        let mut serialized_puback: [u8; 5] = [
+            0x40, // PubAck
+            0x03, // Remaining length
+            0x00, 0x07, // Identifier
+            0x97, // Reason code - Quota exceeded
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_puback);
+        let puback = ReceivedPacket::parse_message(&reader).unwrap();
+        match puback {
+            ReceivedPacket::PubAck(pub_ack) => {
+                assert_eq!(pub_ack.reason, 0x97);
+                assert_eq!(pub_ack.packet_identifier, 7);
+                assert_eq!(pub_ack.properties.len(), 0);
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
+    #[test]
+    fn deserialize_good_pubrec() {
+        let mut serialized_pubrec: [u8; 6] = [
+            0x50, // PubRec
+            0x04, // Remaining length
+            0x00, 0x06, // Identifier
+            0x10, // Reason code - No matching subscribers
+            0x00, // Properties length
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_pubrec);
+        let pubrec = ReceivedPacket::parse_message(&reader).unwrap();
+        match pubrec {
+            ReceivedPacket::PubRec(pub_rec) => {
+                assert_eq!(pub_rec.reason, 0x10);
+                assert_eq!(pub_rec.packet_identifier, 6);
+            }
+            _ => panic!("Invalid message"),
+        }
+
+        let mut serialized_pubcomp: [u8; 4] = [
+            0x70, // PubComp
+            0x02, // Remaining length
+            0x00, 0x06, // Identifier
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_pubcomp);
+        let pubcomp = ReceivedPacket::parse_message(&reader).unwrap();
+        match pubcomp {
+            ReceivedPacket::PubComp(pub_comp) => assert_eq!(pub_comp.packet_identifier, 6),
+            _ => panic!("Invalid message"),
+        }
+    }
+
+    #[test]
+    fn deserialize_good_pubrel() {
+        let mut serialized_pubrel: [u8; 4] = [
+            0x62, // PubRel
+            0x02, // Remaining length
+            0x00, 0x07, // Identifier
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_pubrel);
+        let pubrel = ReceivedPacket::parse_message(&reader).unwrap();
+        match pubrel {
+            ReceivedPacket::PubRel(pub_rel) => {
+                assert_eq!(pub_rel.reason, 0x00);
+                assert_eq!(pub_rel.packet_identifier, 7);
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
+    #[test]
+    fn deserialize_good_disconnect() {
+        let mut serialized_disconnect: [u8; 9] = [
+            0xe0, // Disconnect
+            0x07, // Remaining length
+            0x8b, // Reason code - Server shutting down
+            0x05, // Properties length
+            0x1f, 0x00, 0x02, 0x4f, 0x66, // Reason string - "Of"
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_disconnect);
+        let disconnect = ReceivedPacket::parse_message(&reader).unwrap();
+        match disconnect {
+            ReceivedPacket::Disconnect(disconnect) => {
+                assert_eq!(disconnect.reason_code, 0x8b);
+                assert_eq!(disconnect.properties.len(), 1);
+            }
+            _ => panic!("Invalid message"),
+        }
+
+        let mut serialized_disconnect: [u8; 2] = [
+            0xe0, // Disconnect
+            0x00, // Remaining length
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_disconnect);
+        let disconnect = ReceivedPacket::parse_message(&reader).unwrap();
+        match disconnect {
+            ReceivedPacket::Disconnect(disconnect) => {
+                assert_eq!(disconnect.reason_code, 0x00);
+                assert_eq!(disconnect.properties.len(), 0);
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
+    #[test]
+    fn deserialize_good_auth() {
+        let mut serialized_auth: [u8; 14] = [
+            0xf0, // Auth
+            0x0c, // Remaining length
+            0x18, // Reason code - Continue authentication
+            0x0a, // Properties length
+            0x15, 0x00, 0x03, 0x41, 0x42, 0x43, // Authentication method - "ABC"
+            0x16, 0x00, 0x01, 0xAB, // Authentication data
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_auth);
+        let auth = ReceivedPacket::parse_message(&reader).unwrap();
+        match auth {
+            ReceivedPacket::Auth(auth) => {
+                assert_eq!(auth.reason_code, 0x18);
+                assert_eq!(auth.properties.len(), 2);
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
     #[test]
     fn deserialize_good_suback() {
         let mut serialized_suback: [u8; 6] = [
@@ -288,17 +755,58 @@ mod test {
             0x02, // Response Code
         ];
 
-        let mut reader = PacketReader::<32>::from_serialized(&mut serialized_suback);
-        let suback = ReceivedPacket::parse_message(&mut reader).unwrap();
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_suback);
+        let suback = ReceivedPacket::parse_message(&reader).unwrap();
         match suback {
             ReceivedPacket::SubAck(sub_ack) => {
-                assert_eq!(sub_ack.reason_code, 2);
+                assert_eq!(sub_ack.reason_codes, [2]);
                 assert_eq!(sub_ack.packet_identifier, 5);
             }
             _ => panic!("Invalid message"),
         }
     }
 
+    #[test]
+    fn deserialize_good_suback_multiple() {
+        let mut serialized_suback: [u8; 8] = [
+            0x90, // SubAck
+            0x06, // Remaining length
+            0x00, 0x05, // Identifier
+            0x00, // Properties length
+            0x00, 0x01, 0x87, // Response codes
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_suback);
+        let suback = ReceivedPacket::parse_message(&reader).unwrap();
+        match suback {
+            ReceivedPacket::SubAck(sub_ack) => {
+                assert_eq!(sub_ack.reason_codes, [0x00, 0x01, 0x87]);
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
+    #[test]
+    fn deserialize_good_unsuback() {
+        let mut serialized_unsuback: [u8; 6] = [
+            0xb0, // UnsubAck
+            0x04, // Remaining length
+            0x00, 0x07, // Identifier
+            0x00, // Properties length
+            0x11, // Response Code - No subscription existed
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_unsuback);
+        let unsuback = ReceivedPacket::parse_message(&reader).unwrap();
+        match unsuback {
+            ReceivedPacket::UnsubAck(unsub_ack) => {
+                assert_eq!(unsub_ack.reason_code, 0x11);
+                assert_eq!(unsub_ack.packet_identifier, 7);
+            }
+            _ => panic!("Invalid message"),
+        }
+    }
+
     #[test]
     fn deserialize_good_ping_resp() {
         let mut serialized_ping_req: [u8; 2] = [
@@ -306,11 +814,35 @@ mod test {
             0x00, // Remaining length (0)
         ];
 
-        let mut reader = PacketReader::<32>::from_serialized(&mut serialized_ping_req);
-        let ping_req = ReceivedPacket::parse_message(&mut reader).unwrap();
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_ping_req);
+        let ping_req = ReceivedPacket::parse_message(&reader).unwrap();
         match ping_req {
             ReceivedPacket::PingResp => {}
             _ => panic!("Invalid message"),
         }
     }
+
+    #[test]
+    fn deserialize_malformed_connack() {
+        let mut serialized_connack: [u8; 5] = [
+            0x20, 0x03, // Remaining length = 3 bytes
+            0x02, // Connect acknowledge flags - reserved bit 1 set.
+            0x00, // Connect reason code - 0 (Success)
+            0x00, // Property length = 0
+        ];
+
+        let reader = PacketReader::<[u8; 32]>::from_serialized(&mut serialized_connack);
+        let error = ReceivedPacket::parse_message(&reader).unwrap_err();
+
+        match error {
+            ProtocolError::MalformedPacket(_diagnostic) => {
+                #[cfg(feature = "diagnostics")]
+                {
+                    assert_eq!(_diagnostic.offset, 2);
+                    assert_eq!(_diagnostic.found, Some(0x02));
+                }
+            }
+            error => panic!("Unexpected error: {:?}", error),
+        }
+    }
 }
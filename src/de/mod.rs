@@ -1,3 +1,3 @@
 pub mod deserialize;
 mod packet_reader;
-pub(crate) use packet_reader::PacketReader;
+pub(crate) use packet_reader::{PacketReader, Properties};
@@ -1,6 +1,6 @@
 use crate::{
     message_types::MessageType,
-    Property, ProtocolError as Error, {debug, warn},
+    Diagnostic, Property, ProtocolError as Error, QoS, {debug, warn},
 };
 use bit_field::BitField;
 use heapless::Vec;
@@ -9,42 +9,145 @@ use heapless::Vec;
 // variable length integer size (4).
 const FIXED_HEADER_MAX: usize = 5;
 
-pub(crate) struct PacketReader<const T: usize> {
-    pub buffer: [u8; T],
+/// The maximum number of properties of a received packet. User properties may be repeated, so this
+/// exceeds the number of distinct properties of any packet.
+pub(crate) const MAX_PROPERTIES: usize = 16;
+
+/// The properties of a received packet.
+pub(crate) type Properties<'a> = Vec<Property<'a>, MAX_PROPERTIES>;
+
+/// Storage that packets are received into, e.g. `[u8; N]`.
+pub(crate) trait Storage: AsRef<[u8]> + AsMut<[u8]> {}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]> + ?Sized> Storage for T {}
+
+/// A reader for packets received into a buffer.
+///
+/// # Note
+/// The reader is generic over its buffer so that it may be stored inline, e.g. as
+/// `PacketReader<[u8; N]>`, or in a buffer of a pool. All parsing is implemented for
+/// `PacketReader<dyn Storage>`, which sized readers dereference to, so that it is only compiled once
+/// regardless of the buffers in use. The remainder of the client is still compiled for every
+/// combination of network stack, clock and buffer sizes in use.
+pub(crate) struct PacketReader<B: ?Sized = dyn Storage> {
     read_bytes: usize,
     packet_length: Option<usize>,
     index: core::cell::RefCell<usize>,
+    discard: usize,
+    pub buffer: B,
 }
 
-impl<const T: usize> PacketReader<T> {
-    pub fn new() -> PacketReader<T> {
-        PacketReader {
-            buffer: [0; T],
-            read_bytes: 0,
-            packet_length: None,
-            index: core::cell::RefCell::new(0),
-        }
+/// The header of a PUBLISH packet that may not have been received completely.
+pub(crate) struct PublishHeader<'a> {
+    pub topic: &'a str,
+    pub qos: QoS,
+    pub packet_id: Option<u16>,
+    pub payload_length: usize,
+}
+
+/// Decode a variable-length integer.
+///
+/// # Returns
+/// The value of the integer and the number of bytes that it occupies, or `None` if the integer is
+/// incomplete or invalid.
+fn decode_variable_length_integer(data: &[u8]) -> Option<(usize, usize)> {
+    let length = data.iter().take(4).position(|byte| !byte.get_bit(7))? + 1;
+    let value = data[..length]
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| ((byte & 0x7F) as usize) << (i * 7))
+        .sum();
+
+    Some((value, length))
+}
+
+impl<const T: usize> PacketReader<[u8; T]> {
+    #[cfg(test)]
+    pub fn new() -> Self {
+        Self::with_buffer([0; T])
     }
 
     #[cfg(test)]
-    pub fn from_serialized<'a>(buffer: &'a mut [u8]) -> PacketReader<T> {
+    pub fn from_serialized(buffer: &mut [u8]) -> Self {
         let len = buffer.len();
         let mut reader = PacketReader {
             buffer: [0; T],
             read_bytes: len,
             packet_length: None,
             index: core::cell::RefCell::new(0),
+            discard: 0,
         };
 
-        reader.buffer[..buffer.len()].copy_from_slice(&buffer);
+        reader.buffer[..buffer.len()].copy_from_slice(buffer);
 
         reader.probe_fixed_header();
 
         reader
     }
+}
+
+impl<B: Storage> PacketReader<B> {
+    pub fn with_buffer(buffer: B) -> Self {
+        PacketReader {
+            buffer,
+            read_bytes: 0,
+            packet_length: None,
+            index: core::cell::RefCell::new(0),
+            discard: 0,
+        }
+    }
+
+    /// Replace the buffer of the reader.
+    ///
+    /// # Note
+    /// Data that was received but not yet processed is moved into the new buffer, which must be at
+    /// least as large as the current buffer.
+    ///
+    /// # Args
+    /// * `buffer` - The buffer to receive data into.
+    ///
+    /// # Returns
+    /// The previously used buffer.
+    pub fn replace_buffer(&mut self, mut buffer: B) -> B {
+        buffer.as_mut()[..self.read_bytes]
+            .copy_from_slice(&self.buffer.as_ref()[..self.read_bytes]);
+        core::mem::replace(&mut self.buffer, buffer)
+    }
+}
+
+impl<B: Storage + 'static> core::ops::Deref for PacketReader<B> {
+    type Target = PacketReader;
+
+    fn deref(&self) -> &PacketReader {
+        self
+    }
+}
+
+impl<B: Storage + 'static> core::ops::DerefMut for PacketReader<B> {
+    fn deref_mut(&mut self) -> &mut PacketReader {
+        self
+    }
+}
+
+impl PacketReader {
+    /// Construct an error for malformed data.
+    ///
+    /// # Args
+    /// * `offset` - The offset within the packet at which the malformed data is located.
+    /// * `expected` - A description of the data that was expected.
+    /// * `found` - The value that was found instead, if applicable.
+    pub fn malformed(&self, offset: usize, expected: &'static str, found: Option<u32>) -> Error {
+        warn!("Malformed packet: expected {} at byte {}", expected, offset);
+        Error::MalformedPacket(Diagnostic::new(offset, expected, found))
+    }
+
+    /// Get the offset of the reader within the packet.
+    pub fn offset(&self) -> usize {
+        *self.index.borrow()
+    }
 
     pub fn payload(&self) -> Result<&[u8], Error> {
-        Ok(&self.buffer[*self.index.borrow()..self.packet_length()?])
+        Ok(&self.buffer.as_ref()[*self.index.borrow()..self.packet_length()?])
     }
 
     pub fn read(&self, dest: &mut [u8]) -> Result<(), Error> {
@@ -54,7 +157,7 @@ impl<const T: usize> PacketReader<T> {
             return Err(Error::DataSize);
         }
 
-        dest.copy_from_slice(&self.buffer[*index..][..dest.len()]);
+        dest.copy_from_slice(&self.buffer.as_ref()[*index..][..dest.len()]);
         *index += dest.len();
 
         Ok(())
@@ -67,7 +170,7 @@ impl<const T: usize> PacketReader<T> {
             return Err(Error::DataSize);
         }
 
-        let borrowed_data = &self.buffer[*index..][..count];
+        let borrowed_data = &self.buffer.as_ref()[*index..][..count];
         *index += count;
 
         Ok(borrowed_data)
@@ -78,11 +181,12 @@ impl<const T: usize> PacketReader<T> {
     }
 
     pub fn read_variable_length_integer(&self) -> Result<usize, Error> {
+        let offset = self.offset();
         let mut accumulator: usize = 0;
         for i in 0..4 {
             let mut byte = [0u8; 1];
             self.read(&mut byte)?;
-            accumulator += ((byte[0] & 0x7F) as usize) << i * 7;
+            accumulator += ((byte[0] & 0x7F) as usize) << (i * 7);
 
             if (byte[0] & 0x80) == 0 {
                 return Ok(accumulator);
@@ -90,7 +194,11 @@ impl<const T: usize> PacketReader<T> {
         }
 
         warn!("Encountered invalid variable integer");
-        Err(Error::MalformedInteger)
+        Err(Error::MalformedInteger(Diagnostic::new(
+            offset,
+            "variable-length integer of at most 4 bytes",
+            None,
+        )))
     }
 
     pub fn read_fixed_header(&self) -> Result<(MessageType, u8, usize), Error> {
@@ -107,12 +215,13 @@ impl<const T: usize> PacketReader<T> {
     pub fn read_utf8_string<'a, 'me: 'a>(&'me self) -> Result<&'a str, Error> {
         let string_length = self.read_u16()? as usize;
 
-        if self.buffer.len() < string_length {
+        if self.buffer.as_ref().len() < string_length {
             return Err(Error::DataSize);
         }
 
-        Ok(core::str::from_utf8(self.read_borrowed(string_length)?)
-            .map_err(|_| Error::MalformedPacket)?)
+        let offset = self.offset();
+        core::str::from_utf8(self.read_borrowed(string_length)?)
+            .map_err(|_| self.malformed(offset, "UTF-8 string", None))
     }
 
     pub fn read_binary_data(&self) -> Result<&[u8], Error> {
@@ -139,22 +248,27 @@ impl<const T: usize> PacketReader<T> {
         Ok(byte[0])
     }
 
-    pub fn read_properties<'a, 'me: 'a>(&'me self) -> Result<Vec<Property<'a>, 8>, Error> {
-        let mut properties: Vec<Property, 8> = Vec::new();
+    pub fn read_properties<'a, 'me: 'a>(&'me self) -> Result<Properties<'a>, Error> {
+        let mut properties = Properties::new();
 
         let properties_size = self.read_variable_length_integer()?;
         let mut property_bytes_processed = 0;
 
         while properties_size - property_bytes_processed > 0 {
+            let offset = self.offset();
             let property = Property::parse(self)?;
             property_bytes_processed += property.size();
             properties
                 .push(property)
-                .map_err(|_| Error::MalformedPacket)?;
+                .map_err(|_| self.malformed(offset, "at most 16 properties", None))?;
         }
 
         if properties_size != property_bytes_processed {
-            return Err(Error::MalformedPacket);
+            return Err(self.malformed(
+                self.offset(),
+                "properties matching the properties length",
+                Some(property_bytes_processed as u32),
+            ));
         }
 
         Ok(properties)
@@ -164,7 +278,7 @@ impl<const T: usize> PacketReader<T> {
         if let Some(packet_length) = self.packet_length {
             Ok(packet_length)
         } else {
-            Err(Error::MalformedPacket)
+            Err(self.malformed(0, "complete fixed header", None))
         }
     }
 
@@ -182,11 +296,9 @@ impl<const T: usize> PacketReader<T> {
         let move_length = self.read_bytes - packet_length;
 
         // Move data after the packet to the front.
-        unsafe {
-            let head: *mut u8 = &mut self.buffer[0] as *mut u8;
-            let tail: *const u8 = &self.buffer[packet_length] as *const u8;
-            core::intrinsics::copy(tail, head, move_length);
-        };
+        self.buffer
+            .as_mut()
+            .copy_within(packet_length..packet_length + move_length, 0);
 
         // Reset the read_bytes counter.
         self.read_bytes = move_length;
@@ -204,40 +316,129 @@ impl<const T: usize> PacketReader<T> {
     pub fn reset(&mut self) {
         self.read_bytes = 0;
         self.packet_length = None;
+        self.discard = 0;
     }
 
-    pub fn fill(&mut self, stream: &[u8]) -> usize {
-        let read = core::cmp::min(stream.len(), self.buffer.len() - self.read_bytes);
-        self.buffer[self.read_bytes..][..read].copy_from_slice(&stream[..read]);
-        self.read_bytes += read;
-        read
+    /// Get the header of the current packet if it is a PUBLISH packet.
+    ///
+    /// # Note
+    /// The header is available as soon as it has been received, even if the packet as a whole has
+    /// not been received yet or does not fit into the buffer.
+    pub fn publish_header(&self) -> Option<PublishHeader<'_>> {
+        let packet_length = self.packet_length?;
+        let data = &self.buffer.as_ref()[..self.read_bytes];
+
+        if !matches!(
+            MessageType::from(data[0].get_bits(4..=7)),
+            MessageType::Publish
+        ) {
+            return None;
+        }
+
+        let qos = match data[0].get_bits(1..=2) {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            _ => return None,
+        };
+
+        let (_, mut index) = decode_variable_length_integer(&data[1..])?;
+        index += 1;
+
+        let topic_length = u16::from_be_bytes([*data.get(index)?, *data.get(index + 1)?]) as usize;
+        index += 2;
+        let topic = core::str::from_utf8(data.get(index..index + topic_length)?).ok()?;
+        index += topic_length;
+
+        let packet_id = if qos == QoS::AtMostOnce {
+            None
+        } else {
+            let id = u16::from_be_bytes([*data.get(index)?, *data.get(index + 1)?]);
+            index += 2;
+            Some(id)
+        };
+
+        let (properties_length, length) = decode_variable_length_integer(data.get(index..)?)?;
+        index += length + properties_length;
+
+        Some(PublishHeader {
+            topic,
+            qos,
+            packet_id,
+            payload_length: packet_length.checked_sub(index)?,
+        })
     }
 
-    pub fn slurp(&mut self, stream: &[u8]) -> Result<usize, Error> {
-        let read = self.fill(stream);
-        if let Some(total_len) = self.probe_fixed_header() {
-            if self.packet_length != None {
-                if total_len > self.buffer.len() {
-                    return Err(Error::PacketSize);
-                }
-            } else if self.read_bytes >= FIXED_HEADER_MAX {
-                return Err(Error::MalformedPacket);
-            }
+    /// Discard the current packet, which has not been received completely.
+    ///
+    /// # Note
+    /// The remainder of the packet is dropped as it is received, without being buffered.
+    pub fn discard_packet(&mut self) {
+        if let Some(length) = self.packet_length.take() {
+            debug!("Discarding packet of {} bytes", length);
+            self.discard = length.saturating_sub(self.read_bytes);
+        }
+
+        self.read_bytes = 0;
+        self.index.replace(0);
+    }
+
+    /// Check that the current packet fits into the buffer.
+    pub fn check_size(&self) -> Result<(), Error> {
+        match self.packet_length {
+            Some(length) if length > self.buffer.as_ref().len() => Err(Error::PacketSize),
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the unused portion of the buffer that received data can be stored into.
+    ///
+    /// # Note
+    /// After data has been written into the buffer, it must be registered with `commit()`.
+    pub fn receive_buffer(&mut self) -> &mut [u8] {
+        &mut self.buffer.as_mut()[self.read_bytes..]
+    }
+
+    /// Register data that has been written into the receive buffer.
+    ///
+    /// # Args
+    /// * `count` - The number of bytes that were written into the receive buffer.
+    /// # Note
+    /// The size of the received packet must be checked using `check_size()` afterwards.
+    pub fn commit(&mut self, count: usize) -> Result<(), Error> {
+        let mut count = count;
+
+        // Drop the remainder of a discarded packet.
+        if self.discard > 0 {
+            let dropped = self.discard.min(count);
+            let start = self.read_bytes;
+            self.buffer
+                .as_mut()
+                .copy_within(start + dropped..start + count, start);
+            self.discard -= dropped;
+            count -= dropped;
         }
 
-        Ok(read)
+        self.read_bytes += count;
+
+        if self.probe_fixed_header().is_none() && self.read_bytes >= FIXED_HEADER_MAX {
+            return Err(self.malformed(1, "remaining length of at most 4 bytes", None));
+        }
+
+        Ok(())
     }
 
     pub fn probe_fixed_header(&mut self) -> Option<usize> {
         if self.read_bytes <= 1 {
+            self.packet_length = None;
             return None;
         }
 
         // Attempt to parse a variable byte integer out of the currently available data.
         self.packet_length = if let Some((rlen, nbytes)) = {
-            let int = &self.buffer[1..self.read_bytes];
+            let int = &self.buffer.as_ref()[1..self.read_bytes];
 
-            let len = if int.len() >= 1 && (int[0] & 0b1000_0000) == 0 {
+            let len = if !int.is_empty() && (int[0] & 0b1000_0000) == 0 {
                 1
             } else if int.len() >= 2 && (int[1] & 0b1000_0000) == 0 {
                 2
@@ -249,8 +450,8 @@ impl<const T: usize> PacketReader<T> {
                 return None;
             };
             let mut acc = 0;
-            for i in 0..len {
-                acc += ((int[i] & 0b0111_1111) as usize) << i * 7;
+            for (i, byte) in int.iter().take(len).enumerate() {
+                acc += ((byte & 0b0111_1111) as usize) << (i * 7);
             }
             Some((acc, len))
         } {
@@ -262,3 +463,66 @@ impl<const T: usize> PacketReader<T> {
         self.packet_length
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PacketReader;
+    use crate::QoS;
+
+    #[test]
+    fn discard_oversized_publish() {
+        let mut reader = PacketReader::<[u8; 16]>::new();
+
+        // The header of a publish with 64 bytes of payload that does not fit into the buffer.
+        let header = [
+            0x32, // Publish, QoS 1
+            0x4B, // Remaining length (75)
+            0x00, 0x03, b'a', b'/', b'b', // Topic name: 'a/b'
+            0x12, 0x34, // Packet identifier
+            0x03, // Property length (3)
+            0x02, 0x00, 0x00, // Message expiry interval (partial)
+        ];
+        reader.receive_buffer()[..header.len()].copy_from_slice(&header);
+        reader.commit(header.len()).unwrap();
+        assert!(reader.check_size().is_err());
+
+        let publish = reader.publish_header().unwrap();
+        assert_eq!(publish.topic, "a/b");
+        assert_eq!(publish.qos, QoS::AtLeastOnce);
+        assert_eq!(publish.packet_id, Some(0x1234));
+        assert_eq!(publish.payload_length, 64);
+
+        reader.discard_packet();
+        assert!(reader.check_size().is_ok());
+
+        // The remainder of the packet is dropped, followed by a ping response.
+        let mut remaining = 77 - header.len();
+        while remaining > 0 {
+            let length = remaining.min(16);
+            reader.receive_buffer()[..length].fill(0xAB);
+            reader.commit(length).unwrap();
+            remaining -= length;
+        }
+        assert!(!reader.packet_available());
+
+        reader.receive_buffer()[..2].copy_from_slice(&[0xD0, 0x00]);
+        reader.commit(2).unwrap();
+        assert!(reader.packet_available());
+        assert_eq!(reader.packet_length().unwrap(), 2);
+    }
+
+    #[test]
+    fn replace_buffer_retains_data() {
+        let mut reader = PacketReader::<[u8; 16]>::new();
+
+        // A ping response is received partially before the buffer is replaced.
+        reader.receive_buffer()[0] = 0xD0;
+        reader.commit(1).unwrap();
+        reader.replace_buffer([0; 16]);
+
+        reader.receive_buffer()[0] = 0x00;
+        reader.commit(1).unwrap();
+        assert!(reader.packet_available());
+        assert_eq!(reader.buffer[..2], [0xD0, 0x00]);
+    }
+}
@@ -0,0 +1,352 @@
+//! mDNS Broker Discovery
+//!
+//! # Design
+//! Brokers on the local network may advertise themselves using DNS-based service discovery over
+//! multicast DNS, i.e. as an instance of the `_mqtt._tcp.local` service. `Discovery` sends a
+//! query for this service to the mDNS multicast group using a UDP network stack and extracts the
+//! broker address from the SRV and address records of the responses. This allows devices to
+//! connect to a broker without any configuration:
+//!
+//! ```ignore
+//! let mut discovery = Discovery::new(49_152);
+//! discovery.query(&mut udp_stack)?;
+//!
+//! let broker = loop {
+//!     match discovery.poll(&mut udp_stack) {
+//!         Ok(broker) => break broker,
+//!         Err(nb::Error::WouldBlock) => continue,
+//!         Err(nb::Error::Other(error)) => return Err(error),
+//!     }
+//! };
+//! discovery.close(&mut udp_stack)?;
+//!
+//! let mut mqtt = Minimq::new(broker.ip(), "", tcp_stack, clock)?;
+//! mqtt.client.set_broker_port(broker.port());
+//! ```
+//!
+//! # Note
+//! The query is sent from a port other than 5353, so responders answer directly to the client
+//! instead of to the multicast group, which the network stack does not need to join. Responses
+//! that do not fit into `MAX_RESPONSE_LENGTH` bytes or do not contain the address of the broker
+//! in the same response are ignored. If no broker answers, the query may simply be repeated.
+use embedded_nal::{nb, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpFullStack};
+use heapless::{String, Vec};
+
+/// The maximum length of a received mDNS response.
+pub const MAX_RESPONSE_LENGTH: usize = 512;
+
+/// The maximum length of a domain name in bytes that is extracted from a response.
+const MAX_NAME_LENGTH: usize = 128;
+
+/// The maximum number of address records of a response that are considered.
+const MAX_ADDRESSES: usize = 4;
+
+/// The mDNS multicast group and port.
+const MDNS_ADDRESS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+
+/// The name of the advertised MQTT service.
+const SERVICE: &str = "_mqtt._tcp.local";
+
+/// The maximum number of compression pointers that are followed when decoding a name.
+const MAX_POINTERS: usize = 16;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+
+/// The Internet class with the bit requesting a unicast response set.
+const CLASS_IN_UNICAST: u16 = 0x8001;
+
+/// Discovers a broker on the local network using mDNS.
+pub struct Discovery<S: UdpFullStack> {
+    socket: Option<S::UdpSocket>,
+    local_port: u16,
+}
+
+impl<S: UdpFullStack> Discovery<S> {
+    /// Construct a new broker discovery.
+    ///
+    /// # Args
+    /// * `local_port` - The UDP port to send the query from and receive responses on. This must
+    ///   not be the mDNS port 5353.
+    pub fn new(local_port: u16) -> Self {
+        Self {
+            socket: None,
+            local_port,
+        }
+    }
+
+    /// Send a query for brokers to the local network.
+    ///
+    /// # Note
+    /// The socket is opened by the first query. The query may be repeated, e.g. if no broker
+    /// responded within a second.
+    pub fn query(&mut self, stack: &mut S) -> nb::Result<(), S::Error> {
+        let socket = match self.socket.as_mut() {
+            Some(socket) => socket,
+            None => {
+                let mut socket = stack.socket()?;
+                stack.bind(&mut socket, self.local_port)?;
+                self.socket.get_or_insert(socket)
+            }
+        };
+
+        let mut buffer = [0; 64];
+        let query = write_query(&mut buffer);
+        stack.send_to(socket, MDNS_ADDRESS, query)
+    }
+
+    /// Check for a broker that responded to the query.
+    ///
+    /// # Returns
+    /// The socket address of the first broker found in a response. `nb::Error::WouldBlock` is
+    /// returned until a response with the address of a broker is received.
+    pub fn poll(&mut self, stack: &mut S) -> nb::Result<SocketAddr, S::Error> {
+        let socket = self.socket.as_mut().ok_or(nb::Error::WouldBlock)?;
+
+        let mut buffer = [0; MAX_RESPONSE_LENGTH];
+        loop {
+            let (length, _) = stack.receive(socket, &mut buffer)?;
+            if let Some(broker) = parse_response(&buffer[..length]) {
+                return Ok(broker);
+            }
+        }
+    }
+
+    /// Close the socket used for discovery.
+    pub fn close(self, stack: &mut S) -> Result<(), S::Error> {
+        match self.socket {
+            Some(socket) => stack.close(socket),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Write an mDNS query for the MQTT service.
+///
+/// # Returns
+/// The encoded query within the buffer.
+fn write_query(buffer: &mut [u8; 64]) -> &[u8] {
+    // The header only counts a single question. The identifier and flags are zero.
+    buffer[..12].copy_from_slice(&[0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+    let mut length = 12;
+
+    for label in SERVICE.split('.') {
+        buffer[length] = label.len() as u8;
+        buffer[length + 1..][..label.len()].copy_from_slice(label.as_bytes());
+        length += 1 + label.len();
+    }
+
+    buffer[length] = 0;
+    buffer[length + 1..][..2].copy_from_slice(&TYPE_PTR.to_be_bytes());
+    buffer[length + 3..][..2].copy_from_slice(&CLASS_IN_UNICAST.to_be_bytes());
+    &buffer[..length + 5]
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Option<u16> {
+    let bytes = packet.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Decode a possibly compressed domain name.
+///
+/// # Note
+/// Names that do not fit into the destination are decoded as an empty name.
+///
+/// # Args
+/// * `packet` - The complete DNS message, which compression pointers refer to.
+/// * `offset` - The offset of the name within the message.
+/// * `name` - The destination of the dotted name.
+///
+/// # Returns
+/// The offset following the name, or `None` if the name is malformed.
+fn read_name<const N: usize>(
+    packet: &[u8],
+    mut offset: usize,
+    name: &mut String<N>,
+) -> Option<usize> {
+    name.clear();
+    let mut end = None;
+    let mut pointers = 0;
+    let mut truncated = false;
+
+    loop {
+        let length = *packet.get(offset)? as usize;
+        match length {
+            0 => break,
+
+            // The name continues at the position referenced by a compression pointer.
+            0xC0..=0xFF => {
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return None;
+                }
+
+                end.get_or_insert(offset + 2);
+                offset = (read_u16(packet, offset)? & 0x3FFF) as usize;
+            }
+
+            0x40..=0xBF => return None,
+
+            _ => {
+                let label = packet.get(offset + 1..offset + 1 + length)?;
+                if !name.is_empty() {
+                    truncated |= name.push('.').is_err();
+                }
+
+                for byte in label {
+                    truncated |= name.push(char::from(*byte)).is_err();
+                }
+
+                offset += 1 + length;
+            }
+        }
+    }
+
+    if truncated {
+        name.clear();
+    }
+
+    Some(end.unwrap_or(offset + 1))
+}
+
+/// Extract the address of a broker from an mDNS response.
+///
+/// # Returns
+/// The address of the broker, or `None` if the response does not contain both the SRV record of
+/// an MQTT service and the address of its target.
+fn parse_response(packet: &[u8]) -> Option<SocketAddr> {
+    // Only responses are considered.
+    if read_u16(packet, 2)? & 0x8000 == 0 {
+        return None;
+    }
+
+    let questions = read_u16(packet, 4)?;
+    let records = read_u16(packet, 6)? as usize
+        + read_u16(packet, 8)? as usize
+        + read_u16(packet, 10)? as usize;
+
+    let mut name: String<MAX_NAME_LENGTH> = String::new();
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = read_name(packet, offset, &mut name)? + 4;
+    }
+
+    let mut service: Option<(u16, String<MAX_NAME_LENGTH>)> = None;
+    let mut addresses: Vec<(String<MAX_NAME_LENGTH>, IpAddr), MAX_ADDRESSES> = Vec::new();
+
+    for _ in 0..records {
+        offset = read_name(packet, offset, &mut name)?;
+        let kind = read_u16(packet, offset)?;
+        let length = read_u16(packet, offset + 8)? as usize;
+        let data_offset = offset + 10;
+        let data = packet.get(data_offset..data_offset + length)?;
+
+        // The SRV record belongs to an instance of the service, e.g. `Broker._mqtt._tcp.local`.
+        let instance = name.len().checked_sub(SERVICE.len() + 1);
+        let is_service = instance.is_some_and(|start| {
+            name.as_bytes()[start] == b'.'
+                && name.as_bytes()[start + 1..].eq_ignore_ascii_case(SERVICE.as_bytes())
+        });
+
+        match kind {
+            TYPE_SRV if is_service && service.is_none() && length >= 6 => {
+                let mut target = String::new();
+                read_name(packet, data_offset + 6, &mut target)?;
+                service.replace((read_u16(data, 4)?, target));
+            }
+            TYPE_A if length == 4 => {
+                let ip = Ipv4Addr::new(data[0], data[1], data[2], data[3]);
+                addresses.push((name.clone(), IpAddr::V4(ip))).ok();
+            }
+            TYPE_AAAA if length == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                addresses
+                    .push((name.clone(), IpAddr::V6(Ipv6Addr::from(octets))))
+                    .ok();
+            }
+            _ => {}
+        }
+
+        offset = data_offset + length;
+    }
+
+    let (port, target) = service.filter(|(_, target)| !target.is_empty())?;
+    addresses
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(&target))
+        .map(|(_, ip)| SocketAddr::new(*ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_response, read_name, write_query};
+    use embedded_nal::{IpAddr, Ipv4Addr, SocketAddr};
+    use heapless::String;
+
+    // A response carrying the PTR, SRV, and A records of a broker named `Broker` on `host.local`.
+    const RESPONSE: [u8; 90] = [
+        0x00, 0x00, 0x84, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, // Header
+        // PTR _mqtt._tcp.local -> Broker._mqtt._tcp.local
+        0x05, b'_', b'm', b'q', b't', b't', 0x04, b'_', b't', b'c', b'p', 0x05, b'l', b'o', b'c',
+        b'a', b'l', 0x00, 0x00, 0x0C, 0x00, 0x01, 0x00, 0x00, 0x11, 0x94, 0x00, 0x09, 0x06, b'B',
+        b'r', b'o', b'k', b'e', b'r', 0xC0, 0x0C,
+        // SRV Broker._mqtt._tcp.local -> host.local:1884
+        0xC0, 0x28, 0x00, 0x21, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x0D, 0x00, 0x00, 0x00,
+        0x00, 0x07, 0x5C, 0x04, b'h', b'o', b's', b't', 0xC0, 0x17,
+        // A host.local -> 192.168.1.10
+        0xC0, 0x43, 0x00, 0x01, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x04, 0xC0, 0xA8, 0x01,
+        0x0A,
+    ];
+
+    #[test]
+    fn query_is_encoded() {
+        let mut buffer = [0; 64];
+        let query = write_query(&mut buffer);
+        assert_eq!(
+            query,
+            [
+                0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 5, b'_', b'm', b'q', b't', b't', 4, b'_', b't',
+                b'c', b'p', 5, b'l', b'o', b'c', b'a', b'l', 0, 0, 12, 0x80, 1
+            ]
+        );
+    }
+
+    #[test]
+    fn compressed_names_are_decoded() {
+        let mut name: String<64> = String::new();
+        assert_eq!(read_name(&RESPONSE, 0x28, &mut name), Some(0x31));
+        assert_eq!(name, "Broker._mqtt._tcp.local");
+
+        // Names that do not fit are decoded as empty names.
+        let mut name: String<8> = String::new();
+        assert_eq!(read_name(&RESPONSE, 0x28, &mut name), Some(0x31));
+        assert_eq!(name, "");
+
+        // Pointer loops are rejected.
+        assert_eq!(read_name(&[0xC0, 0x00], 0, &mut name), None);
+    }
+
+    #[test]
+    fn broker_is_found() {
+        assert_eq!(
+            parse_response(&RESPONSE),
+            Some(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)),
+                1884
+            ))
+        );
+
+        // Responses without the address of the target are ignored.
+        let mut response = RESPONSE;
+        response[11] = 0;
+        assert_eq!(parse_response(&response[..74]), None);
+
+        // Queries are ignored.
+        let mut query = RESPONSE;
+        query[2] = 0;
+        assert_eq!(parse_response(&query), None);
+    }
+}
@@ -0,0 +1,243 @@
+//! Payload Encryption
+//!
+//! # Design
+//! Payloads may be encrypted and authenticated with ChaCha20-Poly1305 using a pre-shared key
+//! before they are published. This protects the payload even if the path to the broker cannot be
+//! fully trusted (e.g. when TLS is terminated at a gateway). The topic is used as additional
+//! authenticated data, so an encrypted payload cannot be replayed on a different topic.
+//!
+//! An encrypted payload consists of the 12 byte nonce, followed by the ciphertext and the 16 byte
+//! authentication tag. Encrypted messages are tagged with an `encryption: chacha20poly1305` user
+//! property.
+//!
+//! Once a cipher is configured, only received messages that are encrypted and successfully
+//! authenticated with the key are passed to the `poll()` handler. All other received messages are
+//! dropped and reported to the dead letter handler with `DropReason::DecryptionFailed`.
+//!
+//! # Note
+//! A nonce must never be used twice with the same key. Each nonce consists of a 4 byte prefix
+//! provided by the application and a 64-bit message counter that starts at zero. The prefix must
+//! therefore be unique for every instantiation of the cipher with a given key, e.g. by deriving it
+//! from a persistent boot counter.
+use crate::{debug, Property, ProtocolError};
+use chacha20poly1305::{
+    aead::{AeadInPlace, KeyInit},
+    ChaCha20Poly1305, Key, Nonce, Tag,
+};
+use heapless::Vec;
+
+/// The name of the user property used to tag encrypted payloads.
+const ENCRYPTION: &str = "encryption";
+
+/// The value of the tag for payloads encrypted with ChaCha20-Poly1305.
+const CHACHA20_POLY1305: &str = "chacha20poly1305";
+
+/// The user property that encrypted messages are tagged with.
+pub(crate) const ENCRYPTED: Property<'static> =
+    Property::UserProperty(ENCRYPTION, CHACHA20_POLY1305);
+
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+
+/// The number of bytes that encryption adds to a payload.
+pub(crate) const OVERHEAD: usize = NONCE_SIZE + TAG_SIZE;
+
+/// A cipher for encrypting and authenticating message payloads with a pre-shared key.
+pub struct PayloadCipher {
+    cipher: ChaCha20Poly1305,
+    nonce_prefix: [u8; 4],
+    counter: u64,
+}
+
+impl PayloadCipher {
+    /// Construct a cipher from a pre-shared key.
+    ///
+    /// # Args
+    /// * `key` - The 256-bit key shared with the receivers of published messages.
+    /// * `nonce_prefix` - A prefix for the nonces generated by this cipher. The prefix must never
+    ///   be reused with the same key.
+    pub fn new(key: &[u8; 32], nonce_prefix: [u8; 4]) -> Self {
+        Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(key)),
+            nonce_prefix,
+            counter: 0,
+        }
+    }
+
+    /// Encrypt a payload.
+    ///
+    /// # Args
+    /// * `topic` - The topic that the payload is published to.
+    /// * `payload` - The payload to encrypt.
+    /// * `dest` - The location to store the encrypted payload into.
+    ///
+    /// # Returns
+    /// The encrypted payload. `ProtocolError::BufferTooSmall` is returned if the encrypted payload
+    /// does not fit into `dest`, and `ProtocolError::Failed` if all nonces have been used.
+    pub(crate) fn encrypt<'a>(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        dest: &'a mut [u8],
+    ) -> Result<&'a [u8], ProtocolError> {
+        let length = payload.len() + OVERHEAD;
+        if dest.len() < length {
+            return Err(ProtocolError::BufferTooSmall { needed: length });
+        }
+
+        let mut nonce = [0; NONCE_SIZE];
+        nonce[..4].copy_from_slice(&self.nonce_prefix);
+        nonce[4..].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter = self.counter.checked_add(1).ok_or(ProtocolError::Failed)?;
+
+        let (header, body) = dest[..length].split_at_mut(NONCE_SIZE);
+        let (ciphertext, tag) = body.split_at_mut(payload.len());
+        header.copy_from_slice(&nonce);
+        ciphertext.copy_from_slice(payload);
+
+        let computed = self
+            .cipher
+            .encrypt_in_place_detached(Nonce::from_slice(&nonce), topic.as_bytes(), ciphertext)
+            .map_err(|_| ProtocolError::Failed)?;
+        tag.copy_from_slice(&computed);
+
+        Ok(&dest[..length])
+    }
+
+    /// Decrypt and authenticate the payload of a received message.
+    ///
+    /// # Args
+    /// * `topic` - The topic that the message was received on.
+    /// * `payload` - The received payload.
+    /// * `properties` - The properties of the received message. The encryption tag is removed if
+    ///   the payload was decrypted.
+    /// * `dest` - The location to store the decrypted payload into.
+    ///
+    /// # Returns
+    /// The decrypted payload, or `None` if the message was not encrypted or failed authentication.
+    pub(crate) fn decrypt<'a, const N: usize>(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        properties: &mut Vec<Property<'_>, N>,
+        dest: &'a mut [u8],
+    ) -> Option<&'a [u8]> {
+        let index = properties
+            .iter()
+            .position(|property| *property == ENCRYPTED);
+
+        let index = match index {
+            Some(index) if payload.len() >= NONCE_SIZE + TAG_SIZE => index,
+            _ => {
+                debug!("Received unencrypted message on `{}`", topic);
+                return None;
+            }
+        };
+
+        let (nonce, body) = payload.split_at(NONCE_SIZE);
+        let (ciphertext, tag) = body.split_at(body.len() - TAG_SIZE);
+        let plaintext = dest.get_mut(..ciphertext.len())?;
+        plaintext.copy_from_slice(ciphertext);
+
+        if self
+            .cipher
+            .decrypt_in_place_detached(
+                Nonce::from_slice(nonce),
+                topic.as_bytes(),
+                plaintext,
+                Tag::from_slice(tag),
+            )
+            .is_err()
+        {
+            debug!("Received unauthenticated message on `{}`", topic);
+            return None;
+        }
+
+        properties.remove(index);
+        Some(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PayloadCipher, ENCRYPTED};
+    use crate::Property;
+    use heapless::Vec;
+
+    const KEY: [u8; 32] = [0x42; 32];
+
+    #[test]
+    fn encryption_round_trip() {
+        let mut cipher = PayloadCipher::new(&KEY, [0, 0, 0, 1]);
+
+        let mut encrypted = [0; 64];
+        let encrypted = cipher.encrypt("topic", b"Hello", &mut encrypted).unwrap();
+        assert_eq!(encrypted.len(), 12 + 5 + 16);
+        assert_eq!(encrypted[..12], [0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let mut properties: Vec<Property, 2> = Vec::new();
+        properties.push(ENCRYPTED).unwrap();
+
+        let mut decrypted = [0; 64];
+        let payload = cipher
+            .decrypt("topic", encrypted, &mut properties, &mut decrypted)
+            .unwrap();
+        assert_eq!(payload, b"Hello");
+        assert!(properties.is_empty());
+    }
+
+    #[test]
+    fn nonces_are_not_reused() {
+        let mut cipher = PayloadCipher::new(&KEY, [0; 4]);
+
+        let mut first = [0; 64];
+        let first = cipher.encrypt("topic", b"Hello", &mut first).unwrap();
+        let mut second = [0; 64];
+        let second = cipher.encrypt("topic", b"Hello", &mut second).unwrap();
+
+        assert_ne!(first[..12], second[..12]);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn tampered_messages_are_rejected() {
+        let mut cipher = PayloadCipher::new(&KEY, [0; 4]);
+
+        let mut encrypted = [0; 64];
+        let length = cipher
+            .encrypt("topic", b"Hello", &mut encrypted)
+            .unwrap()
+            .len();
+
+        let mut properties: Vec<Property, 2> = Vec::new();
+        properties.push(ENCRYPTED).unwrap();
+        let mut decrypted = [0; 64];
+
+        // A message replayed on a different topic fails authentication.
+        assert!(cipher
+            .decrypt(
+                "other",
+                &encrypted[..length],
+                &mut properties,
+                &mut decrypted
+            )
+            .is_none());
+
+        // A modified message fails authentication.
+        encrypted[12] ^= 0x01;
+        assert!(cipher
+            .decrypt(
+                "topic",
+                &encrypted[..length],
+                &mut properties,
+                &mut decrypted
+            )
+            .is_none());
+
+        // An untagged message is rejected.
+        properties.clear();
+        assert!(cipher
+            .decrypt("topic", b"Hello", &mut properties, &mut decrypted)
+            .is_none());
+    }
+}
@@ -0,0 +1,651 @@
+//! C Bindings
+//!
+//! # Design
+//! Existing C firmware may use the client through a C-callable API without being rewritten in
+//! Rust. The network connection and the clock are provided by the application as a table of
+//! callbacks in a `MinimqNetwork`, and received messages are passed to a registered callback:
+//!
+//! ```c
+//! static uint8_t storage[32768] __attribute__((aligned(8)));
+//!
+//! MinimqNetwork network = {
+//!     .context = &modem,
+//!     .connect = modem_connect,
+//!     .send = modem_send,
+//!     .receive = modem_receive,
+//!     .close = modem_close,
+//!     .now_ms = systick_ms,
+//! };
+//!
+//! MinimqClient *client = minimq_init(storage, sizeof(storage), 0x7F000001, "device", &network);
+//! minimq_set_message_callback(client, on_message, NULL);
+//!
+//! while (true) {
+//!     minimq_poll(client);
+//! }
+//! ```
+//!
+//! The client is stored in memory provided by the application, since no allocator is available.
+//! Its size and alignment are reported by `minimq_client_size()` and `minimq_client_align()`, and
+//! initialization fails if the storage is insufficient.
+//! Messages are limited to `MINIMQ_MESSAGE_SIZE` bytes, with up to `MINIMQ_MESSAGE_COUNT` messages
+//! in flight.
+//!
+//! The header `include/minimq.h` is generated using `cbindgen --config cbindgen.toml --output
+//! include/minimq.h`. The bindings are linked into the firmware as a static library, which is
+//! built from a crate with `crate-type = ["staticlib"]` that depends on this crate with the `ffi`
+//! feature and provides a panic handler.
+//!
+//! # Note
+//! The API is not reentrant. In particular, the message callback must not call any other function
+//! of the client.
+use core::ffi::{c_char, c_void, CStr};
+
+use embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, TcpClientStack};
+use embedded_time::{clock, fraction::Fraction, Clock, Instant};
+
+use crate::{Error, Minimq, QoS, Retain};
+
+/// The maximum size of messages sent and received by a client.
+pub const MINIMQ_MESSAGE_SIZE: usize = 1024;
+
+/// The maximum number of messages that may be in flight.
+pub const MINIMQ_MESSAGE_COUNT: usize = 16;
+
+/// The status of a call to the C API.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MinimqStatus {
+    /// The call succeeded.
+    Ok = 0,
+
+    /// An argument was invalid, e.g. a null pointer or a string that is not valid UTF-8.
+    InvalidArgument,
+
+    /// The client is not connected to the broker.
+    NotConnected,
+
+    /// The request cannot be handled at the moment and may be retried later.
+    NotReady,
+
+    /// A packet does not fit into a message buffer.
+    BufferTooSmall,
+
+    /// A network callback reported an error.
+    Network,
+
+    /// The broker violated the MQTT protocol or refused a request.
+    Protocol,
+
+    /// Any other error occurred.
+    Failed,
+}
+
+impl<E> From<Error<E>> for MinimqStatus {
+    fn from(error: Error<E>) -> Self {
+        match error {
+            Error::NotConnected => MinimqStatus::NotConnected,
+            Error::NotReady | Error::WriteFail => MinimqStatus::NotReady,
+            Error::BufferTooSmall { .. } | Error::PacketTooLarge { .. } => {
+                MinimqStatus::BufferTooSmall
+            }
+            Error::Network(_) => MinimqStatus::Network,
+            Error::Protocol(_) | Error::ConnectionRefused { .. } | Error::Unacknowledged { .. } => {
+                MinimqStatus::Protocol
+            }
+            Error::ProvidedClientIdTooLong => MinimqStatus::InvalidArgument,
+            _ => MinimqStatus::Failed,
+        }
+    }
+}
+
+/// The callbacks implementing the network connection and clock of a client.
+///
+/// # Note
+/// All callbacks receive `context` as their first argument.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct MinimqNetwork {
+    /// An opaque pointer passed to every callback.
+    pub context: *mut c_void,
+
+    /// Connect to the broker at an IPv4 address in host byte order. Returns zero once connected, a
+    /// positive value while the connection is in progress, or a negative value on failure.
+    pub connect: Option<unsafe extern "C" fn(*mut c_void, u32, u16) -> i32>,
+
+    /// Send data to the broker. Returns the number of bytes accepted, which may be zero, or a
+    /// negative value on failure.
+    pub send: Option<unsafe extern "C" fn(*mut c_void, *const u8, usize) -> isize>,
+
+    /// Receive data from the broker. Returns the number of bytes received, which is zero if no
+    /// data is available, or a negative value on failure.
+    pub receive: Option<unsafe extern "C" fn(*mut c_void, *mut u8, usize) -> isize>,
+
+    /// Close the connection to the broker.
+    pub close: Option<unsafe extern "C" fn(*mut c_void)>,
+
+    /// Get the current time in milliseconds.
+    pub now_ms: Option<unsafe extern "C" fn(*mut c_void) -> u32>,
+}
+
+/// The callback receiving messages. It is passed the context provided at registration, the topic
+/// and the payload of the message. The topic is not null-terminated.
+pub type MinimqMessageCallback =
+    Option<unsafe extern "C" fn(*mut c_void, *const u8, usize, *const u8, usize)>;
+
+/// The network stack that forwards all operations to the callbacks of a `MinimqNetwork`.
+#[derive(Debug)]
+struct CallbackStack {
+    network: MinimqNetwork,
+    connected: bool,
+}
+
+/// The socket of a `CallbackStack`. The application maintains a single connection.
+#[derive(Debug)]
+struct CallbackSocket;
+
+fn check(result: isize) -> nb::Result<usize, i32> {
+    if result < 0 {
+        Err(nb::Error::Other(result as i32))
+    } else {
+        Ok(result as usize)
+    }
+}
+
+impl TcpClientStack for CallbackStack {
+    type TcpSocket = CallbackSocket;
+    type Error = i32;
+
+    fn socket(&mut self) -> Result<CallbackSocket, i32> {
+        Ok(CallbackSocket)
+    }
+
+    fn connect(&mut self, _: &mut CallbackSocket, remote: SocketAddr) -> nb::Result<(), i32> {
+        // Only IPv4 brokers can be configured through the C API.
+        let remote = match remote {
+            SocketAddr::V4(remote) => remote,
+            SocketAddr::V6(_) => return Err(nb::Error::Other(-1)),
+        };
+
+        // Note(unwrap): The callbacks were checked when the client was initialized.
+        let connect = self.network.connect.unwrap();
+        let result =
+            unsafe { connect(self.network.context, u32::from(*remote.ip()), remote.port()) };
+
+        match result {
+            0 => {
+                self.connected = true;
+                Ok(())
+            }
+            result if result > 0 => Err(nb::Error::WouldBlock),
+            result => Err(nb::Error::Other(result)),
+        }
+    }
+
+    fn is_connected(&mut self, _: &CallbackSocket) -> Result<bool, i32> {
+        Ok(self.connected)
+    }
+
+    fn send(&mut self, _: &mut CallbackSocket, buffer: &[u8]) -> nb::Result<usize, i32> {
+        let send = self.network.send.unwrap();
+        let result = unsafe { send(self.network.context, buffer.as_ptr(), buffer.len()) };
+
+        // A failed connection is reestablished by the client.
+        self.connected &= result >= 0;
+        check(result)
+    }
+
+    fn receive(&mut self, _: &mut CallbackSocket, buffer: &mut [u8]) -> nb::Result<usize, i32> {
+        let receive = self.network.receive.unwrap();
+        let result = unsafe { receive(self.network.context, buffer.as_mut_ptr(), buffer.len()) };
+
+        self.connected &= result >= 0;
+        check(result)
+    }
+
+    fn close(&mut self, _: CallbackSocket) -> Result<(), i32> {
+        let close = self.network.close.unwrap();
+        unsafe { close(self.network.context) };
+        self.connected = false;
+        Ok(())
+    }
+}
+
+/// The clock reading the time from the `now_ms` callback of a `MinimqNetwork`.
+#[derive(Debug)]
+struct CallbackClock {
+    network: MinimqNetwork,
+}
+
+impl Clock for CallbackClock {
+    type T = u32;
+
+    const SCALING_FACTOR: Fraction = Fraction::new(1, 1000);
+
+    fn try_now(&self) -> Result<Instant<Self>, clock::Error> {
+        let now_ms = self.network.now_ms.unwrap();
+        Ok(Instant::new(unsafe { now_ms(self.network.context) }))
+    }
+}
+
+/// An MQTT client used through the C API.
+pub struct MinimqClient {
+    mqtt: Minimq<CallbackStack, CallbackClock, MINIMQ_MESSAGE_SIZE, MINIMQ_MESSAGE_COUNT>,
+    on_message: MinimqMessageCallback,
+    context: *mut c_void,
+}
+
+/// Get the size of the storage required for a client in bytes.
+#[no_mangle]
+pub extern "C" fn minimq_client_size() -> usize {
+    core::mem::size_of::<MinimqClient>()
+}
+
+/// Get the alignment of the storage required for a client in bytes.
+#[no_mangle]
+pub extern "C" fn minimq_client_align() -> usize {
+    core::mem::align_of::<MinimqClient>()
+}
+
+unsafe fn string<'a>(string: *const c_char) -> Result<&'a str, MinimqStatus> {
+    if string.is_null() {
+        return Err(MinimqStatus::InvalidArgument);
+    }
+
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| MinimqStatus::InvalidArgument)
+}
+
+fn status(result: Result<(), MinimqStatus>) -> MinimqStatus {
+    result.err().unwrap_or(MinimqStatus::Ok)
+}
+
+/// Initialize a client in the provided storage.
+///
+/// # Args
+/// * `storage` - The memory to store the client in.
+/// * `size` - The size of the storage in bytes. It must be at least `minimq_client_size()`.
+/// * `broker` - The IPv4 address of the broker in host byte order.
+/// * `client_id` - The null-terminated client identifier.
+/// * `network` - The callbacks implementing the network connection and the clock. All callbacks
+///   must be provided. The table is copied into the client.
+///
+/// # Returns
+/// The initialized client, or null if an argument was invalid or the storage is not aligned to
+/// `minimq_client_align()`.
+///
+/// # Safety
+/// `storage` must be valid for writes of `size` bytes for as long as the client is used, and
+/// `client_id` and `network` must be null or valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn minimq_init(
+    storage: *mut c_void,
+    size: usize,
+    broker: u32,
+    client_id: *const c_char,
+    network: *const MinimqNetwork,
+) -> *mut MinimqClient {
+    let client = storage as *mut MinimqClient;
+
+    let misaligned = client as usize % minimq_client_align() != 0;
+
+    if client.is_null() || size < minimq_client_size() || misaligned || network.is_null() {
+        return core::ptr::null_mut();
+    }
+
+    let network = *network;
+    if network.connect.is_none()
+        || network.send.is_none()
+        || network.receive.is_none()
+        || network.close.is_none()
+        || network.now_ms.is_none()
+    {
+        return core::ptr::null_mut();
+    }
+
+    let client_id = match string(client_id) {
+        Ok(client_id) => client_id,
+        Err(_) => return core::ptr::null_mut(),
+    };
+
+    let stack = CallbackStack {
+        network,
+        connected: false,
+    };
+
+    let broker = IpAddr::V4(Ipv4Addr::from(broker));
+    let mqtt = match Minimq::new(broker, client_id, stack, CallbackClock { network }) {
+        Ok(mqtt) => mqtt,
+        Err(_) => return core::ptr::null_mut(),
+    };
+
+    client.write(MinimqClient {
+        mqtt,
+        on_message: None,
+        context: core::ptr::null_mut(),
+    });
+    client
+}
+
+/// Release a client. The storage of the client may be reused afterwards.
+///
+/// # Args
+/// * `client` - The client to release.
+///
+/// # Safety
+/// `client` must be null or a client returned by `minimq_init()` that was not released yet.
+#[no_mangle]
+pub unsafe extern "C" fn minimq_deinit(client: *mut MinimqClient) {
+    if !client.is_null() {
+        core::ptr::drop_in_place(client);
+    }
+}
+
+/// Register the callback receiving messages.
+///
+/// # Args
+/// * `client` - The client to receive messages with.
+/// * `callback` - The function to call for every received message. If null, messages are dropped.
+/// * `context` - An opaque pointer passed to the callback.
+///
+/// # Safety
+/// `client` must be null or a valid client.
+#[no_mangle]
+pub unsafe extern "C" fn minimq_set_message_callback(
+    client: *mut MinimqClient,
+    callback: MinimqMessageCallback,
+    context: *mut c_void,
+) {
+    if let Some(client) = client.as_mut() {
+        client.on_message = callback;
+        client.context = context;
+    }
+}
+
+/// Check for received messages and maintain the connection to the broker.
+///
+/// # Note
+/// The registered callback is called for every message that was received.
+///
+/// # Args
+/// * `client` - The client to poll.
+///
+/// # Safety
+/// `client` must be null or a valid client.
+#[no_mangle]
+pub unsafe extern "C" fn minimq_poll(client: *mut MinimqClient) -> MinimqStatus {
+    let client = match client.as_mut() {
+        Some(client) => client,
+        None => return MinimqStatus::InvalidArgument,
+    };
+
+    let on_message = client.on_message;
+    let context = client.context;
+    let result = client.mqtt.poll(|_, topic, payload, _| {
+        if let Some(callback) = on_message {
+            callback(
+                context,
+                topic.as_ptr(),
+                topic.len(),
+                payload.as_ptr(),
+                payload.len(),
+            );
+        }
+    });
+
+    status(result.map(|_| ()).map_err(MinimqStatus::from))
+}
+
+/// Determine if the client is connected to the broker.
+///
+/// # Args
+/// * `client` - The client to check.
+///
+/// # Safety
+/// `client` must be null or a valid client.
+#[no_mangle]
+pub unsafe extern "C" fn minimq_is_connected(client: *mut MinimqClient) -> bool {
+    client
+        .as_mut()
+        .is_some_and(|client| client.mqtt.client.is_connected())
+}
+
+/// Subscribe to a topic.
+///
+/// # Args
+/// * `client` - The client to subscribe with.
+/// * `topic` - The null-terminated topic filter to subscribe to.
+///
+/// # Safety
+/// `client` must be null or a valid client, and `topic` must be null or a valid string.
+#[no_mangle]
+pub unsafe extern "C" fn minimq_subscribe(
+    client: *mut MinimqClient,
+    topic: *const c_char,
+) -> MinimqStatus {
+    let client = match client.as_mut() {
+        Some(client) => client,
+        None => return MinimqStatus::InvalidArgument,
+    };
+
+    status(string(topic).and_then(|topic| {
+        client
+            .mqtt
+            .client
+            .subscribe(topic, &[])
+            .map_err(MinimqStatus::from)
+    }))
+}
+
+/// Determine if any subscriptions are waiting for acknowledgement by the broker.
+///
+/// # Args
+/// * `client` - The client to check.
+///
+/// # Safety
+/// `client` must be null or a valid client.
+#[no_mangle]
+pub unsafe extern "C" fn minimq_subscriptions_pending(client: *mut MinimqClient) -> bool {
+    client
+        .as_mut()
+        .is_some_and(|client| client.mqtt.client.subscriptions_pending())
+}
+
+/// Publish a message.
+///
+/// # Args
+/// * `client` - The client to publish with.
+/// * `topic` - The null-terminated topic to publish the message to.
+/// * `payload` - The payload of the message. May be null if `length` is zero.
+/// * `length` - The length of the payload in bytes.
+/// * `qos` - The quality-of-service level of the message, which is 0 or 1.
+/// * `retain` - Specifies whether the message should be retained by the broker.
+///
+/// # Safety
+/// `client` must be null or a valid client, `topic` must be null or a valid string, and `payload`
+/// must be valid for reads of `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn minimq_publish(
+    client: *mut MinimqClient,
+    topic: *const c_char,
+    payload: *const u8,
+    length: usize,
+    qos: u8,
+    retain: bool,
+) -> MinimqStatus {
+    let client = match client.as_mut() {
+        Some(client) => client,
+        None => return MinimqStatus::InvalidArgument,
+    };
+
+    let qos = match qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => return MinimqStatus::InvalidArgument,
+    };
+
+    let payload = match (payload.is_null(), length) {
+        (_, 0) => &[][..],
+        (false, length) => core::slice::from_raw_parts(payload, length),
+        (true, _) => return MinimqStatus::InvalidArgument,
+    };
+
+    let retain = if retain {
+        Retain::Retained
+    } else {
+        Retain::NotRetained
+    };
+
+    status(string(topic).and_then(|topic| {
+        client
+            .mqtt
+            .client
+            .publish(topic, payload, qos, retain, &[])
+            .map_err(MinimqStatus::from)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::MaybeUninit;
+    use heapless::Vec;
+
+    #[derive(Default)]
+    struct Broker {
+        rx: Vec<u8, 64>,
+        tx: Vec<u8, 64>,
+        received: Vec<u8, 16>,
+    }
+
+    unsafe fn broker<'a>(context: *mut c_void) -> &'a mut Broker {
+        &mut *(context as *mut Broker)
+    }
+
+    unsafe extern "C" fn connect(_: *mut c_void, address: u32, port: u16) -> i32 {
+        assert_eq!((address, port), (0x7F00_0001, 1883));
+        0
+    }
+
+    unsafe extern "C" fn send(context: *mut c_void, data: *const u8, length: usize) -> isize {
+        let broker = broker(context);
+        let data = core::slice::from_raw_parts(data, length);
+        broker.tx.extend_from_slice(data).unwrap();
+        length as isize
+    }
+
+    unsafe extern "C" fn receive(context: *mut c_void, buffer: *mut u8, length: usize) -> isize {
+        let broker = broker(context);
+        let count = broker.rx.len().min(length);
+        core::ptr::copy_nonoverlapping(broker.rx.as_ptr(), buffer, count);
+        broker.rx = Vec::from_slice(&broker.rx[count..]).unwrap();
+        count as isize
+    }
+
+    unsafe extern "C" fn close(_: *mut c_void) {}
+
+    unsafe extern "C" fn now_ms(_: *mut c_void) -> u32 {
+        0
+    }
+
+    unsafe extern "C" fn on_message(
+        context: *mut c_void,
+        topic: *const u8,
+        topic_length: usize,
+        payload: *const u8,
+        length: usize,
+    ) {
+        let broker = broker(context);
+        assert_eq!(core::slice::from_raw_parts(topic, topic_length), b"a");
+        let payload = core::slice::from_raw_parts(payload, length);
+        broker.received.extend_from_slice(payload).unwrap();
+    }
+
+    #[test]
+    fn ffi_client_receives_messages() {
+        let mut state = Broker::default();
+        let context = &mut state as *mut Broker as *mut c_void;
+        let network = MinimqNetwork {
+            context,
+            connect: Some(connect),
+            send: Some(send),
+            receive: Some(receive),
+            close: Some(close),
+            now_ms: Some(now_ms),
+        };
+
+        let mut storage = MaybeUninit::<MinimqClient>::uninit();
+        let storage = storage.as_mut_ptr() as *mut c_void;
+        let size = minimq_client_size();
+
+        unsafe {
+            let invalid = MinimqNetwork {
+                now_ms: None,
+                ..network
+            };
+            let id = b"test\0".as_ptr() as *const c_char;
+            assert!(minimq_init(storage, size, 0x7F00_0001, id, &invalid).is_null());
+            assert!(minimq_init(storage, size - 1, 0x7F00_0001, id, &network).is_null());
+
+            let client = minimq_init(storage, size, 0x7F00_0001, id, &network);
+            assert!(!client.is_null());
+            minimq_set_message_callback(client, Some(on_message), context);
+
+            // The client sends a CONNECT once the connection is established.
+            while broker(context).tx.is_empty() {
+                assert_eq!(minimq_poll(client), MinimqStatus::Ok);
+            }
+            assert!(!minimq_is_connected(client));
+            assert_eq!(broker(context).tx[0], 0x10);
+
+            // A CONNACK followed by a PUBLISH on topic "a".
+            let rx = &mut broker(context).rx;
+            rx.extend_from_slice(&[0x20, 0x03, 0x00, 0x00, 0x00])
+                .unwrap();
+            rx.extend_from_slice(&[0x30, 0x07, 0x00, 0x01, b'a', 0x00, b'H', b'i', b'!'])
+                .unwrap();
+
+            while broker(context).received.is_empty() {
+                assert_eq!(minimq_poll(client), MinimqStatus::Ok);
+            }
+            assert!(minimq_is_connected(client));
+            assert_eq!(broker(context).received, b"Hi!");
+
+            assert_eq!(
+                minimq_publish(client, core::ptr::null(), core::ptr::null(), 0, 0, false),
+                MinimqStatus::InvalidArgument
+            );
+            assert_eq!(
+                minimq_publish(
+                    client,
+                    b"b\0".as_ptr() as *const c_char,
+                    b"1".as_ptr(),
+                    1,
+                    2,
+                    false
+                ),
+                MinimqStatus::InvalidArgument
+            );
+
+            broker(context).tx.clear();
+            assert_eq!(
+                minimq_publish(
+                    client,
+                    b"b\0".as_ptr() as *const c_char,
+                    b"1".as_ptr(),
+                    1,
+                    0,
+                    false
+                ),
+                MinimqStatus::Ok
+            );
+            assert_eq!(
+                broker(context).tx,
+                [0x30, 0x05, 0x00, 0x01, b'b', 0x00, b'1']
+            );
+
+            minimq_deinit(client);
+        }
+    }
+}
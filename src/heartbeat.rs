@@ -0,0 +1,114 @@
+//! Periodic Heartbeat
+//!
+//! # Design
+//! A heartbeat is a small status message that is published at a fixed interval while the client
+//! is connected to the broker. This provides liveness reporting for a fleet of devices without
+//! every application having to implement the timer logic itself.
+//!
+//! The heartbeat payload is a JSON object containing the uptime in seconds, i.e. the time since
+//! the epoch of the clock. An optional status callback may append further members to the object,
+//! such as the signal strength of the network interface:
+//!
+//! ```text
+//! {"uptime":3600,"rssi":-67}
+//! ```
+//!
+//! The first heartbeat is published as soon as the connection with the broker is established.
+//! Heartbeats are published with `QoS::AtMostOnce` and are not retained, as a missed heartbeat is
+//! superseded by the next one.
+use core::fmt::{self, Display, Write};
+use embedded_time::duration::Milliseconds;
+
+/// A function that writes additional members of the heartbeat JSON object.
+pub type StatusCallback = fn(&mut dyn Write) -> fmt::Result;
+
+/// The configuration of a periodic heartbeat message.
+#[derive(Debug, Copy, Clone)]
+pub struct Heartbeat {
+    pub(crate) topic: &'static str,
+    pub(crate) interval: Milliseconds<u32>,
+    status: Option<StatusCallback>,
+}
+
+impl Heartbeat {
+    /// Construct a new heartbeat.
+    ///
+    /// # Args
+    /// * `topic` - The topic to publish the heartbeat to.
+    /// * `interval` - The interval between two heartbeats.
+    pub fn new(topic: &'static str, interval: Milliseconds<u32>) -> Self {
+        Self {
+            topic,
+            interval,
+            status: None,
+        }
+    }
+
+    /// Set the callback that provides additional status information.
+    ///
+    /// # Args
+    /// * `status` - A function that writes additional, comma-separated members of the heartbeat
+    ///   JSON object, e.g. `"rssi":-67`.
+    pub fn status(&mut self, status: StatusCallback) {
+        self.status.replace(status);
+    }
+
+    /// Write the heartbeat payload.
+    ///
+    /// # Args
+    /// * `uptime` - The uptime in seconds.
+    /// * `dest` - The string to write the payload into. The string is cleared first.
+    pub(crate) fn write_payload<const N: usize>(
+        &self,
+        uptime: impl Display,
+        dest: &mut heapless::String<N>,
+    ) -> fmt::Result {
+        dest.clear();
+        write!(dest, "{{\"uptime\":{}", uptime)?;
+
+        if let Some(status) = self.status {
+            dest.push(',').map_err(|_| fmt::Error)?;
+            let length = dest.len();
+            status(dest)?;
+
+            // If the callback did not provide any status, the separator is not needed.
+            if dest.len() == length {
+                dest.pop();
+            }
+        }
+
+        dest.push('}').map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Heartbeat;
+    use embedded_time::duration::Extensions;
+    use heapless::String;
+
+    #[test]
+    fn heartbeat_payload() {
+        let mut heartbeat = Heartbeat::new("status", 10_000.milliseconds());
+        let mut payload: String<64> = String::new();
+
+        heartbeat.write_payload(3600, &mut payload).unwrap();
+        assert_eq!(payload, r#"{"uptime":3600}"#);
+
+        heartbeat.status(|dest| write!(dest, "\"rssi\":{}", -67));
+        heartbeat.write_payload(3600, &mut payload).unwrap();
+        assert_eq!(payload, r#"{"uptime":3600,"rssi":-67}"#);
+
+        heartbeat.status(|_| Ok(()));
+        heartbeat.write_payload(3600, &mut payload).unwrap();
+        assert_eq!(payload, r#"{"uptime":3600}"#);
+    }
+
+    #[test]
+    fn heartbeat_payload_too_large() {
+        let heartbeat = Heartbeat::new("status", 10_000.milliseconds());
+        let mut payload: String<8> = String::new();
+
+        assert!(heartbeat.write_payload(3600, &mut payload).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! embedded-io Transport
+//!
+//! # Design
+//! Links without a socket interface communicate with the broker through an `MqttTransport`, which
+//! the `TransportStack` adapts to the interface of the client. The `IoTransport` implements this
+//! trait for any byte stream implementing the `embedded-io` traits, such that the client can run
+//! directly over a TLS session, a serial PPP driver, or any other byte pipe to the broker. The
+//! resulting network stack is available as an `IoStack`.
+//!
+//! The byte stream is expected to already be connected to the broker. Because the stream cannot
+//! be reopened by the client, it is considered closed once the client closes its socket (e.g. if
+//! the connection to the broker is reset). A new stream can then be provided using
+//! `MqttClient::suspend_network()` and `MqttClient::resume_network()`.
+//!
+//! # Note
+//! The client is driven by polling, so only the blocking `embedded-io` traits are supported. The
+//! `ReadReady` and `WriteReady` traits are used to avoid blocking when no data can be transferred.
+use crate::{MqttTransport, TransportStack};
+use embedded_io::{Read, ReadReady, Write, WriteReady};
+use embedded_nal::{nb, SocketAddr};
+
+/// Errors that may occur when using an `IoTransport`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IoError<E> {
+    /// The underlying byte stream encountered an error.
+    Io(E),
+
+    /// The byte stream was closed and can no longer be used.
+    Closed,
+}
+
+/// A transport that communicates over an `embedded-io` byte stream.
+pub struct IoTransport<T> {
+    io: T,
+    closed: bool,
+}
+
+/// A network stack that communicates over an `embedded-io` byte stream.
+pub type IoStack<T> = TransportStack<IoTransport<T>>;
+
+impl<T> IoTransport<T> {
+    /// Construct a transport from a byte stream that is connected to the broker.
+    ///
+    /// # Args
+    /// * `io` - The byte stream to communicate over.
+    pub fn new(io: T) -> Self {
+        Self { io, closed: false }
+    }
+
+    /// Get a reference to the underlying byte stream.
+    pub fn inner(&self) -> &T {
+        &self.io
+    }
+
+    /// Get a mutable reference to the underlying byte stream.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    /// Consume the transport and return the underlying byte stream.
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+}
+
+impl<T> MqttTransport for IoTransport<T>
+where
+    T: Read + Write + ReadReady + WriteReady,
+{
+    type Error = IoError<T::Error>;
+
+    fn connect(&mut self, _remote: SocketAddr) -> nb::Result<(), Self::Error> {
+        // The byte stream is already connected to the broker.
+        if self.closed {
+            return Err(nb::Error::Other(IoError::Closed));
+        }
+
+        Ok(())
+    }
+
+    fn is_connected(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.closed)
+    }
+
+    fn write(&mut self, data: &[u8]) -> nb::Result<usize, Self::Error> {
+        if !self.io.write_ready().map_err(IoError::Io)? {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let written = self.io.write(data).map_err(IoError::Io)?;
+        self.io.flush().map_err(IoError::Io)?;
+        Ok(written)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+        if !self.io.read_ready().map_err(IoError::Io)? {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        Ok(self.io.read(buffer).map_err(IoError::Io)?)
+    }
+
+    fn close(&mut self) -> Result<(), Self::Error> {
+        self.closed = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IoError, IoStack, IoTransport};
+    use embedded_io::{ErrorType, Read, ReadReady, Write, WriteReady};
+    use embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, TcpClientStack};
+    use heapless::Vec;
+
+    #[derive(Default)]
+    struct Pipe {
+        rx: Vec<u8, 32>,
+        tx: Vec<u8, 32>,
+    }
+
+    impl ErrorType for Pipe {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let len = core::cmp::min(buf.len(), self.rx.len());
+            buf[..len].copy_from_slice(&self.rx[..len]);
+            self.rx = Vec::from_slice(&self.rx[len..]).unwrap();
+            Ok(len)
+        }
+    }
+
+    impl ReadReady for Pipe {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.rx.is_empty())
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.tx.extend_from_slice(buf).unwrap();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl WriteReady for Pipe {
+        fn write_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+    }
+
+    fn remote() -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 1883)
+    }
+
+    #[test]
+    fn io_stack_transfers_data() {
+        let mut stack = IoStack::new(IoTransport::new(Pipe::default()));
+
+        let mut socket = stack.socket().unwrap();
+        stack.connect(&mut socket, remote()).unwrap();
+        assert!(stack.is_connected(&socket).unwrap());
+
+        let mut buffer = [0u8; 4];
+        assert_eq!(
+            stack.receive(&mut socket, &mut buffer),
+            Err(nb::Error::WouldBlock)
+        );
+
+        stack
+            .inner_mut()
+            .inner_mut()
+            .rx
+            .extend_from_slice(&[1, 2])
+            .unwrap();
+        assert_eq!(stack.receive(&mut socket, &mut buffer).unwrap(), 2);
+        assert_eq!(buffer[..2], [1, 2]);
+
+        assert_eq!(stack.send(&mut socket, &[3, 4, 5]).unwrap(), 3);
+        assert_eq!(stack.inner().inner().tx, [3, 4, 5]);
+    }
+
+    #[test]
+    fn io_stack_cannot_reopen() {
+        let mut stack = IoStack::new(IoTransport::new(Pipe::default()));
+
+        let socket = stack.socket().unwrap();
+        stack.close(socket).unwrap();
+
+        let mut socket = stack.socket().unwrap();
+        assert_eq!(
+            stack.connect(&mut socket, remote()),
+            Err(nb::Error::Other(IoError::Closed))
+        );
+        assert!(!stack.is_connected(&socket).unwrap());
+    }
+}
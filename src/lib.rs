@@ -7,16 +7,39 @@
 //!
 //! # Limitations
 //! This library does not currently support the following elements:
-//! * Quality-of-service `ExactlyOnce`
-//! * Quality-of-service above `AtMostOnce` for inbound messages.
-//! * Bulk subscriptions
 //! * Server Authentication
-//! * Encryption
-//! * Topic aliases
 //!
 //! # Requirements
 //! This library requires that the user provide it an object that implements a basic TcpStack that
-//! can be used as the transport layer for MQTT communications.
+//! can be used as the transport layer for MQTT communications. A `ProxyStack` may wrap the stack to
+//! connect through an HTTP proxy. Links without a socket interface, such as cellular modems or
+//! serial tunnels, may implement `MqttTransport` and are used through a `TransportStack`.
+//!
+//! With the `embedded-io` feature enabled, any byte stream implementing the `embedded-io` traits
+//! may be used as a transport through an `IoTransport`.
+//!
+//! With the `websocket` feature enabled, a `WebSocketStack` carries the connection in WebSocket
+//! frames for brokers that only accept MQTT over WebSockets.
+//!
+//! With the `async` feature enabled, an `AsyncClient` provides futures to connect, publish,
+//! subscribe and receive messages for firmware built on an async executor.
+//!
+//! With the `std` feature enabled, a `SharedMqttClient` allows a single client to be used from
+//! multiple threads. A `StdTcpStack` connects to a broker using `std::net`, e.g. to run the client
+//! on a desktop during development. On Unix, a `UnixStack` connects to a broker over a Unix domain
+//! socket.
+//!
+//! With the `ffi` feature enabled, the `ffi` module provides a C API for use from existing C
+//! firmware.
+//!
+//! With the `discovery` feature enabled, `Discovery` finds a broker on the local network that is
+//! advertised using mDNS, which requires a UDP network stack.
+//!
+//! With the `mqtt-sn` feature enabled, `MqttSn` communicates with an MQTT-SN gateway over a UDP
+//! network stack for networks where TCP is impractical, such as 802.15.4 or LoRa.
+//!
+//! With the `derive` feature enabled, `#[derive(Telemetry)]` generates the code to publish every
+//! field of a structure to its own topic.
 //!
 //! The maximum message size is configured through generic parameters. This allows the maximum
 //! message size to be configured by the user. Note that buffers will be allocated on the stack, so it
@@ -61,22 +84,100 @@
 //! }
 //! ```
 
+#[cfg(feature = "std")]
+extern crate std;
+
 pub(crate) mod de;
 pub(crate) mod ser;
 
+#[cfg(feature = "async")]
+mod async_client;
+mod builder;
+#[cfg(feature = "compression")]
+mod compression;
+#[cfg(feature = "discovery")]
+mod discovery;
+#[cfg(feature = "encryption")]
+mod encryption;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod heartbeat;
+#[cfg(feature = "embedded-io")]
+mod io_stack;
 mod message_types;
 pub mod mqtt_client;
+#[cfg(feature = "mqtt-sn")]
+pub mod mqtt_sn;
 mod network_manager;
+mod pool;
 mod properties;
+mod proxy_stack;
+mod publish_queue;
+mod reconnect;
 mod session_state;
+pub mod settings;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "std")]
+mod std_stack;
+mod subscriptions;
+pub mod telemetry;
+mod topic_aliases;
+mod transport;
+pub mod typestate;
+#[cfg(all(feature = "std", unix))]
+mod unix_stack;
+#[cfg(feature = "websocket")]
+mod websocket_stack;
 mod will;
 
+pub use builder::MinimqBuilder;
+pub use heartbeat::{Heartbeat, StatusCallback};
 use message_types::MessageType;
+pub use pool::{BufferPool, BufferSource, MessagePool, PoolError, PooledBuffer, PooledMessage};
 pub use properties::Property;
+pub use proxy_stack::{ProxyError, ProxySocket, ProxyStack};
+pub use reconnect::{ExponentialBackoff, ReconnectPolicy};
+pub use subscriptions::{RetainHandling, Subscription, SubscriptionOptions};
+pub use telemetry::Telemetry;
+pub use transport::{MqttTransport, TransportSocket, TransportStack};
+
+#[cfg(feature = "derive")]
+pub use minimq_derive::Telemetry;
+
+#[cfg(feature = "async")]
+pub use async_client::{AsyncClient, MessageHandler, Timer};
+
+#[cfg(feature = "discovery")]
+pub use discovery::{Discovery, MAX_RESPONSE_LENGTH};
+
+#[cfg(feature = "encryption")]
+pub use encryption::PayloadCipher;
+
+#[cfg(feature = "mqtt-sn")]
+pub use mqtt_sn::{MqttSn, MqttSnClient};
+
+#[cfg(feature = "std")]
+pub use shared::SharedMqttClient;
+#[cfg(feature = "std")]
+pub use std_stack::{StdTcpSocket, StdTcpStack};
+#[cfg(all(feature = "std", unix))]
+pub use unix_stack::{UnixSocket, UnixStack};
+
+#[cfg(feature = "websocket")]
+pub use websocket_stack::{WebSocketError, WebSocketSocket, WebSocketStack};
+
+#[cfg(feature = "embedded-io")]
+pub use embedded_io;
+#[cfg(feature = "embedded-io")]
+pub use io_stack::{IoError, IoStack, IoTransport};
 
 pub use embedded_nal;
 pub use embedded_time;
-pub use mqtt_client::Minimq;
+pub use mqtt_client::{
+    AckToken, Capabilities, DropReason, Event, MessageFlags, Minimq, PacketCounts, PollSummary,
+    PublishBuilder, SessionStatus,
+};
 
 #[cfg(feature = "logging")]
 pub(crate) use log::{debug, error, info, warn};
@@ -104,33 +205,201 @@ pub enum Retain {
     Retained = 1,
 }
 
+/// The transmission priority of a queued MQTT message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Bulk data that may be delayed, such as periodic telemetry.
+    Low = 0,
+
+    /// The default priority for queued messages.
+    Normal = 1,
+
+    /// Urgent messages, such as alarms, that are transmitted before all other queued messages.
+    High = 2,
+}
+
 /// Errors that are specific to the MQTT protocol implementation.
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum ProtocolError {
-    Bounds,
+    /// A packet does not fit into the provided buffer.
+    ///
+    /// # Note
+    /// This is reported as `Error::BufferTooSmall` by the client.
+    BufferTooSmall {
+        /// The number of bytes required to store the packet.
+        needed: usize,
+    },
+
+    /// A field exceeds the size permitted by the protocol or the remaining data of a packet.
     DataSize,
+
+    /// A property or value is invalid.
     Invalid,
+
+    /// An internal operation failed.
     Failed,
+
+    /// A received packet is larger than the receive buffer.
     PacketSize,
-    MalformedPacket,
-    MalformedInteger,
+
+    /// A received packet could not be parsed.
+    MalformedPacket(Diagnostic),
+
+    /// A received variable-length integer is not encoded correctly.
+    MalformedInteger(Diagnostic),
+
+    /// A received packet contains a property that is not known.
     UnknownProperty,
+
+    /// A received packet is of a type that is not supported.
     UnsupportedPacket,
-    BufferSize,
+
+    /// A property is not permitted in the packet.
     InvalidProperty,
+
+    /// A received packet is not valid in the current state of the connection.
+    UnexpectedPacket,
+
+    /// A received acknowledgement refers to a packet identifier that is not pending.
+    UnknownPacketId,
+}
+
+/// Details about the location and cause of a parsing failure.
+///
+/// # Note
+/// The details are only recorded if the `diagnostics` feature is enabled. Otherwise, this is a
+/// zero-sized type.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[non_exhaustive]
+pub struct Diagnostic {
+    /// The byte offset within the packet at which parsing failed.
+    #[cfg(feature = "diagnostics")]
+    pub offset: usize,
+
+    /// A description of the data that was expected.
+    #[cfg(feature = "diagnostics")]
+    pub expected: &'static str,
+
+    /// The value that was found instead, if applicable.
+    #[cfg(feature = "diagnostics")]
+    pub found: Option<u32>,
+}
+
+impl Diagnostic {
+    #[cfg_attr(not(feature = "diagnostics"), allow(unused_variables))]
+    pub(crate) const fn new(offset: usize, expected: &'static str, found: Option<u32>) -> Self {
+        Self {
+            #[cfg(feature = "diagnostics")]
+            offset,
+            #[cfg(feature = "diagnostics")]
+            expected,
+            #[cfg(feature = "diagnostics")]
+            found,
+        }
+    }
+}
+
+#[cfg(feature = "diagnostics")]
+impl core::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "expected {} at byte {}", self.expected, self.offset)?;
+        if let Some(found) = self.found {
+            write!(f, ", found {:#x}", found)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The reason that the session state with the broker was reset.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum SessionResetReason {
+    /// The broker did not have a session stored for the client upon reconnection, e.g. because the
+    /// session expired or the broker was restarted.
+    Expired,
+
+    /// The client requested a clean start using `MqttClient::set_clean_start()`.
+    CleanStart,
 }
 
 /// Possible errors encountered during an MQTT connection.
 #[derive(Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Error<E> {
+    /// The network stack reported an error.
     Network(E),
+
+    /// The network stack could not accept the data immediately.
     WriteFail,
+
+    /// The client is not connected to the broker.
+    NotConnected,
+
+    /// The request cannot be handled at the moment, e.g. because the maximum number of messages
+    /// are already in flight. The request may be retried later.
     NotReady,
+
+    /// The request is not supported by the client.
     Unsupported,
+
+    /// The client ID does not fit into the session state.
     ProvidedClientIdTooLong,
-    Failed(u8),
+
+    /// A packet does not fit into a message buffer of `MSG_SIZE` bytes.
+    BufferTooSmall {
+        /// The number of bytes required to store the packet.
+        needed: usize,
+    },
+
+    /// A packet exceeds the maximum packet size advertised by the broker.
+    PacketTooLarge {
+        /// The size of the packet.
+        size: usize,
+
+        /// The maximum packet size of the broker.
+        maximum: usize,
+    },
+
+    /// The broker refused the connection.
+    ConnectionRefused {
+        /// The reason code of the CONNACK.
+        reason_code: u8,
+    },
+
+    /// The enhanced authentication with the broker was aborted by the authentication handler.
+    AuthenticationFailed,
+
+    /// The broker did not accept a request, such as a subscription. If only some topic filters of
+    /// a subscription request are rejected, the reason code of the first rejected filter is
+    /// provided. `Event::Subscribed` reports the reason code of every filter.
+    Unacknowledged {
+        /// The reason code provided by the broker.
+        reason_code: u8,
+    },
+
+    /// A packet violated the MQTT protocol.
     Protocol(ProtocolError),
-    SessionReset,
+
+    /// The session state with the broker was reset. Subscriptions were lost and must be
+    /// renewed. Any unacknowledged messages were dropped.
+    SessionReset {
+        /// The reason that the session was reset.
+        reason: SessionResetReason,
+    },
+
+    /// The client stopped connecting to the broker after reaching the configured limit.
+    ReconnectLimit,
+
+    /// The hostname of the broker could not be resolved.
+    ResolutionFailed,
+
+    /// The broker disconnected the client as another client connected using the same client
+    /// identifier. The client stops connecting to the broker until `resume_network()` is called.
+    SessionTakenOver,
+
+    /// The clock reported an error.
     Clock(embedded_time::clock::Error),
 }
 
@@ -142,7 +411,26 @@ impl<E> From<embedded_time::clock::Error> for Error<E> {
 
 impl<E> From<ProtocolError> for Error<E> {
     fn from(error: ProtocolError) -> Self {
-        Error::Protocol(error)
+        match error {
+            ProtocolError::BufferTooSmall { needed } => Error::BufferTooSmall { needed },
+            error => Error::Protocol(error),
+        }
+    }
+}
+
+/// Errors encountered while polling with a fallible message handler.
+#[derive(Debug, PartialEq)]
+pub enum PollError<E, H> {
+    /// The client encountered an error.
+    Mqtt(Error<E>),
+
+    /// The message handler failed.
+    Handler(H),
+}
+
+impl<E, H> From<Error<E>> for PollError<E, H> {
+    fn from(error: Error<E>) -> Self {
+        PollError::Mqtt(error)
     }
 }
 
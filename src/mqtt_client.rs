@@ -1,20 +1,31 @@
 use crate::{
     de::{
-        deserialize::{ConnAck, ReceivedPacket},
+        deserialize::{Auth, ConnAck, ReceivedPacket},
         PacketReader,
     },
+    heartbeat::Heartbeat,
     network_manager::InterfaceHolder,
+    pool::PacketBuffer,
+    publish_queue::{publish_topic, PublishQueue},
     ser::serialize,
-    session_state::SessionState,
+    session_state::{self, SessionState},
+    subscriptions::{self, Subscription, SubscriptionOptions, MAX_FILTER_LENGTH},
+    topic_aliases::TopicAliases,
     will::Will,
-    Error, Property, ProtocolError, QoS, Retain, {debug, error, info},
+    BufferSource, Error, PollError, Priority, Property, ProtocolError, QoS, ReconnectPolicy,
+    Retain, SessionResetReason, {debug, error, info, warn},
 };
 
-use embedded_nal::{IpAddr, SocketAddr, TcpClientStack};
+use embedded_nal::{nb, AddrType, Dns, IpAddr, SocketAddr, TcpClientStack};
+use embedded_time::{
+    duration::{Milliseconds, Seconds},
+    fixed_point::FixedPoint,
+    Instant,
+};
 
-use heapless::String;
+use heapless::{Deque, String, Vec};
 
-use core::str::FromStr;
+use core::{convert::TryFrom, fmt::Write, str::FromStr};
 
 mod sm {
 
@@ -29,6 +40,25 @@ mod sm {
             Establishing + ReceivedConnAck = Active,
             Establishing + Disconnect = Restart,
             Active + Disconnect = Restart,
+            ConnectTransport + Timeout = Restart,
+            ConnectTransport + ConnectFailed = Restart,
+            Establishing + Timeout = Restart,
+            Restart + Suspend = Suspended,
+            ConnectTransport + Suspend = Suspended,
+            ConnectBroker + Suspend = Suspended,
+            Establishing + Suspend = Suspended,
+            Active + Suspend = Suspended,
+            Suspended + Resume = Restart,
+            Restart + GiveUp = Abandoned,
+            Abandoned + Resume = Restart,
+            Restart + Close = Closed,
+            ConnectTransport + Close = Closed,
+            ConnectBroker + Close = Closed,
+            Establishing + Close = Closed,
+            Active + Close = Closed,
+            Suspended + Close = Closed,
+            Abandoned + Close = Closed,
+            Closed + Resume = Restart,
         }
     }
 
@@ -39,6 +69,429 @@ mod sm {
 
 use sm::{Context, Events, StateMachine, States};
 
+/// A function computing the response to an authentication challenge.
+pub(crate) type AuthHandler = fn(&[u8], &mut [u8]) -> Option<usize>;
+
+/// A function resolving the hostname of the broker using the network stack.
+type Resolver<TcpStack> = fn(&mut TcpStack, &str) -> nb::Result<IpAddr, ()>;
+
+fn resolve<TcpStack: Dns>(stack: &mut TcpStack, hostname: &str) -> nb::Result<IpAddr, ()> {
+    stack
+        .get_host_by_name(hostname, AddrType::Either)
+        .map_err(|error| error.map(|_| ()))
+}
+
+/// The DISCONNECT reason code requesting the broker to publish the will message.
+const DISCONNECT_WITH_WILL: u8 = 0x04;
+
+/// The DISCONNECT reason code indicating that another client connected with the same identifier.
+const SESSION_TAKEN_OVER: u8 = 0x8E;
+
+/// The maximum length of the Reason String kept for the last failure reported by the broker.
+const MAX_REASON_LENGTH: usize = 64;
+
+/// The maximum length of the Response Information provided by the broker.
+const MAX_RESPONSE_INFORMATION_LENGTH: usize = 64;
+
+/// A token used to acknowledge a received message at a later point in time.
+///
+/// # Note
+/// Tokens are obtained through `MqttClient::defer_ack()` from within the `poll()` handler.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AckToken {
+    packet_id: u16,
+    qos: QoS,
+}
+
+/// The fixed-header flags of a received message.
+///
+/// # Note
+/// Flags are obtained through `MqttClient::message_flags()` from within the `poll()` handler.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MessageFlags {
+    /// The quality-of-service level that the message was delivered with.
+    pub qos: QoS,
+
+    /// Indicates whether the message was retained by the broker, e.g. because it was published
+    /// before the subscription was made.
+    pub retain: Retain,
+
+    /// Indicates true if the message is a redelivery of a message that may have been received
+    /// before.
+    pub duplicate: bool,
+}
+
+/// The features and limits of the broker that were advertised when the connection was established.
+///
+/// # Note
+/// Features that the broker did not advertise are assumed to be available, and limits that it did
+/// not advertise are assumed to be absent.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// The maximum quality-of-service of messages published by the client.
+    pub maximum_qos: QoS,
+
+    /// Specifies whether messages may be retained.
+    pub retain_available: bool,
+
+    /// Specifies whether topic filters may contain wildcards.
+    pub wildcard_subscriptions: bool,
+
+    /// Specifies whether subscriptions may carry a subscription identifier.
+    pub subscription_identifiers: bool,
+
+    /// Specifies whether shared subscriptions are supported.
+    pub shared_subscriptions: bool,
+
+    /// The maximum number of `QoS::AtLeastOnce` and `QoS::ExactlyOnce` messages that may be in
+    /// flight at once.
+    pub receive_maximum: u16,
+
+    /// The maximum size of packets accepted by the broker, if limited.
+    pub maximum_packet_size: Option<u32>,
+
+    /// The highest topic alias accepted by the broker. If zero, topic aliases are not accepted.
+    pub topic_alias_maximum: u16,
+
+    /// The keep-alive interval in seconds requested by the broker, if it overrides the interval
+    /// requested by the client.
+    pub server_keep_alive: Option<u16>,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            maximum_qos: QoS::ExactlyOnce,
+            retain_available: true,
+            wildcard_subscriptions: true,
+            subscription_identifiers: true,
+            shared_subscriptions: true,
+            receive_maximum: u16::MAX,
+            maximum_packet_size: None,
+            topic_alias_maximum: 0,
+            server_keep_alive: None,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Determine if a message can be published.
+    ///
+    /// # Args
+    /// * `qos` - The quality-of-service level of the message.
+    /// * `retain` - The retained status of the message.
+    fn permit_publish(&self, qos: QoS, retain: Retain) -> bool {
+        (qos as u8) <= (self.maximum_qos as u8)
+            && (retain == Retain::NotRetained || self.retain_available)
+    }
+
+    /// Determine if a subscription can be requested.
+    ///
+    /// # Args
+    /// * `filter` - The topic filter of the subscription.
+    /// * `properties` - The properties of the subscription request.
+    fn permit_subscription(&self, filter: &str, properties: &[Property]) -> bool {
+        let identified = properties
+            .iter()
+            .any(|property| matches!(property, Property::SubscriptionIdentifier(_)));
+
+        (self.wildcard_subscriptions || !filter.contains(['+', '#']))
+            && (self.shared_subscriptions || subscriptions::split_shared(filter).is_none())
+            && (self.subscription_identifiers || !identified)
+    }
+}
+
+/// The status of the connection with the broker.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SessionStatus {
+    /// The client is not connected and connects with the next call to `poll()`, unless the next
+    /// connection attempt is delayed by the reconnect policy.
+    Disconnected,
+
+    /// The client is establishing the TCP connection or waiting for the broker to accept the
+    /// connection.
+    Connecting,
+
+    /// The broker accepted the connection, so messages can be published.
+    Connected,
+
+    /// The network was suspended using `MqttClient::suspend_network()`.
+    Suspended,
+
+    /// The client gave up on connecting, e.g. because the reconnect limit was reached or the
+    /// session was taken over. Connecting is restarted using `MqttClient::resume_network()`.
+    Abandoned,
+
+    /// The client was shut down.
+    Closed,
+}
+
+/// The reason that a message was dropped without being delivered.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DropReason {
+    /// The publish queue was full and the message was replaced by a message of higher priority.
+    QueueFull,
+
+    /// The broker rejected the message with the contained reason code.
+    Rejected(u8),
+
+    /// The broker did not maintain the session, so the unacknowledged message was lost.
+    SessionReset,
+
+    /// The message expired before it could be transmitted.
+    Expired,
+
+    /// A received message was not passed to the `poll()` handler, as its payload could not be
+    /// decrypted and authenticated, e.g. because it was modified or encrypted using another key.
+    DecryptionFailed,
+}
+
+/// A protocol event that occurred while processing the connection to the broker.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Event<'a> {
+    /// The broker acknowledged a topic filter of a subscription request with the contained reason
+    /// code. Reason codes below 0x80 contain the granted quality-of-service. Requests for multiple
+    /// topic filters emit an event for each filter, in the order that they were requested.
+    Subscribed {
+        packet_id: u16,
+        filter: &'a str,
+        reason_code: u8,
+    },
+
+    /// The broker acknowledged an unsubscribe request with the contained reason code. Reason codes
+    /// below 0x80 indicate that the subscription was removed.
+    Unsubscribed { packet_id: u16, reason_code: u8 },
+
+    /// The broker responded to a ping request.
+    PingResponse,
+
+    /// The broker closed the connection with the contained reason code and optional reason
+    /// string. The client reconnects with the next call to `poll()`.
+    Disconnected {
+        reason_code: u8,
+        reason: Option<&'a str>,
+    },
+
+    /// The broker acknowledged a published message with the contained reason code, i.e. using a
+    /// PUBACK for `QoS::AtLeastOnce` or a PUBREC for `QoS::ExactlyOnce` messages. Reason codes of
+    /// 0x80 and above indicate that the broker rejected the message, e.g. 0x87 if the client is not
+    /// authorized or 0x97 if a quota was exceeded. Rejected messages are also passed to the dead
+    /// letter handler.
+    Acknowledged {
+        packet_id: u16,
+        topic: &'a str,
+        reason_code: u8,
+    },
+
+    /// An unacknowledged message was retransmitted after reconnecting to the broker.
+    Retransmitted { packet_id: u16 },
+
+    /// A received message was skipped because its payload exceeded the maximum payload size of the
+    /// matching subscriptions.
+    MessageSkipped { topic: &'a str, size: usize },
+
+    /// The broker redirected the client to another broker when refusing the connection or
+    /// disconnecting. The address is `None` if the Server Reference contains no IP address.
+    Redirected {
+        reference: &'a str,
+        address: Option<SocketAddr>,
+    },
+
+    /// The connection with the broker was established. If the session is not present, the broker
+    /// did not resume a previous session, so subscriptions and any state published by the
+    /// application need to be established again.
+    Connected { session_present: bool },
+
+    /// The established connection with the broker was lost or closed, e.g. because the TCP
+    /// connection broke down or the broker disconnected the client. Messages published until the
+    /// client reconnects are not transmitted unless they are queued.
+    ConnectionLost,
+}
+
+/// The number of packets of each type received from the broker.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct PacketCounts {
+    /// The number of CONNACK packets.
+    pub conn_ack: usize,
+
+    /// The number of PUBLISH packets.
+    pub publish: usize,
+
+    /// The number of PUBACK packets.
+    pub pub_ack: usize,
+
+    /// The number of PUBREC packets.
+    pub pub_rec: usize,
+
+    /// The number of PUBCOMP packets.
+    pub pub_comp: usize,
+
+    /// The number of PUBREL packets.
+    pub pub_rel: usize,
+
+    /// The number of SUBACK packets.
+    pub sub_ack: usize,
+
+    /// The number of UNSUBACK packets.
+    pub unsub_ack: usize,
+
+    /// The number of PINGRESP packets.
+    pub ping_resp: usize,
+
+    /// The number of DISCONNECT packets.
+    pub disconnect: usize,
+
+    /// The number of AUTH packets.
+    pub auth: usize,
+}
+
+impl PacketCounts {
+    fn count(&mut self, packet: &ReceivedPacket<'_>) {
+        let count = match packet {
+            ReceivedPacket::ConnAck(_) => &mut self.conn_ack,
+            ReceivedPacket::Publish(_) => &mut self.publish,
+            ReceivedPacket::PubAck(_) => &mut self.pub_ack,
+            ReceivedPacket::PubRec(_) => &mut self.pub_rec,
+            ReceivedPacket::PubComp(_) => &mut self.pub_comp,
+            ReceivedPacket::PubRel(_) => &mut self.pub_rel,
+            ReceivedPacket::SubAck(_) => &mut self.sub_ack,
+            ReceivedPacket::UnsubAck(_) => &mut self.unsub_ack,
+            ReceivedPacket::PingResp => &mut self.ping_resp,
+            ReceivedPacket::Disconnect(_) => &mut self.disconnect,
+            ReceivedPacket::Auth(_) => &mut self.auth,
+        };
+
+        *count += 1;
+    }
+
+    /// Get the total number of packets.
+    pub fn total(&self) -> usize {
+        self.conn_ack
+            + self.publish
+            + self.pub_ack
+            + self.pub_rec
+            + self.pub_comp
+            + self.pub_rel
+            + self.sub_ack
+            + self.unsub_ack
+            + self.ping_resp
+            + self.disconnect
+            + self.auth
+    }
+}
+
+/// A summary of the work performed by a call to `Minimq::poll_with_summary()`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct PollSummary<R> {
+    /// The value returned by the handler if a message was handled.
+    pub handled: Option<R>,
+
+    /// The packets that were processed.
+    pub packets: PacketCounts,
+
+    /// The number of bytes that were read from the network.
+    pub bytes_received: usize,
+
+    /// Specifies whether further received packets are buffered and will be processed by the next
+    /// call.
+    pub pending: bool,
+}
+
+impl<R> Default for PollSummary<R> {
+    fn default() -> Self {
+        Self {
+            handled: None,
+            packets: PacketCounts::default(),
+            bytes_received: 0,
+            pending: false,
+        }
+    }
+}
+
+/// A message whose payload is appended incrementally.
+///
+/// # Note
+/// The builder is obtained from `MqttClient::start_publish()`. The payload is appended directly
+/// behind the header of the packet in the transmit buffer of the client. If the builder is dropped
+/// without calling `finish()`, the message is discarded.
+pub struct PublishBuilder<'a, TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    client: &'a mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+    qos: QoS,
+    id: u16,
+    start: usize,
+    length: usize,
+    capacity: usize,
+}
+
+impl<'a, TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+    PublishBuilder<'a, TcpStack, Clock, MSG_SIZE, MSG_COUNT>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    /// Append data to the payload.
+    ///
+    /// # Args
+    /// * `data` - The data to append.
+    ///
+    /// # Returns
+    /// `Error::BufferTooSmall` if the payload would no longer fit into the packet. The data is not
+    /// appended in this case.
+    pub fn append(&mut self, data: &[u8]) -> Result<(), Error<TcpStack::Error>> {
+        let length = self.length + data.len();
+        if length > self.capacity {
+            return Err(Error::BufferTooSmall {
+                needed: MSG_SIZE - self.capacity + length,
+            });
+        }
+
+        // The space behind the header is reserved for the payload.
+        let payload = MSG_SIZE - self.capacity;
+        let buffer = self.client.network.write_buffer().ok_or(Error::NotReady)?;
+        buffer[payload + self.length..payload + length].copy_from_slice(data);
+        self.length = length;
+        Ok(())
+    }
+
+    /// Get the number of bytes that may still be appended to the payload.
+    pub fn remaining(&self) -> usize {
+        self.capacity - self.length
+    }
+
+    /// Transmit the message.
+    pub fn finish(self) -> Result<(), Error<TcpStack::Error>> {
+        let client = self.client;
+
+        // The header was serialized for a payload filling the whole capacity. The packet ends with
+        // the appended payload instead.
+        let buffer = client.network.write_buffer().ok_or(Error::NotReady)?;
+        let packet =
+            serialize::truncate_publish(&mut buffer[self.start..], self.capacity - self.length)?;
+
+        let packet = client
+            .network
+            .write_in_place(self.start + packet.start..self.start + packet.end)?;
+        client.session_state.increment_packet_identifier();
+
+        if self.qos != QoS::AtMostOnce {
+            client
+                .session_state
+                .handle_publish(self.qos, self.id, packet);
+            client.update_ack_deadline(false)?;
+        }
+
+        Ok(())
+    }
+}
+
 /// The general structure for managing MQTT via Minimq.
 pub struct Minimq<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
 where
@@ -46,7 +499,7 @@ where
     Clock: embedded_time::Clock,
 {
     pub client: MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
-    packet_reader: PacketReader<MSG_SIZE>,
+    packet_reader: PacketReader<PacketBuffer<MSG_SIZE>>,
 }
 
 /// A client for interacting with an MQTT Broker.
@@ -61,6 +514,59 @@ pub struct MqttClient<
     session_state: SessionState<Clock, MSG_SIZE, MSG_COUNT>,
     connection_state: StateMachine<Context>,
     will: Option<Will<MSG_SIZE>>,
+    pending_ack: Option<AckToken>,
+    message_flags: Option<MessageFlags>,
+    capabilities: Capabilities,
+    acknowledgements: Deque<AckToken, MSG_COUNT>,
+    completions: Deque<(u16, u8), MSG_COUNT>,
+    publish_queue: PublishQueue<Clock, MSG_SIZE, MSG_COUNT>,
+    buffer_pool: Option<&'static dyn BufferSource<MSG_SIZE>>,
+    queue_ttl: Option<Milliseconds<u32>>,
+    dead_letter_handler: Option<fn(&str, DropReason)>,
+    event_handler: Option<fn(Event<'_>)>,
+    receive_paused: bool,
+    redeliver_failed: bool,
+    broker_hostname: Option<(&'static str, Resolver<TcpStack>)>,
+    failover: Option<(&'static [SocketAddr], u32, usize)>,
+    follow_redirects: bool,
+    last_error_detail: Option<String<MAX_REASON_LENGTH>>,
+    connect_timeout: Option<Milliseconds<u32>>,
+    write_timeout: Option<Milliseconds<u32>>,
+    connack_timeout: Option<Milliseconds<u32>>,
+    phase_deadline: Option<Instant<Clock>>,
+    ack_timeout: Option<Milliseconds<u32>>,
+    ack_deadline: Option<Instant<Clock>>,
+    write_deadline: Option<Instant<Clock>>,
+    max_reconnect_attempts: Option<u32>,
+    max_outage: Option<Milliseconds<u32>>,
+    reconnect_attempts: u32,
+    reconnect_policy: Option<&'static dyn ReconnectPolicy>,
+    reconnect_deadline: Option<Instant<Clock>>,
+    min_connect_interval: Option<Milliseconds<u32>>,
+    last_connect_attempt: Option<Instant<Clock>>,
+    session_taken_over: bool,
+    connected: bool,
+    resubscribe: bool,
+    offline_queueing: bool,
+    outage_start: Option<Instant<Clock>>,
+    timestamp_property: Option<&'static str>,
+    heartbeat: Option<Heartbeat>,
+    authentication: Option<(&'static str, AuthHandler)>,
+    session_expiry: u32,
+    clean_start: bool,
+    request_response_information: bool,
+    response_information: Option<String<MAX_RESPONSE_INFORMATION_LENGTH>>,
+    topic_aliases: TopicAliases,
+    availability: Option<(&'static str, &'static [u8])>,
+    birth_pending: bool,
+    heartbeat_deadline: Option<Instant<Clock>>,
+    shutdown_deadline: Option<Instant<Clock>>,
+    shutdown_unsubscribe: bool,
+    disconnect_pending: bool,
+    #[cfg(feature = "compression")]
+    compression_threshold: Option<usize>,
+    #[cfg(feature = "encryption")]
+    cipher: Option<crate::PayloadCipher>,
 }
 
 impl<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
@@ -77,39 +583,118 @@ where
             self.connection_state.process_event(Events::Connect).ok();
         }
 
-        match self.connection_state.state() {
+        // Report a lost connection once, regardless of how it was lost.
+        if self.connected && !self.is_connected() {
+            self.connected = false;
+            self.emit(Event::ConnectionLost);
+        }
+
+        let now = self.clock.try_now()?;
+
+        // If establishing the connection takes too long, start over with a new socket.
+        if let Some(deadline) = self.phase_deadline {
+            if now > deadline && self.connection_state.process_event(Events::Timeout).is_ok() {
+                warn!("Timed out while connecting to the broker");
+            }
+        }
+
+        match *self.connection_state.state() {
             // In the RESTART state, we need to reopen the TCP socket.
-            &States::Restart => {
+            States::Restart => {
+                if self.reconnect_limit_reached(now) {
+                    warn!("Giving up on connecting to the broker");
+                    self.network.close_socket()?;
+                    self.connection_state.process_event(Events::GiveUp).unwrap();
+                    return Err(Error::ReconnectLimit);
+                }
+
+                if self.connect_throttled(now)
+                    || !self.reconnect_due(now)
+                    || !self.resolve_broker(now)?
+                {
+                    return Ok(());
+                }
+
                 self.network.allocate_socket()?;
+                self.last_connect_attempt.replace(now);
+                self.select_broker();
+                self.reconnect_attempts = self.reconnect_attempts.saturating_add(1);
+                self.outage_start.get_or_insert(now);
 
                 self.connection_state
                     .process_event(Events::GotSocket)
                     .unwrap();
+
+                self.phase_deadline = self.connect_timeout.map(|timeout| now + timeout);
             }
 
             // In the connect transport state, we need to connect our TCP socket to the broker.
-            &States::ConnectTransport => {
-                self.network
-                    .connect(SocketAddr::new(self.session_state.broker, 1883))?;
+            States::ConnectTransport => {
+                match self.network.connect(self.session_state.broker_address()) {
+                    // The connection is still being established.
+                    Err(Error::WriteFail) => {}
+
+                    // If the connection failed, the next attempt is made with a new socket.
+                    Err(err) => {
+                        self.connection_state
+                            .process_event(Events::ConnectFailed)
+                            .unwrap();
+                        return Err(err);
+                    }
+
+                    Ok(()) => {}
+                }
             }
 
             // Next, connect to the broker via the MQTT protocol.
-            &States::ConnectBroker => {
-                let properties = [
+            States::ConnectBroker => {
+                let mut data: [u8; MSG_SIZE] = [0; MSG_SIZE];
+
+                // Note(unwrap): The vector has room for all properties.
+                let mut properties: Vec<Property, 6> = Vec::from_slice(&[
                     // Tell the broker our maximum packet size.
                     Property::MaximumPacketSize(MSG_SIZE as u32),
-                    // The session does not expire.
-                    Property::SessionExpiryInterval(u32::MAX),
-                ];
+                    // Tell the broker how long to keep the session after disconnecting.
+                    Property::SessionExpiryInterval(self.session_expiry),
+                ])
+                .unwrap();
+
+                // Topic aliases are only used by the broker if a maximum is advertised.
+                if self.topic_aliases.maximum() > 0 {
+                    properties
+                        .push(Property::TopicAliasMaximum(self.topic_aliases.maximum()))
+                        .unwrap();
+                }
+
+                // The broker only provides Response Information if requested.
+                if self.request_response_information {
+                    properties
+                        .push(Property::RequestResponseInformation(1))
+                        .unwrap();
+                }
+
+                if let Some((method, handler)) = self.authentication {
+                    properties
+                        .push(Property::AuthenticationMethod(method))
+                        .unwrap();
+
+                    // The initial authentication data is optional.
+                    if let Some(length) = handler(&[], &mut data).filter(|length| *length > 0) {
+                        properties
+                            .push(Property::AuthenticationData(&data[..length]))
+                            .unwrap();
+                    }
+                }
 
                 let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
                 let packet = serialize::connect_message(
                     &mut buffer,
                     self.session_state.client_id.as_str().as_bytes(),
-                    self.session_state.keepalive_interval(),
+                    self.session_state.requested_keepalive(),
                     &properties,
-                    // Only perform a clean start if we do not have any session state.
-                    !self.session_state.is_present(),
+                    // Only perform a clean start if we do not have any session state, unless
+                    // requested otherwise.
+                    self.clean_start || !self.session_state.is_present(),
                     self.will.as_ref(),
                 )?;
 
@@ -119,9 +704,16 @@ where
                 self.connection_state
                     .process_event(Events::SentConnect)
                     .unwrap();
+
+                self.phase_deadline = self.connack_timeout.map(|timeout| now + timeout);
             }
 
-            &States::Establishing => {}
+            States::Establishing => {}
+
+            // Once the client has given up on connecting, no further attempts are made until the
+            // network is resumed.
+            States::Abandoned if self.session_taken_over => return Err(Error::SessionTakenOver),
+            States::Abandoned => return Err(Error::ReconnectLimit),
 
             _ => {}
         }
@@ -129,13 +721,52 @@ where
         // Attempt to finish any pending packets.
         self.network.finish_write()?;
 
+        // If a packet cannot be completely written in time, the connection has stalled and is
+        // reset.
+        if self.network.has_pending_write() {
+            if let Some(timeout) = self.write_timeout {
+                let deadline = *self.write_deadline.get_or_insert(now + timeout);
+                if now > deadline {
+                    warn!("Timed out while writing to the broker");
+                    self.write_deadline.take();
+                    self.reset();
+                }
+            }
+        } else {
+            self.write_deadline.take();
+        }
+
+        self.send_acknowledgements()?;
+
+        self.send_releases()?;
+
+        self.send_completions()?;
+
+        self.send_renewals();
+
+        self.send_birth()?;
+
+        self.send_queued()?;
+
+        self.send_heartbeat(now)?;
+
         self.handle_timers()?;
 
-        Ok(())
+        self.handle_shutdown(now)
     }
 
     /// Specify the Will message to be sent if the client disconnects.
     ///
+    /// # Note
+    /// The will is transmitted to the broker when connecting. It should be configured before the
+    /// first call to `poll()`, as changes only take effect once the client reconnects.
+    ///
+    /// # Note
+    /// To avoid publishing the will if the connection is only lost briefly, the will may carry a
+    /// `Property::WillDelayInterval`. The broker then publishes the will only if the client does
+    /// not reconnect within the delay. The will is published no later than the session expires,
+    /// see `set_session_expiry()`.
+    ///
     /// # Args
     /// * `topic` - The topic to send the message on
     /// * `data` - The message to transmit
@@ -158,295 +789,2743 @@ where
         Ok(())
     }
 
-    fn reset(&mut self) {
-        self.connection_state.process_event(Events::Disconnect).ok();
+    /// Authenticate with the broker using MQTT 5 enhanced authentication, e.g. SCRAM.
+    ///
+    /// # Note
+    /// This must be configured before connecting to a broker. The handler is called with an empty
+    /// challenge to provide the initial authentication data of the CONNECT, with every challenge
+    /// sent by the broker in an AUTH packet, and with the final authentication data of the
+    /// CONNACK, if any.
+    ///
+    /// # Args
+    /// * `method` - The name of the authentication method.
+    /// * `handler` - The function computing the response to a challenge. It writes the response
+    ///   into the provided buffer and returns its length. If `None` is returned, the
+    ///   authentication is aborted and the connection is closed.
+    pub fn set_authentication(&mut self, method: &'static str, handler: AuthHandler) {
+        self.authentication.replace((method, handler));
     }
 
-    /// Configure the MQTT keep-alive interval.
+    /// Report the availability of the client on a topic.
     ///
     /// # Note
-    /// This must be completed before connecting to a broker.
+    /// A retained `online` message is published to the topic every time the connection with the
+    /// broker is established, and a retained `offline` message is configured as the will. If the
+    /// connection is lost, the broker publishes the will, which is replaced by the `online`
+    /// message once the client reconnects. Both messages are published with `QoS::AtLeastOnce`.
     ///
     /// # Note
-    /// The broker may override the requested keep-alive interval. Any value requested by the
-    /// broker will be used instead.
+    /// This replaces any will configured with `set_will()`. This must be configured before
+    /// connecting to a broker.
     ///
     /// # Args
-    /// * `interval` - The keep-alive interval in seconds. A ping will be transmitted if no other
-    /// messages are sent within 50% of the keep-alive interval.
-    pub fn set_keepalive_interval(
+    /// * `topic` - The topic to report the availability on.
+    /// * `online` - The message published when the client is connected.
+    /// * `offline` - The message published by the broker when the client is disconnected.
+    pub fn set_availability(
         &mut self,
-        interval_seconds: u16,
+        topic: &'static str,
+        online: &'static [u8],
+        offline: &[u8],
     ) -> Result<(), Error<TcpStack::Error>> {
-        if (self.connection_state.state() == &States::Active)
-            || (self.connection_state.state() == &States::Establishing)
-        {
-            return Err(Error::NotReady);
-        }
+        self.set_will(topic, offline, QoS::AtLeastOnce, Retain::Retained, &[])?;
 
-        self.session_state.set_keepalive(interval_seconds);
+        self.availability.replace((topic, online));
         Ok(())
     }
 
-    /// Subscribe to a topic.
-    ///
-    /// # Note
-    /// A subscription is not maintained across a disconnection with the broker. In the case of MQTT
-    /// disconnections, topics will need to be subscribed to again.
-    ///
-    /// # Args
-    /// * `topic` - The topic to subscribe to.
-    /// * `properties` - A list of properties to attach to the subscription request. May be empty.
-    pub fn subscribe<'a, 'b>(
-        &mut self,
-        topic: &'a str,
-        properties: &[Property<'b>],
-    ) -> Result<(), Error<TcpStack::Error>> {
-        if self.connection_state.state() != &States::Active {
-            return Err(Error::NotReady);
+    /// Request subscriptions again that were lost with the session.
+    fn send_renewals(&mut self) {
+        if !self.is_connected() {
+            return;
         }
 
-        // We can't subscribe if there's a pending write in the network.
-        if self.network.has_pending_write() {
-            return Err(Error::NotReady);
-        }
+        while let Some(subscription) = self.session_state.subscriptions.next_renewal() {
+            // The subscription is borrowed from the session state, which is modified when
+            // subscribing.
+            let subscription = subscription.clone();
+            let filter = subscription.filter();
 
-        let packet_id = self.session_state.get_packet_identifier();
+            match self.subscribe_with_options(filter, subscription.options(), &[]) {
+                Ok(()) => {}
 
-        let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
-        let packet = serialize::subscribe_message(&mut buffer, topic, packet_id, properties)?;
+                // The subscription is requested once the network stack can send it.
+                Err(Error::NotReady) => break,
 
-        self.network.write(packet).and_then(|_| {
-            info!("Subscribing to `{}`: {}", topic, packet_id);
-            self.session_state
-                .pending_subscriptions
-                .push(packet_id)
-                .map_err(|_| Error::Unsupported)?;
-            self.session_state.increment_packet_identifier();
-            Ok(())
-        })
+                Err(_error) => {
+                    warn!("Failed to renew subscription to `{}`: {:?}", filter, _error);
+                    self.session_state.subscriptions.cancel_renewal(filter);
+                }
+            }
+        }
     }
 
-    /// Determine if any subscriptions are waiting for completion.
-    ///
-    /// # Returns
-    /// True if any subscriptions are waiting for confirmation from the broker.
-    pub fn subscriptions_pending(&self) -> bool {
-        self.session_state.pending_subscriptions.len() != 0
+    fn send_birth(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        let (topic, online) = match self.availability {
+            Some(availability) if self.birth_pending => availability,
+            _ => return Ok(()),
+        };
+
+        if !self.is_connected() || !self.can_publish(QoS::AtLeastOnce) {
+            return Ok(());
+        }
+
+        // The online status precedes any messages queued while disconnected.
+        self.transmit_publish(topic, online, QoS::AtLeastOnce, Retain::Retained, &[])?;
+        self.birth_pending = false;
+
+        Ok(())
     }
 
-    /// Determine if the client has established a connection with the broker.
-    ///
-    /// # Returns
-    /// True if the client is connected to the broker.
-    pub fn is_connected(&mut self) -> bool {
-        self.connection_state.state() == &States::Active
+    fn reset(&mut self) {
+        self.connection_state.process_event(Events::Disconnect).ok();
     }
 
-    /// Get the count of unacknowledged QoS 1 messages.
+    /// Resolve the hostname of the broker, if configured.
     ///
     /// # Returns
-    /// Number of pending messages with QoS 1.
-    pub fn pending_messages(&self, qos: QoS) -> usize {
-        self.session_state.pending_messages(qos)
+    /// False while the hostname is being resolved.
+    fn resolve_broker(&mut self, now: Instant<Clock>) -> Result<bool, Error<TcpStack::Error>> {
+        let (hostname, resolve) = match self.broker_hostname {
+            Some(broker) => broker,
+            None => return Ok(true),
+        };
+
+        match resolve(self.network.stack_mut(), hostname) {
+            Ok(ip) => {
+                self.session_state.broker = ip;
+                Ok(true)
+            }
+            Err(nb::Error::WouldBlock) => Ok(false),
+            Err(nb::Error::Other(())) => {
+                // A failed resolution counts as a failed connection attempt.
+                warn!("Failed to resolve `{}`", hostname);
+                self.reconnect_attempts = self.reconnect_attempts.saturating_add(1);
+                self.outage_start.get_or_insert(now);
+                Err(Error::ResolutionFailed)
+            }
+        }
     }
 
-    /// Determine if the client is able to process QoS 1 publish requess.
-    ///
-    /// # Returns
-    /// True if the client is able to service QoS 1 requests.
-    pub fn can_publish(&self, qos: QoS) -> bool {
-        // We cannot publish if there's a pending write in the network stack. That message must be
-        // completed first.
-        if self.network.has_pending_write() {
+    /// Determine whether the reconnect policy permits the next connection attempt.
+    fn reconnect_due(&mut self, now: Instant<Clock>) -> bool {
+        let policy = match self.reconnect_policy {
+            // The first attempt after a lost connection is never delayed.
+            Some(policy) if self.reconnect_attempts > 0 => policy,
+            _ => return true,
+        };
+
+        let attempts = self.reconnect_attempts;
+        let deadline = *self
+            .reconnect_deadline
+            .get_or_insert_with(|| now + policy.delay(attempts));
+        if now < deadline {
             return false;
         }
 
-        self.session_state.can_publish(qos)
+        self.reconnect_deadline = None;
+        true
     }
 
-    /// Publish a message over MQTT.
-    ///
-    /// # Note
-    /// If the client is not yet connected to the broker, the message will be silently ignored.
-    ///
-    /// # Note
-    /// Currently, QoS level 2 (exactly once) delivery is not supported.
-    ///
-    /// # Args
+    /// Determine whether the minimum interval since the previous connection attempt has not yet
+    /// elapsed.
+    fn connect_throttled(&self, now: Instant<Clock>) -> bool {
+        matches!(
+            self.min_connect_interval.zip(self.last_connect_attempt),
+            Some((interval, last)) if now < last + interval
+        )
+    }
+
+    fn reconnect_limit_reached(&self, now: Instant<Clock>) -> bool {
+        if let Some(attempts) = self.max_reconnect_attempts {
+            if self.reconnect_attempts >= attempts {
+                return true;
+            }
+        }
+
+        if let Some((max_outage, start)) = self.max_outage.zip(self.outage_start) {
+            if now > start + max_outage {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Suspend the network connection to the broker.
+    ///
+    /// # Note
+    /// The TCP socket is closed and no reconnection is attempted until `resume_network()` is
+    /// called. The session state is kept, so the session is resumed upon reconnection if the
+    /// broker still maintains it. This allows the network interface (e.g. a modem) to be powered
+    /// down between reporting windows without having to subscribe to topics again.
+    ///
+    /// # Note
+    /// No DISCONNECT is sent to the broker, so the broker may publish the will message. Use
+    /// `suspend()` to park the session with a DISCONNECT instead.
+    pub fn suspend_network(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        self.connection_state
+            .process_event(Events::Suspend)
+            .map_err(|_| Error::NotReady)?;
+
+        self.network.close_socket()
+    }
+
+    /// Resume the network connection after a call to `suspend_network()`.
+    ///
+    /// # Note
+    /// This is also used to start connecting again after the client gave up on connecting to the
+    /// broker and returned `Error::ReconnectLimit` or `Error::SessionTakenOver`, or after the
+    /// client was shut down.
+    ///
+    /// # Args
+    /// * `stack` - Optionally, a new network stack to use for the connection. This is useful if the
+    ///   network stack had to be recreated while the network interface was powered down.
+    ///
+    /// # Returns
+    /// The previously used network stack if a new stack was provided.
+    pub fn resume_network(
+        &mut self,
+        stack: Option<TcpStack>,
+    ) -> Result<Option<TcpStack>, Error<TcpStack::Error>> {
+        self.connection_state
+            .process_event(Events::Resume)
+            .map_err(|_| Error::NotReady)?;
+
+        // Start counting connection attempts anew.
+        self.reconnect_attempts = 0;
+        self.reconnect_deadline = None;
+        self.last_connect_attempt = None;
+        self.session_taken_over = false;
+        self.outage_start.take();
+
+        // The socket belongs to the previous stack, so it must be closed before replacing it.
+        if stack.is_some() {
+            self.network.close_socket()?;
+        }
+
+        Ok(stack.map(|stack| self.network.replace_stack(stack)))
+    }
+
+    /// Gracefully shut down the connection with the broker.
+    ///
+    /// # Note
+    /// The shutdown is completed by subsequent calls to `poll()`. Queued messages are transmitted
+    /// and outstanding acknowledgements from the broker are awaited until the timeout expires.
+    /// Afterwards, a DISCONNECT is sent to the broker and the TCP socket is closed once the
+    /// DISCONNECT is transmitted. Completion of the shutdown is indicated by `is_shut_down()`.
+    ///
+    /// # Note
+    /// If the availability of the client is reported using `set_availability()`, the broker is
+    /// requested to publish the `offline` will upon disconnection. Otherwise, the will is not
+    /// published.
+    ///
+    /// # Note
+    /// Any messages that could not be transmitted or were not acknowledged within the timeout are
+    /// kept, and are transmitted upon reconnection using `resume_network()`.
+    ///
+    /// # Args
+    /// * `timeout` - The maximum duration to wait for messages to be transmitted and acknowledged.
+    /// * `unsubscribe` - Specifies whether all active subscriptions are removed before
+    ///   disconnecting. If true, the acknowledgements of the unsubscribe requests are awaited
+    ///   within the timeout as well.
+    pub fn shutdown(
+        &mut self,
+        timeout: Milliseconds<u32>,
+        unsubscribe: bool,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        let now = self.clock.try_now()?;
+        self.shutdown_deadline.replace(now + timeout);
+        self.shutdown_unsubscribe = unsubscribe;
+        Ok(())
+    }
+
+    /// Immediately disconnect from the broker.
+    ///
+    /// # Note
+    /// Unlike `shutdown()`, queued and unacknowledged messages are not awaited. A DISCONNECT is
+    /// sent to the broker and the TCP socket is closed. The client reconnects upon
+    /// `resume_network()`.
+    ///
+    /// # Note
+    /// If the session expiry interval is set to zero, the broker discards the session and any
+    /// unacknowledged messages are dropped with `DropReason::SessionReset`.
+    ///
+    /// # Args
+    /// * `reason_code` - The MQTT 5 reason code of the disconnection, e.g. 0x00 for a normal
+    ///   disconnection or 0x04 to request the broker to publish the will message.
+    /// * `session_expiry` - The session expiry interval in seconds to request from the broker. If
+    ///   `None`, the interval requested when connecting is used.
+    ///
+    /// # Returns
+    /// `Error::NotReady` if a previously written packet could not be completely transmitted yet.
+    /// `Error::Protocol` if a non-zero `session_expiry` is requested for a session that was
+    /// configured to expire upon disconnection, which the broker is not allowed to accept.
+    pub fn disconnect(
+        &mut self,
+        reason_code: u8,
+        session_expiry: Option<u32>,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        if !self.is_connected() {
+            return Err(Error::NotConnected);
+        }
+
+        self.send_disconnect(reason_code, session_expiry)?;
+
+        if session_expiry == Some(0) {
+            self.discard_session();
+        }
+
+        self.shutdown_deadline.take();
+        self.shutdown_unsubscribe = false;
+        self.disconnect_pending = false;
+        self.connection_state.process_event(Events::Close).ok();
+        self.network.close_socket()
+    }
+
+    fn send_disconnect(
+        &mut self,
+        reason_code: u8,
+        session_expiry: Option<u32>,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        // The DISCONNECT must not be interleaved with a partially transmitted packet.
+        self.network.finish_write()?;
+        if self.network.has_pending_write() {
+            return Err(Error::NotReady);
+        }
+
+        // A session that was requested to expire upon disconnection cannot be extended afterwards
+        // (MQTT5 §3.14.2.2.2).
+        if self.session_expiry == 0 && matches!(session_expiry, Some(expiry) if expiry != 0) {
+            return Err(Error::Protocol(ProtocolError::Invalid));
+        }
+
+        let expiry = session_expiry.map(Property::SessionExpiryInterval);
+
+        let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
+        let packet = serialize::disconnect_message(&mut buffer, reason_code, expiry.as_slice())?;
+
+        info!("Sending DISCONNECT");
+        self.network.write(packet)?;
+        self.network.finish_write()
+    }
+
+    /// Park the session with the broker, e.g. before the device enters deep sleep.
+    ///
+    /// # Note
+    /// If connected, a DISCONNECT is sent that asks the broker to keep the session for the provided
+    /// interval, and the TCP socket is closed. The subscriptions and unacknowledged messages are
+    /// kept, so that the session continues upon `resume()` if the broker still maintains it. If the
+    /// broker discarded the session in the meantime, subscriptions are only renewed if enabled
+    /// using `set_auto_resubscribe()`.
+    ///
+    /// # Note
+    /// The DISCONNECT is a normal disconnection, so the broker does not publish the will message.
+    ///
+    /// # Args
+    /// * `session_expiry` - The session expiry interval in seconds to request from the broker, which
+    ///   should cover the duration of the sleep. If `None`, the interval requested when connecting
+    ///   is used.
+    ///
+    /// # Returns
+    /// `Error::NotReady` if a previously written packet could not be completely transmitted yet.
+    /// `Error::Protocol` if a non-zero `session_expiry` is requested for a session that was
+    /// configured to expire upon disconnection, which the broker is not allowed to accept.
+    pub fn suspend(&mut self, session_expiry: Option<u32>) -> Result<(), Error<TcpStack::Error>> {
+        if self.is_connected() {
+            self.send_disconnect(0, session_expiry)?;
+        }
+
+        // A session that expires immediately cannot be resumed.
+        if session_expiry == Some(0) {
+            self.discard_session();
+        }
+
+        self.suspend_network()
+    }
+
+    /// Reconnect to the broker after a call to `suspend()`.
+    ///
+    /// # Note
+    /// This is equivalent to `resume_network()` with the network stack that is already in use.
+    pub fn resume(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        self.resume_network(None).map(|_| ())
+    }
+
+    /// Determine if the client was shut down using `shutdown()` or `disconnect()`.
+    pub fn is_shut_down(&self) -> bool {
+        self.connection_state.state() == &States::Closed
+    }
+
+    fn handle_shutdown(&mut self, now: Instant<Clock>) -> Result<(), Error<TcpStack::Error>> {
+        let deadline = match self.shutdown_deadline {
+            Some(deadline) => deadline,
+            None => return Ok(()),
+        };
+
+        if self.shutdown_unsubscribe && !self.disconnect_pending && now <= deadline {
+            self.send_removals()?;
+        }
+
+        let drained = self.publish_queue.len() == 0
+            && !self.shutdown_unsubscribe
+            && !self.session_state.subscriptions.is_removing()
+            && self.pending_messages(QoS::AtLeastOnce) == 0
+            && self.pending_messages(QoS::ExactlyOnce) == 0
+            && self.acknowledgements.is_empty()
+            && self.completions.is_empty()
+            && !self.network.has_pending_write();
+
+        if !self.disconnect_pending {
+            if !drained && now <= deadline {
+                return Ok(());
+            }
+
+            if self.is_connected() && !self.network.has_pending_write() {
+                // If the availability is reported, the broker publishes the offline status as the
+                // will.
+                let reason_code = if self.availability.is_some() {
+                    DISCONNECT_WITH_WILL
+                } else {
+                    0
+                };
+
+                let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
+                let packet = serialize::disconnect_message(&mut buffer, reason_code, &[])?;
+
+                info!("Sending DISCONNECT");
+                self.network.write(packet)?;
+                self.disconnect_pending = true;
+            }
+        }
+
+        // The socket is only closed once the DISCONNECT is completely transmitted. Otherwise, the
+        // broker considers the connection to be lost and publishes the will.
+        if self.disconnect_pending && self.is_connected() && self.network.has_pending_write() {
+            return Ok(());
+        }
+
+        self.disconnect_pending = false;
+        self.shutdown_unsubscribe = false;
+        self.shutdown_deadline.take();
+        self.connection_state.process_event(Events::Close).ok();
+        self.network.close_socket()
+    }
+
+    /// Request the removal of all active subscriptions while shutting down.
+    fn send_removals(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        if self.connection_state.state() != &States::Active {
+            return Ok(());
+        }
+
+        while let Some(filter) = self
+            .session_state
+            .subscriptions
+            .next_unrequested_removal()
+            .map(|subscription| String::<MAX_FILTER_LENGTH>::from(subscription.filter()))
+        {
+            match self.unsubscribe(&filter, &[]) {
+                // The remaining subscriptions are removed once the request is transmitted.
+                Err(Error::NotReady) => return Ok(()),
+                result => result?,
+            }
+        }
+
+        // Subscriptions that the broker refuses to remove are not requested again.
+        self.shutdown_unsubscribe = false;
+        Ok(())
+    }
+
+    /// Ensure that no connection with the broker is established, e.g. before the configuration of
+    /// the connection is changed.
+    fn ensure_disconnected(&self) -> Result<(), Error<TcpStack::Error>> {
+        if (self.connection_state.state() == &States::Active)
+            || (self.connection_state.state() == &States::Establishing)
+        {
+            return Err(Error::NotReady);
+        }
+
+        Ok(())
+    }
+
+    /// Configure the MQTT keep-alive interval.
+    ///
+    /// # Note
+    /// This must be completed before connecting to a broker.
+    ///
+    /// # Note
+    /// The broker may override the requested keep-alive interval. Any value requested by the
+    /// broker will be used instead.
+    ///
+    /// # Args
+    /// * `interval` - The keep-alive interval in seconds. A ping will be transmitted if no other
+    ///   messages are sent within 50% of the keep-alive interval. The connection is considered
+    ///   lost if no data is received from the broker within 150% of the keep-alive interval. If
+    ///   zero, keep-alive is disabled: no pings are sent and a silent broker is not considered
+    ///   lost, unless the broker requests a keep-alive interval.
+    pub fn set_keepalive_interval(
+        &mut self,
+        interval_seconds: u16,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.ensure_disconnected()?;
+
+        self.session_state.set_keepalive(interval_seconds);
+        Ok(())
+    }
+
+    /// Configure the session expiry interval.
+    ///
+    /// # Note
+    /// This must be completed before connecting to a broker. By default, the session does not
+    /// expire.
+    ///
+    /// # Args
+    /// * `interval_seconds` - The number of seconds that the broker keeps the session, including
+    ///   subscriptions and unacknowledged messages, after the connection is lost. If zero, the
+    ///   session ends with the connection. If `u32::MAX`, the session does not expire.
+    pub fn set_session_expiry(
+        &mut self,
+        interval_seconds: u32,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.ensure_disconnected()?;
+
+        self.session_expiry = interval_seconds;
+        Ok(())
+    }
+
+    /// Configure whether the broker is asked to provide Response Information.
+    ///
+    /// # Note
+    /// This must be completed before connecting to a broker. The Response Information is
+    /// commonly used as the prefix of response topics, see `response_information()`.
+    ///
+    /// # Args
+    /// * `request` - Specifies whether Response Information is requested when connecting.
+    pub fn set_request_response_information(
+        &mut self,
+        request: bool,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.ensure_disconnected()?;
+
+        self.request_response_information = request;
+        Ok(())
+    }
+
+    /// Configure whether the broker discards the session whenever the client connects.
+    ///
+    /// # Note
+    /// This must be completed before connecting to a broker. By default, a clean start is only
+    /// requested if the client does not hold any session state. If a session is discarded,
+    /// `poll()` returns `Error::SessionReset` with `SessionResetReason::CleanStart`.
+    ///
+    /// # Args
+    /// * `clean_start` - Specifies whether a clean start is requested for every connection.
+    pub fn set_clean_start(&mut self, clean_start: bool) -> Result<(), Error<TcpStack::Error>> {
+        self.ensure_disconnected()?;
+
+        self.clean_start = clean_start;
+        Ok(())
+    }
+
+    /// Configure the number of topic aliases that the broker may use when delivering messages.
+    ///
+    /// # Note
+    /// This must be completed before connecting to a broker. By default, topic aliases are not
+    /// used. Messages delivered using an alias are handled with their resolved topic.
+    ///
+    /// # Args
+    /// * `maximum` - The Topic Alias Maximum to advertise to the broker. At most 8 aliases are
+    ///   supported, and topics of up to 128 bytes may be aliased.
+    pub fn set_topic_alias_maximum(&mut self, maximum: u16) -> Result<(), Error<TcpStack::Error>> {
+        self.ensure_disconnected()?;
+
+        self.topic_aliases
+            .set_maximum(maximum)
+            .map_err(|_| Error::Unsupported)
+    }
+
+    /// Configure the timeout for establishing the TCP connection with the broker.
+    ///
+    /// # Note
+    /// If the TCP connection is not established within the timeout, the socket is closed and a new
+    /// connection attempt is started.
+    ///
+    /// # Args
+    /// * `timeout` - The maximum duration of a connection attempt. If `None`, connection attempts
+    ///   never time out.
+    pub fn set_connect_timeout(&mut self, timeout: Option<Milliseconds<u32>>) {
+        self.connect_timeout = timeout;
+    }
+
+    /// Configure the timeout for writing a packet to the broker.
+    ///
+    /// # Note
+    /// If a packet cannot be completely written within the timeout, the connection is considered
+    /// stalled and is reset.
+    ///
+    /// # Args
+    /// * `timeout` - The maximum duration for writing a packet. If `None`, writes never time out.
+    pub fn set_write_timeout(&mut self, timeout: Option<Milliseconds<u32>>) {
+        self.write_timeout = timeout;
+    }
+
+    /// Configure the timeout for receiving the CONNACK after sending the CONNECT to the broker.
+    ///
+    /// # Note
+    /// If the broker does not acknowledge the connection within the timeout, the socket is closed
+    /// and a new connection attempt is started, which sends the CONNECT again. This counts as a
+    /// failed attempt towards `set_max_reconnect_attempts()`.
+    ///
+    /// # Args
+    /// * `timeout` - The maximum duration to wait for the CONNACK. If `None`, the client waits
+    ///   indefinitely.
+    pub fn set_connack_timeout(&mut self, timeout: Option<Milliseconds<u32>>) {
+        self.connack_timeout = timeout;
+    }
+
+    /// Configure the timeout for the broker to acknowledge published messages.
+    ///
+    /// # Note
+    /// While messages with `QoS::AtLeastOnce` are unacknowledged, the broker must acknowledge one
+    /// of them within the timeout. Otherwise, the connection is considered failed and is
+    /// reestablished, after which the unacknowledged messages are retransmitted. Messages are never
+    /// retransmitted on an established connection, as this is not permitted by MQTT 5.
+    ///
+    /// # Args
+    /// * `timeout` - The maximum duration to wait for an acknowledgement. If `None`, the client
+    ///   waits indefinitely.
+    pub fn set_ack_timeout(&mut self, timeout: Option<Milliseconds<u32>>) {
+        self.ack_timeout = timeout;
+        self.ack_deadline = None;
+    }
+
+    /// Update the deadline for the broker to acknowledge pending messages.
+    ///
+    /// # Args
+    /// * `restart` - Specifies whether a running deadline is restarted.
+    fn update_ack_deadline(&mut self, restart: bool) -> Result<(), Error<TcpStack::Error>> {
+        let pending = self.session_state.pending_messages(QoS::AtLeastOnce) > 0
+            || self.session_state.pending_messages(QoS::ExactlyOnce) > 0;
+        self.ack_deadline = match self.ack_timeout {
+            Some(timeout) if pending && (restart || self.ack_deadline.is_none()) => {
+                Some(self.clock.try_now()? + timeout)
+            }
+            Some(_) if pending => self.ack_deadline,
+            _ => None,
+        };
+
+        Ok(())
+    }
+
+    /// Configure the maximum number of consecutive failed attempts to connect to the broker.
+    ///
+    /// # Note
+    /// Once the limit is reached, the client stops connecting to the broker and
+    /// `Error::ReconnectLimit` is returned from `poll()`. Connecting can be restarted using
+    /// `resume_network()`, which allows switching to a fallback network stack.
+    ///
+    /// # Args
+    /// * `attempts` - The maximum number of connection attempts. If `None`, the client never stops
+    ///   attempting to connect.
+    pub fn set_max_reconnect_attempts(&mut self, attempts: Option<u32>) {
+        self.max_reconnect_attempts = attempts;
+    }
+
+    /// Configure the maximum duration of an outage of the connection to the broker.
+    ///
+    /// # Note
+    /// If no connection with the broker is established within the duration since the first failed
+    /// connection attempt, the client stops connecting to the broker and `Error::ReconnectLimit` is
+    /// returned from `poll()`. Connecting can be restarted using `resume_network()`.
+    ///
+    /// # Args
+    /// * `duration` - The maximum duration of an outage. If `None`, outages may last indefinitely.
+    pub fn set_max_outage(&mut self, duration: Option<Milliseconds<u32>>) {
+        self.max_outage = duration;
+    }
+
+    /// Configure the policy that delays connection attempts after a failed attempt.
+    ///
+    /// # Note
+    /// The delay is applied before every connection attempt following a failed attempt, so an
+    /// unreachable broker is not contacted continuously. `poll()` returns immediately while the
+    /// next attempt is delayed.
+    ///
+    /// # Args
+    /// * `policy` - The policy to consult, e.g. an `ExponentialBackoff`. If `None`, the client
+    ///   reconnects immediately.
+    pub fn set_reconnect_policy(&mut self, policy: Option<&'static dyn ReconnectPolicy>) {
+        self.reconnect_policy = policy;
+        self.reconnect_deadline = None;
+    }
+
+    /// Configure the minimum interval between the starts of two connection attempts.
+    ///
+    /// # Note
+    /// Unlike the reconnect policy, the interval also applies to the first attempt after an
+    /// established connection was lost. This prevents a broker that accepts connections and drops
+    /// them right away from causing a tight loop of opening, connecting and closing sockets.
+    ///
+    /// # Args
+    /// * `interval` - The minimum interval. If `None`, connection attempts are not throttled.
+    pub fn set_min_connect_interval(&mut self, interval: Option<Milliseconds<u32>>) {
+        self.min_connect_interval = interval;
+    }
+
+    /// Subscribe to a topic.
+    ///
+    /// # Note
+    /// A subscription is not maintained if the broker does not maintain the session. In this case,
+    /// topics will need to be subscribed to again, unless `set_auto_resubscribe()` is enabled.
+    ///
+    /// # Note
+    /// Subscriptions are tracked by the client, so up to 16 topic filters of up to 128 bytes each
+    /// are supported. `Error::Unsupported` is returned for further topic filters.
+    ///
+    /// # Note
+    /// The topic filter may contain the `+` and `#` wildcards. Malformed filters, such as filters
+    /// with a `#` wildcard that is not the last level, are rejected with `ProtocolError::Invalid`.
+    ///
+    /// # Note
+    /// Shared subscriptions use filters of the form `$share/{share}/{filter}`. The broker delivers
+    /// each message to only one of the clients subscribed to the same share. Messages are received
+    /// on topics matching `{filter}`.
+    ///
+    /// # Note
+    /// Messages are delivered with `QoS::AtMostOnce`. Use `subscribe_with_options()` to request
+    /// a higher quality-of-service or to configure the handling of retained messages.
+    ///
+    /// # Args
+    /// * `topic` - The topic filter to subscribe to.
+    /// * `properties` - A list of properties to attach to the subscription request. May be empty.
+    pub fn subscribe<'a, 'b>(
+        &mut self,
+        topic: &'a str,
+        properties: &[Property<'b>],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.subscribe_with_options(topic, SubscriptionOptions::default(), properties)
+    }
+
+    /// Subscribe to a topic using the provided subscription options.
+    ///
+    /// # Note
+    /// Wildcard filters, shared subscriptions and subscription identifiers are rejected with
+    /// `Error::Unsupported` if the broker does not support them. See `capabilities()`.
+    ///
+    /// # Note
+    /// The subscription is tracked in the same manner as with `subscribe()`. The No Local option
+    /// must not be used for shared subscriptions, which are rejected with `ProtocolError::Invalid`.
+    ///
+    /// # Args
+    /// * `topic` - The topic filter to subscribe to.
+    /// * `options` - The options of the subscription, such as the maximum quality-of-service.
+    /// * `properties` - A list of properties to attach to the subscription request, e.g. a
+    ///   `Property::SubscriptionIdentifier` that the broker attaches to every message delivered for
+    ///   the subscription. May be empty.
+    pub fn subscribe_with_options(
+        &mut self,
+        topic: &str,
+        options: SubscriptionOptions,
+        properties: &[Property],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.subscribe_many(&[(topic, options)], properties)
+    }
+
+    /// Configure whether subscriptions are renewed automatically after reconnecting.
+    ///
+    /// # Note
+    /// If enabled, subscriptions are requested again with their original options whenever the
+    /// broker did not maintain the session, as well as subscription requests that were not
+    /// acknowledged before the connection was lost. Properties of the original request, such as
+    /// a subscription identifier, are not repeated. `Event::Subscribed` is emitted for each
+    /// renewed subscription.
+    ///
+    /// # Args
+    /// * `enabled` - Specifies whether subscriptions are renewed. By default, subscriptions have to
+    ///   be renewed by the application.
+    pub fn set_auto_resubscribe(&mut self, enabled: bool) {
+        self.resubscribe = enabled;
+        if !enabled {
+            self.session_state.subscriptions.cancel_renewals();
+        }
+    }
+
+    /// Subscribe to multiple topics using a single subscription request.
+    ///
+    /// # Note
+    /// Each topic filter is tracked in the same manner as with `subscribe()`. The broker
+    /// acknowledges each filter individually. If any filter is rejected, `poll()` reports the
+    /// first rejection as `Error::Unacknowledged` once the request is acknowledged.
+    ///
+    /// # Args
+    /// * `filters` - The topic filters to subscribe to, each with the options of its subscription.
+    /// * `properties` - A list of properties to attach to the subscription request. May be empty.
+    pub fn subscribe_many(
+        &mut self,
+        filters: &[(&str, SubscriptionOptions)],
+        properties: &[Property],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        if self.connection_state.state() != &States::Active {
+            return Err(Error::NotConnected);
+        }
+
+        // Shared subscriptions must not use the No Local option.
+        if filters.is_empty()
+            || !filters.iter().all(|(filter, options)| {
+                subscriptions::is_valid_filter(filter)
+                    && !(options.is_no_local() && subscriptions::split_shared(filter).is_some())
+            })
+        {
+            return Err(Error::Protocol(ProtocolError::Invalid));
+        }
+
+        if !filters
+            .iter()
+            .all(|(filter, _)| self.capabilities.permit_subscription(filter, properties))
+        {
+            return Err(Error::Unsupported);
+        }
+
+        // We can't subscribe if there's a pending write in the network.
+        if self.network.has_pending_write() {
+            return Err(Error::NotReady);
+        }
+
+        if !self
+            .session_state
+            .subscriptions
+            .can_request_all(filters.iter().map(|(filter, _)| *filter))
+        {
+            return Err(Error::Unsupported);
+        }
+
+        let packet_id = self.session_state.get_packet_identifier();
+
+        let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
+        let packet = serialize::subscribe_message(&mut buffer, filters, packet_id, properties)?;
+        self.check_packet_size(packet)?;
+
+        self.network.write(packet).and_then(|_| {
+            for (filter, options) in filters {
+                info!("Subscribing to `{}`: {}", filter, packet_id);
+                self.session_state
+                    .subscriptions
+                    .request(packet_id, filter, *options)
+                    .map_err(|_| Error::Unsupported)?;
+            }
+
+            self.session_state.increment_packet_identifier();
+            Ok(())
+        })
+    }
+
+    /// Unsubscribe from a topic.
+    ///
+    /// # Note
+    /// The subscription remains active until the broker acknowledges the request, after which it
+    /// is no longer listed in `subscriptions()`. Failed requests are reported by `poll()` as
+    /// `Error::Unacknowledged`.
+    ///
+    /// # Args
+    /// * `topic` - The topic filter to unsubscribe from. It must match the filter that was
+    ///   subscribed to exactly.
+    /// * `properties` - A list of properties to attach to the unsubscribe request. May be empty.
+    pub fn unsubscribe(
+        &mut self,
+        topic: &str,
+        properties: &[Property],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        if self.connection_state.state() != &States::Active {
+            return Err(Error::NotConnected);
+        }
+
+        if !subscriptions::is_valid_filter(topic) {
+            return Err(Error::Protocol(ProtocolError::Invalid));
+        }
+
+        if self.network.has_pending_write()
+            || !self.session_state.subscriptions.can_request_removal(topic)
+        {
+            return Err(Error::NotReady);
+        }
+
+        let packet_id = self.session_state.get_packet_identifier();
+
+        let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
+        let packet = serialize::unsubscribe_message(&mut buffer, topic, packet_id, properties)?;
+
+        self.network.write(packet).and_then(|_| {
+            info!("Unsubscribing from `{}`: {}", topic, packet_id);
+            self.session_state
+                .subscriptions
+                .request_removal(packet_id, topic)
+                .map_err(|_| Error::NotReady)?;
+            self.session_state.increment_packet_identifier();
+            Ok(())
+        })
+    }
+
+    /// Determine if any subscriptions are waiting for completion.
+    ///
+    /// # Note
+    /// Requests to unsubscribe are also considered. Requests that were not acknowledged before the
+    /// connection was lost are abandoned upon reconnection and must be repeated.
+    ///
+    /// # Returns
+    /// True if any subscriptions are waiting for confirmation from the broker.
+    pub fn subscriptions_pending(&self) -> bool {
+        self.session_state.subscriptions.is_pending()
+    }
+
+    /// Get the active subscriptions.
+    ///
+    /// # Returns
+    /// An iterator over the subscriptions acknowledged by the broker, including the topic filter
+    /// and the granted quality-of-service.
+    pub fn subscriptions(&self) -> impl Iterator<Item = &Subscription> {
+        self.session_state.subscriptions.active()
+    }
+
+    /// Get the keep-alive interval in effect as an integer number of seconds.
+    ///
+    /// # Note
+    /// Once connected, this is the interval requested by the broker, if any. Otherwise, it is the
+    /// interval configured using `set_keepalive_interval()`. If zero, no pings are sent.
+    pub fn keepalive_interval(&self) -> u16 {
+        self.session_state.keepalive_interval()
+    }
+
+    /// Get the Reason String of the last failure reported by the broker.
+    ///
+    /// # Note
+    /// Failures include refused connections, rejected subscriptions and messages, and
+    /// disconnections by the broker. Reason strings longer than 64 bytes are truncated.
+    ///
+    /// # Returns
+    /// The reason of the last failure, or `None` if the broker did not provide a Reason String.
+    pub fn last_error_detail(&self) -> Option<&str> {
+        self.last_error_detail.as_deref()
+    }
+
+    /// Get the Response Information provided by the broker.
+    ///
+    /// # Note
+    /// The broker only provides Response Information if it was requested using
+    /// `set_request_response_information()`. Its meaning is not defined by MQTT, but it is
+    /// commonly used as the prefix of response topics. Response Information longer than 64 bytes
+    /// is ignored.
+    pub fn response_information(&self) -> Option<&str> {
+        self.response_information.as_deref()
+    }
+
+    /// Get the identifier of the client.
+    ///
+    /// # Note
+    /// If the client was constructed with an empty identifier, this is the identifier assigned by
+    /// the broker once connected. It is reused whenever the client reconnects.
+    pub fn client_id(&self) -> &str {
+        self.session_state.client_id.as_str()
+    }
+
+    /// Get the features and limits of the broker.
+    ///
+    /// # Note
+    /// The capabilities are advertised by the broker when the connection is established. Requests
+    /// using features that are not available are rejected with `Error::Unsupported`.
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Limit the size of payloads accepted by a subscription.
+    ///
+    /// # Note
+    /// Received messages whose payload exceeds the limit of all matching subscriptions are skipped
+    /// and reported to the event handler with `Event::MessageSkipped`. Skipped messages are still
+    /// acknowledged. Messages are skipped as soon as their header is received, so messages that do
+    /// not fit into `MSG_SIZE` bytes can be skipped without interrupting the connection.
+    ///
+    /// # Args
+    /// * `filter` - The topic filter of a pending or active subscription.
+    /// * `limit` - The maximum payload size in bytes. If `None`, payloads of any size are accepted.
+    ///
+    /// # Returns
+    /// True if a subscription with the topic filter was found.
+    pub fn set_max_payload_size(&mut self, filter: &str, limit: Option<usize>) -> bool {
+        self.session_state
+            .subscriptions
+            .set_max_payload_size(filter, limit)
+    }
+
+    fn skip_message(
+        &mut self,
+        topic: &str,
+        size: usize,
+        packet_id: Option<u16>,
+        qos: QoS,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        info!("Skipping message on `{}` of {} bytes", topic, size);
+        self.emit(Event::MessageSkipped { topic, size });
+
+        // The message is still acknowledged so that the broker does not redeliver it.
+        match packet_id {
+            Some(packet_id) => self.ack(AckToken { packet_id, qos }),
+            None => Ok(()),
+        }
+    }
+
+    /// Pause the reception of messages from the broker.
+    ///
+    /// # Note
+    /// This may be called from within the `poll()` handler to signal that the application is not
+    /// ready to process further messages. While paused, received data is left in the network stack
+    /// so that TCP flow control applies back pressure to the broker.
+    ///
+    /// # Note
+    /// Keep-alive pings are not sent while reception is paused. Reception should be resumed within
+    /// the keep-alive interval to prevent the broker from closing the connection.
+    pub fn pause_receive(&mut self) {
+        self.receive_paused = true;
+    }
+
+    /// Resume the reception of messages from the broker after `pause_receive()`.
+    pub fn resume_receive(&mut self) {
+        // Any ping response that was outstanding may still be waiting in the network stack, so the
+        // pending ping is cancelled instead of being allowed to time out.
+        if self.receive_paused {
+            self.session_state.cancel_ping();
+            self.session_state.restart_reception_watchdog();
+        }
+
+        self.receive_paused = false;
+    }
+
+    /// Determine if the reception of messages has been paused using `pause_receive()`.
+    pub fn is_receive_paused(&self) -> bool {
+        self.receive_paused
+    }
+
+    fn receive_blocked(&self) -> bool {
+        // Reception is only paused once connected so that the CONNACK is always processed.
+        self.receive_paused && self.connection_state.state() == &States::Active
+    }
+
+    /// Determine if the client has established a connection with the broker.
+    ///
+    /// # Note
+    /// The client is only connected once the broker accepted the connection, not as soon as the
+    /// TCP connection is established.
+    ///
+    /// # Returns
+    /// True if the client is connected to the broker.
+    pub fn is_connected(&self) -> bool {
+        self.connection_state.state() == &States::Active
+    }
+
+    /// Get the status of the connection with the broker, e.g. to report it using an LED.
+    pub fn status(&self) -> SessionStatus {
+        match self.connection_state.state() {
+            States::Restart => SessionStatus::Disconnected,
+            States::ConnectTransport | States::ConnectBroker | States::Establishing => {
+                SessionStatus::Connecting
+            }
+            States::Active => SessionStatus::Connected,
+            States::Suspended => SessionStatus::Suspended,
+            States::Abandoned => SessionStatus::Abandoned,
+            States::Closed => SessionStatus::Closed,
+        }
+    }
+
+    /// Get the instant at which the client needs to be polled next.
+    ///
+    /// # Note
+    /// This allows low-power applications to sleep between calls to `poll()` instead of polling
+    /// continuously. Timers expire once the returned instant has passed. Independent of the
+    /// deadline, the client must be polled when the network stack received data or is ready to
+    /// transmit, and after requests such as `publish()` were made.
+    ///
+    /// # Returns
+    /// The instant at which the next timer expires, such as the keep-alive ping, the
+    /// acknowledgement timeout or the reconnect delay. The current instant is returned if the
+    /// client requires polling right away, e.g. while the connection is being established. `None`
+    /// is returned if no timer is running.
+    pub fn next_deadline(&self) -> Result<Option<Instant<Clock>>, Error<TcpStack::Error>> {
+        let now = self.clock.try_now()?;
+
+        let deadline = match self.connection_state.state() {
+            States::Restart => {
+                let throttle = self
+                    .min_connect_interval
+                    .zip(self.last_connect_attempt)
+                    .map(|(interval, last)| last + interval);
+                let attempt = match (throttle, self.reconnect_deadline) {
+                    (Some(throttle), Some(delay)) => throttle.max(delay),
+                    (throttle, delay) => throttle.or(delay).unwrap_or(now),
+                };
+
+                Some(attempt)
+            }
+
+            States::ConnectTransport | States::ConnectBroker => Some(now),
+
+            States::Establishing => self.phase_deadline,
+
+            States::Active => {
+                let queued = self
+                    .publish_queue
+                    .peek()
+                    .is_some_and(|message| self.can_publish(message.qos()));
+
+                let busy = queued
+                    || self.network.has_pending_write()
+                    || !self.acknowledgements.is_empty()
+                    || !self.completions.is_empty()
+                    || self.birth_pending
+                    || self.session_state.subscriptions.next_renewal().is_some();
+
+                // The first heartbeat is sent right away.
+                let heartbeat = self
+                    .heartbeat
+                    .as_ref()
+                    .map(|_| self.heartbeat_deadline.unwrap_or(now));
+
+                // Keep-alive timers are halted while reception is paused.
+                let keep_alive = if self.receive_blocked() {
+                    None
+                } else {
+                    self.session_state.next_deadline()
+                };
+
+                if busy {
+                    Some(now)
+                } else {
+                    [
+                        keep_alive,
+                        self.ack_deadline,
+                        heartbeat,
+                        self.shutdown_deadline,
+                    ]
+                    .iter()
+                    .flatten()
+                    .min()
+                    .copied()
+                }
+            }
+
+            States::Suspended => None,
+
+            States::Abandoned | States::Closed => return Ok(None),
+        };
+
+        // Queued messages expire even while the broker cannot be reached.
+        let deadline = match (deadline, self.publish_queue.next_expiry()) {
+            (Some(deadline), Some(expiry)) => Some(deadline.min(expiry)),
+            (deadline, expiry) => deadline.or(expiry),
+        };
+
+        Ok(deadline.map(|deadline| deadline.max(now)))
+    }
+
+    /// Get the duration until the client needs to be polled next.
+    ///
+    /// # Returns
+    /// The duration until `next_deadline()`, which is zero if the deadline has passed. `None` is
+    /// returned if no timer is running.
+    #[cfg(feature = "async")]
+    pub(crate) fn time_until_deadline(
+        &self,
+    ) -> Result<Option<Milliseconds<u32>>, Error<TcpStack::Error>>
+    where
+        u32: TryFrom<Clock::T>,
+    {
+        let deadline = match self.next_deadline()? {
+            Some(deadline) => deadline,
+            None => return Ok(None),
+        };
+
+        let remaining = match deadline.checked_duration_since(&self.clock.try_now()?) {
+            Some(remaining) => {
+                Milliseconds::<u32>::try_from(remaining).unwrap_or(Milliseconds(u32::MAX))
+            }
+            None => Milliseconds(0),
+        };
+
+        Ok(Some(remaining))
+    }
+
+    /// Get the count of unacknowledged messages.
+    ///
+    /// # Note
+    /// `QoS::ExactlyOnce` messages are pending until the broker completes their delivery.
+    ///
+    /// # Returns
+    /// Number of pending messages with the provided QoS.
+    pub fn pending_messages(&self, qos: QoS) -> usize {
+        self.session_state.pending_messages(qos)
+    }
+
+    /// Determine if the client is able to process publish requests.
+    ///
+    /// # Note
+    /// Messages with `QoS::AtLeastOnce` and `QoS::ExactlyOnce` share the `MSG_COUNT` slots for
+    /// messages in flight. The broker may limit the number of messages in flight further using
+    /// its Receive Maximum, see `capabilities()`.
+    ///
+    /// # Returns
+    /// True if the client is able to service requests with the provided QoS.
+    pub fn can_publish(&self, qos: QoS) -> bool {
+        // We cannot publish if there's a pending write in the network stack. That message must be
+        // completed first.
+        if self.network.has_pending_write() {
+            return false;
+        }
+
+        // The broker disconnects clients exceeding its Receive Maximum.
+        if qos != QoS::AtMostOnce
+            && self.session_state.in_flight() >= self.capabilities.receive_maximum as usize
+        {
+            return false;
+        }
+
+        self.session_state.can_publish(qos)
+    }
+
+    /// Determine the maximum size of a payload that can be published to a topic.
+    ///
+    /// # Note
+    /// The size is limited by `MSG_SIZE` and the maximum packet size of the broker. The properties
+    /// added by the client, such as timestamps, are accounted for. Properties passed to
+    /// `publish()` reduce the size further by their encoded size.
+    ///
+    /// # Args
+    /// * `topic` - The topic that the message is published to.
+    /// * `qos` - The quality-of-service level of the message.
+    ///
+    /// # Returns
+    /// The maximum payload size in bytes.
+    pub fn max_payload_size(&self, topic: &str, qos: QoS) -> usize {
+        self.payload_capacity(topic, qos, 0)
+    }
+
+    /// Check that a packet does not exceed the maximum packet size of the broker.
+    ///
+    /// # Note
+    /// The broker disconnects clients sending larger packets.
+    fn check_packet_size(&self, packet: &[u8]) -> Result<(), Error<TcpStack::Error>> {
+        match self.session_state.maximum_packet_size {
+            Some(maximum) if packet.len() > maximum as usize => Err(Error::PacketTooLarge {
+                size: packet.len(),
+                maximum: maximum as usize,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn payload_capacity(&self, topic: &str, qos: QoS, properties_size: usize) -> usize {
+        let packet_size = match self.session_state.maximum_packet_size {
+            Some(size) => MSG_SIZE.min(size as usize),
+            None => MSG_SIZE,
+        };
+
+        let mut properties_size = properties_size;
+
+        if let Some(key) = self.timestamp_property {
+            // Timestamps are 64-bit integers, which have at most 20 digits.
+            properties_size += Property::UserProperty(key, "").size() + 20;
+        }
+
+        #[cfg(feature = "compression")]
+        if self.compression_threshold.is_some() {
+            properties_size += crate::compression::COMPRESSED.size();
+        }
+
+        #[cfg(feature = "encryption")]
+        let payload_overhead = if self.cipher.is_some() {
+            properties_size += crate::encryption::ENCRYPTED.size();
+            crate::encryption::OVERHEAD
+        } else {
+            0
+        };
+
+        #[cfg(not(feature = "encryption"))]
+        let payload_overhead = 0;
+
+        serialize::max_publish_payload(packet_size, topic, qos, properties_size)
+            .saturating_sub(payload_overhead)
+    }
+
+    /// Publish a message over MQTT.
+    ///
+    /// # Note
+    /// If the client is not yet connected to the broker, the message will be silently ignored,
+    /// unless queueing while disconnected is enabled using `set_offline_queueing()`.
+    ///
+    /// # Note
+    /// Messages with `QoS::AtLeastOnce` and `QoS::ExactlyOnce` remain pending until the broker
+    /// completes their delivery, and are retransmitted after reconnecting. Messages that the
+    /// broker rejects are reported to the dead letter handler. The broker failing to complete the
+    /// delivery of a `QoS::ExactlyOnce` message is reported by `poll()` as
+    /// `Error::Unacknowledged`.
+    ///
+    /// # Note
+    /// Messages exceeding the maximum quality-of-service of the broker, or retained messages if the
+    /// broker does not support retaining, are rejected with `Error::Unsupported`. See
+    /// `capabilities()`.
+    /// Messages exceeding the maximum packet size of the broker are rejected with
+    /// `Error::PacketTooLarge`. See `max_payload_size()`.
+    ///
+    /// # Args
+    /// * `topic` - The topic to publish the message to.
+    /// * `data` - The data to transmit as the message contents.
+    /// * `qos` - The desired quality-of-service level of the message.
+    /// * `retain` - Specifies whether the message should be retained by the broker.
+    /// * `properties` - A list of properties to associate with the message being published, e.g.
+    ///   `Property::UserProperty` key-value pairs that are forwarded to subscribers. May be empty.
+    pub fn publish(
+        &mut self,
+        topic: &str,
+        data: &[u8],
+        qos: QoS,
+        retain: Retain,
+        properties: &[Property],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        // While messages queued during an outage are pending, further messages are queued as well
+        // so that they are transmitted in order.
+        if self.offline_queueing && (!self.is_connected() || self.queued_messages() > 0) {
+            return self.enqueue_publish(topic, data, qos, retain, properties, Priority::Normal);
+        }
+
+        // If we are not yet connected to the broker, we can't transmit a message.
+        if !self.is_connected() {
+            return Ok(());
+        }
+
+        self.transmit_publish(topic, data, qos, retain, properties)
+    }
+
+    fn transmit_publish(
+        &mut self,
+        topic: &str,
+        data: &[u8],
+        qos: QoS,
+        retain: Retain,
+        properties: &[Property],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        if !self.capabilities.permit_publish(qos, retain) {
+            return Err(Error::Unsupported);
+        }
+
+        if !self.can_publish(qos) {
+            return Err(Error::NotReady);
+        }
+
+        debug!(
+            "Publishing to `{}`: {:?} Props: {:?}",
+            topic, data, properties
+        );
+
+        // If QoS 0 the ID will be ignored
+        let id = self.session_state.get_packet_identifier();
+
+        let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
+        let packet =
+            self.serialize_publish(&mut buffer, topic, data, qos, retain, id, properties)?;
+
+        self.send_publish(packet, qos, id)
+    }
+
+    /// Publish the response to a received request.
+    ///
+    /// # Note
+    /// The response is published to the `Property::ResponseTopic` of the request. If the request
+    /// carries `Property::CorrelationData`, it is attached to the response so that the requester
+    /// can match the response to its request.
+    ///
+    /// # Args
+    /// * `request` - The properties of the received request, as provided to the `poll()` handler.
+    /// * `data` - The data to transmit as the response contents.
+    /// * `qos` - The desired quality-of-service level of the response.
+    ///
+    /// # Returns
+    /// True if the response was published. False if the request does not carry a response topic.
+    pub fn reply(
+        &mut self,
+        request: &[Property],
+        data: &[u8],
+        qos: QoS,
+    ) -> Result<bool, Error<TcpStack::Error>> {
+        let mut topic = None;
+        let mut correlation_data = None;
+        for property in request {
+            match property {
+                Property::ResponseTopic(response_topic) => topic = Some(*response_topic),
+                Property::CorrelationData(data) => correlation_data = Some(*data),
+                _ => {}
+            }
+        }
+
+        let topic = match topic {
+            Some(topic) => topic,
+            None => return Ok(false),
+        };
+
+        let properties = correlation_data.map(Property::CorrelationData);
+        self.publish(topic, data, qos, Retain::NotRetained, properties.as_slice())?;
+        Ok(true)
+    }
+
+    /// Start publishing a message whose payload is appended incrementally.
+    ///
+    /// # Note
+    /// The header of the packet is serialized into the transmit buffer upfront, and the payload is
+    /// appended directly behind it, so it does not need to be assembled beforehand. The message is
+    /// transmitted once `PublishBuilder::finish()` is called.
+    ///
+    /// # Note
+    /// Incrementally published payloads are not compressed. `Error::Unsupported` is returned if
+    /// payloads are encrypted.
+    ///
+    /// # Args
+    /// * `topic` - The topic to publish the message to.
+    /// * `qos` - The desired quality-of-service level of the message.
+    /// * `retain` - Specifies whether the message should be retained by the broker.
+    /// * `properties` - A list of properties to associate with the message being published. May be
+    ///   empty.
+    ///
+    /// # Returns
+    /// A builder to append the payload with.
+    pub fn start_publish<'a>(
+        &'a mut self,
+        topic: &str,
+        qos: QoS,
+        retain: Retain,
+        properties: &[Property],
+    ) -> Result<PublishBuilder<'a, TcpStack, Clock, MSG_SIZE, MSG_COUNT>, Error<TcpStack::Error>>
+    {
+        #[cfg(feature = "encryption")]
+        if self.cipher.is_some() {
+            return Err(Error::Unsupported);
+        }
+
+        if !self.is_connected() {
+            return Err(Error::NotConnected);
+        }
+
+        if !self.capabilities.permit_publish(qos, retain) {
+            return Err(Error::Unsupported);
+        }
+
+        if !self.can_publish(qos) {
+            return Err(Error::NotReady);
+        }
+
+        let properties_size: usize = properties.iter().map(|property| property.size()).sum();
+        let capacity = self.payload_capacity(topic, qos, properties_size);
+        let id = self.session_state.get_packet_identifier();
+
+        let mut timestamp: String<20> = String::new();
+        let stamp = self.timestamp(&mut timestamp)?;
+
+        // The header is serialized in front of the space reserved for the payload at the end of the
+        // transmit buffer, so that the payload can be appended behind it.
+        let buffer = self.network.write_buffer().ok_or(Error::NotReady)?;
+        let packet = serialize::publish_message_in_place(
+            buffer,
+            capacity,
+            topic,
+            qos,
+            retain,
+            id,
+            properties.iter().chain(stamp.iter()),
+        )?;
+        let start = MSG_SIZE - packet.len();
+
+        Ok(PublishBuilder {
+            client: self,
+            qos,
+            id,
+            start,
+            length: 0,
+            capacity,
+        })
+    }
+
+    /// Stamp every published message with the current time of the clock.
+    ///
+    /// # Note
+    /// The timestamp is attached as a user property containing the number of milliseconds since
+    /// the epoch of the clock as a decimal string. Messages in the publish queue are stamped at the
+    /// time that they are queued. This allows the time of measurement to be distinguished from the
+    /// time of arrival at the broker.
+    ///
+    /// # Args
+    /// * `key` - The name of the user property to store the timestamp in. If `None`, messages are
+    ///   not stamped.
+    pub fn set_timestamp_property(&mut self, key: Option<&'static str>) {
+        self.timestamp_property = key;
+    }
+
+    /// Periodically publish a heartbeat message while connected to the broker.
+    ///
+    /// # Args
+    /// * `heartbeat` - The heartbeat to publish. If `None`, no heartbeat is published.
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.heartbeat = heartbeat;
+        self.heartbeat_deadline.take();
+    }
+
+    fn send_heartbeat(&mut self, now: Instant<Clock>) -> Result<(), Error<TcpStack::Error>> {
+        if !self.is_connected() {
+            return Ok(());
+        }
+
+        let heartbeat = match self.heartbeat {
+            Some(heartbeat) => heartbeat,
+            None => return Ok(()),
+        };
+
+        if matches!(self.heartbeat_deadline, Some(deadline) if now < deadline) {
+            return Ok(());
+        }
+
+        // The heartbeat is delayed until the network stack can accept another message.
+        if !self.can_publish(QoS::AtMostOnce) {
+            return Ok(());
+        }
+
+        self.heartbeat_deadline.replace(now + heartbeat.interval);
+
+        let uptime = Seconds::<Clock::T>::try_from(now.duration_since_epoch())
+            .map_err(|_| Error::Clock(embedded_time::clock::Error::Unspecified))?;
+
+        let mut payload: String<MSG_SIZE> = String::new();
+        heartbeat
+            .write_payload(uptime.integer(), &mut payload)
+            .map_err(|_| Error::Protocol(ProtocolError::Failed))?;
+
+        debug!("Publishing heartbeat: {}", payload);
+        self.publish(
+            heartbeat.topic,
+            payload.as_bytes(),
+            QoS::AtMostOnce,
+            Retain::NotRetained,
+            &[],
+        )
+    }
+
+    /// Get the timestamp property of a published message, if configured.
+    ///
+    /// # Args
+    /// * `timestamp` - The location to store the formatted timestamp in.
+    fn timestamp<'a>(
+        &self,
+        timestamp: &'a mut String<20>,
+    ) -> Result<Option<Property<'a>>, Error<TcpStack::Error>> {
+        let key = match self.timestamp_property {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+
+        let since_epoch =
+            Milliseconds::<Clock::T>::try_from(self.clock.try_now()?.duration_since_epoch())
+                .map_err(|_| Error::Clock(embedded_time::clock::Error::Unspecified))?;
+
+        // Note(unwrap): Any 64-bit integer fits into the string.
+        write!(timestamp, "{}", since_epoch.integer()).unwrap();
+        Ok(Some(Property::UserProperty(key, timestamp)))
+    }
+
+    /// Transmit a serialized PUBLISH packet.
+    fn send_publish(
+        &mut self,
+        packet: &[u8],
+        qos: QoS,
+        id: u16,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.network.write(packet)?;
+        self.session_state.increment_packet_identifier();
+
+        if qos != QoS::AtMostOnce {
+            self.session_state.handle_publish(qos, id, packet);
+            self.update_ack_deadline(false)?;
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn serialize_publish<'a>(
+        &mut self,
+        buffer: &'a mut [u8],
+        topic: &str,
+        data: &[u8],
+        qos: QoS,
+        retain: Retain,
+        id: u16,
+        properties: &[Property],
+    ) -> Result<&'a [u8], Error<TcpStack::Error>> {
+        let mut timestamp: String<20> = String::new();
+        let stamp = self.timestamp(&mut timestamp)?;
+
+        #[cfg(feature = "compression")]
+        let mut compressed: [u8; MSG_SIZE] = [0; MSG_SIZE];
+
+        #[cfg(feature = "compression")]
+        let (data, encoding) = match self.compression_threshold {
+            Some(threshold) if data.len() > threshold => {
+                match crate::compression::compress(data, &mut compressed) {
+                    Some(compressed) => (compressed, Some(crate::compression::COMPRESSED)),
+                    None => (data, None),
+                }
+            }
+            _ => (data, None),
+        };
+
+        #[cfg(not(feature = "compression"))]
+        let encoding: Option<Property> = None;
+
+        // Note: Payloads are compressed before they are encrypted, as encrypted data cannot be
+        // compressed.
+        #[cfg(feature = "encryption")]
+        let mut encrypted: [u8; MSG_SIZE] = [0; MSG_SIZE];
+
+        #[cfg(feature = "encryption")]
+        let (data, encryption) = match self.cipher.as_mut() {
+            Some(cipher) => {
+                let encrypted = cipher.encrypt(topic, data, &mut encrypted)?;
+                (encrypted, Some(crate::encryption::ENCRYPTED))
+            }
+            None => (data, None),
+        };
+
+        #[cfg(not(feature = "encryption"))]
+        let encryption: Option<Property> = None;
+
+        let packet = serialize::publish_message(
+            buffer,
+            topic,
+            data,
+            qos,
+            retain,
+            id,
+            properties
+                .iter()
+                .chain(stamp.iter())
+                .chain(encoding.iter())
+                .chain(encryption.iter()),
+        )?;
+
+        self.check_packet_size(packet)?;
+        Ok(packet)
+    }
+
+    /// Compress the payloads of published messages.
+    ///
+    /// # Note
+    /// Payloads larger than the threshold are compressed using heatshrink and tagged with a
+    /// `content-encoding: heatshrink` user property. Received messages with this tag are
+    /// decompressed before they are passed to the `poll()` handler, regardless of this setting.
+    ///
+    /// # Args
+    /// * `threshold` - The payload size in bytes above which payloads are compressed. If `None`,
+    ///   payloads are never compressed.
+    #[cfg(feature = "compression")]
+    pub fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        self.compression_threshold = threshold;
+    }
+
+    /// Encrypt and authenticate the payloads of published and received messages.
+    ///
+    /// # Note
+    /// Once a cipher is configured, the payloads of all published messages are encrypted, and only
+    /// received messages that are successfully authenticated are passed to the `poll()` handler.
+    ///
+    /// # Args
+    /// * `cipher` - The cipher to use. If `None`, payloads are neither encrypted nor authenticated.
+    #[cfg(feature = "encryption")]
+    pub fn set_payload_cipher(&mut self, cipher: Option<crate::PayloadCipher>) {
+        self.cipher = cipher;
+    }
+
+    /// Queue a message to be published to the broker.
+    ///
+    /// # Note
+    /// Queued messages are transmitted as soon as the connection to the broker permits, in order of
+    /// their priority. Messages of equal priority are transmitted in the order that they were
+    /// queued. Up to `MSG_COUNT` messages may be queued.
+    ///
+    /// # Note
+    /// If a pool was configured using `Minimq::set_buffer_pool()`, queued messages are stored in
+    /// buffers of the pool, and `Error::NotReady` is returned if all buffers of the pool are in
+    /// use.
+    ///
+    /// # Note
+    /// If the queue is full, the most recently queued message of the lowest priority is dropped to
+    /// make room for the new message if it has a higher priority. Otherwise, `Error::NotReady` is
+    /// returned.
+    ///
+    /// # Note
+    /// If the message carries a `Property::MessageExpiryInterval`, the message is discarded if it
+    /// cannot be transmitted within the expiry interval. Otherwise, the time-to-live configured
+    /// using `set_queue_ttl()` applies.
+    ///
+    /// # Args
     /// * `topic` - The topic to publish the message to.
     /// * `data` - The data to transmit as the message contents.
-    /// * `qos` - The desired quality-of-service level of the message. Must be QoS::AtMostOnce
+    /// * `qos` - The desired quality-of-service level of the message. Must not be
+    ///   `QoS::ExactlyOnce`.
+    /// * `retain` - Specifies whether the message should be retained by the broker.
     /// * `properties` - A list of properties to associate with the message being published. May be
     ///   empty.
-    pub fn publish(
+    /// * `priority` - The transmission priority of the message.
+    pub fn enqueue_publish(
         &mut self,
         topic: &str,
         data: &[u8],
         qos: QoS,
         retain: Retain,
         properties: &[Property],
+        priority: Priority,
     ) -> Result<(), Error<TcpStack::Error>> {
-        // If we are not yet connected to the broker, we can't transmit a message.
-        if self.is_connected() == false {
+        if qos == QoS::ExactlyOnce {
+            return Err(Error::Unsupported);
+        }
+
+        if !self.capabilities.permit_publish(qos, retain) {
+            return Err(Error::Unsupported);
+        }
+
+        let now = self.clock.try_now()?;
+
+        // Stale messages are discarded first, as they may make room for the new message.
+        self.drop_expired(now);
+
+        let expiry_interval = properties.iter().find_map(|property| match property {
+            Property::MessageExpiryInterval(interval) => Some(Seconds(*interval)),
+            _ => None,
+        });
+
+        let expiry = match expiry_interval {
+            Some(interval) => Some(now + interval),
+            None => self.queue_ttl.map(|ttl| now + ttl),
+        };
+
+        // The packet identifier is assigned once the message is transmitted.
+        let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
+        let packet =
+            self.serialize_publish(&mut buffer, topic, data, qos, retain, 0, properties)?;
+
+        let buffer = PacketBuffer::new(self.buffer_pool).map_err(|_| Error::NotReady)?;
+        let dropped = self
+            .publish_queue
+            .push(priority, packet, buffer, expiry)
+            .map_err(|_| Error::NotReady)?;
+
+        if let Some(message) = dropped {
+            self.drop_message(message.packet(), DropReason::QueueFull);
+        }
+
+        self.send_queued()
+    }
+
+    /// Register a handler for messages that are dropped without being delivered.
+    ///
+    /// # Note
+    /// The handler is called with the topic of the message and the reason that it was dropped. This
+    /// allows the application to count, persist, or escalate lost messages.
+    ///
+    /// # Args
+    /// * `handler` - The function to call for each dropped message. If `None`, dropped messages are
+    ///   only logged.
+    pub fn set_dead_letter_handler(&mut self, handler: Option<fn(&str, DropReason)>) {
+        self.dead_letter_handler = handler;
+    }
+
+    /// Select the network interface used to reach an IPv6 link-local broker.
+    ///
+    /// # Note
+    /// Link-local addresses (i.e. `fe80::/10`) are only unique on a single link, so the interface
+    /// must be identified by its scope identifier when connecting, e.g. in Thread border router
+    /// deployments. The scope identifier is ignored for IPv4 brokers. It is applied the next time
+    /// the client connects to the broker.
+    ///
+    /// # Args
+    /// * `scope_id` - The scope identifier of the interface, as defined by the network stack. If
+    ///   `None`, the network stack selects the interface.
+    pub fn set_broker_scope_id(&mut self, scope_id: Option<u32>) {
+        self.session_state.broker_scope_id = scope_id;
+    }
+
+    /// Select the TCP port of the broker.
+    ///
+    /// # Note
+    /// By default, the broker is reached on the standard MQTT port 1883. The port is applied the
+    /// next time the client connects to the broker.
+    ///
+    /// # Args
+    /// * `port` - The TCP port that the broker listens on.
+    pub fn set_broker_port(&mut self, port: u16) {
+        self.session_state.broker_port = port;
+    }
+
+    /// Get the socket address of the broker that the client connects to.
+    pub fn broker_address(&self) -> SocketAddr {
+        self.session_state.broker_address()
+    }
+
+    /// Configure a list of brokers to fail over between.
+    ///
+    /// # Note
+    /// The client connects to the first broker of the list. Once `attempts` consecutive connection
+    /// attempts failed, it moves on to the next broker, starting over at the end of the list. The
+    /// broker that the client last connected to is kept, so the client reconnects to it first after
+    /// the connection is lost. The brokers replace the address provided to `Minimq::new()` and any
+    /// hostname configured using `set_broker_hostname()`.
+    ///
+    /// # Args
+    /// * `brokers` - The socket addresses of the brokers in order of preference. If empty, the
+    ///   client keeps connecting to the current broker.
+    /// * `attempts` - The number of failed connection attempts before moving on to the next
+    ///   broker. Zero is treated as one.
+    pub fn set_broker_failover(&mut self, brokers: &'static [SocketAddr], attempts: u32) {
+        self.failover = None;
+
+        if let Some(primary) = brokers.first() {
+            self.session_state.set_broker_address(*primary);
+            self.broker_hostname.take();
+            self.failover.replace((brokers, attempts.max(1), 0));
+        }
+    }
+
+    /// Move on to the next broker of the failover list after repeated connection failures.
+    fn select_broker(&mut self) {
+        let (brokers, attempts, index) = match self.failover.as_mut() {
+            Some((brokers, attempts, index)) => (*brokers, *attempts, index),
+            None => return,
+        };
+
+        let fail_over = self.reconnect_attempts > 0 && self.reconnect_attempts % attempts == 0;
+
+        if fail_over {
+            *index = (*index + 1) % brokers.len();
+            info!("Failing over to broker {}", *index);
+            self.session_state.set_broker_address(brokers[*index]);
+        }
+    }
+
+    /// Configure whether the client follows redirections to another broker.
+    ///
+    /// # Note
+    /// The broker may redirect the client using the reason codes 0x9C (Use another server) and
+    /// 0x9D (Server moved) when refusing the connection or disconnecting the client. If enabled,
+    /// the client reconnects to the first IP address of the Server Reference, which replaces the
+    /// configured broker. `Event::Redirected` is emitted in either case.
+    ///
+    /// # Args
+    /// * `follow` - Specifies whether redirections are followed. Disabled by default.
+    pub fn set_follow_redirects(&mut self, follow: bool) {
+        self.follow_redirects = follow;
+    }
+
+    /// Configure the acknowledgement of messages that a fallible handler failed to process.
+    ///
+    /// # Note
+    /// By default, messages are acknowledged even if the handler passed to `Minimq::try_poll()`
+    /// fails. If redelivery is enabled, the acknowledgement is withheld instead, so that the broker
+    /// redelivers the message upon reconnection. Withheld acknowledgements count towards the
+    /// messages the broker may have in flight, so the connection should be reestablished if too
+    /// many messages fail.
+    ///
+    /// # Args
+    /// * `redeliver` - Specifies whether failed messages are left unacknowledged.
+    pub fn set_redeliver_failed_messages(&mut self, redeliver: bool) {
+        self.redeliver_failed = redeliver;
+    }
+
+    /// Register a handler for protocol events.
+    ///
+    /// # Note
+    /// The handler is called from within `poll()` for every protocol event that does not carry an
+    /// application message, such as the acknowledgement of a subscription. This allows the
+    /// application to observe the session without parsing logs.
+    ///
+    /// # Args
+    /// * `handler` - The function to call for each event. If `None`, events are not reported.
+    pub fn set_event_handler(&mut self, handler: Option<fn(Event<'_>)>) {
+        self.event_handler = handler;
+    }
+
+    fn emit(&self, event: Event<'_>) {
+        debug!("Event: {:?}", event);
+
+        if let Some(handler) = self.event_handler {
+            handler(event);
+        }
+    }
+
+    fn drop_message(&self, packet: &[u8], reason: DropReason) {
+        let topic = publish_topic(packet);
+        warn!("Dropping message to `{}`: {:?}", topic, reason);
+
+        if let Some(handler) = self.dead_letter_handler {
+            handler(topic, reason);
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    fn drop_received(&self, topic: &str, reason: DropReason) {
+        warn!("Dropping message from `{}`: {:?}", topic, reason);
+
+        if let Some(handler) = self.dead_letter_handler {
+            handler(topic, reason);
+        }
+    }
+
+    /// Get the number of messages waiting in the publish queue.
+    pub fn queued_messages(&self) -> usize {
+        self.publish_queue.len()
+    }
+
+    /// Configure whether messages published while disconnected are queued.
+    ///
+    /// # Note
+    /// If enabled, `publish()` places messages in the publish queue while the client is not
+    /// connected to the broker, instead of ignoring them. Once the connection is established, the
+    /// queued messages are transmitted in the order that they were published. Until the queue is
+    /// empty, newly published messages are queued behind them. See `enqueue_publish()` for the
+    /// requirements of queued messages.
+    ///
+    /// # Args
+    /// * `enabled` - Specifies whether messages are queued while disconnected.
+    pub fn set_offline_queueing(&mut self, enabled: bool) {
+        self.offline_queueing = enabled;
+    }
+
+    /// Configure the time-to-live of messages in the publish queue.
+    ///
+    /// # Note
+    /// Queued messages that cannot be transmitted within their time-to-live are discarded and
+    /// reported to the dead letter handler with `DropReason::Expired`. This applies to messages
+    /// that do not carry a `Property::MessageExpiryInterval`.
+    ///
+    /// # Args
+    /// * `ttl` - The time-to-live of queued messages. If `None`, queued messages never expire.
+    pub fn set_queue_ttl(&mut self, ttl: Option<Milliseconds<u32>>) {
+        self.queue_ttl = ttl;
+    }
+
+    fn drop_expired(&mut self, now: Instant<Clock>) {
+        while let Some(message) = self.publish_queue.pop_expired(now) {
+            self.drop_message(message.packet(), DropReason::Expired);
+        }
+    }
+
+    fn send_queued(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        // Messages expire even while the broker cannot be reached.
+        self.drop_expired(self.clock.try_now()?);
+
+        if !self.is_connected() {
             return Ok(());
         }
 
-        if !self.can_publish(qos) {
-            return Err(Error::NotReady);
+        while let Some(message) = self.publish_queue.peek() {
+            // Messages are strictly sent in order of priority, so transmission stops until the
+            // next message can be sent.
+            if !self.can_publish(message.qos()) {
+                break;
+            }
+
+            // Note(unwrap): A message was available above.
+            let mut message = self.publish_queue.pop().unwrap();
+
+            let id = self.session_state.get_packet_identifier();
+            message.set_packet_id(id);
+
+            debug!("Publishing queued message to `{}`", message.topic());
+            self.network.write(message.packet())?;
+            self.session_state.increment_packet_identifier();
+
+            if message.qos() == QoS::AtLeastOnce {
+                self.session_state
+                    .handle_publish(message.qos(), id, message.packet());
+                self.update_ack_deadline(false)?;
+            }
         }
 
-        debug!(
-            "Publishing to `{}`: {:?} Props: {:?}",
-            topic, data, properties
-        );
+        Ok(())
+    }
 
-        // If QoS 0 the ID will be ignored
-        let id = self.session_state.get_packet_identifier();
+    /// Defer the acknowledgement of the message currently being handled.
+    ///
+    /// # Note
+    /// This may only be called from within the `poll()` handler. By default, received messages are
+    /// acknowledged as soon as the handler returns. If the acknowledgement is deferred, the message
+    /// must later be acknowledged using `ack()`. Until then, the broker will redeliver the message
+    /// upon reconnection.
+    ///
+    /// # Returns
+    /// A token to later acknowledge the message with. If the message does not require an
+    /// acknowledgement (i.e. it was received with `QoS::AtMostOnce`), `None` is returned.
+    pub fn defer_ack(&mut self) -> Option<AckToken> {
+        self.pending_ack.take()
+    }
 
-        let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
-        let packet =
-            serialize::publish_message(&mut buffer, topic, data, qos, retain, id, properties)?;
+    /// Get the flags of the message that is currently being handled.
+    ///
+    /// # Note
+    /// This may only be called from within the `poll()` handler.
+    ///
+    /// # Returns
+    /// The flags of the message, or `None` if no message is being handled.
+    pub fn message_flags(&self) -> Option<MessageFlags> {
+        self.message_flags
+    }
 
-        self.network.write(packet)?;
-        self.session_state.increment_packet_identifier();
+    /// Acknowledge a received message that had its acknowledgement deferred.
+    ///
+    /// # Args
+    /// * `token` - The token obtained from `defer_ack()` when the message was handled.
+    pub fn ack(&mut self, token: AckToken) -> Result<(), Error<TcpStack::Error>> {
+        if !self.is_connected() {
+            return Err(Error::NotConnected);
+        }
 
-        if qos == QoS::AtLeastOnce {
-            self.session_state.handle_publish(qos, id, packet);
+        self.acknowledgements
+            .push_back(token)
+            .map_err(|_| Error::NotReady)?;
+
+        self.send_acknowledgements()
+    }
+
+    fn send_acknowledgements(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        if !self.is_connected() {
+            return Ok(());
+        }
+
+        while let Some(token) = self.acknowledgements.front() {
+            // If the network stack cannot send another message, the acknowledgement will be sent
+            // once the pending write completes.
+            if self.network.has_pending_write() {
+                break;
+            }
+
+            let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
+            let packet = match token.qos {
+                QoS::ExactlyOnce => {
+                    // Messages that exceeded the receive quota were not registered as received.
+                    let reason_code = if self.session_state.received.contains(&token.packet_id) {
+                        0
+                    } else {
+                        0x97
+                    };
+
+                    serialize::pubrec_message(&mut buffer, token.packet_id, reason_code)?
+                }
+                _ => serialize::puback_message(&mut buffer, token.packet_id, 0)?,
+            };
+            self.network.write(packet)?;
+            self.acknowledgements.pop_front();
+        }
+
+        Ok(())
+    }
+
+    fn send_completions(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        if !self.is_connected() {
+            return Ok(());
+        }
+
+        while let Some((packet_id, reason_code)) = self.completions.front() {
+            // If the network stack cannot send another message, the completion will be sent once
+            // the pending write completes.
+            if self.network.has_pending_write() {
+                break;
+            }
+
+            let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
+            let packet = serialize::pubcomp_message(&mut buffer, *packet_id, *reason_code)?;
+            self.network.write(packet)?;
+            self.completions.pop_front();
+        }
+
+        Ok(())
+    }
+
+    fn send_releases(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        if !self.is_connected() {
+            return Ok(());
+        }
+
+        for index in 0..self.session_state.pending_release.len() {
+            let (id, released) = self.session_state.pending_release[index];
+            if released {
+                continue;
+            }
+
+            // If the network stack cannot send another message, the release will be sent once the
+            // pending write completes.
+            if self.network.has_pending_write() {
+                break;
+            }
+
+            let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
+            let packet = serialize::pubrel_message(&mut buffer, id)?;
+            self.network.write(packet)?;
+            self.session_state.pending_release[index].1 = true;
         }
 
         Ok(())
     }
 
+    /// Discard the session state, e.g. because the broker no longer holds the session.
+    fn discard_session(&mut self) {
+        // Any messages that were not acknowledged by the broker are lost.
+        for id in self.session_state.pending_publish_ordering.iter() {
+            let packet = self.session_state.pending_publish.get(id).unwrap();
+            self.drop_message(packet, DropReason::SessionReset);
+        }
+
+        self.session_state.reset();
+        self.acknowledgements.clear();
+        self.completions.clear();
+    }
+
+    fn handle_auth(&mut self, auth: Auth) -> Result<(), Error<TcpStack::Error>> {
+        if self.connection_state.state() != &States::Establishing {
+            return Err(Error::Protocol(ProtocolError::UnexpectedPacket));
+        }
+
+        // The broker may only continue an authentication that was requested by the client.
+        let (method, handler) = match self.authentication {
+            Some(authentication) if auth.reason_code == 0x18 => authentication,
+            _ => return Err(Error::Protocol(ProtocolError::UnexpectedPacket)),
+        };
+
+        let mut challenge: &[u8] = &[];
+        for property in auth.properties.iter() {
+            match property {
+                Property::AuthenticationMethod(name) if *name != method => {
+                    return Err(Error::Protocol(ProtocolError::Invalid));
+                }
+                Property::AuthenticationData(data) => challenge = data,
+                _ => {}
+            }
+        }
+
+        let mut data: [u8; MSG_SIZE] = [0; MSG_SIZE];
+        let length = match handler(challenge, &mut data) {
+            Some(length) => length,
+            None => {
+                warn!("Aborting authentication with the broker");
+                self.reset();
+                return Err(Error::AuthenticationFailed);
+            }
+        };
+
+        let properties = [
+            Property::AuthenticationMethod(method),
+            Property::AuthenticationData(&data[..length]),
+        ];
+
+        let mut buffer: [u8; MSG_SIZE] = [0; MSG_SIZE];
+        let packet = serialize::auth_message(&mut buffer, 0x18, &properties)?;
+
+        // If the response cannot be sent, the broker does not complete the connection and the
+        // connection attempt times out.
+        self.network.finish_write()?;
+        if self.network.has_pending_write() {
+            return Err(Error::NotReady);
+        }
+
+        self.network.write(packet)
+    }
+
+    /// Keep the Reason String of a failure reported by the broker.
+    ///
+    /// # Args
+    /// * `properties` - The properties of the packet reporting the failure.
+    fn record_failure(&mut self, properties: &[Property]) {
+        let reason = properties.iter().find_map(|property| match property {
+            Property::ReasonString(reason) => Some(*reason),
+            _ => None,
+        });
+
+        self.last_error_detail = reason.map(|reason| {
+            // Reason strings that do not fit are truncated at a character boundary.
+            let mut detail = String::new();
+            for character in reason.chars() {
+                if detail.push(character).is_err() {
+                    break;
+                }
+            }
+
+            detail
+        });
+    }
+
+    /// Handle a redirection to another broker.
+    ///
+    /// # Args
+    /// * `reason_code` - The reason code of the CONNACK or DISCONNECT.
+    /// * `properties` - The properties of the CONNACK or DISCONNECT.
+    ///
+    /// # Returns
+    /// True if the client connects to the referenced broker from now on.
+    fn handle_redirect(&mut self, reason_code: u8, properties: &[Property]) -> bool {
+        if reason_code != 0x9C && reason_code != 0x9D {
+            return false;
+        }
+
+        let reference = match properties.iter().find_map(|property| match property {
+            Property::ServerReference(reference) => Some(*reference),
+            _ => None,
+        }) {
+            Some(reference) => reference,
+            None => return false,
+        };
+
+        let address = session_state::parse_server_reference(reference);
+        self.emit(Event::Redirected { reference, address });
+
+        match address.filter(|_| self.follow_redirects) {
+            Some(address) => {
+                info!("Redirected to {:?}", address);
+                self.broker_hostname.take();
+                self.session_state.set_broker_address(address);
+                true
+            }
+            None => false,
+        }
+    }
+
     fn handle_connection_acknowledge(
         &mut self,
         acknowledge: ConnAck,
     ) -> Result<(), Error<TcpStack::Error>> {
         if self.connection_state.state() != &States::Establishing {
-            return Err(Error::Protocol(ProtocolError::Invalid));
+            return Err(Error::Protocol(ProtocolError::UnexpectedPacket));
         }
 
         let mut result = Ok(());
 
         if acknowledge.reason_code != 0 {
-            return Err(Error::Failed(acknowledge.reason_code));
+            self.record_failure(&acknowledge.properties);
+
+            if self.handle_redirect(acknowledge.reason_code, &acknowledge.properties) {
+                self.reset();
+                return Ok(());
+            }
+
+            // The broker closes the connection after refusing it, so start the next attempt.
+            self.reset();
+            return Err(Error::ConnectionRefused {
+                reason_code: acknowledge.reason_code,
+            });
+        }
+
+        // The broker may provide final authentication data, e.g. to prove its identity.
+        if let Some((_, handler)) = self.authentication {
+            let data = acknowledge
+                .properties
+                .iter()
+                .find_map(|property| match property {
+                    Property::AuthenticationData(data) => Some(*data),
+                    _ => None,
+                });
+
+            if let Some(data) = data {
+                if handler(data, &mut []).is_none() {
+                    warn!("Aborting authentication with the broker");
+                    self.reset();
+                    return Err(Error::AuthenticationFailed);
+                }
+            }
         }
 
         if !acknowledge.session_present {
             if self.session_state.is_present() {
-                result = Err(Error::SessionReset);
+                let reason = if self.clean_start {
+                    SessionResetReason::CleanStart
+                } else {
+                    SessionResetReason::Expired
+                };
+
+                result = Err(Error::SessionReset { reason });
+            }
+
+            // Subscriptions lost with the session are requested again once connected.
+            if self.resubscribe {
+                self.session_state.subscriptions.renew(true);
             }
 
             // Reset the session state upon connection with a broker that doesn't have a
             // session state saved for us.
-            self.session_state.reset();
+            self.discard_session();
+        } else {
+            // The session is resumed, but requests that were not acknowledged on the previous
+            // connection are never acknowledged.
+            if self.resubscribe {
+                self.session_state.subscriptions.renew(false);
+            }
+            self.session_state.subscriptions.abandon_requests();
         }
 
         self.connection_state
             .process_event(Events::ReceivedConnAck)
             .unwrap();
 
+        // Topic aliases are only valid for a single connection.
+        self.topic_aliases.clear();
+        self.phase_deadline.take();
+        self.reconnect_attempts = 0;
+        self.outage_start.take();
+
+        // The first heartbeat is published as soon as the connection is established.
+        self.heartbeat_deadline.take();
+
+        // The broker may have published the offline status while the client was disconnected.
+        self.birth_pending = self.availability.is_some();
+
+        // The capabilities of the broker may change with every connection.
+        self.capabilities = Capabilities::default();
+        self.session_state.maximum_packet_size.take();
+        self.response_information.take();
+
+        // The broker may override the requested keep-alive interval for this connection.
+        let mut keep_alive = self.session_state.requested_keepalive();
+
         for property in acknowledge.properties {
             match property {
+                Property::MaximumQoS(qos) => {
+                    self.capabilities.maximum_qos = match qos {
+                        0 => QoS::AtMostOnce,
+                        _ => QoS::AtLeastOnce,
+                    };
+                }
+                Property::RetainAvailable(available) => {
+                    self.capabilities.retain_available = available != 0;
+                }
+                Property::WildcardSubscriptionAvailable(available) => {
+                    self.capabilities.wildcard_subscriptions = available != 0;
+                }
+                Property::SubscriptionIdentifierAvailable(available) => {
+                    self.capabilities.subscription_identifiers = available != 0;
+                }
+                Property::SharedSubscriptionAvailable(available) => {
+                    self.capabilities.shared_subscriptions = available != 0;
+                }
+                Property::ReceiveMaximum(maximum) => {
+                    self.capabilities.receive_maximum = maximum;
+                }
                 Property::MaximumPacketSize(size) => {
                     self.session_state.maximum_packet_size.replace(size);
+                    self.capabilities.maximum_packet_size.replace(size);
                 }
                 Property::AssignedClientIdentifier(id) => {
                     self.session_state.client_id =
                         String::from_str(id).or(Err(Error::ProvidedClientIdTooLong))?;
                 }
-                Property::ServerKeepAlive(keep_alive) => {
-                    self.session_state.set_keepalive(keep_alive);
+                Property::TopicAliasMaximum(maximum) => {
+                    self.capabilities.topic_alias_maximum = maximum;
+                }
+                Property::ServerKeepAlive(interval) => {
+                    self.capabilities.server_keep_alive.replace(interval);
+                    keep_alive = interval;
+                }
+                Property::ResponseInformation(information) => {
+                    self.response_information = String::from_str(information).ok();
+                    if self.response_information.is_none() {
+                        warn!("Ignoring Response Information: {}", information);
+                    }
+                }
+                _prop => info!("Ignoring property: {:?}", _prop),
+            };
+        }
+
+        self.session_state.set_server_keepalive(keep_alive);
+
+        // Now that we are connected, we have session state that will be persisted.
+        self.session_state
+            .register_connection(self.clock.try_now()?);
+
+        // Messages that were received by the broker are released again on the new connection.
+        for (_, released) in self.session_state.pending_release.iter_mut() {
+            *released = false;
+        }
+
+        self.connected = true;
+        self.emit(Event::Connected {
+            session_present: acknowledge.session_present,
+        });
+
+        // Replay QoS 1 and QoS 2 messages
+        for key in self.session_state.pending_publish_ordering.iter() {
+            // If the network stack cannot send another message, do not attempt to send one.
+            if self.network.has_pending_write() {
+                break;
+            }
+
+            let message = self.session_state.pending_publish.get(key).unwrap();
+            self.network.write(message)?;
+            self.emit(Event::Retransmitted { packet_id: *key });
+        }
+
+        // The retransmitted messages are acknowledged within the timeout of the new connection.
+        self.update_ack_deadline(true)?;
+
+        result
+    }
+
+    fn handle_packet<F, R>(
+        &mut self,
+        packet: ReceivedPacket<'_>,
+        f: &mut F,
+    ) -> Result<Option<R>, Error<TcpStack::Error>>
+    where
+        for<'a> F: FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+            &'a str,
+            &[u8],
+            &[Property<'a>],
+        ) -> R,
+    {
+        // ConnAck and Auth packets are received outside of the connection state.
+        if let ReceivedPacket::ConnAck(ack) = packet {
+            return self.handle_connection_acknowledge(ack).map(|_| None);
+        }
+
+        if let ReceivedPacket::Auth(auth) = packet {
+            return self.handle_auth(auth).map(|_| None);
+        }
+
+        // All other packets must be received in the active state.
+        if !self.is_connected() {
+            error!(
+                "Received invalid packet outside of connected state: {:?}",
+                packet
+            );
+            return Err(Error::Protocol(ProtocolError::UnexpectedPacket));
+        }
+
+        match packet {
+            ReceivedPacket::Publish(info) => {
+                // The broker may replace the topic by an alias that was established before.
+                let alias = info.properties.iter().find_map(|property| match property {
+                    Property::TopicAlias(alias) => Some(*alias),
+                    _ => None,
+                });
+                let resolved = match alias {
+                    Some(alias) => Some(self.topic_aliases.resolve(alias, info.topic)?),
+                    None => None,
+                };
+                let topic = resolved.as_deref().unwrap_or(info.topic);
+
+                let token = info.packet_id.map(|packet_id| AckToken {
+                    packet_id,
+                    qos: info.qos,
+                });
+
+                if let Some(token) = token.filter(|token| token.qos == QoS::ExactlyOnce) {
+                    // A QoS 2 message that was already received is redelivered if the broker did
+                    // not receive the acknowledgement. It is acknowledged again without being
+                    // handled.
+                    if self.session_state.received.contains(&token.packet_id) {
+                        self.ack(token)?;
+                        return Ok(None);
+                    }
+
+                    // The message is rejected without being handled if no more messages can be
+                    // received until the broker releases one, as the broker would deliver it again
+                    // otherwise.
+                    if !self.session_state.handle_received(token.packet_id) {
+                        warn!("Rejecting message {}: Quota exceeded", token.packet_id);
+                        self.ack(token)?;
+                        return Ok(None);
+                    }
+                }
+
+                if self
+                    .session_state
+                    .subscriptions
+                    .exceeds_payload_limit(topic, info.payload.len())
+                {
+                    self.skip_message(topic, info.payload.len(), info.packet_id, info.qos)?;
+                    return Ok(None);
+                }
+
+                self.pending_ack = token;
+
+                #[cfg(any(feature = "compression", feature = "encryption"))]
+                let mut info = info;
+
+                #[cfg(feature = "encryption")]
+                let mut decrypted: [u8; MSG_SIZE] = [0; MSG_SIZE];
+
+                #[cfg(feature = "encryption")]
+                let payload = match self.cipher.as_ref() {
+                    Some(cipher) => match cipher.decrypt(
+                        topic,
+                        info.payload,
+                        &mut info.properties,
+                        &mut decrypted,
+                    ) {
+                        Some(payload) => payload,
+                        None => {
+                            // The message is dropped, but still acknowledged so that the broker
+                            // does not redeliver it.
+                            self.drop_received(topic, DropReason::DecryptionFailed);
+                            if let Some(token) = self.pending_ack.take() {
+                                self.ack(token)?;
+                            }
+
+                            return Ok(None);
+                        }
+                    },
+                    None => info.payload,
+                };
+
+                #[cfg(not(feature = "encryption"))]
+                let payload = info.payload;
+
+                #[cfg(feature = "compression")]
+                let mut decompressed: [u8; MSG_SIZE] = [0; MSG_SIZE];
+
+                #[cfg(feature = "compression")]
+                let payload = crate::compression::decompress(
+                    payload,
+                    &mut info.properties,
+                    &mut decompressed,
+                );
+
+                // Call a handler function to deal with the received data.
+                self.message_flags = Some(MessageFlags {
+                    qos: info.qos,
+                    retain: info.retain,
+                    duplicate: info.duplicate,
+                });
+                let result = f(self, topic, payload, &info.properties);
+                self.message_flags = None;
+
+                // If the handler did not defer the acknowledgement, acknowledge the message now.
+                if let Some(token) = self.pending_ack.take() {
+                    self.ack(token)?;
+                }
+
+                Ok(Some(result))
+            }
+
+            ReceivedPacket::PubAck(ack) => {
+                // No matter the status code the message is considered acknowledged at this point
+                let message = self.session_state.handle_puback(ack.packet_identifier);
+                self.update_ack_deadline(true)?;
+
+                if let Some(packet) = &message {
+                    self.emit(Event::Acknowledged {
+                        packet_id: ack.packet_identifier,
+                        topic: publish_topic(packet),
+                        reason_code: ack.reason,
+                    });
+                }
+
+                // Reason codes of 0x80 and above indicate that the broker rejected the message.
+                if ack.reason >= 0x80 {
+                    self.record_failure(&ack.properties);
+                    if let Some(packet) = message {
+                        self.drop_message(&packet, DropReason::Rejected(ack.reason));
+                    }
+                }
+
+                Ok(None)
+            }
+
+            ReceivedPacket::PubRec(rec) => {
+                let message = self
+                    .session_state
+                    .handle_pubrec(rec.packet_identifier, rec.reason);
+                self.update_ack_deadline(true)?;
+
+                if let Some(packet) = &message {
+                    self.emit(Event::Acknowledged {
+                        packet_id: rec.packet_identifier,
+                        topic: publish_topic(packet),
+                        reason_code: rec.reason,
+                    });
+                }
+
+                if rec.reason >= 0x80 {
+                    self.record_failure(&rec.properties);
+                    if let Some(packet) = message {
+                        self.drop_message(&packet, DropReason::Rejected(rec.reason));
+                    }
+                }
+
+                self.send_releases()?;
+                Ok(None)
+            }
+
+            ReceivedPacket::PubComp(comp) => {
+                if !self.session_state.handle_pubcomp(comp.packet_identifier) {
+                    warn!("Got unexpected pubcomp: {:?}", comp);
+                }
+                self.update_ack_deadline(true)?;
+
+                // The broker may fail to complete the exchange, e.g. if it lost the message.
+                if comp.reason >= 0x80 {
+                    return Err(Error::Unacknowledged {
+                        reason_code: comp.reason,
+                    });
                 }
-                _prop => info!("Ignoring property: {:?}", _prop),
-            };
-        }
 
-        // Now that we are connected, we have session state that will be persisted.
-        self.session_state
-            .register_connection(self.clock.try_now()?);
+                Ok(None)
+            }
 
-        // Replay QoS 1 messages
-        for key in self.session_state.pending_publish_ordering.iter() {
-            // If the network stack cannot send another message, do not attempt to send one.
-            if self.network.has_pending_write() {
-                break;
+            ReceivedPacket::PubRel(rel) => {
+                // The exchange is completed even if the message is unknown, e.g. because the
+                // completion was lost before reconnecting.
+                let reason_code = if self.session_state.handle_pubrel(rel.packet_identifier) {
+                    0
+                } else {
+                    warn!("Got unexpected pubrel: {:?}", rel);
+                    0x92
+                };
+
+                // If the completion cannot be queued, the broker releases the message again
+                // after reconnecting.
+                if self
+                    .completions
+                    .push_back((rel.packet_identifier, reason_code))
+                    .is_err()
+                {
+                    warn!("Dropping completion of message {}", rel.packet_identifier);
+                }
+
+                self.send_completions()?;
+                Ok(None)
             }
 
-            let message = self.session_state.pending_publish.get(&key).unwrap();
-            self.network.write(message)?;
-        }
+            ReceivedPacket::SubAck(subscribe_acknowledge) => {
+                let packet_id = subscribe_acknowledge.packet_identifier;
+                let subscriptions = &self.session_state.subscriptions;
 
-        result
-    }
+                if subscriptions.requested(packet_id).count()
+                    != subscribe_acknowledge.reason_codes.len()
+                {
+                    error!("Got bad suback: {:?}", subscribe_acknowledge);
+                    return Err(Error::Protocol(ProtocolError::UnknownPacketId));
+                }
 
-    fn handle_packet<'a, F>(
-        &mut self,
-        packet: ReceivedPacket<'a>,
-        f: &mut F,
-    ) -> Result<(), Error<TcpStack::Error>>
-    where
-        F: FnMut(
-            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
-            &'a str,
-            &[u8],
-            &[Property<'a>],
-        ),
-    {
-        // ConnAck packets are received outside of the connection state.
-        if let ReceivedPacket::ConnAck(ack) = packet {
-            return self.handle_connection_acknowledge(ack);
-        }
+                for (filter, reason_code) in subscriptions
+                    .requested(packet_id)
+                    .zip(subscribe_acknowledge.reason_codes)
+                {
+                    if *reason_code >= 0x80 {
+                        warn!(
+                            "Subscription to `{}` rejected: {:#04x}",
+                            filter, reason_code
+                        );
+                    }
 
-        // All other packets must be received in the active state.
-        if !self.is_connected() {
-            error!(
-                "Received invalid packet outside of connected state: {:?}",
-                packet
-            );
-            return Err(Error::Protocol(ProtocolError::Invalid));
-        }
+                    self.emit(Event::Subscribed {
+                        packet_id,
+                        filter,
+                        reason_code: *reason_code,
+                    });
+                }
 
-        match packet {
-            ReceivedPacket::Publish(info) => {
-                // Call a handler function to deal with the received data.
-                f(self, info.topic, info.payload, &info.properties);
+                // Note(unwrap): The number of reason codes was checked above.
+                self.session_state
+                    .subscriptions
+                    .acknowledge(packet_id, subscribe_acknowledge.reason_codes)
+                    .unwrap();
+
+                // Reason codes below 0x80 indicate the granted quality-of-service.
+                if let Some(reason_code) = subscribe_acknowledge
+                    .reason_codes
+                    .iter()
+                    .find(|reason_code| **reason_code >= 0x80)
+                {
+                    self.record_failure(&subscribe_acknowledge.properties);
+                    return Err(Error::Unacknowledged {
+                        reason_code: *reason_code,
+                    });
+                }
 
-                Ok(())
+                Ok(None)
             }
 
-            ReceivedPacket::PubAck(ack) => {
-                // No matter the status code the message is considered acknowledged at this point
-                self.session_state.handle_puback(ack.packet_identifier);
+            ReceivedPacket::UnsubAck(unsubscribe_acknowledge) => {
+                if self
+                    .session_state
+                    .subscriptions
+                    .acknowledge_removal(
+                        unsubscribe_acknowledge.packet_identifier,
+                        unsubscribe_acknowledge.reason_code,
+                    )
+                    .is_err()
+                {
+                    error!("Got bad unsuback: {:?}", unsubscribe_acknowledge);
+                    return Err(Error::Protocol(ProtocolError::UnknownPacketId));
+                }
+
+                self.emit(Event::Unsubscribed {
+                    packet_id: unsubscribe_acknowledge.packet_identifier,
+                    reason_code: unsubscribe_acknowledge.reason_code,
+                });
+
+                if unsubscribe_acknowledge.reason_code >= 0x80 {
+                    self.record_failure(&unsubscribe_acknowledge.properties);
+                    return Err(Error::Unacknowledged {
+                        reason_code: unsubscribe_acknowledge.reason_code,
+                    });
+                }
 
-                Ok(())
+                Ok(None)
             }
 
-            ReceivedPacket::SubAck(subscribe_acknowledge) => {
-                match self
-                    .session_state
-                    .pending_subscriptions
+            ReceivedPacket::Disconnect(disconnect) => {
+                let reason = disconnect
+                    .properties
                     .iter()
-                    .position(|v| *v == subscribe_acknowledge.packet_identifier)
-                {
-                    None => {
-                        error!("Got bad suback: {:?}", subscribe_acknowledge);
-                        return Err(Error::Protocol(ProtocolError::Invalid));
-                    }
-                    Some(index) => self.session_state.pending_subscriptions.swap_remove(index),
-                };
+                    .find_map(|property| match property {
+                        Property::ReasonString(reason) => Some(*reason),
+                        _ => None,
+                    });
+
+                warn!(
+                    "Broker disconnected with reason {:#04x}: {:?}",
+                    disconnect.reason_code, reason
+                );
+                self.emit(Event::Disconnected {
+                    reason_code: disconnect.reason_code,
+                    reason,
+                });
+
+                if disconnect.reason_code >= 0x80 {
+                    self.record_failure(&disconnect.properties);
+                }
+
+                self.handle_redirect(disconnect.reason_code, &disconnect.properties);
+
+                // The connection is reestablished with the next poll.
+                self.reset();
 
-                if subscribe_acknowledge.reason_code != 0 {
-                    return Err(Error::Failed(subscribe_acknowledge.reason_code));
+                // Reconnecting would take the session over from the other client in turn, so the
+                // client stops connecting instead.
+                if disconnect.reason_code == SESSION_TAKEN_OVER {
+                    warn!("Session taken over by another client");
+                    self.network.close_socket()?;
+                    self.connection_state.process_event(Events::GiveUp).unwrap();
+                    self.session_taken_over = true;
+                    return Err(Error::SessionTakenOver);
                 }
 
-                Ok(())
+                Ok(None)
             }
 
             ReceivedPacket::PingResp => {
                 // Cancel the ping response timeout.
                 self.session_state.register_ping_response();
-                Ok(())
+                self.emit(Event::PingResponse);
+                Ok(None)
             }
 
             _ => Err(Error::Unsupported),
@@ -459,12 +3538,32 @@ where
             return Ok(());
         }
 
+        // While reception is paused, ping responses cannot be received.
+        if self.receive_blocked() {
+            return Ok(());
+        }
+
+        let now = self.clock.try_now()?;
+
+        // Many network stacks do not notice when the broker silently vanished, e.g. because
+        // writes simply stall. The connection is reset once the broker was silent for too long.
+        if self.session_state.reception_expired(now) {
+            warn!("No data received from the broker within the keep-alive interval, reconnecting");
+            self.connection_state.process_event(Events::Disconnect).ok();
+            return Ok(());
+        }
+
         // If there's a pending write, we can't send a ping no matter if it is due.
         if self.network.has_pending_write() {
             return Ok(());
         }
 
-        let now = self.clock.try_now()?;
+        if matches!(self.ack_deadline, Some(deadline) if now > deadline) {
+            warn!("Broker did not acknowledge messages in time, reconnecting");
+            self.ack_deadline.take();
+            self.connection_state.process_event(Events::Disconnect).ok();
+            return Ok(());
+        }
 
         // Note: The ping timeout is set at this point so that it's running even if we fail
         // to write the ping message. This is intentional incase the underlying transport
@@ -491,6 +3590,29 @@ where
     }
 }
 
+impl<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+    MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>
+where
+    TcpStack: TcpClientStack + Dns,
+    Clock: embedded_time::Clock,
+{
+    /// Connect to the broker using its hostname.
+    ///
+    /// # Note
+    /// The hostname is resolved using the network stack whenever the client connects, so changes
+    /// of the address are followed, e.g. for brokers behind load balancers. The resolved address
+    /// replaces the address provided to `Minimq::new()`. If the hostname cannot be resolved,
+    /// `Error::ResolutionFailed` is returned from `poll()`, which counts as a failed connection
+    /// attempt.
+    ///
+    /// # Args
+    /// * `hostname` - The hostname of the broker.
+    pub fn set_broker_hostname(&mut self, hostname: &'static str) {
+        self.broker_hostname
+            .replace((hostname, resolve::<TcpStack>));
+    }
+}
+
 impl<
         TcpStack: TcpClientStack,
         Clock: embedded_time::Clock,
@@ -500,6 +3622,12 @@ impl<
 {
     /// Construct a new MQTT interface.
     ///
+    /// # Note
+    /// No network operations are performed until `poll()` is called, which connects to the broker
+    /// and maintains the connection. To defer connecting, e.g. until the network is available, call
+    /// `MqttClient::suspend_network()` before polling and `MqttClient::resume_network()` once the
+    /// client should connect.
+    ///
     /// # Args
     /// * `broker` - The IP address of the broker to connect to.
     /// * `client_id` The client ID to use for communicating with the broker. If empty, rely on the
@@ -528,27 +3656,237 @@ impl<
                 session_state,
                 connection_state: StateMachine::new(Context),
                 will: None,
+                pending_ack: None,
+                message_flags: None,
+                capabilities: Capabilities::default(),
+                acknowledgements: Deque::new(),
+                completions: Deque::new(),
+                publish_queue: PublishQueue::new(),
+                buffer_pool: None,
+                queue_ttl: None,
+                dead_letter_handler: None,
+                event_handler: None,
+                receive_paused: false,
+                redeliver_failed: false,
+                broker_hostname: None,
+                failover: None,
+                follow_redirects: false,
+                last_error_detail: None,
+                connect_timeout: None,
+                write_timeout: None,
+                connack_timeout: None,
+                phase_deadline: None,
+                ack_timeout: None,
+                ack_deadline: None,
+                write_deadline: None,
+                max_reconnect_attempts: None,
+                max_outage: None,
+                reconnect_attempts: 0,
+                reconnect_policy: None,
+                reconnect_deadline: None,
+                min_connect_interval: None,
+                last_connect_attempt: None,
+                session_taken_over: false,
+                connected: false,
+                resubscribe: false,
+                offline_queueing: false,
+                outage_start: None,
+                timestamp_property: None,
+                heartbeat: None,
+                authentication: None,
+                session_expiry: u32::MAX,
+                clean_start: false,
+                request_response_information: false,
+                response_information: None,
+                topic_aliases: TopicAliases::new(),
+                availability: None,
+                birth_pending: false,
+                heartbeat_deadline: None,
+                shutdown_deadline: None,
+                shutdown_unsubscribe: false,
+                disconnect_pending: false,
+                #[cfg(feature = "compression")]
+                compression_threshold: None,
+                #[cfg(feature = "encryption")]
+                cipher: None,
             },
-            packet_reader: PacketReader::new(),
+            packet_reader: PacketReader::with_buffer(PacketBuffer::Inline([0; MSG_SIZE])),
         };
 
         Ok(minimq)
     }
 
+    /// Configure the pool that the buffers of the client are claimed from.
+    ///
+    /// # Note
+    /// The receive buffer, the buffer of unfinished packet writes, and the buffers of queued
+    /// messages are claimed from the pool. The pool may be shared with other clients, which bounds
+    /// the number of buffers in use across all of them. Data that was received or is waiting to be
+    /// written is moved into the new buffers. Messages that are already queued keep their buffers
+    /// until they are transmitted or dropped.
+    ///
+    /// # Args
+    /// * `pool` - The pool to claim buffers from. If `None`, the buffers are stored inline.
+    ///
+    /// # Returns
+    /// `Error::NotReady` if the pool does not provide a receive and a transmit buffer, in which case
+    /// the configuration is unchanged.
+    pub fn set_buffer_pool(
+        &mut self,
+        pool: Option<&'static dyn BufferSource<MSG_SIZE>>,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        let receive_buffer = PacketBuffer::new(pool).map_err(|_| Error::NotReady)?;
+        let write_buffer = PacketBuffer::new(pool).map_err(|_| Error::NotReady)?;
+
+        self.packet_reader.replace_buffer(receive_buffer);
+        self.client.network.replace_write_buffer(write_buffer);
+        self.client.buffer_pool = pool;
+
+        Ok(())
+    }
+
     /// Check the MQTT interface for available messages.
     ///
+    /// # Note
+    /// The topic, message, and properties provided to the handler are only valid while the handler
+    /// is executing. A `MessagePool` may be used to take ownership of a received message.
+    ///
+    /// # Note
+    /// Every message that was already received is passed to the handler. Use `poll_one()` to
+    /// handle at most one message per call.
+    ///
+    /// # Note
+    /// The properties include any `Property::UserProperty` key-value pairs attached by the
+    /// publisher. Messages with more than 16 properties in total are rejected as malformed.
+    ///
     /// # Args
     /// * `f` - A closure to process any received messages. The closure should accept the client,
-    /// topic, message, and list of proprties (in that order).
-    pub fn poll<F>(&mut self, mut f: F) -> Result<(), Error<TcpStack::Error>>
+    ///   topic, message, and list of proprties (in that order).
+    ///
+    /// # Returns
+    /// The value returned by the handler for the last message that was handled, if any.
+    pub fn poll<F, R>(&mut self, f: F) -> Result<Option<R>, Error<TcpStack::Error>>
     where
         for<'a> F: FnMut(
             &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
             &'a str,
             &[u8],
             &[Property<'a>],
-        ),
+        ) -> R,
+    {
+        self.poll_with_summary(f).map(|summary| summary.handled)
+    }
+
+    /// Check the MQTT interface for available messages, handling at most one message.
+    ///
+    /// # Note
+    /// This behaves identically to `poll()`, but stops after the first message that was passed to
+    /// the handler. Any further messages that were already received are handled in subsequent
+    /// calls.
+    ///
+    /// # Args
+    /// * `f` - A closure to process a received message. See `poll()`.
+    ///
+    /// # Returns
+    /// The value returned by the handler if a message was handled.
+    pub fn poll_one<F, R>(&mut self, mut f: F) -> Result<Option<R>, Error<TcpStack::Error>>
+    where
+        for<'a> F: FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+            &'a str,
+            &[u8],
+            &[Property<'a>],
+        ) -> R,
+    {
+        self.poll_messages(&mut f, false)
+            .map(|summary| summary.handled)
+    }
+
+    /// Check the MQTT interface for available messages using a fallible handler.
+    ///
+    /// # Note
+    /// At most one message is handled per call, as with `poll_one()`, so that a failure is
+    /// returned before any further messages are handled. Whether the failed message is
+    /// acknowledged is configured with `MqttClient::set_redeliver_failed_messages()`.
+    ///
+    /// # Args
+    /// * `f` - A closure to process any received messages. See `poll()`.
+    ///
+    /// # Returns
+    /// True if a message was handled successfully.
+    pub fn try_poll<F, H>(&mut self, mut f: F) -> Result<bool, PollError<TcpStack::Error, H>>
+    where
+        for<'a> F: FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+            &'a str,
+            &[u8],
+            &[Property<'a>],
+        ) -> Result<(), H>,
+    {
+        let handled = self.poll_one(|client, topic, payload, properties| {
+            let result = f(client, topic, payload, properties);
+            if result.is_err() && client.redeliver_failed {
+                // Dropping the token leaves the message unacknowledged.
+                client.defer_ack();
+            }
+
+            result
+        })?;
+
+        match handled {
+            Some(Err(error)) => Err(PollError::Handler(error)),
+            handled => Ok(handled.is_some()),
+        }
+    }
+
+    /// Check the MQTT interface for available messages and summarize the work performed.
+    ///
+    /// # Note
+    /// This behaves identically to `poll()`, but additionally reports the packets processed, the
+    /// data received, and whether further packets are pending. This allows schedulers to decide
+    /// when to poll again.
+    ///
+    /// # Args
+    /// * `f` - A closure to process any received messages. See `poll()`.
+    ///
+    /// # Returns
+    /// A summary of the call, including the value returned by the handler for the last message
+    /// that was handled.
+    pub fn poll_with_summary<F, R>(
+        &mut self,
+        mut f: F,
+    ) -> Result<PollSummary<R>, Error<TcpStack::Error>>
+    where
+        for<'a> F: FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+            &'a str,
+            &[u8],
+            &[Property<'a>],
+        ) -> R,
+    {
+        self.poll_messages(&mut f, true)
+    }
+
+    /// Service the connection and handle received messages.
+    ///
+    /// # Args
+    /// * `f` - A closure to process any received messages.
+    /// * `drain` - Specifies whether all buffered messages are handled. Otherwise, the call returns
+    ///   after the first message that was handled.
+    fn poll_messages<F, R>(
+        &mut self,
+        f: &mut F,
+        drain: bool,
+    ) -> Result<PollSummary<R>, Error<TcpStack::Error>>
+    where
+        for<'a> F: FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+            &'a str,
+            &[u8],
+            &[Property<'a>],
+        ) -> R,
     {
+        let mut summary = PollSummary::default();
         self.client.process()?;
 
         // If the connection is no longer active, reset the packet reader state and return. There's
@@ -557,46 +3895,185 @@ impl<
             && self.client.connection_state.state() != &States::Establishing
         {
             self.packet_reader.reset();
-            return Ok(());
+            return Ok(summary);
         }
 
-        let mut buf: [u8; 1024] = [0; 1024];
-        let received = self.client.network.read(&mut buf)?;
-        if received > 0 {
-            debug!("Received {} bytes", received);
+        // Handle any packets that are still buffered from a previous call.
+        self.handle_buffered_packets(f, &mut summary, drain)?;
+        if !drain && summary.handled.is_some() {
+            return Ok(summary);
         }
 
-        let mut processed = 0;
-        while processed < received {
-            match self.packet_reader.slurp(&buf[processed..received]) {
-                Ok(count) => {
-                    debug!("Processed {} bytes", count);
-                    processed += count
-                }
+        // If the application is not ready to receive messages, leave any data in the network
+        // stack. This allows TCP flow control to apply back pressure to the broker.
+        if self.client.receive_blocked() {
+            summary.pending = self.packet_reader.packet_available();
+            return Ok(summary);
+        }
+
+        summary.bytes_received = self.receive()?;
+        self.handle_buffered_packets(f, &mut summary, drain)?;
+        Ok(summary)
+    }
+
+    /// Check the MQTT interface and handle all available messages.
+    ///
+    /// # Note
+    /// Unlike `poll()`, the network stack is read until no more data is available and every
+    /// received message is passed to the handler within a single call. This reduces the overhead
+    /// per message when receiving at high rates. Reading stops early if the application is not
+    /// ready to receive further messages, e.g. because acknowledgements are deferred.
+    ///
+    /// # Args
+    /// * `f` - A closure to process any received messages. See `poll()`.
+    ///
+    /// # Returns
+    /// The number of messages that were handled.
+    pub fn poll_all<F>(&mut self, mut f: F) -> Result<usize, Error<TcpStack::Error>>
+    where
+        for<'a> F: FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+            &'a str,
+            &[u8],
+            &[Property<'a>],
+        ),
+    {
+        self.client.process()?;
+
+        let mut handled = 0;
+        loop {
+            // The connection may also be lost while handling messages.
+            if self.client.connection_state.state() != &States::Active
+                && self.client.connection_state.state() != &States::Establishing
+            {
+                self.packet_reader.reset();
+                return Ok(handled);
+            }
+
+            // Each pass handles at most one message, so buffered packets are handled repeatedly
+            // before reading more data.
+            while self.packet_reader.packet_available() && !self.client.receive_blocked() {
+                let mut summary = PollSummary::default();
+                self.handle_buffered_packets(
+                    &mut |client, topic, payload, properties| f(client, topic, payload, properties),
+                    &mut summary,
+                    false,
+                )?;
 
-                Err(e) => {
-                    self.client.reset();
-                    self.packet_reader.reset();
-                    return Err(Error::Protocol(e));
+                if summary.handled.is_some() {
+                    handled += 1;
                 }
             }
 
-            // Handle any received packets.
-            while self.packet_reader.packet_available() {
-                let packet = ReceivedPacket::parse_message(&self.packet_reader)?;
+            if self.client.receive_blocked() || self.receive()? == 0 {
+                return Ok(handled);
+            }
+        }
+    }
+
+    /// Read available data from the network into the packet reader.
+    ///
+    /// # Returns
+    /// The number of bytes that were read.
+    fn receive(&mut self) -> Result<usize, Error<TcpStack::Error>> {
+        let received = self
+            .client
+            .network
+            .read(self.packet_reader.receive_buffer())?;
+        if received > 0 {
+            debug!("Received {} bytes", received);
+            let now = self.client.clock.try_now()?;
+            self.client.session_state.register_reception(now);
+        }
+
+        if let Err(e) = self.packet_reader.commit(received) {
+            self.client.reset();
+            self.packet_reader.reset();
+            return Err(Error::Protocol(e));
+        }
+
+        self.skip_oversized_message()?;
 
-                info!("Received {:?}", packet);
+        if let Err(e) = self.packet_reader.check_size() {
+            self.client.reset();
+            self.packet_reader.reset();
+            return Err(Error::Protocol(e));
+        }
+
+        Ok(received)
+    }
+
+    /// Skip the message that is currently being received if it exceeds the payload size limits of
+    /// the subscriptions.
+    ///
+    /// # Note
+    /// Completely received messages are checked when they are handled.
+    fn skip_oversized_message(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        if self.packet_reader.packet_available() {
+            return Ok(());
+        }
+
+        let header = match self.packet_reader.publish_header() {
+            Some(header) => header,
+            _ => return Ok(()),
+        };
+
+        if !self
+            .client
+            .session_state
+            .subscriptions
+            .exceeds_payload_limit(header.topic, header.payload_length)
+        {
+            return Ok(());
+        }
+
+        let result = self.client.skip_message(
+            header.topic,
+            header.payload_length,
+            header.packet_id,
+            header.qos,
+        );
+        self.packet_reader.discard_packet();
+        result
+    }
+
+    fn handle_buffered_packets<F, R>(
+        &mut self,
+        f: &mut F,
+        summary: &mut PollSummary<R>,
+        drain: bool,
+    ) -> Result<(), Error<TcpStack::Error>>
+    where
+        for<'a> F: FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+            &'a str,
+            &[u8],
+            &[Property<'a>],
+        ) -> R,
+    {
+        while self.packet_reader.packet_available() && !self.client.receive_blocked() {
+            let packet = ReceivedPacket::parse_message(&self.packet_reader)?;
 
-                let result = self.client.handle_packet(packet, &mut f);
+            info!("Received {:?}", packet);
+            summary.packets.count(&packet);
 
-                self.packet_reader.pop_packet()?;
+            let result = self.client.handle_packet(packet, f);
 
-                // If there was an error, return it now. Note that we ensure the packet is removed
-                // from buffering after processing even in error conditions..
-                result?;
+            self.packet_reader.pop_packet()?;
+
+            // If there was an error, return it now. Note that we ensure the packet is removed
+            // from buffering after processing even in error conditions..
+            //
+            // Unless all packets are drained, the result of the handler is returned immediately.
+            if let Some(result) = result? {
+                summary.handled.replace(result);
+                if !drain {
+                    break;
+                }
             }
         }
 
+        summary.pending = self.packet_reader.packet_available();
         Ok(())
     }
 }
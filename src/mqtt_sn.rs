@@ -0,0 +1,1161 @@
+//! MQTT-SN Client
+//!
+//! # Design
+//! On 802.15.4, LoRa and similar networks, TCP is impractical due to the small frame sizes and the
+//! cost of maintaining a connection. MQTT for Sensor Networks (MQTT-SN 1.2) carries the MQTT
+//! operations in compact UDP datagrams to a gateway, which relays them to an MQTT broker. Instead
+//! of transmitting the topic name with every message, the client registers each topic with the
+//! gateway once and publishes using the two-byte topic ID assigned by the gateway.
+//!
+//! The `MqttSn` client communicates with the gateway using an embedded-nal `UdpClientStack`. It
+//! uses the same error types, quality-of-service levels and keep-alive behavior as the MQTT
+//! client, and is driven the same way by calling `poll()` periodically:
+//!
+//! ```ignore
+//! let mut mqtt: MqttSn<_, _, 128, 8> = MqttSn::new(gateway, "sensor", udp_stack, clock)?;
+//!
+//! loop {
+//!     if mqtt.client.is_connected() && !mqtt.client.request_pending() {
+//!         match mqtt.client.publish("sensor/temp", b"21.5", QoS::AtLeastOnce, Retain::NotRetained) {
+//!             // The topic is registered with the gateway first.
+//!             Err(Error::NotReady) => {}
+//!             result => result?,
+//!         }
+//!     }
+//!
+//!     mqtt.poll(|client, topic, payload| log::info!("{}: {:?}", topic, payload))?;
+//! }
+//! ```
+//!
+//! # Note
+//! As required by the protocol, only a single request that requires an acknowledgement, such as
+//! a registration, a subscription or a `QoS::AtLeastOnce` message, is outstanding at a time.
+//! Requests are retransmitted if the gateway does not acknowledge them within the retry interval.
+//! If the gateway does not answer repeated retransmissions, the connection is considered lost.
+//!
+//! Every connection starts a clean session, so topic registrations and subscriptions are lost
+//! whenever the client reconnects. `QoS::ExactlyOnce`, predefined topic IDs, wills, sleeping
+//! clients and gateway discovery are not supported.
+use crate::{
+    debug,
+    session_state::{keep_alive_interval, DEFAULT_KEEP_ALIVE},
+    warn, Diagnostic, Error, ProtocolError, QoS, Retain,
+};
+
+use core::str::FromStr;
+use embedded_nal::{nb, SocketAddr, UdpClientStack};
+use embedded_time::{duration::Milliseconds, Instant};
+use heapless::{String, Vec};
+
+/// The maximum length of a topic name that is registered with the gateway.
+pub const MAX_TOPIC_LENGTH: usize = 64;
+
+/// The maximum length of a client ID permitted by the protocol.
+const MAX_CLIENT_ID_LENGTH: usize = 23;
+
+/// The interval after which an unacknowledged request is retransmitted by default.
+const DEFAULT_RETRY_INTERVAL: Milliseconds<u32> = Milliseconds(10_000);
+
+/// The number of retransmissions after which the gateway is considered lost.
+const MAX_RETRIES: u8 = 5;
+
+/// The protocol ID of MQTT-SN 1.2.
+const PROTOCOL_ID: u8 = 0x01;
+
+const CONNECT: u8 = 0x04;
+const CONNACK: u8 = 0x05;
+const REGISTER: u8 = 0x0A;
+const REGACK: u8 = 0x0B;
+const PUBLISH: u8 = 0x0C;
+const PUBACK: u8 = 0x0D;
+const SUBSCRIBE: u8 = 0x12;
+const SUBACK: u8 = 0x13;
+const PINGREQ: u8 = 0x16;
+const PINGRESP: u8 = 0x17;
+const DISCONNECT: u8 = 0x18;
+
+const FLAG_DUP: u8 = 0x80;
+const FLAG_RETAIN: u8 = 0x10;
+const FLAG_CLEAN_SESSION: u8 = 0x04;
+const QOS_SHIFT: u8 = 5;
+const QOS_MASK: u8 = 0x60;
+const TOPIC_TYPE_MASK: u8 = 0x03;
+
+const TOPIC_TYPE_NORMAL: u8 = 0x00;
+const TOPIC_TYPE_SHORT: u8 = 0x02;
+
+const ACCEPTED: u8 = 0x00;
+const REJECTED_INVALID_TOPIC_ID: u8 = 0x02;
+const REJECTED_NOT_SUPPORTED: u8 = 0x03;
+
+/// Encode a packet consisting of the provided fields.
+///
+/// # Note
+/// Packets longer than 255 bytes use the three-byte length encoding.
+fn encode<const N: usize>(msg_type: u8, fields: &[&[u8]]) -> Result<Vec<u8, N>, ProtocolError> {
+    let length = 1 + fields.iter().map(|field| field.len()).sum::<usize>();
+    let needed = if length + 1 > u8::MAX as usize {
+        length + 3
+    } else {
+        length + 1
+    };
+
+    if needed > u16::MAX as usize {
+        return Err(ProtocolError::DataSize);
+    }
+
+    if needed > N {
+        return Err(ProtocolError::BufferTooSmall { needed });
+    }
+
+    let mut packet = Vec::new();
+    if needed > u8::MAX as usize {
+        packet.push(0x01).ok();
+        packet
+            .extend_from_slice(&(needed as u16).to_be_bytes())
+            .ok();
+    } else {
+        packet.push(needed as u8).ok();
+    }
+
+    packet.push(msg_type).ok();
+    for field in fields {
+        packet.extend_from_slice(field).ok();
+    }
+
+    Ok(packet)
+}
+
+/// Get the offset of the message type within an encoded packet.
+fn header_length(packet: &[u8]) -> usize {
+    if packet[0] == 0x01 {
+        3
+    } else {
+        1
+    }
+}
+
+fn malformed(offset: usize, expected: &'static str, found: Option<u32>) -> ProtocolError {
+    ProtocolError::MalformedPacket(Diagnostic::new(offset, expected, found))
+}
+
+/// A packet received from the gateway.
+#[derive(Debug, PartialEq)]
+enum Packet<'a> {
+    Connack {
+        return_code: u8,
+    },
+    Register {
+        topic_id: u16,
+        msg_id: u16,
+        topic: &'a str,
+    },
+    Regack {
+        topic_id: u16,
+        msg_id: u16,
+        return_code: u8,
+    },
+    Publish {
+        flags: u8,
+        topic_id: u16,
+        msg_id: u16,
+        payload: &'a [u8],
+    },
+    Puback {
+        topic_id: u16,
+        msg_id: u16,
+        return_code: u8,
+    },
+    Suback {
+        topic_id: u16,
+        msg_id: u16,
+        return_code: u8,
+    },
+    Pingreq,
+    Pingresp,
+    Disconnect,
+}
+
+impl<'a> Packet<'a> {
+    /// Parse a datagram received from the gateway.
+    fn parse(datagram: &'a [u8]) -> Result<Self, ProtocolError> {
+        let (length, offset) = match datagram {
+            [0x01, high, low, ..] => (u16::from_be_bytes([*high, *low]) as usize, 3),
+            [length, ..] => (*length as usize, 1),
+            [] => return Err(malformed(0, "length", None)),
+        };
+
+        if length != datagram.len() || length <= offset {
+            return Err(malformed(0, "length", Some(length as u32)));
+        }
+
+        let msg_type = datagram[offset];
+        let body = &datagram[offset + 1..];
+        let body_offset = offset + 1;
+
+        let u16_at = |index: usize| -> Result<u16, ProtocolError> {
+            body.get(index..index + 2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+                .ok_or_else(|| malformed(body_offset + index, "u16", None))
+        };
+        let u8_at = |index: usize| -> Result<u8, ProtocolError> {
+            body.get(index)
+                .copied()
+                .ok_or_else(|| malformed(body_offset + index, "u8", None))
+        };
+
+        let packet = match msg_type {
+            CONNACK => Packet::Connack {
+                return_code: u8_at(0)?,
+            },
+            REGISTER => Packet::Register {
+                topic_id: u16_at(0)?,
+                msg_id: u16_at(2)?,
+                topic: core::str::from_utf8(&body[4..])
+                    .map_err(|_| malformed(body_offset + 4, "topic name", None))?,
+            },
+            REGACK => Packet::Regack {
+                topic_id: u16_at(0)?,
+                msg_id: u16_at(2)?,
+                return_code: u8_at(4)?,
+            },
+            PUBLISH => Packet::Publish {
+                flags: u8_at(0)?,
+                topic_id: u16_at(1)?,
+                msg_id: u16_at(3)?,
+                payload: &body[5..],
+            },
+            PUBACK => Packet::Puback {
+                topic_id: u16_at(0)?,
+                msg_id: u16_at(2)?,
+                return_code: u8_at(4)?,
+            },
+            SUBACK => Packet::Suback {
+                topic_id: u16_at(1)?,
+                msg_id: u16_at(3)?,
+                return_code: u8_at(5)?,
+            },
+            PINGREQ => Packet::Pingreq,
+            PINGRESP => Packet::Pingresp,
+            DISCONNECT => Packet::Disconnect,
+            _ => return Err(ProtocolError::UnsupportedPacket),
+        };
+
+        Ok(packet)
+    }
+}
+
+/// A topic registered with the gateway.
+#[derive(Debug)]
+struct Topic {
+    id: u16,
+    name: String<MAX_TOPIC_LENGTH>,
+}
+
+/// The kind of an outstanding request.
+#[derive(Debug, PartialEq)]
+enum RequestKind {
+    Connect,
+    Register(String<MAX_TOPIC_LENGTH>),
+    Subscribe(String<MAX_TOPIC_LENGTH>),
+    Publish,
+    Ping,
+}
+
+/// A request awaiting acknowledgement by the gateway.
+struct Request<Clock: embedded_time::Clock, const MSG_SIZE: usize> {
+    kind: RequestKind,
+    msg_id: u16,
+    packet: Vec<u8, MSG_SIZE>,
+    sent: Instant<Clock>,
+    retries: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum State {
+    Disconnected,
+    Connecting,
+    Connected,
+}
+
+/// Encode a topic name as a topic ID if it is a short topic name.
+fn short_topic(topic: &str) -> Option<u16> {
+    match topic.as_bytes() {
+        [high, low] => Some(u16::from_be_bytes([*high, *low])),
+        _ => None,
+    }
+}
+
+/// A client for interacting with an MQTT-SN gateway.
+pub struct MqttSnClient<
+    UdpStack: UdpClientStack,
+    Clock: embedded_time::Clock,
+    const MSG_SIZE: usize,
+    const TOPIC_COUNT: usize,
+> {
+    network: UdpStack,
+    socket: Option<UdpStack::UdpSocket>,
+    gateway: SocketAddr,
+    clock: Clock,
+    client_id: String<MAX_CLIENT_ID_LENGTH>,
+    state: State,
+    keep_alive: u16,
+    retry_interval: Milliseconds<u32>,
+    msg_id: u16,
+    request: Option<Request<Clock, MSG_SIZE>>,
+    topics: Vec<Topic, TOPIC_COUNT>,
+    last_transmission: Option<Instant<Clock>>,
+}
+
+impl<UdpStack, Clock, const MSG_SIZE: usize, const TOPIC_COUNT: usize>
+    MqttSnClient<UdpStack, Clock, MSG_SIZE, TOPIC_COUNT>
+where
+    UdpStack: UdpClientStack,
+    Clock: embedded_time::Clock,
+{
+    /// Determine if the client is connected to the gateway.
+    pub fn is_connected(&self) -> bool {
+        self.state == State::Connected
+    }
+
+    /// Determine if a request is awaiting acknowledgement by the gateway.
+    ///
+    /// # Note
+    /// Registrations, subscriptions and `QoS::AtLeastOnce` messages are rejected with
+    /// `Error::NotReady` while a request is outstanding.
+    pub fn request_pending(&self) -> bool {
+        self.request.is_some()
+    }
+
+    /// Configure the keep-alive interval.
+    ///
+    /// # Note
+    /// This takes effect when the client connects to the gateway next.
+    ///
+    /// # Args
+    /// * `interval` - The keep-alive interval in seconds. A ping is transmitted if no other
+    ///   packets are sent within 50% of the keep-alive interval. If zero, keep-alive is disabled.
+    pub fn set_keepalive_interval(&mut self, interval: u16) {
+        self.keep_alive = interval;
+    }
+
+    /// Configure the interval after which unacknowledged requests are retransmitted.
+    ///
+    /// # Args
+    /// * `interval` - The retry interval. Defaults to 10 seconds.
+    pub fn set_retry_interval(&mut self, interval: Milliseconds<u32>) {
+        self.retry_interval = interval;
+    }
+
+    /// Get the topic ID that the gateway assigned to a topic.
+    ///
+    /// # Args
+    /// * `topic` - The name of the topic.
+    ///
+    /// # Returns
+    /// The topic ID if the topic is registered.
+    pub fn topic_id(&self, topic: &str) -> Option<u16> {
+        self.topics
+            .iter()
+            .find(|registered| registered.name == topic)
+            .map(|registered| registered.id)
+    }
+
+    fn topic_name(&self, topic_id: u16) -> Option<&str> {
+        self.topics
+            .iter()
+            .find(|registered| registered.id == topic_id)
+            .map(|registered| registered.name.as_str())
+    }
+
+    fn store_topic(&mut self, id: u16, name: &str) -> bool {
+        self.topics.retain(|registered| registered.name != name);
+        match String::from_str(name) {
+            Ok(name) => self.topics.push(Topic { id, name }).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn next_msg_id(&mut self) -> u16 {
+        self.msg_id = self.msg_id.checked_add(1).unwrap_or(1);
+        self.msg_id
+    }
+
+    fn transmit(&mut self, packet: &[u8]) -> Result<(), Error<UdpStack::Error>> {
+        let socket = self.socket.as_mut().ok_or(Error::NotConnected)?;
+        match self.network.send(socket, packet) {
+            Ok(()) => {
+                self.last_transmission = Some(self.clock.try_now()?);
+                Ok(())
+            }
+            Err(nb::Error::WouldBlock) => Err(Error::WriteFail),
+            Err(nb::Error::Other(error)) => Err(Error::Network(error)),
+        }
+    }
+
+    fn send_request(
+        &mut self,
+        kind: RequestKind,
+        msg_id: u16,
+        packet: Vec<u8, MSG_SIZE>,
+    ) -> Result<(), Error<UdpStack::Error>> {
+        // Datagrams that cannot be sent are treated as lost and are retransmitted later.
+        match self.transmit(&packet) {
+            Err(Error::WriteFail) => debug!("Request could not be sent"),
+            result => result?,
+        }
+
+        self.request.replace(Request {
+            kind,
+            msg_id,
+            packet,
+            sent: self.clock.try_now()?,
+            retries: 0,
+        });
+        Ok(())
+    }
+
+    fn check_request(&self) -> Result<(), Error<UdpStack::Error>> {
+        if !self.is_connected() {
+            return Err(Error::NotConnected);
+        }
+
+        if self.request.is_some() {
+            return Err(Error::NotReady);
+        }
+
+        Ok(())
+    }
+
+    /// Register a topic with the gateway.
+    ///
+    /// # Note
+    /// The topic ID is available from `topic_id()` once the gateway acknowledged the registration.
+    /// Registering a topic that is already registered has no effect. If `TOPIC_COUNT` topics are
+    /// registered already, the request is rejected with `Error::Unsupported`.
+    ///
+    /// # Args
+    /// * `topic` - The name of the topic. Wildcards are not permitted.
+    pub fn register(&mut self, topic: &str) -> Result<(), Error<UdpStack::Error>> {
+        if self.topic_id(topic).is_some() {
+            return Ok(());
+        }
+
+        self.check_request()?;
+        if self.topics.is_full() {
+            return Err(Error::Unsupported);
+        }
+
+        let name = String::from_str(topic).map_err(|_| ProtocolError::DataSize)?;
+        let msg_id = self.next_msg_id();
+        let packet = encode(
+            REGISTER,
+            &[&0u16.to_be_bytes(), &msg_id.to_be_bytes(), topic.as_bytes()],
+        )?;
+
+        self.send_request(RequestKind::Register(name), msg_id, packet)
+    }
+
+    /// Publish a message to the gateway.
+    ///
+    /// # Note
+    /// Topics with a name of two characters are published as short topic names. Other topics must
+    /// be registered with the gateway first. If the topic is not registered, the registration is
+    /// requested and `Error::NotReady` is returned, so the message may be published again once the
+    /// registration is acknowledged.
+    ///
+    /// # Args
+    /// * `topic` - The topic to publish the message to.
+    /// * `data` - The data to transmit as the message contents.
+    /// * `qos` - The desired quality-of-service level of the message. `QoS::ExactlyOnce` is not
+    ///   supported.
+    /// * `retain` - Specifies whether the message should be retained by the broker.
+    pub fn publish(
+        &mut self,
+        topic: &str,
+        data: &[u8],
+        qos: QoS,
+        retain: Retain,
+    ) -> Result<(), Error<UdpStack::Error>> {
+        if qos == QoS::ExactlyOnce {
+            return Err(Error::Unsupported);
+        }
+
+        if !self.is_connected() {
+            return Err(Error::NotConnected);
+        }
+
+        let (topic_type, topic_id) = match short_topic(topic) {
+            Some(topic_id) => (TOPIC_TYPE_SHORT, topic_id),
+            None => match self.topic_id(topic) {
+                Some(topic_id) => (TOPIC_TYPE_NORMAL, topic_id),
+                None => {
+                    self.register(topic)?;
+                    return Err(Error::NotReady);
+                }
+            },
+        };
+
+        let mut flags = (qos as u8) << QOS_SHIFT | topic_type;
+        if retain == Retain::Retained {
+            flags |= FLAG_RETAIN;
+        }
+
+        if qos == QoS::AtMostOnce {
+            let packet: Vec<u8, MSG_SIZE> = encode(
+                PUBLISH,
+                &[&[flags], &topic_id.to_be_bytes(), &0u16.to_be_bytes(), data],
+            )?;
+            return self.transmit(&packet);
+        }
+
+        self.check_request()?;
+        let msg_id = self.next_msg_id();
+        let packet = encode(
+            PUBLISH,
+            &[
+                &[flags],
+                &topic_id.to_be_bytes(),
+                &msg_id.to_be_bytes(),
+                data,
+            ],
+        )?;
+
+        self.send_request(RequestKind::Publish, msg_id, packet)
+    }
+
+    /// Subscribe to a topic.
+    ///
+    /// # Note
+    /// Messages are only delivered for topics that are known to the client. The gateway registers
+    /// the topics matching a wildcard filter before delivering messages to them.
+    ///
+    /// # Args
+    /// * `topic` - The topic filter to subscribe to.
+    /// * `qos` - The maximum quality-of-service level of delivered messages. `QoS::ExactlyOnce` is
+    ///   not supported.
+    pub fn subscribe(&mut self, topic: &str, qos: QoS) -> Result<(), Error<UdpStack::Error>> {
+        if qos == QoS::ExactlyOnce {
+            return Err(Error::Unsupported);
+        }
+
+        self.check_request()?;
+        let name = String::from_str(topic).map_err(|_| ProtocolError::DataSize)?;
+        let topic_type = if short_topic(topic).is_some() {
+            TOPIC_TYPE_SHORT
+        } else {
+            TOPIC_TYPE_NORMAL
+        };
+
+        let msg_id = self.next_msg_id();
+        let packet = encode(
+            SUBSCRIBE,
+            &[
+                &[(qos as u8) << QOS_SHIFT | topic_type],
+                &msg_id.to_be_bytes(),
+                topic.as_bytes(),
+            ],
+        )?;
+
+        self.send_request(RequestKind::Subscribe(name), msg_id, packet)
+    }
+
+    /// Disconnect from the gateway.
+    ///
+    /// # Note
+    /// Any outstanding request is dropped. The client connects to the gateway again when it is
+    /// polled next.
+    pub fn disconnect(&mut self) -> Result<(), Error<UdpStack::Error>> {
+        if !self.is_connected() {
+            return Err(Error::NotConnected);
+        }
+
+        let packet: Vec<u8, MSG_SIZE> = encode(DISCONNECT, &[])?;
+        self.state = State::Disconnected;
+        self.request.take();
+        self.transmit(&packet)
+    }
+
+    fn connect(&mut self) -> Result<(), Error<UdpStack::Error>> {
+        if self.socket.is_none() {
+            let mut socket = self.network.socket().map_err(Error::Network)?;
+            self.network
+                .connect(&mut socket, self.gateway)
+                .map_err(Error::Network)?;
+            self.socket.replace(socket);
+        }
+
+        // Registrations do not persist across connections, as every connection starts a clean
+        // session.
+        self.topics.clear();
+
+        let packet = encode(
+            CONNECT,
+            &[
+                &[FLAG_CLEAN_SESSION, PROTOCOL_ID],
+                &self.keep_alive.to_be_bytes(),
+                self.client_id.as_bytes(),
+            ],
+        )?;
+
+        self.state = State::Connecting;
+        self.send_request(RequestKind::Connect, 0, packet)
+    }
+
+    /// Handle connection establishment, retransmissions and keep-alive.
+    fn update(&mut self) -> Result<(), Error<UdpStack::Error>> {
+        if self.state == State::Disconnected {
+            return self.connect();
+        }
+
+        let now = self.clock.try_now()?;
+        if let Some(request) = &mut self.request {
+            if now < request.sent + self.retry_interval {
+                return Ok(());
+            }
+
+            // The connection is requested until the gateway answers.
+            if request.retries >= MAX_RETRIES && request.kind != RequestKind::Connect {
+                warn!("Gateway did not acknowledge request. Reconnecting");
+                self.request.take();
+                self.state = State::Disconnected;
+                return Ok(());
+            }
+
+            request.retries = request.retries.saturating_add(1);
+            request.sent = now;
+            if request.kind == RequestKind::Publish {
+                let offset = header_length(&request.packet) + 1;
+                request.packet[offset] |= FLAG_DUP;
+            }
+
+            let packet = request.packet.clone();
+            return match self.transmit(&packet) {
+                Err(Error::WriteFail) => Ok(()),
+                result => result,
+            };
+        }
+
+        let interval = match keep_alive_interval(self.keep_alive) {
+            Some(interval) if self.is_connected() => interval,
+            _ => return Ok(()),
+        };
+
+        let idle = match self.last_transmission {
+            Some(last) => now >= last + interval / 2,
+            None => true,
+        };
+
+        if idle {
+            let packet = encode(PINGREQ, &[])?;
+            self.send_request(RequestKind::Ping, 0, packet)?;
+        }
+
+        Ok(())
+    }
+
+    /// Take the outstanding request if it matches the acknowledgement.
+    fn acknowledge(
+        &mut self,
+        msg_id: u16,
+        matches: impl FnOnce(&RequestKind) -> bool,
+    ) -> Option<RequestKind> {
+        match &self.request {
+            Some(request) if request.msg_id == msg_id && matches(&request.kind) => {
+                self.request.take().map(|request| request.kind)
+            }
+            _ => {
+                debug!("Ignoring unexpected acknowledgement {}", msg_id);
+                None
+            }
+        }
+    }
+
+    fn send_ack(
+        &mut self,
+        msg_type: u8,
+        topic_id: u16,
+        msg_id: u16,
+        return_code: u8,
+    ) -> Result<(), Error<UdpStack::Error>> {
+        let packet: Vec<u8, MSG_SIZE> = encode(
+            msg_type,
+            &[
+                &topic_id.to_be_bytes(),
+                &msg_id.to_be_bytes(),
+                &[return_code],
+            ],
+        )?;
+
+        match self.transmit(&packet) {
+            // The gateway retransmits the request if the acknowledgement is lost.
+            Err(Error::WriteFail) => Ok(()),
+            result => result,
+        }
+    }
+
+    fn handle_packet<F, R>(
+        &mut self,
+        packet: Packet<'_>,
+        f: &mut F,
+    ) -> Result<Option<R>, Error<UdpStack::Error>>
+    where
+        F: FnMut(&mut Self, &str, &[u8]) -> R,
+    {
+        if self.state == State::Connecting && !matches!(packet, Packet::Connack { .. }) {
+            debug!("Ignoring packet while connecting");
+            return Ok(None);
+        }
+
+        match packet {
+            Packet::Connack { return_code } => {
+                if self.state != State::Connecting {
+                    return Err(ProtocolError::UnexpectedPacket.into());
+                }
+
+                // A rejected connection is requested again after the retry interval.
+                if return_code != ACCEPTED {
+                    return Err(Error::ConnectionRefused {
+                        reason_code: return_code,
+                    });
+                }
+
+                self.request.take();
+                self.state = State::Connected;
+                self.last_transmission = Some(self.clock.try_now()?);
+            }
+
+            Packet::Register {
+                topic_id,
+                msg_id,
+                topic,
+            } => {
+                let return_code = if self.store_topic(topic_id, topic) {
+                    ACCEPTED
+                } else {
+                    warn!("Cannot store topic {}", topic);
+                    REJECTED_NOT_SUPPORTED
+                };
+
+                self.send_ack(REGACK, topic_id, msg_id, return_code)?;
+            }
+
+            Packet::Regack {
+                topic_id,
+                msg_id,
+                return_code,
+            } => {
+                let kind =
+                    self.acknowledge(msg_id, |kind| matches!(kind, RequestKind::Register(_)));
+                if let Some(RequestKind::Register(name)) = kind {
+                    if return_code != ACCEPTED {
+                        return Err(Error::Unacknowledged {
+                            reason_code: return_code,
+                        });
+                    }
+
+                    self.store_topic(topic_id, &name);
+                }
+            }
+
+            Packet::Suback {
+                topic_id,
+                msg_id,
+                return_code,
+            } => {
+                let kind =
+                    self.acknowledge(msg_id, |kind| matches!(kind, RequestKind::Subscribe(_)));
+                if let Some(RequestKind::Subscribe(name)) = kind {
+                    if return_code != ACCEPTED {
+                        return Err(Error::Unacknowledged {
+                            reason_code: return_code,
+                        });
+                    }
+
+                    // Wildcard filters are acknowledged without a topic ID.
+                    if topic_id != 0 && short_topic(&name).is_none() {
+                        self.store_topic(topic_id, &name);
+                    }
+                }
+            }
+
+            Packet::Puback {
+                topic_id,
+                msg_id,
+                return_code,
+            } => {
+                if self
+                    .acknowledge(msg_id, |kind| *kind == RequestKind::Publish)
+                    .is_some()
+                    && return_code != ACCEPTED
+                {
+                    // The topic is registered again before it is published next.
+                    if return_code == REJECTED_INVALID_TOPIC_ID {
+                        self.topics.retain(|registered| registered.id != topic_id);
+                    }
+
+                    return Err(Error::Unacknowledged {
+                        reason_code: return_code,
+                    });
+                }
+            }
+
+            Packet::Publish {
+                flags,
+                topic_id,
+                msg_id,
+                payload,
+            } => {
+                let qos = (flags & QOS_MASK) >> QOS_SHIFT;
+                if qos > QoS::AtLeastOnce as u8 {
+                    return Err(ProtocolError::UnexpectedPacket.into());
+                }
+
+                let short_name;
+                let topic = match flags & TOPIC_TYPE_MASK {
+                    TOPIC_TYPE_NORMAL => self.topic_name(topic_id),
+                    TOPIC_TYPE_SHORT => {
+                        short_name = topic_id.to_be_bytes();
+                        core::str::from_utf8(&short_name).ok()
+                    }
+                    _ => None,
+                };
+
+                let topic = match topic.map(String::<MAX_TOPIC_LENGTH>::from_str) {
+                    Some(Ok(topic)) => topic,
+                    _ => {
+                        warn!("Received message for unknown topic ID {}", topic_id);
+                        if qos == QoS::AtLeastOnce as u8 {
+                            self.send_ack(PUBACK, topic_id, msg_id, REJECTED_INVALID_TOPIC_ID)?;
+                        }
+
+                        return Ok(None);
+                    }
+                };
+
+                let result = f(self, &topic, payload);
+                if qos == QoS::AtLeastOnce as u8 {
+                    self.send_ack(PUBACK, topic_id, msg_id, ACCEPTED)?;
+                }
+
+                return Ok(Some(result));
+            }
+
+            Packet::Pingreq => {
+                let packet: Vec<u8, MSG_SIZE> = encode(PINGRESP, &[])?;
+                match self.transmit(&packet) {
+                    Err(Error::WriteFail) => {}
+                    result => result?,
+                }
+            }
+
+            Packet::Pingresp => {
+                if matches!(&self.request, Some(request) if request.kind == RequestKind::Ping) {
+                    self.request.take();
+                }
+            }
+
+            Packet::Disconnect => {
+                warn!("Gateway disconnected the client");
+                self.request.take();
+                self.state = State::Disconnected;
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// An MQTT-SN client communicating with a gateway over UDP.
+pub struct MqttSn<UdpStack, Clock, const MSG_SIZE: usize, const TOPIC_COUNT: usize>
+where
+    UdpStack: UdpClientStack,
+    Clock: embedded_time::Clock,
+{
+    pub client: MqttSnClient<UdpStack, Clock, MSG_SIZE, TOPIC_COUNT>,
+    buffer: [u8; MSG_SIZE],
+}
+
+impl<UdpStack, Clock, const MSG_SIZE: usize, const TOPIC_COUNT: usize>
+    MqttSn<UdpStack, Clock, MSG_SIZE, TOPIC_COUNT>
+where
+    UdpStack: UdpClientStack,
+    Clock: embedded_time::Clock,
+{
+    /// Construct a new MQTT-SN client.
+    ///
+    /// # Args
+    /// * `gateway` - The address of the gateway to connect to.
+    /// * `client_id` - The client ID to use for communicating with the gateway. It must consist of
+    ///   1 to 23 characters.
+    /// * `network_stack` - The UDP network stack to use for communication.
+    /// * `clock` - The clock to use for managing retransmissions and keep-alive.
+    pub fn new(
+        gateway: SocketAddr,
+        client_id: &str,
+        network_stack: UdpStack,
+        clock: Clock,
+    ) -> Result<Self, Error<UdpStack::Error>> {
+        let client_id = String::from_str(client_id).or(Err(Error::ProvidedClientIdTooLong))?;
+
+        Ok(Self {
+            client: MqttSnClient {
+                network: network_stack,
+                socket: None,
+                gateway,
+                clock,
+                client_id,
+                state: State::Disconnected,
+                keep_alive: DEFAULT_KEEP_ALIVE,
+                retry_interval: DEFAULT_RETRY_INTERVAL,
+                msg_id: 0,
+                request: None,
+                topics: Vec::new(),
+                last_transmission: None,
+            },
+            buffer: [0; MSG_SIZE],
+        })
+    }
+
+    /// Check the gateway for a received message and service the connection.
+    ///
+    /// # Note
+    /// This function should be called periodically to connect to the gateway, retransmit
+    /// unacknowledged requests and keep the connection alive.
+    ///
+    /// # Args
+    /// * `f` - A closure to process a received message. It is provided the client, the topic of the
+    ///   message and its payload.
+    ///
+    /// # Returns
+    /// The value returned by the closure if a message was received.
+    pub fn poll<F, R>(&mut self, mut f: F) -> Result<Option<R>, Error<UdpStack::Error>>
+    where
+        F: FnMut(&mut MqttSnClient<UdpStack, Clock, MSG_SIZE, TOPIC_COUNT>, &str, &[u8]) -> R,
+    {
+        self.client.update()?;
+
+        let socket = match self.client.socket.as_mut() {
+            Some(socket) => socket,
+            None => return Ok(None),
+        };
+
+        let length = match self.client.network.receive(socket, &mut self.buffer) {
+            Ok((length, _)) => length,
+            Err(nb::Error::WouldBlock) => return Ok(None),
+            Err(nb::Error::Other(error)) => return Err(Error::Network(error)),
+        };
+
+        match Packet::parse(&self.buffer[..length]) {
+            Ok(packet) => self.client.handle_packet(packet, &mut f),
+            Err(ProtocolError::UnsupportedPacket) => {
+                debug!("Ignoring unsupported packet");
+                Ok(None)
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, MqttSn, Packet};
+    use crate::{Error, QoS, Retain};
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, UdpClientStack};
+    use embedded_time::{clock, duration::Milliseconds, fraction::Fraction, Clock, Instant};
+    use heapless::{Deque, Vec};
+
+    /// A clock reporting the milliseconds stored by the test.
+    struct TestClock(&'static AtomicU32);
+
+    impl Clock for TestClock {
+        type T = u32;
+
+        const SCALING_FACTOR: Fraction = Fraction::new(1, 1000);
+
+        fn try_now(&self) -> Result<Instant<Self>, clock::Error> {
+            Ok(Instant::new(self.0.load(Ordering::Relaxed)))
+        }
+    }
+
+    /// A gateway that answers with queued datagrams and records the datagrams it received.
+    #[derive(Default)]
+    struct Gateway {
+        rx: Deque<Vec<u8, 64>, 8>,
+        tx: Vec<Vec<u8, 64>, 16>,
+    }
+
+    impl Gateway {
+        fn answer(&mut self, datagram: &[u8]) {
+            self.rx
+                .push_back(Vec::from_slice(datagram).unwrap())
+                .unwrap();
+        }
+    }
+
+    impl UdpClientStack for Gateway {
+        type UdpSocket = ();
+        type Error = ();
+
+        fn socket(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn connect(&mut self, _socket: &mut (), _remote: SocketAddr) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn send(&mut self, _socket: &mut (), buffer: &[u8]) -> nb::Result<(), ()> {
+            self.tx.push(Vec::from_slice(buffer).unwrap()).unwrap();
+            Ok(())
+        }
+
+        fn receive(
+            &mut self,
+            _socket: &mut (),
+            buffer: &mut [u8],
+        ) -> nb::Result<(usize, SocketAddr), ()> {
+            let datagram = self.rx.pop_front().ok_or(nb::Error::WouldBlock)?;
+            buffer[..datagram.len()].copy_from_slice(&datagram);
+            Ok((datagram.len(), gateway()))
+        }
+
+        fn close(&mut self, _socket: ()) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    fn gateway() -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 10000)
+    }
+
+    #[test]
+    fn packets_are_encoded() {
+        let packet: Vec<u8, 8> = encode(0x16, &[]).unwrap();
+        assert_eq!(packet, [0x02, 0x16]);
+
+        let packet: Vec<u8, 8> = encode(0x0A, &[&[0, 0], &[0, 1], b"ab"]).unwrap();
+        assert_eq!(packet, [0x08, 0x0A, 0, 0, 0, 1, b'a', b'b']);
+
+        // Long packets use the three-byte length encoding.
+        let payload = [0u8; 300];
+        let packet: Vec<u8, 512> = encode(0x0C, &[&payload]).unwrap();
+        assert_eq!(packet[..4], [0x01, 0x01, 0x30, 0x0C]);
+        assert_eq!(packet.len(), 304);
+        assert_eq!(
+            Packet::parse(&packet),
+            Ok(Packet::Publish {
+                flags: 0,
+                topic_id: 0,
+                msg_id: 0,
+                payload: &payload[5..],
+            })
+        );
+
+        assert!(encode::<4>(0x0C, &[&payload]).is_err());
+        assert!(Packet::parse(&[0x05, 0x05]).is_err());
+    }
+
+    #[test]
+    fn register_publish_and_receive() {
+        static NOW: AtomicU32 = AtomicU32::new(0);
+        let mut mqtt: MqttSn<_, _, 64, 4> =
+            MqttSn::new(gateway(), "sn", Gateway::default(), TestClock(&NOW)).unwrap();
+        mqtt.client.set_retry_interval(Milliseconds(1000));
+
+        // The client connects right away and retransmits the request until it is answered.
+        assert_eq!(mqtt.poll(|_, _, _| ()), Ok(None));
+        NOW.store(1000, Ordering::Relaxed);
+        mqtt.client.network.answer(&[0x03, 0x05, 0x00]);
+        assert_eq!(mqtt.poll(|_, _, _| ()), Ok(None));
+        assert!(mqtt.client.is_connected());
+        let connect = [0x08, 0x04, 0x04, 0x01, 0x00, 0x3b, b's', b'n'];
+        assert_eq!(mqtt.client.network.tx, [connect, connect]);
+        mqtt.client.network.tx.clear();
+
+        // Publishing to an unknown topic registers it first.
+        assert_eq!(
+            mqtt.client
+                .publish("data", b"1", QoS::AtLeastOnce, Retain::NotRetained),
+            Err(Error::NotReady)
+        );
+        assert!(mqtt.client.request_pending());
+        mqtt.client
+            .network
+            .answer(&[0x07, 0x0B, 0x00, 0x05, 0x00, 0x01, 0x00]);
+        mqtt.poll(|_, _, _| ()).unwrap();
+        assert_eq!(mqtt.client.topic_id("data"), Some(5));
+
+        mqtt.client
+            .publish("data", b"1", QoS::AtLeastOnce, Retain::NotRetained)
+            .unwrap();
+        assert_eq!(
+            mqtt.client
+                .publish("data", b"2", QoS::AtLeastOnce, Retain::NotRetained),
+            Err(Error::NotReady)
+        );
+        mqtt.client
+            .network
+            .answer(&[0x07, 0x0D, 0x00, 0x05, 0x00, 0x02, 0x00]);
+        mqtt.poll(|_, _, _| ()).unwrap();
+        assert!(!mqtt.client.request_pending());
+
+        let register = [0x0a, 0x0A, 0x00, 0x00, 0x00, 0x01, b'd', b'a', b't', b'a'];
+        let publish = [0x08, 0x0C, 0x20, 0x00, 0x05, 0x00, 0x02, b'1'];
+        assert_eq!(mqtt.client.network.tx[0], register);
+        assert_eq!(mqtt.client.network.tx[1], publish);
+        mqtt.client.network.tx.clear();
+
+        // Messages for topics registered by the gateway are delivered and acknowledged.
+        mqtt.client.network.answer(&[
+            0x0b, 0x0A, 0x00, 0x07, 0x00, 0x09, b'c', b'm', b'd', b'/', b'x',
+        ]);
+        mqtt.client
+            .network
+            .answer(&[0x08, 0x0C, 0x20, 0x00, 0x07, 0x00, 0x0a, b'!']);
+        assert_eq!(mqtt.poll(|_, _, _| ()), Ok(None));
+        let received = mqtt
+            .poll(|_, topic, payload| {
+                assert_eq!(topic, "cmd/x");
+                payload == b"!"
+            })
+            .unwrap();
+        assert_eq!(received, Some(true));
+
+        let regack = [0x07, 0x0B, 0x00, 0x07, 0x00, 0x09, 0x00];
+        let puback = [0x07, 0x0D, 0x00, 0x07, 0x00, 0x0a, 0x00];
+        assert_eq!(mqtt.client.network.tx, [regack, puback]);
+    }
+
+    #[test]
+    fn unacknowledged_request_reconnects() {
+        static NOW: AtomicU32 = AtomicU32::new(0);
+        let mut mqtt: MqttSn<_, _, 64, 4> =
+            MqttSn::new(gateway(), "sn", Gateway::default(), TestClock(&NOW)).unwrap();
+        mqtt.client.set_retry_interval(Milliseconds(1000));
+
+        mqtt.client.network.answer(&[0x03, 0x05, 0x00]);
+        mqtt.poll(|_, _, _| ()).unwrap();
+        mqtt.client.subscribe("cmd/#", QoS::AtMostOnce).unwrap();
+        mqtt.client.network.tx.clear();
+
+        for retry in 1..=5 {
+            NOW.store(retry * 1000, Ordering::Relaxed);
+            mqtt.poll(|_, _, _| ()).unwrap();
+            assert!(mqtt.client.is_connected());
+        }
+
+        assert_eq!(mqtt.client.network.tx.len(), 5);
+
+        // The gateway is considered lost once the retransmissions are exhausted.
+        NOW.store(6000, Ordering::Relaxed);
+        mqtt.poll(|_, _, _| ()).unwrap();
+        assert!(!mqtt.client.is_connected());
+        assert!(!mqtt.client.request_pending());
+
+        // The client connects again.
+        mqtt.poll(|_, _, _| ()).unwrap();
+        assert_eq!(mqtt.client.network.tx.last().unwrap()[1], 0x04);
+    }
+}
@@ -5,16 +5,17 @@
 //! simple ownership semantics of reading and writing to the network stack. This allows the network
 //! stack to be used to transmit buffers that may be stored internally in other structs without
 //! violating Rust's borrow rules.
+use core::ops::Range;
 use embedded_nal::{nb, SocketAddr, TcpClientStack};
-use heapless::Vec;
 
-use crate::Error;
+use crate::{pool::PacketBuffer, Error};
 
 /// Simple structure for maintaining state of the network connection.
 pub(crate) struct InterfaceHolder<TcpStack: TcpClientStack, const MSG_SIZE: usize> {
     socket: Option<TcpStack::TcpSocket>,
     network_stack: TcpStack,
-    pending_write: Option<Vec<u8, MSG_SIZE>>,
+    write_buffer: PacketBuffer<MSG_SIZE>,
+    pending_write: Option<Range<usize>>,
 }
 
 impl<TcpStack, const MSG_SIZE: usize> InterfaceHolder<TcpStack, MSG_SIZE>
@@ -26,6 +27,7 @@ where
         Self {
             socket: None,
             network_stack: stack,
+            write_buffer: PacketBuffer::Inline([0; MSG_SIZE]),
             pending_write: None,
         }
     }
@@ -44,7 +46,7 @@ where
         let socket = self.socket.as_ref().unwrap();
         self.network_stack
             .is_connected(socket)
-            .map_err(|err| Error::Network(err))
+            .map_err(Error::Network)
     }
 
     /// Allocate a new TCP socket.
@@ -53,27 +55,83 @@ where
     /// If a TCP socket was previously open, it will be closed and a new socket will be allocated.
     pub fn allocate_socket(&mut self) -> Result<(), Error<TcpStack::Error>> {
         if let Some(socket) = self.socket.take() {
-            self.network_stack
-                .close(socket)
-                .map_err(|err| Error::Network(err))?;
+            self.network_stack.close(socket).map_err(Error::Network)?;
         }
 
         // Allocate a new socket to use and begin connecting it.
-        self.socket.replace(
-            self.network_stack
-                .socket()
-                .map_err(|err| Error::Network(err))?,
-        );
+        self.socket
+            .replace(self.network_stack.socket().map_err(Error::Network)?);
 
         Ok(())
     }
 
+    /// Close the TCP socket if one is open.
+    pub fn close_socket(&mut self) -> Result<(), Error<TcpStack::Error>> {
+        // Any unfinished packet can no longer be sent.
+        self.pending_write.take();
+
+        if let Some(socket) = self.socket.take() {
+            self.network_stack.close(socket).map_err(Error::Network)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the network stack.
+    ///
+    /// # Note
+    /// The TCP socket must be closed before the network stack is replaced.
+    ///
+    /// # Args
+    /// * `stack` - The new network stack to use.
+    ///
+    /// # Returns
+    /// The previously used network stack.
+    pub fn replace_stack(&mut self, stack: TcpStack) -> TcpStack {
+        assert!(self.socket.is_none());
+        core::mem::replace(&mut self.network_stack, stack)
+    }
+
+    /// Replace the buffer that unfinished packet writes are stored in.
+    ///
+    /// # Note
+    /// Any unfinished packet is moved into the new buffer.
+    ///
+    /// # Args
+    /// * `buffer` - The new buffer to use.
+    pub fn replace_write_buffer(&mut self, mut buffer: PacketBuffer<MSG_SIZE>) {
+        if let Some(pending) = self.pending_write.take() {
+            let length = pending.len();
+            buffer[..length].copy_from_slice(&self.write_buffer[pending]);
+            self.pending_write.replace(0..length);
+        }
+
+        self.write_buffer = buffer;
+    }
+
+    /// Get the buffer that unfinished packet writes are stored in to assemble a packet in place.
+    ///
+    /// # Returns
+    /// `None` if the buffer still holds an unfinished packet.
+    pub fn write_buffer(&mut self) -> Option<&mut [u8]> {
+        if self.pending_write.is_some() {
+            return None;
+        }
+
+        Some(&mut self.write_buffer[..])
+    }
+
+    /// Get the network stack, e.g. to resolve the hostname of the broker.
+    pub fn stack_mut(&mut self) -> &mut TcpStack {
+        &mut self.network_stack
+    }
+
     /// Connect the TCP socket to a remote address.
     ///
     /// # Args
     /// * `remote` - The address of the remote to connect to.
     pub fn connect(&mut self, remote: SocketAddr) -> Result<(), Error<TcpStack::Error>> {
-        let socket = self.socket.as_mut().ok_or(Error::NotReady)?;
+        let socket = self.socket.as_mut().ok_or(Error::NotConnected)?;
 
         // Drop any pending unfinished packets, as we're establishing a new connection.
         self.pending_write.take();
@@ -95,33 +153,74 @@ where
         // previous write must first be completed.
         assert!(self.pending_write.is_none());
 
-        let socket = self.socket.as_mut().ok_or(Error::NotReady)?;
-        self.network_stack
-            .send(socket, &data)
-            .or_else(|err| match err {
-                nb::Error::WouldBlock => Ok(0),
-                nb::Error::Other(err) => Err(Error::Network(err)),
-            })
-            .and_then(|written| {
-                if written != data.len() {
-                    // Note(unwrap): The packet should always be smaller than a single message.
-                    self.pending_write
-                        .replace(Vec::from_slice(&data[written..]).unwrap());
-                }
-
-                Ok(())
-            })
+        let socket = self.socket.as_mut().ok_or(Error::NotConnected)?;
+        let written = Self::send_to(&mut self.network_stack, socket, data)?;
+        if written != data.len() {
+            let remainder = &data[written..];
+
+            // Note: The packet is never larger than a single message, so it fits into the buffer.
+            self.write_buffer[..remainder.len()].copy_from_slice(remainder);
+            self.pending_write.replace(0..remainder.len());
+        }
+
+        Ok(())
+    }
+
+    /// Write a packet that was assembled in the buffer provided by `write_buffer()`.
+    ///
+    /// # Args
+    /// * `packet` - The location of the packet in the buffer.
+    ///
+    /// # Returns
+    /// The packet that was written.
+    pub fn write_in_place(
+        &mut self,
+        packet: Range<usize>,
+    ) -> Result<&[u8], Error<TcpStack::Error>> {
+        assert!(self.pending_write.is_none());
+
+        let socket = self.socket.as_mut().ok_or(Error::NotConnected)?;
+        let data = &self.write_buffer[packet.clone()];
+        let written = Self::send_to(&mut self.network_stack, socket, data)?;
+        if written != data.len() {
+            self.pending_write
+                .replace(packet.start + written..packet.end);
+        }
+
+        Ok(&self.write_buffer[packet])
     }
 
     /// Finish writing an MQTT control packet to the interface if one exists.
     pub fn finish_write(&mut self) -> Result<(), Error<TcpStack::Error>> {
-        if let Some(ref packet) = self.pending_write.take() {
-            self.write(packet.as_slice())?;
+        if let Some(pending) = self.pending_write.take() {
+            let socket = self.socket.as_mut().ok_or(Error::NotConnected)?;
+            let written = Self::send_to(
+                &mut self.network_stack,
+                socket,
+                &self.write_buffer[pending.clone()],
+            )?;
+
+            if written != pending.len() {
+                self.pending_write
+                    .replace(pending.start + written..pending.end);
+            }
         }
 
         Ok(())
     }
 
+    /// Send data over a socket, returning the number of bytes that were written.
+    fn send_to(
+        stack: &mut TcpStack,
+        socket: &mut TcpStack::TcpSocket,
+        data: &[u8],
+    ) -> Result<usize, Error<TcpStack::Error>> {
+        stack.send(socket, data).or_else(|err| match err {
+            nb::Error::WouldBlock => Ok(0),
+            nb::Error::Other(err) => Err(Error::Network(err)),
+        })
+    }
+
     /// Read data from the TCP interface.
     ///
     /// # Args
@@ -129,10 +228,10 @@ where
     ///
     /// # Returns
     /// The number of bytes successfully read.
-    pub fn read(&mut self, mut buf: &mut [u8]) -> Result<usize, Error<TcpStack::Error>> {
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error<TcpStack::Error>> {
         // Atomically access the socket.
-        let socket = self.socket.as_mut().ok_or(Error::NotReady)?;
-        let result = self.network_stack.receive(socket, &mut buf);
+        let socket = self.socket.as_mut().ok_or(Error::NotConnected)?;
+        let result = self.network_stack.receive(socket, buf);
 
         result.or_else(|err| match err {
             nb::Error::WouldBlock => Ok(0),
@@ -140,3 +239,74 @@ where
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::InterfaceHolder;
+    use crate::{pool::PacketBuffer, BufferPool, BufferSource};
+    use embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, TcpClientStack};
+    use heapless::Vec;
+
+    /// A stack that accepts at most three bytes per call to `send()`.
+    #[derive(Default)]
+    struct SlowStack {
+        tx: Vec<u8, 32>,
+    }
+
+    impl TcpClientStack for SlowStack {
+        type TcpSocket = ();
+        type Error = ();
+
+        fn socket(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn connect(&mut self, _socket: &mut (), _remote: SocketAddr) -> nb::Result<(), ()> {
+            Ok(())
+        }
+
+        fn is_connected(&mut self, _socket: &()) -> Result<bool, ()> {
+            Ok(true)
+        }
+
+        fn send(&mut self, _socket: &mut (), buffer: &[u8]) -> nb::Result<usize, ()> {
+            let len = core::cmp::min(buffer.len(), 3);
+            self.tx.extend_from_slice(&buffer[..len]).unwrap();
+            Ok(len)
+        }
+
+        fn receive(&mut self, _socket: &mut (), _buffer: &mut [u8]) -> nb::Result<usize, ()> {
+            Err(nb::Error::WouldBlock)
+        }
+
+        fn close(&mut self, _socket: ()) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn pending_write_moves_to_pooled_buffer() {
+        static POOL: BufferPool<16, 1> = BufferPool::new();
+
+        let mut network: InterfaceHolder<_, 16> = InterfaceHolder::new(SlowStack::default());
+        network.allocate_socket().unwrap();
+        network
+            .connect(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                1883,
+            ))
+            .unwrap();
+
+        network.write(&[1, 2, 3, 4, 5, 6, 7]).unwrap();
+        assert!(network.has_pending_write());
+
+        network.replace_write_buffer(PacketBuffer::new(Some(&POOL)).unwrap());
+        assert_eq!(POOL.available(), 0);
+
+        while network.has_pending_write() {
+            network.finish_write().unwrap();
+        }
+
+        assert_eq!(network.network_stack.tx, [1, 2, 3, 4, 5, 6, 7][..]);
+    }
+}
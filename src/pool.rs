@@ -0,0 +1,343 @@
+//! Buffer Pools
+//!
+//! # Design
+//! Messages passed to the `Minimq::poll()` handler borrow the internal packet reader, so they are
+//! only valid for the duration of the handler. The `MessagePool` provides a fixed number of
+//! buffers that the handler can copy a received message into. The resulting `PooledMessage` owns
+//! its buffer until it is dropped, at which point the buffer is returned to the pool. This allows
+//! received messages to be handed off to another task.
+//!
+//! # Note
+//! The hand-off is not zero-copy: `MessagePool::store()` copies the topic and payload out of the
+//! packet reader once. The message is not copied again when the `PooledMessage` is moved.
+//!
+//! The underlying `BufferPool` may also be shared between multiple clients. Gateways maintaining
+//! connections to several brokers can then claim the receive and transmit buffers and the queued
+//! messages of all clients from a single pool (see `Minimq::set_buffer_pool()`), which bounds the
+//! number of buffers in use across all clients. Without a pool, every client stores its buffers
+//! inline.
+//!
+//! # Example
+//! ```
+//! use minimq::MessagePool;
+//!
+//! static POOL: MessagePool<128, 4> = MessagePool::new();
+//!
+//! // Inside of the `poll()` handler, take ownership of the message.
+//! let message = POOL.store("topic", &[0xAB, 0xCD]).unwrap();
+//! assert_eq!(POOL.available(), 3);
+//!
+//! // The message can now be moved elsewhere and inspected later.
+//! assert_eq!(message.topic(), "topic");
+//! assert_eq!(message.payload(), &[0xAB, 0xCD]);
+//!
+//! // Dropping the message returns the buffer to the pool.
+//! drop(message);
+//! assert_eq!(POOL.available(), 4);
+//! ```
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Errors that may occur when claiming a buffer from a pool.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PoolError {
+    /// All buffers of the pool are currently in use.
+    Exhausted,
+
+    /// The message is larger than the buffers of the pool.
+    TooLarge,
+}
+
+struct Slot<const SIZE: usize> {
+    in_use: AtomicBool,
+    buffer: UnsafeCell<[u8; SIZE]>,
+}
+
+impl<const SIZE: usize> Slot<SIZE> {
+    // Note: This constant is only used to initialize the slot array of a new pool.
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Self = Slot {
+        in_use: AtomicBool::new(false),
+        buffer: UnsafeCell::new([0; SIZE]),
+    };
+}
+
+// Safety: The buffer of a slot is only ever accessed by the single `PooledBuffer` that claimed the
+// slot through the atomic `in_use` flag.
+unsafe impl<const SIZE: usize> Sync for Slot<SIZE> {}
+
+/// A source of buffers that may be shared between multiple users, such as a `BufferPool`.
+pub trait BufferSource<const SIZE: usize>: Sync {
+    /// Claim an unused buffer.
+    ///
+    /// # Returns
+    /// A handle that owns the buffer. The buffer is returned once the handle is dropped.
+    fn claim(&self) -> Result<PooledBuffer<'_, SIZE>, PoolError>;
+
+    /// Get the number of buffers that are currently unused.
+    fn available(&self) -> usize;
+}
+
+/// A fixed-size pool of buffers.
+///
+/// # Note
+/// `SIZE` is the number of bytes of each buffer. `COUNT` is the number of buffers that may be
+/// claimed simultaneously.
+pub struct BufferPool<const SIZE: usize, const COUNT: usize> {
+    slots: [Slot<SIZE>; COUNT],
+}
+
+impl<const SIZE: usize, const COUNT: usize> Default for BufferPool<SIZE, COUNT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize, const COUNT: usize> BufferPool<SIZE, COUNT> {
+    /// Construct a new, empty buffer pool.
+    pub const fn new() -> Self {
+        Self {
+            slots: [Slot::EMPTY; COUNT],
+        }
+    }
+}
+
+impl<const SIZE: usize, const COUNT: usize> BufferSource<SIZE> for BufferPool<SIZE, COUNT> {
+    fn claim(&self) -> Result<PooledBuffer<'_, SIZE>, PoolError> {
+        let slot = self
+            .slots
+            .iter()
+            .find(|slot| {
+                slot.in_use
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            })
+            .ok_or(PoolError::Exhausted)?;
+
+        Ok(PooledBuffer { slot })
+    }
+
+    fn available(&self) -> usize {
+        self.slots
+            .iter()
+            .filter(|slot| !slot.in_use.load(Ordering::Relaxed))
+            .count()
+    }
+}
+
+/// A buffer claimed from a `BufferPool`.
+pub struct PooledBuffer<'a, const SIZE: usize> {
+    slot: &'a Slot<SIZE>,
+}
+
+impl<'a, const SIZE: usize> Deref for PooledBuffer<'a, SIZE> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // Safety: The slot is claimed for as long as this buffer exists.
+        unsafe { &*self.slot.buffer.get() }
+    }
+}
+
+impl<'a, const SIZE: usize> DerefMut for PooledBuffer<'a, SIZE> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // Safety: The slot is claimed for as long as this buffer exists, and the buffer is only
+        // accessible through this handle.
+        unsafe { &mut *self.slot.buffer.get() }
+    }
+}
+
+impl<'a, const SIZE: usize> Drop for PooledBuffer<'a, SIZE> {
+    fn drop(&mut self) {
+        self.slot.in_use.store(false, Ordering::Release);
+    }
+}
+
+impl<'a, const SIZE: usize> core::fmt::Debug for PooledBuffer<'a, SIZE> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PooledBuffer").finish()
+    }
+}
+
+/// A packet buffer that is either stored inline or claimed from a `BufferSource`.
+pub(crate) enum PacketBuffer<const SIZE: usize> {
+    Inline([u8; SIZE]),
+    Pooled(PooledBuffer<'static, SIZE>),
+}
+
+impl<const SIZE: usize> PacketBuffer<SIZE> {
+    /// Get a packet buffer.
+    ///
+    /// # Args
+    /// * `pool` - The pool to claim the buffer from. If `None`, the buffer is stored inline.
+    pub fn new(pool: Option<&'static dyn BufferSource<SIZE>>) -> Result<Self, PoolError> {
+        match pool {
+            Some(pool) => pool.claim().map(PacketBuffer::Pooled),
+            None => Ok(PacketBuffer::Inline([0; SIZE])),
+        }
+    }
+}
+
+impl<const SIZE: usize> Deref for PacketBuffer<SIZE> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            PacketBuffer::Inline(buffer) => buffer,
+            PacketBuffer::Pooled(buffer) => buffer,
+        }
+    }
+}
+
+impl<const SIZE: usize> DerefMut for PacketBuffer<SIZE> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            PacketBuffer::Inline(buffer) => buffer,
+            PacketBuffer::Pooled(buffer) => buffer,
+        }
+    }
+}
+
+impl<const SIZE: usize> AsRef<[u8]> for PacketBuffer<SIZE> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<const SIZE: usize> AsMut<[u8]> for PacketBuffer<SIZE> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+/// A fixed-size pool of buffers for received messages.
+///
+/// # Note
+/// `SIZE` is the number of bytes available to each message for both the topic and the payload.
+/// `COUNT` is the number of messages that may be owned simultaneously.
+pub struct MessagePool<const SIZE: usize, const COUNT: usize> {
+    pool: BufferPool<SIZE, COUNT>,
+}
+
+impl<const SIZE: usize, const COUNT: usize> Default for MessagePool<SIZE, COUNT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize, const COUNT: usize> MessagePool<SIZE, COUNT> {
+    /// Construct a new, empty message pool.
+    pub const fn new() -> Self {
+        Self {
+            pool: BufferPool::new(),
+        }
+    }
+
+    /// Copy a received message into a buffer of the pool.
+    ///
+    /// # Args
+    /// * `topic` - The topic that the message was received on.
+    /// * `payload` - The payload of the received message.
+    ///
+    /// # Returns
+    /// A handle that owns the pooled buffer. The buffer is returned to the pool once the handle is
+    /// dropped.
+    pub fn store(&self, topic: &str, payload: &[u8]) -> Result<PooledMessage<'_, SIZE>, PoolError> {
+        if topic.len() + payload.len() > SIZE {
+            return Err(PoolError::TooLarge);
+        }
+
+        let mut buffer = self.pool.claim()?;
+        buffer[..topic.len()].copy_from_slice(topic.as_bytes());
+        buffer[topic.len()..][..payload.len()].copy_from_slice(payload);
+
+        Ok(PooledMessage {
+            buffer,
+            topic_length: topic.len(),
+            payload_length: payload.len(),
+        })
+    }
+
+    /// Get the number of buffers that are currently unused.
+    pub fn available(&self) -> usize {
+        self.pool.available()
+    }
+}
+
+/// A received message that owns a buffer of a `MessagePool`.
+pub struct PooledMessage<'a, const SIZE: usize> {
+    buffer: PooledBuffer<'a, SIZE>,
+    topic_length: usize,
+    payload_length: usize,
+}
+
+impl<'a, const SIZE: usize> PooledMessage<'a, SIZE> {
+    /// The topic that the message was received on.
+    pub fn topic(&self) -> &str {
+        // Note(unwrap): The topic was copied from a valid string slice.
+        core::str::from_utf8(&self.buffer[..self.topic_length]).unwrap()
+    }
+
+    /// The payload of the message.
+    pub fn payload(&self) -> &[u8] {
+        &self.buffer[self.topic_length..][..self.payload_length]
+    }
+}
+
+impl<'a, const SIZE: usize> core::fmt::Debug for PooledMessage<'a, SIZE> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PooledMessage")
+            .field("topic", &self.topic())
+            .field("payload", &self.payload())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufferPool, BufferSource, MessagePool, PoolError};
+
+    #[test]
+    fn pool_exhaustion() {
+        let pool: MessagePool<16, 2> = MessagePool::new();
+        let first = pool.store("a", &[1]).unwrap();
+        let second = pool.store("b", &[2]).unwrap();
+        assert_eq!(pool.store("c", &[3]).unwrap_err(), PoolError::Exhausted);
+
+        drop(first);
+        let third = pool.store("c", &[3]).unwrap();
+
+        assert_eq!(second.topic(), "b");
+        assert_eq!(second.payload(), &[2]);
+        assert_eq!(third.topic(), "c");
+        assert_eq!(third.payload(), &[3]);
+    }
+
+    #[test]
+    fn pool_message_too_large() {
+        let pool: MessagePool<4, 1> = MessagePool::new();
+        assert_eq!(pool.store("ABC", &[1, 2]).unwrap_err(), PoolError::TooLarge);
+        assert_eq!(pool.available(), 1);
+
+        let message = pool.store("ABC", &[1]).unwrap();
+        assert_eq!(message.payload(), &[1]);
+    }
+
+    #[test]
+    fn buffer_pool_shared_between_users() {
+        static POOL: BufferPool<16, 2> = BufferPool::new();
+        let sources: [&'static dyn BufferSource<16>; 2] = [&POOL, &POOL];
+
+        let mut first = sources[0].claim().unwrap();
+        let second = sources[1].claim().unwrap();
+        assert_eq!(sources[0].claim().unwrap_err(), PoolError::Exhausted);
+        assert_eq!(second.len(), 16);
+
+        first[0] = 0xAB;
+        assert_eq!(first[0], 0xAB);
+
+        drop(first);
+        assert_eq!(sources[1].available(), 1);
+    }
+}
@@ -86,7 +86,7 @@ impl From<usize> for PropertyIdentifier {
             }
         }
 
-        return PropertyIdentifier::Invalid;
+        PropertyIdentifier::Invalid
     }
 }
 
@@ -131,9 +131,7 @@ impl<'a> Property<'a> {
         }
     }
 
-    pub(crate) fn parse<'reader: 'a, const T: usize>(
-        packet: &'reader PacketReader<T>,
-    ) -> Result<Property<'a>, Error> {
+    pub(crate) fn parse<'reader: 'a>(packet: &'reader PacketReader) -> Result<Property<'a>, Error> {
         let identifier: PropertyIdentifier = packet.read_variable_length_integer()?.into();
 
         match identifier {
@@ -235,7 +233,7 @@ impl<'a> Property<'a> {
             Property::CorrelationData(data) | Property::AuthenticationData(data) => {
                 data.len() + 2 + identifier_length
             }
-            Property::SubscriptionIdentifier(id) => integer_size(*id),
+            Property::SubscriptionIdentifier(id) => integer_size(*id) + identifier_length,
 
             Property::MessageExpiryInterval(_)
             | Property::SessionExpiryInterval(_)
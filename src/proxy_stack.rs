@@ -0,0 +1,411 @@
+//! HTTP CONNECT Proxy Network Stack
+//!
+//! # Design
+//! In factory and enterprise networks, outbound connections are frequently only possible through
+//! an HTTP proxy. The `ProxyStack` wraps another network stack and tunnels the connection to the
+//! broker through such a proxy using the HTTP `CONNECT` method. The proxy is connected to instead
+//! of the broker, after which the tunnel to the broker address is requested. The socket is only
+//! reported as connected once the proxy accepted the tunnel, so the client starts communicating
+//! with the broker afterwards.
+//!
+//! Credentials for HTTP basic authentication may be provided using `set_credentials()`.
+//!
+//! # Note
+//! If the proxy refuses the tunnel, `ProxyError::Refused` is returned when connecting, and the
+//! client retries with a new socket.
+use core::fmt::Write;
+use embedded_nal::{nb, SocketAddr, TcpClientStack};
+use heapless::{String, Vec};
+
+/// The maximum length of the encoded credentials for basic authentication.
+const MAX_CREDENTIALS_LENGTH: usize = 128;
+
+/// The maximum length of a tunnel request.
+const MAX_REQUEST_LENGTH: usize = 256;
+
+/// The length of the status line prefix containing the status code, e.g. `HTTP/1.1 200`.
+pub(crate) const STATUS_LENGTH: usize = 12;
+
+/// Errors that may occur when using a `ProxyStack`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ProxyError<E> {
+    /// The underlying network stack encountered an error.
+    Network(E),
+
+    /// The proxy refused to establish the tunnel with the contained HTTP status code.
+    Refused(u16),
+
+    /// The response of the proxy was not a valid HTTP response.
+    MalformedResponse,
+
+    /// The credentials do not fit into the proxy configuration.
+    CredentialsTooLong,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Tunnel {
+    Connecting,
+    Requesting { sent: usize },
+    AwaitingResponse,
+    Established,
+}
+
+/// Parse the status code from the status line of an HTTP response, e.g. `HTTP/1.1 200`.
+pub(crate) fn parse_status(status: &[u8]) -> Option<u16> {
+    core::str::from_utf8(status)
+        .ok()
+        .filter(|status| status.starts_with("HTTP/1."))
+        .and_then(|status| status.get(9..)?.parse::<u16>().ok())
+}
+
+/// A network stack that tunnels connections through an HTTP proxy.
+pub struct ProxyStack<S> {
+    stack: S,
+    proxy: SocketAddr,
+    credentials: Option<String<MAX_CREDENTIALS_LENGTH>>,
+}
+
+/// The socket of a `ProxyStack`.
+#[derive(Debug)]
+pub struct ProxySocket<T> {
+    socket: T,
+    tunnel: Tunnel,
+    request: Vec<u8, MAX_REQUEST_LENGTH>,
+    status: Vec<u8, STATUS_LENGTH>,
+    trailer: [u8; 4],
+}
+
+/// Encode data using base64 with padding.
+pub(crate) fn write_base64<const N: usize>(data: &[u8], dest: &mut String<N>) -> Result<(), ()> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    for chunk in data.chunks(3) {
+        let block = chunk.iter().enumerate().fold(0u32, |block, (i, byte)| {
+            block | (*byte as u32) << (16 - 8 * i)
+        });
+
+        for i in 0..4 {
+            let symbol = if i <= chunk.len() {
+                ALPHABET[(block >> (18 - 6 * i)) as usize & 0x3F] as char
+            } else {
+                '='
+            };
+            dest.push(symbol)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl<S: TcpClientStack> ProxyStack<S> {
+    /// Construct a network stack that tunnels connections through a proxy.
+    ///
+    /// # Args
+    /// * `stack` - The network stack used to connect to the proxy.
+    /// * `proxy` - The address of the proxy.
+    pub fn new(stack: S, proxy: SocketAddr) -> Self {
+        Self {
+            stack,
+            proxy,
+            credentials: None,
+        }
+    }
+
+    /// Authenticate with the proxy using HTTP basic authentication.
+    ///
+    /// # Args
+    /// * `username` - The name of the user to authenticate as.
+    /// * `password` - The password of the user.
+    pub fn set_credentials(
+        &mut self,
+        username: &str,
+        password: &str,
+    ) -> Result<(), ProxyError<S::Error>> {
+        let mut plain: Vec<u8, { MAX_CREDENTIALS_LENGTH / 4 * 3 }> = Vec::new();
+        plain
+            .extend_from_slice(username.as_bytes())
+            .and_then(|_| plain.push(b':').map_err(|_| ()))
+            .and_then(|_| plain.extend_from_slice(password.as_bytes()))
+            .map_err(|_| ProxyError::CredentialsTooLong)?;
+
+        let mut encoded = String::new();
+        write_base64(&plain, &mut encoded).map_err(|_| ProxyError::CredentialsTooLong)?;
+        self.credentials.replace(encoded);
+        Ok(())
+    }
+
+    /// Get a reference to the underlying network stack.
+    pub fn inner(&self) -> &S {
+        &self.stack
+    }
+
+    /// Get a mutable reference to the underlying network stack.
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.stack
+    }
+
+    fn request_tunnel(
+        &self,
+        request: &mut Vec<u8, MAX_REQUEST_LENGTH>,
+        remote: SocketAddr,
+    ) -> Result<(), core::fmt::Error> {
+        let mut text: String<MAX_REQUEST_LENGTH> = String::new();
+        write!(text, "CONNECT {} HTTP/1.1\r\nHost: {}\r\n", remote, remote)?;
+
+        if let Some(credentials) = &self.credentials {
+            write!(text, "Proxy-Authorization: Basic {}\r\n", credentials)?;
+        }
+
+        text.push_str("\r\n").map_err(|_| core::fmt::Error)?;
+
+        request.clear();
+        // Note(unwrap): The request has the same capacity as the string.
+        request.extend_from_slice(text.as_bytes()).unwrap();
+        Ok(())
+    }
+
+    /// Receive the response of the proxy to the tunnel request.
+    ///
+    /// # Note
+    /// The response is read byte by byte, so no data sent through the tunnel is consumed.
+    fn receive_response(
+        &mut self,
+        socket: &mut ProxySocket<S::TcpSocket>,
+    ) -> nb::Result<(), ProxyError<S::Error>> {
+        loop {
+            let mut byte = [0];
+            let received = self
+                .stack
+                .receive(&mut socket.socket, &mut byte)
+                .map_err(|err| err.map(ProxyError::Network))?;
+            if received == 0 {
+                return Err(nb::Error::WouldBlock);
+            }
+
+            // Only the status code of the response is of interest.
+            socket.status.push(byte[0]).ok();
+            socket.trailer.rotate_left(1);
+            socket.trailer[3] = byte[0];
+
+            // The headers of the response are terminated by an empty line.
+            if &socket.trailer == b"\r\n\r\n" {
+                break;
+            }
+        }
+
+        let status =
+            parse_status(&socket.status).ok_or(nb::Error::Other(ProxyError::MalformedResponse))?;
+
+        if !(200..300).contains(&status) {
+            return Err(nb::Error::Other(ProxyError::Refused(status)));
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: TcpClientStack> TcpClientStack for ProxyStack<S> {
+    type TcpSocket = ProxySocket<S::TcpSocket>;
+    type Error = ProxyError<S::Error>;
+
+    fn socket(&mut self) -> Result<Self::TcpSocket, Self::Error> {
+        Ok(ProxySocket {
+            socket: self.stack.socket().map_err(ProxyError::Network)?,
+            tunnel: Tunnel::Connecting,
+            request: Vec::new(),
+            status: Vec::new(),
+            trailer: [0; 4],
+        })
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        remote: SocketAddr,
+    ) -> nb::Result<(), Self::Error> {
+        loop {
+            match socket.tunnel {
+                Tunnel::Connecting => {
+                    self.stack
+                        .connect(&mut socket.socket, self.proxy)
+                        .map_err(|err| err.map(ProxyError::Network))?;
+
+                    self.request_tunnel(&mut socket.request, remote)
+                        .map_err(|_| ProxyError::CredentialsTooLong)?;
+                    socket.tunnel = Tunnel::Requesting { sent: 0 };
+                }
+
+                Tunnel::Requesting { sent } => {
+                    let written = self
+                        .stack
+                        .send(&mut socket.socket, &socket.request[sent..])
+                        .map_err(|err| err.map(ProxyError::Network))?;
+
+                    socket.tunnel = if sent + written == socket.request.len() {
+                        Tunnel::AwaitingResponse
+                    } else {
+                        Tunnel::Requesting {
+                            sent: sent + written,
+                        }
+                    };
+                }
+
+                Tunnel::AwaitingResponse => {
+                    self.receive_response(socket)?;
+                    socket.tunnel = Tunnel::Established;
+                }
+
+                Tunnel::Established => return Ok(()),
+            }
+        }
+    }
+
+    fn is_connected(&mut self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+        if socket.tunnel != Tunnel::Established {
+            return Ok(false);
+        }
+
+        self.stack
+            .is_connected(&socket.socket)
+            .map_err(ProxyError::Network)
+    }
+
+    fn send(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &[u8],
+    ) -> nb::Result<usize, Self::Error> {
+        self.stack
+            .send(&mut socket.socket, buffer)
+            .map_err(|err| err.map(ProxyError::Network))
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, Self::Error> {
+        self.stack
+            .receive(&mut socket.socket, buffer)
+            .map_err(|err| err.map(ProxyError::Network))
+    }
+
+    fn close(&mut self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+        self.stack.close(socket.socket).map_err(ProxyError::Network)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_base64, ProxyError, ProxyStack};
+    use embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, TcpClientStack};
+    use heapless::{String, Vec};
+
+    #[derive(Default)]
+    struct Pipe {
+        connected: bool,
+        rx: Vec<u8, 128>,
+        tx: Vec<u8, 256>,
+    }
+
+    impl TcpClientStack for Pipe {
+        type TcpSocket = ();
+        type Error = ();
+
+        fn socket(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn connect(&mut self, _socket: &mut (), _remote: SocketAddr) -> nb::Result<(), ()> {
+            self.connected = true;
+            Ok(())
+        }
+
+        fn is_connected(&mut self, _socket: &()) -> Result<bool, ()> {
+            Ok(self.connected)
+        }
+
+        fn send(&mut self, _socket: &mut (), buffer: &[u8]) -> nb::Result<usize, ()> {
+            self.tx.extend_from_slice(buffer).unwrap();
+            Ok(buffer.len())
+        }
+
+        fn receive(&mut self, _socket: &mut (), buffer: &mut [u8]) -> nb::Result<usize, ()> {
+            let len = core::cmp::min(buffer.len(), self.rx.len());
+            buffer[..len].copy_from_slice(&self.rx[..len]);
+            self.rx = Vec::from_slice(&self.rx[len..]).unwrap();
+            Ok(len)
+        }
+
+        fn close(&mut self, _socket: ()) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    fn broker() -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 1883)
+    }
+
+    fn proxy() -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 3128)
+    }
+
+    #[test]
+    fn base64() {
+        let mut encoded: String<16> = String::new();
+        write_base64(b"user:pass", &mut encoded).unwrap();
+        assert_eq!(encoded, "dXNlcjpwYXNz");
+
+        encoded.clear();
+        write_base64(b"ab", &mut encoded).unwrap();
+        assert_eq!(encoded, "YWI=");
+    }
+
+    #[test]
+    fn proxy_establishes_tunnel() {
+        let mut stack = ProxyStack::new(Pipe::default(), proxy());
+        stack.set_credentials("user", "pass").unwrap();
+
+        let mut socket = stack.socket().unwrap();
+        assert_eq!(
+            stack.connect(&mut socket, broker()),
+            Err(nb::Error::WouldBlock)
+        );
+        assert!(!stack.is_connected(&socket).unwrap());
+        assert_eq!(
+            stack.inner().tx,
+            b"CONNECT 10.0.0.1:1883 HTTP/1.1\r\nHost: 10.0.0.1:1883\r\n\
+              Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n"[..]
+        );
+
+        // Data following the response belongs to the tunnel.
+        stack
+            .inner_mut()
+            .rx
+            .extend_from_slice(b"HTTP/1.1 200 Connection established\r\n\r\n\x20")
+            .unwrap();
+        stack.connect(&mut socket, broker()).unwrap();
+        assert!(stack.is_connected(&socket).unwrap());
+
+        let mut buffer = [0; 4];
+        assert_eq!(stack.receive(&mut socket, &mut buffer).unwrap(), 1);
+        assert_eq!(buffer[0], 0x20);
+    }
+
+    #[test]
+    fn proxy_refuses_tunnel() {
+        let mut stack = ProxyStack::new(Pipe::default(), proxy());
+        stack
+            .inner_mut()
+            .rx
+            .extend_from_slice(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+            .unwrap();
+
+        let mut socket = stack.socket().unwrap();
+        assert_eq!(
+            stack.connect(&mut socket, broker()),
+            Err(nb::Error::Other(ProxyError::Refused(407)))
+        );
+        assert!(!stack.is_connected(&socket).unwrap());
+    }
+}
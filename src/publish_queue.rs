@@ -0,0 +1,405 @@
+//! Outbound Publish Queue
+//!
+//! # Design
+//! Messages that cannot be transmitted immediately (e.g. because the connection to the broker is
+//! down) are serialized into the publish queue, where they wait until the broker can be written
+//! to. Each queued message carries a `Priority`. Messages are transmitted in order of priority,
+//! and messages of equal priority are transmitted in the order that they were queued. This ensures
+//! that a backlog of bulk data does not delay urgent messages once the link recovers.
+//!
+//! The packet identifier of a queued message is only assigned once the message is transmitted, so
+//! queued messages survive a reset of the session state.
+//!
+//! If the queue is full, the most recently queued message of the lowest priority is dropped to
+//! make room for a message of higher priority.
+//!
+//! Queued messages are stored inline, or in buffers claimed from a `BufferSource` that may be
+//! shared with other clients.
+//!
+//! A queued message may carry an expiry time, after which it is discarded without being
+//! transmitted. This prevents stale data from flooding the broker once the link recovers after an
+//! outage.
+use crate::{pool::PacketBuffer, Priority, QoS};
+use bit_field::BitField;
+use embedded_time::{Clock, Instant};
+use heapless::Vec;
+
+/// A serialized PUBLISH packet that is waiting to be transmitted.
+pub(crate) struct QueuedPublish<C: Clock, const MSG_SIZE: usize> {
+    priority: Priority,
+    expiry: Option<Instant<C>>,
+    buffer: PacketBuffer<MSG_SIZE>,
+    length: usize,
+}
+
+/// Get the offset of the topic length in the variable header of a serialized PUBLISH packet.
+fn topic_offset(packet: &[u8]) -> usize {
+    // The fixed header consists of the packet type and a variable-length remaining length.
+    let length_bytes = packet[1..]
+        .iter()
+        .position(|byte| !byte.get_bit(7))
+        .unwrap();
+
+    2 + length_bytes
+}
+
+/// Get the topic of a serialized PUBLISH packet.
+///
+/// # Args
+/// * `packet` - The serialized PUBLISH packet.
+pub(crate) fn publish_topic(packet: &[u8]) -> &str {
+    let offset = topic_offset(packet);
+    let length = u16::from_be_bytes([packet[offset], packet[offset + 1]]) as usize;
+
+    // Note(unwrap): The topic was serialized from a valid string slice.
+    core::str::from_utf8(&packet[offset + 2..][..length]).unwrap()
+}
+
+impl<C: Clock, const MSG_SIZE: usize> QueuedPublish<C, MSG_SIZE> {
+    /// The serialized PUBLISH packet.
+    pub fn packet(&self) -> &[u8] {
+        &self.buffer[..self.length]
+    }
+
+    /// The quality-of-service level that the message is published with.
+    pub fn qos(&self) -> QoS {
+        match self.buffer[0].get_bits(1..=2) {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            _ => QoS::ExactlyOnce,
+        }
+    }
+
+    /// The topic that the message is published to.
+    pub fn topic(&self) -> &str {
+        publish_topic(self.packet())
+    }
+
+    /// Assign the packet identifier that the message is transmitted with.
+    ///
+    /// # Note
+    /// The packet identifier is only present for QoS levels above `AtMostOnce`.
+    ///
+    /// # Args
+    /// * `id` - The packet identifier to assign.
+    pub fn set_packet_id(&mut self, id: u16) {
+        if self.qos() == QoS::AtMostOnce {
+            return;
+        }
+
+        let id_offset = topic_offset(&self.buffer) + 2 + self.topic().len();
+        self.buffer[id_offset..][..2].copy_from_slice(&id.to_be_bytes());
+    }
+}
+
+/// A bounded queue of outbound messages ordered by priority.
+pub(crate) struct PublishQueue<C: Clock, const MSG_SIZE: usize, const MSG_COUNT: usize> {
+    messages: Vec<QueuedPublish<C, MSG_SIZE>, MSG_COUNT>,
+}
+
+impl<C: Clock, const MSG_SIZE: usize, const MSG_COUNT: usize> PublishQueue<C, MSG_SIZE, MSG_COUNT> {
+    /// Construct a new, empty queue.
+    pub fn new() -> Self {
+        Self {
+            messages: Vec::new(),
+        }
+    }
+
+    /// Add a serialized PUBLISH packet to the queue.
+    ///
+    /// # Args
+    /// * `priority` - The priority of the message.
+    /// * `packet` - The serialized PUBLISH packet. The packet identifier is assigned upon
+    ///   transmission.
+    /// * `buffer` - The buffer to store the packet in. A pooled buffer is released when the message
+    ///   is removed from the queue.
+    /// * `expiry` - The time after which the message is discarded if it was not transmitted. If
+    ///   `None`, the message does not expire.
+    ///
+    /// # Returns
+    /// The message that was dropped to make room for the new message, if any. An error is returned
+    /// if the queue is full of messages with at least the same priority.
+    pub fn push(
+        &mut self,
+        priority: Priority,
+        packet: &[u8],
+        mut buffer: PacketBuffer<MSG_SIZE>,
+        expiry: Option<Instant<C>>,
+    ) -> Result<Option<QueuedPublish<C, MSG_SIZE>>, ()> {
+        buffer
+            .get_mut(..packet.len())
+            .ok_or(())?
+            .copy_from_slice(packet);
+        let message = QueuedPublish {
+            priority,
+            expiry,
+            buffer,
+            length: packet.len(),
+        };
+
+        let mut dropped = None;
+        if self.messages.is_full() {
+            // Find the most recently queued message with the lowest priority.
+            let (index, lowest) = self
+                .messages
+                .iter()
+                .enumerate()
+                .rev()
+                .min_by_key(|(_, message)| message.priority)
+                .ok_or(())?;
+
+            if lowest.priority >= priority {
+                return Err(());
+            }
+
+            dropped.replace(self.messages.remove(index));
+        }
+
+        // Note(unwrap): Space was made in the queue above.
+        self.messages.push(message).ok().unwrap();
+
+        Ok(dropped)
+    }
+
+    fn next_index(&self) -> Option<usize> {
+        // Note: `max_by_key()` returns the last maximum element, so the search is performed in
+        // reverse to find the message that was queued first.
+        self.messages
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, message)| message.priority)
+            .map(|(index, _)| index)
+    }
+
+    /// Get the next message to transmit.
+    pub fn peek(&self) -> Option<&QueuedPublish<C, MSG_SIZE>> {
+        self.next_index().map(|index| &self.messages[index])
+    }
+
+    /// Remove the next message to transmit from the queue.
+    pub fn pop(&mut self) -> Option<QueuedPublish<C, MSG_SIZE>> {
+        self.next_index().map(|index| self.messages.remove(index))
+    }
+
+    /// Remove a message that has expired from the queue.
+    ///
+    /// # Args
+    /// * `now` - The current time.
+    ///
+    /// # Returns
+    /// The oldest queued message that expired, if any.
+    pub fn pop_expired(&mut self, now: Instant<C>) -> Option<QueuedPublish<C, MSG_SIZE>> {
+        let index = self
+            .messages
+            .iter()
+            .position(|message| matches!(message.expiry, Some(expiry) if expiry <= now))?;
+
+        Some(self.messages.remove(index))
+    }
+
+    /// Get the time at which the next queued message expires, if any.
+    pub fn next_expiry(&self) -> Option<Instant<C>> {
+        self.messages
+            .iter()
+            .filter_map(|message| message.expiry)
+            .min()
+    }
+
+    /// Get the number of queued messages.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PublishQueue;
+    use crate::{
+        pool::PacketBuffer, ser::serialize, BufferPool, BufferSource, Priority, QoS, Retain,
+    };
+    use embedded_time::{clock, duration::Extensions, fraction::Fraction, Clock, Instant};
+
+    struct TestClock;
+
+    impl Clock for TestClock {
+        type T = u32;
+
+        const SCALING_FACTOR: Fraction = Fraction::new(1, 1000);
+
+        fn try_now(&self) -> Result<Instant<Self>, clock::Error> {
+            Ok(Instant::new(0))
+        }
+    }
+
+    fn publish<'a>(buffer: &'a mut [u8], topic: &str, qos: QoS) -> &'a [u8] {
+        serialize::publish_message(buffer, topic, &[0xAB], qos, Retain::NotRetained, 0, &[])
+            .unwrap()
+    }
+
+    #[test]
+    fn queue_priority_order() {
+        let mut queue: PublishQueue<TestClock, 32, 4> = PublishQueue::new();
+        let mut buffer = [0; 32];
+
+        for (topic, priority) in [
+            ("a", Priority::Low),
+            ("b", Priority::Normal),
+            ("c", Priority::High),
+            ("d", Priority::Normal),
+        ] {
+            let packet = publish(&mut buffer, topic, QoS::AtMostOnce);
+            assert!(queue
+                .push(priority, packet, PacketBuffer::new(None).unwrap(), None)
+                .unwrap()
+                .is_none());
+        }
+
+        assert_eq!(queue.peek().unwrap().topic(), "c");
+        assert_eq!(queue.pop().unwrap().topic(), "c");
+        assert_eq!(queue.pop().unwrap().topic(), "b");
+        assert_eq!(queue.pop().unwrap().topic(), "d");
+        assert_eq!(queue.pop().unwrap().topic(), "a");
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn queue_full_drops_lowest_priority() {
+        static POOL: BufferPool<32, 4> = BufferPool::new();
+        let mut queue: PublishQueue<TestClock, 32, 2> = PublishQueue::new();
+        let mut buffer = [0; 32];
+
+        let packet = publish(&mut buffer, "a", QoS::AtMostOnce);
+        queue
+            .push(
+                Priority::Low,
+                packet,
+                PacketBuffer::new(Some(&POOL)).unwrap(),
+                None,
+            )
+            .unwrap();
+        let packet = publish(&mut buffer, "b", QoS::AtMostOnce);
+        queue
+            .push(
+                Priority::Low,
+                packet,
+                PacketBuffer::new(Some(&POOL)).unwrap(),
+                None,
+            )
+            .unwrap();
+
+        // A message of equal priority cannot replace a queued message.
+        let packet = publish(&mut buffer, "c", QoS::AtMostOnce);
+        assert!(queue
+            .push(
+                Priority::Low,
+                packet,
+                PacketBuffer::new(Some(&POOL)).unwrap(),
+                None
+            )
+            .is_err());
+
+        // The buffer of a rejected message is returned to the pool.
+        assert_eq!(POOL.available(), 2);
+
+        // The most recently queued message of the lowest priority is dropped.
+        let packet = publish(&mut buffer, "d", QoS::AtMostOnce);
+        let dropped = queue
+            .push(
+                Priority::High,
+                packet,
+                PacketBuffer::new(Some(&POOL)).unwrap(),
+                None,
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(dropped.topic(), "b");
+        assert_eq!(dropped.priority, Priority::Low);
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop().unwrap().topic(), "d");
+        assert_eq!(queue.pop().unwrap().topic(), "a");
+    }
+
+    #[test]
+    fn queue_assigns_packet_id() {
+        static POOL: BufferPool<32, 4> = BufferPool::new();
+        let mut queue: PublishQueue<TestClock, 32, 1> = PublishQueue::new();
+        let mut buffer = [0; 32];
+
+        let packet = publish(&mut buffer, "topic", QoS::AtLeastOnce);
+        queue
+            .push(
+                Priority::Normal,
+                packet,
+                PacketBuffer::new(Some(&POOL)).unwrap(),
+                None,
+            )
+            .unwrap();
+
+        let mut message = queue.pop().unwrap();
+        assert_eq!(message.qos(), QoS::AtLeastOnce);
+        message.set_packet_id(0x1234);
+
+        let mut buffer = [0; 32];
+        let expected = serialize::publish_message(
+            &mut buffer,
+            "topic",
+            &[0xAB],
+            QoS::AtLeastOnce,
+            Retain::NotRetained,
+            0x1234,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(message.packet(), expected);
+    }
+
+    #[test]
+    fn queue_discards_expired_messages() {
+        static POOL: BufferPool<32, 4> = BufferPool::new();
+        let mut queue: PublishQueue<TestClock, 32, 4> = PublishQueue::new();
+        let mut buffer = [0; 32];
+        let start = Instant::<TestClock>::new(0);
+
+        let packet = publish(&mut buffer, "a", QoS::AtMostOnce);
+        queue
+            .push(
+                Priority::Normal,
+                packet,
+                PacketBuffer::new(Some(&POOL)).unwrap(),
+                None,
+            )
+            .unwrap();
+        let packet = publish(&mut buffer, "b", QoS::AtMostOnce);
+        queue
+            .push(
+                Priority::Normal,
+                packet,
+                PacketBuffer::new(Some(&POOL)).unwrap(),
+                Some(start + 1_000.milliseconds()),
+            )
+            .unwrap();
+        let packet = publish(&mut buffer, "c", QoS::AtMostOnce);
+        queue
+            .push(
+                Priority::Normal,
+                packet,
+                PacketBuffer::new(Some(&POOL)).unwrap(),
+                Some(start + 2_000.milliseconds()),
+            )
+            .unwrap();
+
+        assert!(queue.next_expiry() == Some(start + 1_000.milliseconds()));
+        assert!(queue.pop_expired(start + 999.milliseconds()).is_none());
+
+        let now = start + 1_500.milliseconds();
+        assert_eq!(queue.pop_expired(now).unwrap().topic(), "b");
+        assert!(queue.pop_expired(now).is_none());
+        assert!(queue.next_expiry() == Some(start + 2_000.milliseconds()));
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop().unwrap().topic(), "a");
+        assert_eq!(queue.pop().unwrap().topic(), "c");
+    }
+}
@@ -0,0 +1,116 @@
+//! Reconnect Policies
+//!
+//! # Design
+//! By default, the client attempts to reconnect to the broker as soon as a connection attempt
+//! failed. If the broker is unreachable for a longer time, this keeps the network interface busy
+//! and floods the broker with connection attempts once it becomes reachable again. A
+//! `ReconnectPolicy` delays each connection attempt following a failed attempt, e.g. using the
+//! `ExponentialBackoff` provided here.
+//!
+//! Policies are shared by reference, so a single policy may be used by multiple clients:
+//!
+//! ```
+//! use minimq::{embedded_time::duration::Milliseconds, ExponentialBackoff};
+//!
+//! static BACKOFF: ExponentialBackoff =
+//!     ExponentialBackoff::new(Milliseconds(500), Milliseconds(60_000), 0x1234_5678);
+//! ```
+//!
+//! # Note
+//! The first connection attempt after an established connection was lost is never delayed.
+use embedded_time::duration::Milliseconds;
+
+/// A policy that determines the delay between two connection attempts.
+pub trait ReconnectPolicy: Sync {
+    /// Get the delay before the next connection attempt.
+    ///
+    /// # Args
+    /// * `attempt` - The number of consecutive failed connection attempts. This is at least one.
+    fn delay(&self, attempt: u32) -> Milliseconds<u32>;
+}
+
+/// A policy that doubles the delay with every failed connection attempt.
+///
+/// # Note
+/// A random jitter of up to half of the delay is subtracted from every delay, so that devices
+/// losing their connection at the same time do not reconnect at the same time.
+#[derive(Debug, Copy, Clone)]
+pub struct ExponentialBackoff {
+    initial: Milliseconds<u32>,
+    maximum: Milliseconds<u32>,
+    seed: u32,
+}
+
+impl ExponentialBackoff {
+    /// Construct a new exponential backoff.
+    ///
+    /// # Args
+    /// * `initial` - The delay after the first failed connection attempt.
+    /// * `maximum` - The upper bound of the delay.
+    /// * `seed` - The seed of the jitter. This should differ between devices, e.g. by deriving it
+    ///   from a serial number or MAC address.
+    pub const fn new(initial: Milliseconds<u32>, maximum: Milliseconds<u32>, seed: u32) -> Self {
+        Self {
+            initial,
+            maximum,
+            seed,
+        }
+    }
+
+    /// Get a pseudo-random number for a connection attempt.
+    fn jitter(&self, attempt: u32) -> u32 {
+        // A xorshift generator, seeded once per attempt so that the policy does not need state.
+        let mut x = (self.seed ^ attempt.wrapping_mul(0x9E37_79B9)) | 1;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        x
+    }
+}
+
+impl ReconnectPolicy for ExponentialBackoff {
+    fn delay(&self, attempt: u32) -> Milliseconds<u32> {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let delay = self.initial.0.saturating_mul(factor).min(self.maximum.0);
+
+        let range = delay / 2;
+        Milliseconds(delay - self.jitter(attempt) % (range + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExponentialBackoff, ReconnectPolicy};
+    use embedded_time::duration::Milliseconds;
+
+    #[test]
+    fn delay_grows_exponentially() {
+        let backoff = ExponentialBackoff::new(Milliseconds(100), Milliseconds(1000), 42);
+
+        for (attempt, delay) in [
+            (1, 100),
+            (2, 200),
+            (3, 400),
+            (4, 800),
+            (5, 1000),
+            (40, 1000),
+        ]
+        .iter()
+        .copied()
+        {
+            let Milliseconds(actual) = backoff.delay(attempt);
+            assert!(actual <= delay, "{} > {}", actual, delay);
+            assert!(actual >= delay / 2, "{} < {}", actual, delay / 2);
+        }
+    }
+
+    #[test]
+    fn jitter_depends_on_seed() {
+        let first = ExponentialBackoff::new(Milliseconds(1000), Milliseconds(1000), 1);
+        let second = ExponentialBackoff::new(Milliseconds(1000), Milliseconds(1000), 2);
+        assert_ne!(first.delay(1), second.delay(1));
+        assert_eq!(first.delay(1), first.delay(1));
+    }
+}
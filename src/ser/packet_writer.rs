@@ -1,5 +1,5 @@
 /// Provides a means of serializing an MQTT control packet.
-use crate::{MessageType, Property, ProtocolError as Error};
+use crate::{Diagnostic, MessageType, Property, ProtocolError as Error};
 
 use bit_field::BitField;
 
@@ -9,9 +9,14 @@ use bit_field::BitField;
 /// # Note
 /// Serialization is performed tail -> head. That is, the last bytes of the packet are serialized
 /// first. This is done so that packet section length calculation is trivial.
+///
+/// # Note
+/// If the packet does not fit into the buffer, the remainder of the packet is only measured, so
+/// that the required buffer size can be reported once the packet is finalized.
 pub(crate) struct ReversedPacketWriter<'a> {
     buffer: &'a mut [u8],
     index: usize,
+    overflow: usize,
 }
 
 impl<'a> ReversedPacketWriter<'a> {
@@ -22,7 +27,11 @@ impl<'a> ReversedPacketWriter<'a> {
     pub fn new(buffer: &'a mut [u8]) -> Self {
         let index = buffer.len();
 
-        ReversedPacketWriter { buffer, index }
+        ReversedPacketWriter {
+            buffer,
+            index,
+            overflow: 0,
+        }
     }
 
     /// Write data at the tail of the packet.
@@ -33,8 +42,9 @@ impl<'a> ReversedPacketWriter<'a> {
     /// # Args
     /// * `data` - The data to push to the current head of the packet.
     pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
-        if self.index < data.len() {
-            return Err(Error::Bounds);
+        if self.overflow > 0 || self.index < data.len() {
+            self.overflow += data.len();
+            return Ok(());
         }
 
         let write_start = self.index - data.len();
@@ -45,12 +55,26 @@ impl<'a> ReversedPacketWriter<'a> {
         Ok(())
     }
 
+    /// Include data that is already located at the tail of the packet.
+    ///
+    /// # Args
+    /// * `length` - The number of bytes at the current head of the packet to include.
+    pub fn include(&mut self, length: usize) -> Result<(), Error> {
+        if self.overflow > 0 || self.index < length {
+            self.overflow += length;
+            return Ok(());
+        }
+
+        self.index -= length;
+        Ok(())
+    }
+
     /// Write a binary block of data into the control packet.
     ///
     /// # Args
     /// * `data` - The binary data block to write.
     pub fn write_binary_data(&mut self, data: &[u8]) -> Result<(), Error> {
-        if data.len() > u16::max as usize {
+        if data.len() > u16::MAX as usize {
             return Err(Error::DataSize);
         }
 
@@ -83,7 +107,7 @@ impl<'a> ReversedPacketWriter<'a> {
     ///
     ///  # Args
     /// * `string` - The string to encode.
-    pub fn write_utf8_string<'b>(&mut self, string: &'b str) -> Result<(), Error> {
+    pub fn write_utf8_string(&mut self, string: &str) -> Result<(), Error> {
         self.write_binary_data(string.as_bytes())
     }
 
@@ -99,7 +123,7 @@ impl<'a> ReversedPacketWriter<'a> {
 
         if value & (0b0111_1111 << 21) > 0 {
             let data: [u8; 4] = [
-                (value >> 0) as u8 | 0x80,
+                value as u8 | 0x80,
                 (value >> 7) as u8 | 0x80,
                 (value >> 14) as u8 | 0x80,
                 (value >> 21) as u8 & 0x7F,
@@ -108,18 +132,18 @@ impl<'a> ReversedPacketWriter<'a> {
             self.write(&data)
         } else if value & (0b0111_1111 << 14) > 0 {
             let data: [u8; 3] = [
-                (value >> 0) as u8 | 0x80,
+                value as u8 | 0x80,
                 (value >> 7) as u8 | 0x80,
                 (value >> 14) as u8 & 0x7F,
             ];
 
             self.write(&data)
         } else if value & (0b0111_1111 << 7) > 0 {
-            let data: [u8; 2] = [(value >> 0) as u8 | 0x80, ((value >> 7) & 0x7F) as u8];
+            let data: [u8; 2] = [value as u8 | 0x80, ((value >> 7) & 0x7F) as u8];
 
             self.write(&data)
         } else {
-            let data: [u8; 1] = [(value >> 0) as u8 & 0x7F];
+            let data: [u8; 1] = [value as u8 & 0x7F];
 
             self.write(&data)
         }
@@ -146,10 +170,23 @@ impl<'a> ReversedPacketWriter<'a> {
     }
 
     pub fn current_length(&self) -> usize {
-        self.buffer.len() - self.index
+        self.buffer.len() - self.index + self.overflow
     }
 
-    pub fn write_properties<'b>(&mut self, properties: &[Property<'b>]) -> Result<(), Error> {
+    fn check_overflow(&self) -> Result<(), Error> {
+        if self.overflow > 0 {
+            return Err(Error::BufferTooSmall {
+                needed: self.current_length(),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn write_properties<'p, 'b: 'p>(
+        &mut self,
+        properties: impl IntoIterator<Item = &'p Property<'b>>,
+    ) -> Result<(), Error> {
         let start_length = self.current_length();
 
         for property in properties {
@@ -165,22 +202,29 @@ impl<'a> ReversedPacketWriter<'a> {
     pub fn finalize(mut self, typ: MessageType, flags: u8) -> Result<&'a [u8], Error> {
         // Write the fixed header.
         self.write_fixed_header(typ, flags, self.current_length())?;
+        self.check_overflow()?;
 
         if self.index == self.buffer.len() {
-            Err(Error::MalformedPacket)
+            Err(Error::MalformedPacket(Diagnostic::new(
+                0,
+                "packet data",
+                None,
+            )))
         } else {
             Ok(&self.buffer[self.index..])
         }
     }
 
-    pub fn finish(self) -> &'a [u8] {
-        &self.buffer[self.index..]
+    pub fn finish(self) -> Result<&'a [u8], Error> {
+        self.check_overflow()?;
+        Ok(&self.buffer[self.index..])
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::ReversedPacketWriter;
+    use crate::{MessageType, ProtocolError};
 
     #[test]
     fn variable_length_single_byte_integers() {
@@ -188,17 +232,17 @@ mod tests {
         let mut writer = ReversedPacketWriter::new(&mut buffer);
 
         writer.write_variable_length_integer(127).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0x7F]);
 
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(0).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0x00]);
 
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(16).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0x10]);
     }
 
@@ -209,27 +253,27 @@ mod tests {
         // Encodings here taken directly from Table 1-1 of the MQTT 5 specification.
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(128).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0x80, 0x01]);
 
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(129).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0x81, 0x01]);
 
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(256).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0x80, 0x02]);
 
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(256).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0x80, 0x02]);
 
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(16_383).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0xFF, 0x7F]);
     }
 
@@ -240,12 +284,12 @@ mod tests {
         // Encodings here taken directly from Table 1-1 of the MQTT 5 specification.
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(16_384).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0x80, 0x80, 0x01]);
 
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(2_097_151).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0xFF, 0xFF, 0x7F]);
     }
 
@@ -256,12 +300,27 @@ mod tests {
         // Encodings here taken directly from Table 1-1 of the MQTT 5 specification.
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(2_097_152).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0x80, 0x80, 0x80, 0x01]);
 
         let mut writer = ReversedPacketWriter::new(&mut buffer);
         writer.write_variable_length_integer(268_435_455).unwrap();
-        let result = writer.finish();
+        let result = writer.finish().unwrap();
         assert_eq!(result, [0xFF, 0xFF, 0xFF, 0x7F]);
     }
+
+    #[test]
+    fn buffer_too_small_reports_size() {
+        let mut buffer: [u8; 4] = [0; 4];
+        let mut writer = ReversedPacketWriter::new(&mut buffer);
+
+        writer.write(&[0; 200]).unwrap();
+        writer.write_u16(0xABCD).unwrap();
+
+        // The fixed header consists of the control byte and a 2 byte remaining length.
+        assert_eq!(
+            writer.finalize(MessageType::Publish, 0),
+            Err(ProtocolError::BufferTooSmall { needed: 205 })
+        );
+    }
 }
@@ -1,18 +1,20 @@
 use crate::{
     message_types::MessageType, properties::PropertyIdentifier, ser::ReversedPacketWriter,
-    will::Will, Property, ProtocolError as Error, QoS, Retain,
+    will::Will, Property, ProtocolError as Error, QoS, Retain, SubscriptionOptions,
 };
 
 use bit_field::BitField;
+use core::ops::Range;
 
 pub fn integer_size(value: usize) -> usize {
-    if value < 0x80 {
+    // Each byte of a variable-length integer encodes 7 bits of the value.
+    if value < 1 << 7 {
         1
-    } else if value < 0x7FFF {
+    } else if value < 1 << 14 {
         2
-    } else if value < 0x7F_FFFF {
+    } else if value < 1 << 21 {
         3
-    } else if value < 0x7FFF_FFFF {
+    } else if value < 1 << 28 {
         4
     } else {
         panic!("Invalid integer");
@@ -74,21 +76,92 @@ pub fn connect_message<'a, const S: usize>(
     packet.finalize(MessageType::Connect, 0)
 }
 
-pub fn ping_req_message<'a>(dest: &'a mut [u8]) -> Result<&'a [u8], Error> {
+pub fn ping_req_message(dest: &mut [u8]) -> Result<&[u8], Error> {
     ReversedPacketWriter::new(dest).finalize(MessageType::PingReq, 0x00)
 }
 
-pub fn publish_message<'a, 'b, 'c>(
+pub fn publish_message<'a, 'b, 'c: 'p, 'p>(
     dest: &'b mut [u8],
     topic: &'a str,
     payload: &[u8],
     qos: QoS,
     retain: Retain,
     id: u16,
-    properties: &[Property<'c>],
+    properties: impl IntoIterator<Item = &'p Property<'c>> + Clone,
+) -> Result<&'b [u8], Error> {
+    let mut packet = ReversedPacketWriter::new(dest);
+
+    // Write the payload into the packet.
+    packet.write(payload)?;
+
+    finalize_publish(packet, topic, qos, retain, id, properties)
+}
+
+/// Serialize a PUBLISH packet whose payload is located at the end of the buffer.
+///
+/// # Note
+/// The payload may also be written once the packet is serialized, e.g. to stream it into the
+/// buffer. If it turns out shorter than `payload_length`, the packet is shortened using
+/// `truncate_publish()`.
+///
+/// # Args
+/// * `dest` - The buffer to serialize the packet into. The payload occupies its last bytes.
+/// * `payload_length` - The length of the payload.
+pub fn publish_message_in_place<'a, 'b, 'c: 'p, 'p>(
+    dest: &'b mut [u8],
+    payload_length: usize,
+    topic: &'a str,
+    qos: QoS,
+    retain: Retain,
+    id: u16,
+    properties: impl IntoIterator<Item = &'p Property<'c>> + Clone,
+) -> Result<&'b [u8], Error> {
+    let mut packet = ReversedPacketWriter::new(dest);
+    packet.include(payload_length)?;
+
+    finalize_publish(packet, topic, qos, retain, id, properties)
+}
+
+/// Remove the end of the payload of a serialized PUBLISH packet.
+///
+/// # Note
+/// Only the remaining length in the fixed header is updated. If it is encoded using fewer bytes,
+/// the packet starts later in the buffer.
+///
+/// # Args
+/// * `packet` - The serialized packet.
+/// * `length` - The number of bytes to remove from the end of the payload.
+///
+/// # Returns
+/// The location of the shortened packet in `packet`.
+pub fn truncate_publish(packet: &mut [u8], length: usize) -> Result<Range<usize>, Error> {
+    // The remaining length follows the control byte. All but its last byte have the continuation
+    // bit set.
+    let header_length = 2 + packet[1..]
+        .iter()
+        .take_while(|byte| **byte & 0x80 != 0)
+        .count();
+    let control = packet[0];
+    let remaining = packet.len() - header_length - length;
+
+    let mut header = ReversedPacketWriter::new(&mut packet[..header_length]);
+    header.write_variable_length_integer(remaining)?;
+    header.write(&[control])?;
+    let start = header_length - header.finish()?.len();
+
+    Ok(start..packet.len() - length)
+}
+
+fn finalize_publish<'a, 'b, 'c: 'p, 'p>(
+    mut packet: ReversedPacketWriter<'b>,
+    topic: &'a str,
+    qos: QoS,
+    retain: Retain,
+    id: u16,
+    properties: impl IntoIterator<Item = &'p Property<'c>> + Clone,
 ) -> Result<&'b [u8], Error> {
     // Validate the properties for this packet.
-    for property in properties {
+    for property in properties.clone() {
         match property.id() {
             PropertyIdentifier::ResponseTopic
             | PropertyIdentifier::PayloadFormatIndicator
@@ -96,18 +169,14 @@ pub fn publish_message<'a, 'b, 'c>(
             | PropertyIdentifier::ContentType
             | PropertyIdentifier::CorrelationData
             | PropertyIdentifier::SubscriptionIdentifier
-            | PropertyIdentifier::TopicAlias => {}
+            | PropertyIdentifier::TopicAlias
+            | PropertyIdentifier::UserProperty => {}
             _ => {
                 return Err(Error::InvalidProperty);
             }
         };
     }
 
-    let mut packet = ReversedPacketWriter::new(dest);
-
-    // Write the payload into the packet.
-    packet.write(payload)?;
-
     // Write the variable header into the packet.
     packet.write_properties(properties)?;
 
@@ -126,16 +195,188 @@ pub fn publish_message<'a, 'b, 'c>(
     packet.finalize(MessageType::Publish, flags)
 }
 
+/// Determine the maximum size of a payload for a PUBLISH packet.
+///
+/// # Args
+/// * `packet_size` - The maximum size of the serialized packet.
+/// * `topic` - The topic of the message.
+/// * `qos` - The quality-of-service of the message.
+/// * `properties_size` - The size of the properties of the message.
+pub fn max_publish_payload(
+    packet_size: usize,
+    topic: &str,
+    qos: QoS,
+    properties_size: usize,
+) -> usize {
+    let packet_id_size = if qos == QoS::AtMostOnce { 0 } else { 2 };
+    let header_size =
+        2 + topic.len() + packet_id_size + integer_size(properties_size) + properties_size;
+
+    // The remaining length is encoded in the fixed header after the type byte, so it shrinks the
+    // space available for the remaining packet.
+    let mut remaining = packet_size.saturating_sub(2);
+    while remaining > 0 && 1 + integer_size(remaining) + remaining > packet_size {
+        remaining -= 1;
+    }
+
+    remaining.saturating_sub(header_size)
+}
+
+fn ack_message(
+    dest: &mut [u8],
+    message_type: MessageType,
+    packet_id: u16,
+    reason_code: u8,
+) -> Result<&[u8], Error> {
+    let mut packet = ReversedPacketWriter::new(dest);
+
+    // The reason code and properties may be omitted if the message was accepted without any
+    // properties.
+    if reason_code != 0 {
+        packet.write_properties(&[])?;
+        packet.write(&[reason_code])?;
+    }
+
+    packet.write_u16(packet_id)?;
+
+    packet.finalize(message_type, 0)
+}
+
+pub fn puback_message(dest: &mut [u8], packet_id: u16, reason_code: u8) -> Result<&[u8], Error> {
+    ack_message(dest, MessageType::PubAck, packet_id, reason_code)
+}
+
+pub fn pubrec_message(dest: &mut [u8], packet_id: u16, reason_code: u8) -> Result<&[u8], Error> {
+    ack_message(dest, MessageType::PubRec, packet_id, reason_code)
+}
+
+pub fn pubcomp_message(dest: &mut [u8], packet_id: u16, reason_code: u8) -> Result<&[u8], Error> {
+    ack_message(dest, MessageType::PubComp, packet_id, reason_code)
+}
+
+pub fn pubrel_message(dest: &mut [u8], packet_id: u16) -> Result<&[u8], Error> {
+    let mut packet = ReversedPacketWriter::new(dest);
+
+    // The reason code is omitted, as the message is always released successfully.
+    packet.write_u16(packet_id)?;
+
+    packet.finalize(MessageType::PubRel, 0b0010)
+}
+
+pub fn disconnect_message<'a>(
+    dest: &'a mut [u8],
+    reason_code: u8,
+    properties: &[Property],
+) -> Result<&'a [u8], Error> {
+    // Validate the properties for this packet.
+    for property in properties {
+        match property.id() {
+            PropertyIdentifier::SessionExpiryInterval
+            | PropertyIdentifier::ReasonString
+            | PropertyIdentifier::UserProperty => {}
+
+            _ => return Err(Error::InvalidProperty),
+        }
+    }
+
+    let mut packet = ReversedPacketWriter::new(dest);
+
+    // The reason code and properties may be omitted for a normal disconnection without any
+    // properties.
+    if reason_code != 0 || !properties.is_empty() {
+        packet.write_properties(properties)?;
+        packet.write(&[reason_code])?;
+    }
+
+    packet.finalize(MessageType::Disconnect, 0)
+}
+
+pub fn auth_message<'a>(
+    dest: &'a mut [u8],
+    reason_code: u8,
+    properties: &[Property],
+) -> Result<&'a [u8], Error> {
+    // Validate the properties for this packet.
+    for property in properties {
+        match property.id() {
+            PropertyIdentifier::AuthenticationMethod
+            | PropertyIdentifier::AuthenticationData
+            | PropertyIdentifier::ReasonString
+            | PropertyIdentifier::UserProperty => {}
+
+            _ => return Err(Error::InvalidProperty),
+        }
+    }
+
+    let mut packet = ReversedPacketWriter::new(dest);
+
+    // The reason code and properties may be omitted for a successful authentication without any
+    // properties.
+    if reason_code != 0 || !properties.is_empty() {
+        packet.write_properties(properties)?;
+        packet.write(&[reason_code])?;
+    }
+
+    packet.finalize(MessageType::Auth, 0)
+}
+
 pub fn subscribe_message<'a, 'b, 'c>(
     dest: &'c mut [u8],
-    topic: &'b str,
+    filters: &[(&'b str, SubscriptionOptions)],
     packet_id: u16,
     properties: &[Property<'a>],
 ) -> Result<&'c [u8], Error> {
+    if filters.is_empty() {
+        return Err(Error::Invalid);
+    }
+
     // Validate the properties for this packet.
+    for property in properties {
+        match property {
+            // A subscription identifier of zero is not permitted.
+            Property::SubscriptionIdentifier(0) => return Err(Error::Invalid),
+            Property::SubscriptionIdentifier(_) => {}
+            _ => {
+                return Err(Error::InvalidProperty);
+            }
+        }
+    }
+
+    let mut packet = ReversedPacketWriter::new(dest);
+
+    // Write the payload (topic filter + options byte for each filter). The packet is written in
+    // reverse, so the filters are written from last to first.
+    for (filter, options) in filters.iter().rev() {
+        packet.write(&[options.as_byte()])?;
+        packet.write_utf8_string(filter)?;
+    }
+
+    // Write the variable packet header.
+    packet.write_properties(properties)?;
+    packet.write_u16(packet_id)?;
+
+    packet.finalize(MessageType::Subscribe, 0b0010)
+}
+
+/// Serialize an MQTT unsubscribe request.
+///
+/// # Args
+/// * `dest` - The location to serialize the request into.
+/// * `topic` - The topic filter to unsubscribe from.
+/// * `packet_id` - The packet identifier of the request.
+/// * `properties` - The properties to attach to the request.
+///
+/// # Returns
+/// A slice of `dest` containing the serialized request.
+pub fn unsubscribe_message<'a, 'b, 'c>(
+    dest: &'c mut [u8],
+    topic: &'b str,
+    packet_id: u16,
+    properties: &[Property<'a>],
+) -> Result<&'c [u8], Error> {
     for property in properties {
         match property.id() {
-            PropertyIdentifier::SubscriptionIdentifier => {}
+            PropertyIdentifier::UserProperty => {}
             _ => {
                 return Err(Error::InvalidProperty);
             }
@@ -144,16 +385,25 @@ pub fn subscribe_message<'a, 'b, 'c>(
 
     let mut packet = ReversedPacketWriter::new(dest);
 
-    // TODO: Support multiple topics.
-    // Write the payload (topic filter + options byte)
-    packet.write(&[0])?;
+    // Write the payload (topic filter)
     packet.write_utf8_string(topic)?;
 
     // Write the variable packet header.
     packet.write_properties(properties)?;
     packet.write_u16(packet_id)?;
 
-    packet.finalize(MessageType::Subscribe, 0b0010)
+    packet.finalize(MessageType::Unsubscribe, 0b0010)
+}
+
+#[test]
+fn integer_size_boundaries() {
+    assert_eq!(integer_size(0x7F), 1);
+    assert_eq!(integer_size(0x80), 2);
+    assert_eq!(integer_size(0x3FFF), 2);
+    assert_eq!(integer_size(0x4000), 3);
+    assert_eq!(integer_size(0x1F_FFFF), 3);
+    assert_eq!(integer_size(0x20_0000), 4);
+    assert_eq!(integer_size(0xFFF_FFFF), 4);
 }
 
 #[test]
@@ -182,6 +432,139 @@ pub fn serialize_publish() {
     assert_eq!(message, good_publish);
 }
 
+#[test]
+pub fn serialize_publish_retained() {
+    let good_publish: [u8; 10] = [
+        0x31, // Publish message, retained
+        0x08, // Remaining length (8)
+        0x00, 0x03, 0x41, 0x42, 0x43, // Topic: ABC
+        0x00, // Properties length
+        0xAB, 0xCD, // Payload
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let payload: [u8; 2] = [0xAB, 0xCD];
+    let message = publish_message(
+        &mut buffer,
+        "ABC",
+        &payload,
+        QoS::AtMostOnce,
+        Retain::Retained,
+        0,
+        &[],
+    )
+    .unwrap();
+
+    assert_eq!(message, good_publish);
+}
+
+#[test]
+pub fn serialize_publish_in_place() {
+    let mut buffer: [u8; 32] = [0; 32];
+    let properties = [Property::UserProperty("key", "value")];
+    let expected: heapless::Vec<u8, 32> = heapless::Vec::from_slice(
+        publish_message(
+            &mut buffer,
+            "ABC",
+            &[0xAB, 0xCD],
+            QoS::AtLeastOnce,
+            Retain::Retained,
+            5,
+            &properties,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    let mut buffer: [u8; 32] = [0; 32];
+    buffer[30..].copy_from_slice(&[0xAB, 0xCD]);
+    let message = publish_message_in_place(
+        &mut buffer,
+        2,
+        "ABC",
+        QoS::AtLeastOnce,
+        Retain::Retained,
+        5,
+        &properties,
+    )
+    .unwrap();
+
+    assert_eq!(message, &expected[..]);
+}
+
+#[test]
+pub fn truncate_publish_in_place() {
+    let mut buffer: [u8; 256] = [0; 256];
+    let expected: heapless::Vec<u8, 256> = heapless::Vec::from_slice(
+        publish_message(
+            &mut buffer,
+            "ABC",
+            &[0xAB, 0xCD],
+            QoS::AtLeastOnce,
+            Retain::NotRetained,
+            5,
+            &[],
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    // Space for a payload of 200 bytes is reserved, so that the remaining length is encoded using
+    // two bytes instead of one.
+    let mut buffer: [u8; 256] = [0; 256];
+    let packet = publish_message_in_place(
+        &mut buffer,
+        200,
+        "ABC",
+        QoS::AtLeastOnce,
+        Retain::NotRetained,
+        5,
+        &[],
+    )
+    .unwrap();
+    let start = 256 - packet.len();
+    buffer[56..58].copy_from_slice(&[0xAB, 0xCD]);
+
+    let packet = truncate_publish(&mut buffer[start..], 198).unwrap();
+    assert_eq!(&buffer[start..][packet], &expected[..]);
+}
+
+#[test]
+pub fn max_publish_payload_fits() {
+    let mut buffer: [u8; 300] = [0; 300];
+    let properties = [Property::UserProperty("key", "value")];
+
+    for size in [32, 128, 129, 131, 300] {
+        let payload = max_publish_payload(size, "ABC", QoS::AtLeastOnce, properties[0].size());
+        let data = [0xAB; 300];
+
+        let message = publish_message(
+            &mut buffer[..size],
+            "ABC",
+            &data[..payload],
+            QoS::AtLeastOnce,
+            Retain::NotRetained,
+            1,
+            &properties,
+        )
+        .unwrap();
+        assert_eq!(message.len(), size);
+
+        assert!(publish_message(
+            &mut buffer[..size],
+            "ABC",
+            &data[..payload + 1],
+            QoS::AtLeastOnce,
+            Retain::NotRetained,
+            1,
+            &properties,
+        )
+        .is_err());
+    }
+
+    assert_eq!(max_publish_payload(4, "ABC", QoS::AtMostOnce, 0), 0);
+}
+
 #[test]
 pub fn serialize_publish_qos1() {
     let good_publish: [u8; 12] = [
@@ -209,6 +592,173 @@ pub fn serialize_publish_qos1() {
     assert_eq!(message, good_publish);
 }
 
+#[test]
+pub fn serialize_publish_with_user_property() {
+    let good_publish: [u8; 17] = [
+        0x30, // Publish message
+        0x0f, // Remaining length (15)
+        0x00, 0x03, 0x41, 0x42, 0x43, // Topic: ABC
+        0x07, // Properties length
+        0x26, 0x00, 0x01, 0x41, 0x00, 0x01, 0x42, // User property: A=B
+        0xAB, 0xCD, // Payload
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let payload: [u8; 2] = [0xAB, 0xCD];
+    let message = publish_message(
+        &mut buffer,
+        "ABC",
+        &payload,
+        QoS::AtMostOnce,
+        Retain::NotRetained,
+        0,
+        &[Property::UserProperty("A", "B")],
+    )
+    .unwrap();
+
+    assert_eq!(message, good_publish);
+}
+
+#[test]
+fn serialize_puback() {
+    let good_puback: [u8; 4] = [
+        0x40, // PubAck
+        0x02, // Remaining length (2)
+        0x00, 0x10, // Packet identifier (16)
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let message = puback_message(&mut buffer, 16, 0).unwrap();
+
+    assert_eq!(message, good_puback);
+}
+
+#[test]
+fn serialize_puback_with_reason() {
+    let good_puback: [u8; 6] = [
+        0x40, // PubAck
+        0x04, // Remaining length (4)
+        0x00, 0x10, // Packet identifier (16)
+        0x80, // Reason code: Unspecified error
+        0x00, // Properties length
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let message = puback_message(&mut buffer, 16, 0x80).unwrap();
+
+    assert_eq!(message, good_puback);
+}
+
+#[test]
+fn serialize_pubrel() {
+    let good_pubrel: [u8; 4] = [
+        0x62, // PubRel
+        0x02, // Remaining length (2)
+        0x00, 0x10, // Packet identifier (16)
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let message = pubrel_message(&mut buffer, 16).unwrap();
+
+    assert_eq!(message, good_pubrel);
+}
+
+#[test]
+fn serialize_pubrec() {
+    let good_pubrec: [u8; 6] = [
+        0x50, // PubRec
+        0x04, // Remaining length (4)
+        0x00, 0x10, // Packet identifier (16)
+        0x97, // Reason code: Quota exceeded
+        0x00, // Properties length
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let message = pubrec_message(&mut buffer, 16, 0x97).unwrap();
+
+    assert_eq!(message, good_pubrec);
+}
+
+#[test]
+fn serialize_pubcomp() {
+    let good_pubcomp: [u8; 4] = [
+        0x70, // PubComp
+        0x02, // Remaining length (2)
+        0x00, 0x10, // Packet identifier (16)
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let message = pubcomp_message(&mut buffer, 16, 0).unwrap();
+
+    assert_eq!(message, good_pubcomp);
+}
+
+#[test]
+fn serialize_disconnect() {
+    let good_disconnect: [u8; 2] = [
+        0xe0, // Disconnect
+        0x00, // Remaining length (0)
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let message = disconnect_message(&mut buffer, 0, &[]).unwrap();
+
+    assert_eq!(message, good_disconnect);
+}
+
+#[test]
+fn serialize_disconnect_with_will() {
+    let good_disconnect: [u8; 4] = [
+        0xe0, // Disconnect
+        0x02, // Remaining length (2)
+        0x04, // Reason code: Disconnect with Will Message
+        0x00, // Properties length
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let message = disconnect_message(&mut buffer, 0x04, &[]).unwrap();
+
+    assert_eq!(message, good_disconnect);
+}
+
+#[test]
+fn serialize_disconnect_with_session_expiry() {
+    let good_disconnect: [u8; 9] = [
+        0xe0, // Disconnect
+        0x07, // Remaining length (7)
+        0x00, // Reason code: Normal disconnection
+        0x05, // Properties length
+        0x11, 0x00, 0x00, 0x00, 0x00, // Session expiry interval (0)
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let properties = [Property::SessionExpiryInterval(0)];
+    let message = disconnect_message(&mut buffer, 0, &properties).unwrap();
+
+    assert_eq!(message, good_disconnect);
+}
+
+#[test]
+fn serialize_auth() {
+    let good_auth: [u8; 14] = [
+        0xf0, // Auth
+        0x0c, // Remaining length (12)
+        0x18, // Reason code: Continue authentication
+        0x0a, // Properties length
+        0x15, 0x00, 0x03, 0x41, 0x42, 0x43, // Authentication method: "ABC"
+        0x16, 0x00, 0x01, 0xAB, // Authentication data
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let properties = [
+        Property::AuthenticationData(&[0xAB]),
+        Property::AuthenticationMethod("ABC"),
+    ];
+    let message = auth_message(&mut buffer, 0x18, &properties).unwrap();
+
+    assert_eq!(message, good_auth);
+}
+
 #[test]
 fn serialize_subscribe() {
     let good_subscribe: [u8; 11] = [
@@ -221,11 +771,81 @@ fn serialize_subscribe() {
     ];
 
     let mut buffer: [u8; 900] = [0; 900];
-    let message = subscribe_message(&mut buffer, "ABC", 16, &[]).unwrap();
+    let filters = [("ABC", SubscriptionOptions::default())];
+    let message = subscribe_message(&mut buffer, &filters, 16, &[]).unwrap();
 
     assert_eq!(message, good_subscribe);
 }
 
+#[test]
+fn serialize_subscribe_with_identifier() {
+    let good_subscribe: [u8; 15] = [
+        0x82, // Subscribe request
+        0x0d, // Remaining length (13)
+        0x00, 0x10, // Packet identifier (16)
+        0x04, // Property length
+        0x0B, 0x80, 0x80, 0x01, // Subscription identifier (16384)
+        0x00, 0x03, 0x41, 0x42, 0x43, // Topic: ABC
+        0x01, // Options byte = QoS 1
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let filters = [("ABC", SubscriptionOptions::default().qos(QoS::AtLeastOnce))];
+    let properties = [Property::SubscriptionIdentifier(16384)];
+    let message = subscribe_message(&mut buffer, &filters, 16, &properties).unwrap();
+
+    assert_eq!(message, good_subscribe);
+
+    let properties = [Property::SubscriptionIdentifier(0)];
+    assert_eq!(
+        subscribe_message(&mut buffer, &filters, 16, &properties),
+        Err(Error::Invalid)
+    );
+}
+
+#[test]
+fn serialize_subscribe_multiple() {
+    let good_subscribe: [u8; 14] = [
+        0x82, // Subscribe request
+        0x0c, // Remaining length (12)
+        0x00, 0x10, // Packet identifier (16)
+        0x00, // Property length
+        0x00, 0x02, 0x41, 0x42, // Topic: AB
+        0x00, // Options byte = 0
+        0x00, 0x01, 0x43, // Topic: C
+        0x04, // Options byte = No Local
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let filters = [
+        ("AB", SubscriptionOptions::default()),
+        ("C", SubscriptionOptions::default().no_local(true)),
+    ];
+    let message = subscribe_message(&mut buffer, &filters, 16, &[]).unwrap();
+
+    assert_eq!(message, good_subscribe);
+    assert_eq!(
+        subscribe_message(&mut buffer, &[], 16, &[]),
+        Err(Error::Invalid)
+    );
+}
+
+#[test]
+fn serialize_unsubscribe() {
+    let good_unsubscribe: [u8; 10] = [
+        0xa2, // Unsubscribe request
+        0x08, // Remaining length (8)
+        0x00, 0x10, // Packet identifier (16)
+        0x00, // Property length
+        0x00, 0x03, 0x41, 0x42, 0x43, // Topic: ABC
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let message = unsubscribe_message(&mut buffer, "ABC", 16, &[]).unwrap();
+
+    assert_eq!(message, good_unsubscribe);
+}
+
 #[test]
 pub fn serialize_publish_with_properties() {
     let good_publish: [u8; 14] = [
@@ -253,6 +873,33 @@ pub fn serialize_publish_with_properties() {
     assert_eq!(message, good_publish);
 }
 
+#[test]
+pub fn serialize_publish_with_message_expiry() {
+    let good_publish: [u8; 15] = [
+        0x30, // Publish message
+        0x0d, // Remaining length (13)
+        0x00, 0x03, 0x41, 0x42, 0x43, // Topic: ABC
+        0x05, // Properties length
+        0x02, 0x00, 0x00, 0x00, 0x3c, // Message expiry interval (60 seconds)
+        0xAB, 0xCD, // Payload
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let payload: [u8; 2] = [0xAB, 0xCD];
+    let message = publish_message(
+        &mut buffer,
+        "ABC",
+        &payload,
+        QoS::AtMostOnce,
+        Retain::NotRetained,
+        0,
+        &[Property::MessageExpiryInterval(60)],
+    )
+    .unwrap();
+
+    assert_eq!(message, good_publish);
+}
+
 #[test]
 fn serialize_connect() {
     let good_serialized_connect: [u8; 18] = [
@@ -311,6 +958,43 @@ fn serialize_connect_with_will() {
     assert_eq!(message, good_serialized_connect)
 }
 
+#[test]
+fn serialize_connect_with_will_delay() {
+    #[rustfmt::skip]
+    let good_serialized_connect: [u8; 33] = [
+        0x10, // Connect
+        31, // Remaining length
+
+        // Header: "MQTT5"
+        0x00, 0x04, 0x4d, 0x51, 0x54, 0x54, 0x05,
+
+        // Flags: Clean start, will present, will not retained, will QoS = 0,
+        0b0000_0110,
+
+        // Keep-alive: 10 seconds
+        0x00, 0x0a,
+
+        // Connected Properties: None
+        0x00,
+        // Client ID: "ABC"
+        0x00, 0x03, 0x41, 0x42, 0x43,
+        // Will properties: Will Delay Interval of 30 seconds
+        0x05, 0x18, 0x00, 0x00, 0x00, 0x1e,
+        // Will topic: "EFG"
+        0x00, 0x03, 0x45, 0x46, 0x47,
+        // Will payload: [0xAB, 0xCD]
+        0x00, 0x02, 0xAB, 0xCD,
+    ];
+
+    let mut buffer: [u8; 900] = [0; 900];
+    let client_id = "ABC".as_bytes();
+    let will = Will::<100>::new("EFG", &[0xAB, 0xCD], &[Property::WillDelayInterval(30)]).unwrap();
+
+    let message = connect_message(&mut buffer, client_id, 10, &[], true, Some(&will)).unwrap();
+
+    assert_eq!(message, good_serialized_connect)
+}
+
 #[test]
 fn serialize_ping_req() {
     let good_ping_req: [u8; 2] = [
@@ -1,6 +1,7 @@
 /// This module represents the session state of an MQTT communication session.
-use crate::{warn, QoS};
-use embedded_nal::IpAddr;
+use crate::{subscriptions::Subscriptions, warn, QoS};
+use core::str::FromStr;
+use embedded_nal::{IpAddr, SocketAddr, SocketAddrV6};
 use heapless::{LinearMap, String, Vec};
 
 use embedded_time::{
@@ -8,20 +9,66 @@ use embedded_time::{
     Instant,
 };
 
+/// The TCP port of the broker.
+const MQTT_PORT: u16 = 1883;
+
 /// The default duration to wait for a ping response from the broker.
 const PING_TIMEOUT: Seconds = Seconds(5);
 
+/// Parse the address of a broker that the client is redirected to.
+///
+/// # Args
+/// * `reference` - The Server Reference, i.e. a space-separated list of brokers, each of which may
+///   include a port.
+///
+/// # Returns
+/// The first address in the list, if any. Host names are skipped, as they cannot be resolved.
+pub fn parse_server_reference(reference: &str) -> Option<SocketAddr> {
+    reference.split(' ').find_map(|broker| {
+        SocketAddr::from_str(broker).ok().or_else(|| {
+            // IPv6 addresses may be enclosed in brackets even if no port is specified.
+            IpAddr::from_str(broker.trim_start_matches('[').trim_end_matches(']'))
+                .ok()
+                .map(|ip| SocketAddr::new(ip, MQTT_PORT))
+        })
+    })
+}
+
+/// The keep-alive interval requested by default, in seconds.
+pub(crate) const DEFAULT_KEEP_ALIVE: u16 = 59;
+
+/// Convert a keep-alive interval in seconds, where an interval of zero disables keep-alive.
+pub(crate) fn keep_alive_interval(seconds: u16) -> Option<Milliseconds<u32>> {
+    if seconds == 0 {
+        None
+    } else {
+        Some(Milliseconds(seconds as u32 * 1000))
+    }
+}
+
 pub struct SessionState<Clock: embedded_time::Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
 {
+    /// The keep-alive interval requested when connecting, in seconds.
+    requested_keep_alive: u16,
+    /// The keep-alive interval in effect on the current connection.
     keep_alive_interval: Option<Milliseconds<u32>>,
     ping_timeout: Option<Instant<Clock>>,
     next_ping: Option<Instant<Clock>>,
+    /// The instant at which data was last received from the broker.
+    last_reception: Option<Instant<Clock>>,
     pub broker: IpAddr,
+    pub broker_port: u16,
+    pub broker_scope_id: Option<u32>,
     pub maximum_packet_size: Option<u32>,
     pub client_id: String<64>,
-    pub pending_subscriptions: Vec<u16, 32>,
+    pub subscriptions: Subscriptions,
     pub pending_publish: LinearMap<u16, Vec<u8, MSG_SIZE>, MSG_COUNT>,
     pub pending_publish_ordering: Vec<u16, MSG_COUNT>,
+    /// The QoS 2 messages received by the broker that await completion, and whether they were
+    /// released on the current connection.
+    pub pending_release: Vec<(u16, bool), MSG_COUNT>,
+    /// The packet identifiers of the QoS 2 messages received from the server that await release.
+    pub received: Vec<u16, MSG_COUNT>,
     packet_id: u16,
     active: bool,
 }
@@ -34,13 +81,19 @@ impl<Clock: embedded_time::Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
             active: false,
             ping_timeout: None,
             next_ping: None,
+            last_reception: None,
             broker,
+            broker_port: MQTT_PORT,
+            broker_scope_id: None,
             client_id: id,
             packet_id: 1,
-            keep_alive_interval: Some(59_000.milliseconds()),
-            pending_subscriptions: Vec::new(),
+            requested_keep_alive: DEFAULT_KEEP_ALIVE,
+            keep_alive_interval: keep_alive_interval(DEFAULT_KEEP_ALIVE),
+            subscriptions: Subscriptions::new(),
             pending_publish: LinearMap::new(),
             pending_publish_ordering: Vec::new(),
+            pending_release: Vec::new(),
+            received: Vec::new(),
             maximum_packet_size: None,
         }
     }
@@ -48,11 +101,42 @@ impl<Clock: embedded_time::Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
     pub fn reset(&mut self) {
         self.active = false;
         self.packet_id = 1;
-        self.keep_alive_interval = Some(59_000.milliseconds());
+        self.keep_alive_interval = keep_alive_interval(self.requested_keep_alive);
         self.maximum_packet_size = None;
-        self.pending_subscriptions.clear();
+        self.subscriptions.clear();
         self.pending_publish.clear();
         self.pending_publish_ordering.clear();
+        self.pending_release.clear();
+        self.received.clear();
+    }
+
+    /// Get the socket address of the broker.
+    ///
+    /// # Note
+    /// The scope identifier is only applied to IPv6 addresses.
+    pub fn broker_address(&self) -> SocketAddr {
+        match (self.broker, self.broker_scope_id) {
+            (IpAddr::V6(ip), Some(scope_id)) => {
+                SocketAddr::V6(SocketAddrV6::new(ip, self.broker_port, 0, scope_id))
+            }
+            (ip, _) => SocketAddr::new(ip, self.broker_port),
+        }
+    }
+
+    /// Connect to another broker from now on.
+    ///
+    /// # Args
+    /// * `address` - The socket address of the broker. The scope identifier of IPv6 addresses is
+    ///   only applied if it is non-zero.
+    pub fn set_broker_address(&mut self, address: SocketAddr) {
+        self.broker = address.ip();
+        self.broker_port = address.port();
+
+        if let SocketAddr::V6(address) = address {
+            if address.scope_id() != 0 {
+                self.broker_scope_id.replace(address.scope_id());
+            }
+        }
     }
 
     /// Get the keep-alive interval as an integer number of seconds.
@@ -63,19 +147,32 @@ impl<Clock: embedded_time::Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
         (self.keep_alive_interval.unwrap_or(0.milliseconds()).0 / 1000) as u16
     }
 
+    /// Get the keep-alive interval to request from the broker as an integer number of seconds.
+    pub fn requested_keepalive(&self) -> u16 {
+        self.requested_keep_alive
+    }
+
     /// Update the keep-alive interval.
     ///
     /// # Args
-    /// * `seconds` - The number of seconds in the keep-alive interval.
+    /// * `seconds` - The number of seconds in the keep-alive interval. If zero, no pings are sent.
     pub fn set_keepalive(&mut self, seconds: u16) {
-        self.keep_alive_interval
-            .replace(Milliseconds(seconds as u32 * 1000));
+        self.requested_keep_alive = seconds;
+        self.keep_alive_interval = keep_alive_interval(seconds);
+    }
+
+    /// Apply the keep-alive interval of the current connection without changing the request.
+    ///
+    /// # Args
+    /// * `seconds` - The number of seconds in the keep-alive interval. If zero, no pings are sent.
+    pub fn set_server_keepalive(&mut self, seconds: u16) {
+        self.keep_alive_interval = keep_alive_interval(seconds);
     }
 
-    /// Called when publish with QoS 1 is called so that we can keep track of PUBACK
+    /// Called when publish with QoS 1 or 2 is called so that we can keep track of PUBACK or PUBREC
     pub fn handle_publish(&mut self, qos: QoS, id: u16, packet: &[u8]) {
-        // This is not called for QoS 0 and QoS 2 is not implemented yet
-        assert_eq!(qos, QoS::AtLeastOnce);
+        // This is not called for QoS 0
+        assert_ne!(qos, QoS::AtMostOnce);
 
         let mut buf: Vec<u8, MSG_SIZE> = Vec::from_slice(packet).unwrap();
         // Set DUP = 1 (bit 3). If this packet is ever read it's just because we want to resend it
@@ -87,8 +184,11 @@ impl<Clock: embedded_time::Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
     }
 
     /// Delete given pending publish as the server took ownership of it
-    pub fn handle_puback(&mut self, id: u16) {
-        self.pending_publish.remove(&id);
+    ///
+    /// # Returns
+    /// The serialized message that was pending acknowledgement, if any.
+    pub fn handle_puback(&mut self, id: u16) -> Option<Vec<u8, MSG_SIZE>> {
+        let message = self.pending_publish.remove(&id);
         let mut found = false;
         for i in 0..self.pending_publish_ordering.len() {
             if found {
@@ -99,30 +199,100 @@ impl<Clock: embedded_time::Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
                 }
             }
         }
-        self.pending_publish_ordering.pop();
+        if found {
+            self.pending_publish_ordering.pop();
+        }
+
+        message
+    }
+
+    /// Handle the receipt of a QoS 2 message by the server.
+    ///
+    /// # Args
+    /// * `id` - The packet identifier of the PUBREC.
+    /// * `reason_code` - The reason code provided by the server.
+    ///
+    /// # Returns
+    /// The serialized message that was pending receipt, if any.
+    pub fn handle_pubrec(&mut self, id: u16, reason_code: u8) -> Option<Vec<u8, MSG_SIZE>> {
+        let message = self.handle_puback(id);
+
+        // The message must be released once received by the server. Reason codes of 0x80 and
+        // above indicate that the server rejected the message, which completes the exchange.
+        if message.is_some() && reason_code < 0x80 {
+            // Note(unwrap): The message occupied a slot of the pending messages.
+            self.pending_release.push((id, false)).unwrap();
+        }
+
+        message
     }
 
-    /// Indicates if publish with QoS 1 is possible.
+    /// Delete the given pending release as the server completed the exchange.
+    ///
+    /// # Returns
+    /// True if the release was pending.
+    pub fn handle_pubcomp(&mut self, id: u16) -> bool {
+        let count = self.pending_release.len();
+        self.pending_release.retain(|(pending, _)| *pending != id);
+        self.pending_release.len() != count
+    }
+
+    /// Record the receipt of a QoS 2 message from the server.
+    ///
+    /// # Args
+    /// * `id` - The packet identifier of the received message.
+    ///
+    /// # Returns
+    /// False if no more messages can be received until the server releases a message.
+    pub fn handle_received(&mut self, id: u16) -> bool {
+        self.received.contains(&id) || self.received.push(id).is_ok()
+    }
+
+    /// Delete the given received message as the server released it.
+    ///
+    /// # Returns
+    /// True if the message was received.
+    pub fn handle_pubrel(&mut self, id: u16) -> bool {
+        let count = self.received.len();
+        self.received.retain(|received| *received != id);
+        self.received.len() != count
+    }
+
+    /// Indicates if publish with QoS 1 or 2 is possible.
     pub fn can_publish(&self, qos: QoS) -> bool {
         match qos {
             QoS::AtMostOnce => true,
-            QoS::AtLeastOnce => self.pending_publish.len() < MSG_COUNT,
-            QoS::ExactlyOnce => false,
+            _ => self.in_flight() < MSG_COUNT,
         }
     }
 
+    /// Count the QoS 1 and QoS 2 messages whose delivery has not been completed by the server.
+    pub fn in_flight(&self) -> usize {
+        self.pending_publish.len() + self.pending_release.len()
+    }
+
     pub fn pending_messages(&self, qos: QoS) -> usize {
         match qos {
             QoS::AtMostOnce => 0,
-            QoS::AtLeastOnce => self.pending_publish.len(),
-            QoS::ExactlyOnce => 0,
+            QoS::AtLeastOnce => self.pending_publish_count(qos),
+            QoS::ExactlyOnce => self.pending_publish_count(qos) + self.pending_release.len(),
         }
     }
 
+    /// Count the messages with the given QoS that await acknowledgement or receipt.
+    fn pending_publish_count(&self, qos: QoS) -> usize {
+        // The QoS is encoded in bits 1 and 2 of the fixed header.
+        self.pending_publish
+            .values()
+            .filter(|packet| packet[0] & 0b0110 == (qos as u8) << 1)
+            .count()
+    }
+
     /// Called whenever an active connection has been made with a broker.
     pub fn register_connection(&mut self, now: Instant<Clock>) {
         self.active = true;
         self.ping_timeout = None;
+        self.last_reception.replace(now);
 
         // The next ping should be sent out in half the keep-alive interval from now.
         if let Some(interval) = self.keep_alive_interval {
@@ -161,6 +331,55 @@ impl<Clock: embedded_time::Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
         }
     }
 
+    /// Cancel any ping response that is currently awaited.
+    pub fn cancel_ping(&mut self) {
+        self.ping_timeout.take();
+    }
+
+    /// Called whenever data has been received from the broker.
+    pub fn register_reception(&mut self, now: Instant<Clock>) {
+        self.last_reception.replace(now);
+    }
+
+    /// Restart the reception watchdog, e.g. after data could not be received for a while.
+    pub fn restart_reception_watchdog(&mut self) {
+        self.last_reception.take();
+    }
+
+    /// Check if the broker has been silent for longer than the keep-alive interval permits.
+    ///
+    /// # Note
+    /// The broker responds to the pings sent every half keep-alive interval, so no data arriving
+    /// within one and a half keep-alive intervals indicates that the connection is half-open.
+    ///
+    /// # Args
+    /// * `now` - The current instant in time.
+    ///
+    /// # Returns
+    /// True if the connection should be considered lost.
+    pub fn reception_expired(&mut self, now: Instant<Clock>) -> bool {
+        let interval = match self.keep_alive_interval {
+            Some(interval) => interval,
+            None => return false,
+        };
+
+        let last_reception = *self.last_reception.get_or_insert(now);
+        now > last_reception + interval + interval / 2
+    }
+
+    /// Get the time at which the keep-alive handling requires attention next, if any.
+    pub fn next_deadline(&self) -> Option<Instant<Clock>> {
+        let interval = self.keep_alive_interval?;
+
+        // No further ping is sent while a ping response is awaited.
+        let ping = self.ping_timeout.or(self.next_ping);
+        let reception = self
+            .last_reception
+            .map(|last_reception| last_reception + interval + interval / 2);
+
+        ping.into_iter().chain(reception).min()
+    }
+
     /// Handle ping time management.
     ///
     /// # Args
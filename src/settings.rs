@@ -0,0 +1,352 @@
+//! Remote Settings
+//!
+//! # Design
+//! Devices are commonly configured remotely by publishing the desired value of each setting to its
+//! own topic. `Settings` maps a registry of named settings to `prefix/name` topics:
+//!
+//! ```ignore
+//! let mut interval = settings::Value::new("interval", 10u32);
+//! let mut enabled = settings::Value::new("enabled", true);
+//!
+//! let mut settings: Settings<'_, 2> = Settings::new("settings");
+//! settings.register(&mut interval)?;
+//! settings.register(&mut enabled)?;
+//!
+//! loop {
+//!     settings.update(&mut mqtt.client)?;
+//!     mqtt.poll(|_, topic, payload, _| settings.handle(topic, payload))?;
+//! }
+//! ```
+//!
+//! Once connected, the topics of all settings are subscribed to. Received values are parsed and
+//! applied to the setting, after which an optional typed callback is notified. The current value
+//! of every setting is published as a retained message when connecting and whenever it changes, so
+//! the broker always reflects the configuration in use.
+//!
+//! # Note
+//! The topics are subscribed to without receiving the client's own publications, so published
+//! values are not applied again. If a received value is invalid, the current value is published to
+//! replace it.
+use crate::{mqtt_client::MqttClient, Error, ProtocolError, QoS, Retain, SubscriptionOptions};
+use core::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
+use embedded_nal::TcpClientStack;
+use heapless::{String, Vec};
+
+/// Errors that may occur when handling settings.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SettingsError {
+    /// The received value could not be parsed for the setting.
+    InvalidValue,
+
+    /// No more settings can be registered.
+    Full,
+}
+
+/// A setting that can be configured remotely.
+pub trait Setting {
+    /// The name of the setting, which is used as the last level of its topic.
+    fn name(&self) -> &str;
+
+    /// Apply a received value.
+    ///
+    /// # Args
+    /// * `value` - The received value.
+    ///
+    /// # Returns
+    /// True if the value of the setting changed.
+    fn apply(&mut self, value: &[u8]) -> Result<bool, SettingsError>;
+
+    /// Write the current value of the setting.
+    ///
+    /// # Args
+    /// * `dest` - The location to write the value to.
+    fn write_value(&self, dest: &mut dyn Write) -> core::fmt::Result;
+}
+
+/// A setting holding a value that is parsed from and formatted as a string.
+pub struct Value<T> {
+    name: &'static str,
+    value: T,
+    on_change: Option<fn(&T)>,
+}
+
+impl<T> Value<T> {
+    /// Construct a setting.
+    ///
+    /// # Args
+    /// * `name` - The name of the setting.
+    /// * `value` - The initial value of the setting.
+    pub fn new(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            value,
+            on_change: None,
+        }
+    }
+
+    /// Notify a callback whenever the value of the setting is changed remotely.
+    ///
+    /// # Args
+    /// * `callback` - The function to call with the new value. If `None`, no callback is notified.
+    pub fn set_on_change(&mut self, callback: Option<fn(&T)>) {
+        self.on_change = callback;
+    }
+
+    /// Get the current value of the setting.
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> Setting for Value<T>
+where
+    T: FromStr + Display + PartialEq,
+{
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn apply(&mut self, value: &[u8]) -> Result<bool, SettingsError> {
+        let value: T = core::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .ok_or(SettingsError::InvalidValue)?;
+
+        if value == self.value {
+            return Ok(false);
+        }
+
+        self.value = value;
+        if let Some(callback) = self.on_change {
+            callback(&self.value);
+        }
+
+        Ok(true)
+    }
+
+    fn write_value(&self, dest: &mut dyn Write) -> core::fmt::Result {
+        write!(dest, "{}", self.value)
+    }
+}
+
+struct Entry<'a> {
+    setting: &'a mut dyn Setting,
+    publish: bool,
+}
+
+/// A registry of settings that are configured remotely.
+pub struct Settings<'a, const N: usize> {
+    prefix: &'a str,
+    entries: Vec<Entry<'a>, N>,
+    subscribed: bool,
+}
+
+impl<'a, const N: usize> Settings<'a, N> {
+    /// Construct an empty registry.
+    ///
+    /// # Args
+    /// * `prefix` - The topic prefix of the settings. Each setting uses the topic `prefix/name`.
+    pub fn new(prefix: &'a str) -> Self {
+        Self {
+            prefix,
+            entries: Vec::new(),
+            subscribed: false,
+        }
+    }
+
+    /// Register a setting.
+    ///
+    /// # Args
+    /// * `setting` - The setting to register.
+    pub fn register(&mut self, setting: &'a mut dyn Setting) -> Result<(), SettingsError> {
+        self.entries
+            .push(Entry {
+                setting,
+                publish: true,
+            })
+            .map_err(|_| SettingsError::Full)?;
+
+        // Newly registered settings are subscribed to with the next update.
+        self.subscribed = false;
+        Ok(())
+    }
+
+    fn topic<const S: usize>(&self, name: &str) -> Result<String<S>, ProtocolError> {
+        let mut topic = String::new();
+        write!(topic, "{}/{}", self.prefix, name).map_err(|_| ProtocolError::BufferTooSmall {
+            needed: self.prefix.len() + 1 + name.len(),
+        })?;
+        Ok(topic)
+    }
+
+    /// Subscribe to the settings and publish their values as required.
+    ///
+    /// # Note
+    /// This should be called periodically, e.g. before every call to `Minimq::poll()`.
+    ///
+    /// # Args
+    /// * `client` - The client to communicate with the broker.
+    pub fn update<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>(
+        &mut self,
+        client: &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+    ) -> Result<(), Error<TcpStack::Error>>
+    where
+        TcpStack: TcpClientStack,
+        Clock: embedded_time::Clock,
+    {
+        if !client.is_connected() {
+            // All settings are subscribed to and published again after reconnecting.
+            if self.subscribed {
+                self.subscribed = false;
+                for entry in self.entries.iter_mut() {
+                    entry.publish = true;
+                }
+            }
+
+            return Ok(());
+        }
+
+        if !self.subscribed {
+            let topic: String<MSG_SIZE> = self.topic("+")?;
+            client.subscribe_with_options(
+                &topic,
+                SubscriptionOptions::default().no_local(true),
+                &[],
+            )?;
+            self.subscribed = true;
+        }
+
+        // Values are published once the subscription is active. Values retained by the broker may
+        // still be received afterwards, in which case they are published again once applied.
+        if client.subscriptions_pending() {
+            return Ok(());
+        }
+
+        for index in 0..self.entries.len() {
+            if !self.entries[index].publish {
+                continue;
+            }
+
+            if !client.can_publish(QoS::AtLeastOnce) {
+                break;
+            }
+
+            let setting = &self.entries[index].setting;
+            let topic: String<MSG_SIZE> = self.topic(setting.name())?;
+            let mut value: String<MSG_SIZE> = String::new();
+            setting
+                .write_value(&mut value)
+                .map_err(|_| ProtocolError::Failed)?;
+
+            client.publish(
+                &topic,
+                value.as_bytes(),
+                QoS::AtLeastOnce,
+                Retain::Retained,
+                &[],
+            )?;
+            self.entries[index].publish = false;
+        }
+
+        Ok(())
+    }
+
+    /// Determine if any values are waiting to be published.
+    pub fn publish_pending(&self) -> bool {
+        self.entries.iter().any(|entry| entry.publish)
+    }
+
+    /// Handle a received message.
+    ///
+    /// # Note
+    /// This should be called from the `Minimq::poll()` handler for every received message.
+    ///
+    /// # Args
+    /// * `topic` - The topic that the message was received on.
+    /// * `payload` - The payload of the message.
+    ///
+    /// # Returns
+    /// True if the message updated a setting. `SettingsError::InvalidValue` is returned if the
+    /// value was invalid.
+    pub fn handle(&mut self, topic: &str, payload: &[u8]) -> Result<bool, SettingsError> {
+        let name = match topic
+            .strip_prefix(self.prefix)
+            .and_then(|name| name.strip_prefix('/'))
+        {
+            Some(name) => name,
+            None => return Ok(false),
+        };
+
+        let entry = match self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.setting.name() == name)
+        {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+
+        match entry.setting.apply(payload) {
+            Ok(changed) => {
+                entry.publish |= changed;
+                Ok(changed)
+            }
+            Err(error) => {
+                // The invalid value is replaced by the current one.
+                entry.publish = true;
+                Err(error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Setting, Settings, SettingsError, Value};
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use heapless::String;
+
+    #[test]
+    fn settings_apply_values() {
+        static CHANGED: AtomicU32 = AtomicU32::new(0);
+
+        let mut interval = Value::new("interval", 10u32);
+        interval.set_on_change(Some(|value| CHANGED.store(*value, Ordering::Relaxed)));
+        let mut enabled = Value::new("enabled", false);
+
+        let mut settings: Settings<'_, 2> = Settings::new("settings");
+        settings.register(&mut interval).unwrap();
+        settings.register(&mut enabled).unwrap();
+
+        assert_eq!(settings.handle("settings/interval", b"20"), Ok(true));
+        assert_eq!(settings.handle("settings/interval", b"20"), Ok(false));
+        assert_eq!(
+            settings.handle("settings/enabled", b"yes"),
+            Err(SettingsError::InvalidValue)
+        );
+        assert_eq!(settings.handle("settings/other", b"1"), Ok(false));
+        assert_eq!(settings.handle("other/interval", b"1"), Ok(false));
+        assert_eq!(CHANGED.load(Ordering::Relaxed), 20);
+
+        drop(settings);
+        assert_eq!(*interval.get(), 20);
+        assert!(!*enabled.get());
+
+        let mut value: String<8> = String::new();
+        interval.write_value(&mut value).unwrap();
+        assert_eq!(value, "20");
+    }
+
+    #[test]
+    fn settings_are_bounded() {
+        let mut first = Value::new("first", 1u8);
+        let mut second = Value::new("second", 2u8);
+
+        let mut settings: Settings<'_, 1> = Settings::new("settings");
+        settings.register(&mut first).unwrap();
+        assert_eq!(settings.register(&mut second), Err(SettingsError::Full));
+    }
+}
@@ -0,0 +1,204 @@
+//! Thread-Safe Shared Client
+//!
+//! # Design
+//! On `std` targets, such as gateway services, a single connection with the broker is often used
+//! by multiple threads. The `SharedMqttClient` stores the `Minimq` client behind a mutex and hands
+//! out cloneable handles, so that any thread may publish or subscribe while one thread drives the
+//! client using `poll()`.
+//!
+//! Requests that cannot be handled at the moment (i.e. those failing with `Error::NotReady`, for
+//! example because the maximum number of messages are in flight) block the calling thread until
+//! `poll()` or `with_client()` has made progress, or until a timeout expires.
+//!
+//! # Note
+//! Blocked requests are only retried once another thread calls `poll()` or `with_client()`. A
+//! request that is not ready for any other reason, e.g. because the client is connecting, is
+//! retried once `poll()` was called again.
+use crate::{mqtt_client::MqttClient, Error, Minimq, Property, QoS, Retain};
+
+use embedded_nal::TcpClientStack;
+
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+struct Shared<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    mqtt: Mutex<Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT>>,
+    progress: Condvar,
+}
+
+/// A handle to an MQTT client that is shared between threads.
+pub struct SharedMqttClient<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    shared: Arc<Shared<TcpStack, Clock, MSG_SIZE, MSG_COUNT>>,
+}
+
+impl<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize> Clone
+    for SharedMqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+    SharedMqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    /// Share an MQTT client between threads.
+    ///
+    /// # Args
+    /// * `mqtt` - The client to share.
+    pub fn new(mqtt: Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                mqtt: Mutex::new(mqtt),
+                progress: Condvar::new(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT>> {
+        // Note(unwrap): The lock is only poisoned if another thread panicked while using the
+        // client, in which case the state of the client cannot be relied upon.
+        self.shared.mqtt.lock().unwrap()
+    }
+
+    /// Check the MQTT interface for available messages.
+    ///
+    /// # Note
+    /// This should only be called from a single thread. Other threads blocked on a request are
+    /// woken up afterwards. The client is locked while the handler is executing.
+    ///
+    /// # Args
+    /// * `f` - A closure to process any received messages. See `Minimq::poll()`.
+    ///
+    /// # Returns
+    /// The value returned by the handler if a message was handled.
+    pub fn poll<F, R>(&self, f: F) -> Result<Option<R>, Error<TcpStack::Error>>
+    where
+        for<'a> F: FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+            &'a str,
+            &[u8],
+            &[Property<'a>],
+        ) -> R,
+    {
+        let result = self.lock().poll(f);
+        self.shared.progress.notify_all();
+        result
+    }
+
+    /// Perform an operation on the client.
+    ///
+    /// # Note
+    /// Other threads blocked on a request are woken up afterwards, as the operation may have
+    /// allowed the request to proceed.
+    ///
+    /// # Args
+    /// * `f` - The operation to perform while the client is locked.
+    ///
+    /// # Returns
+    /// The value returned by the operation.
+    pub fn with_client<R>(
+        &self,
+        f: impl FnOnce(&mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>) -> R,
+    ) -> R {
+        let result = f(&mut self.lock().client);
+        self.shared.progress.notify_all();
+        result
+    }
+
+    fn retry<R>(
+        &self,
+        timeout: Duration,
+        mut f: impl FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+        ) -> Result<R, Error<TcpStack::Error>>,
+    ) -> Result<R, Error<TcpStack::Error>> {
+        let deadline = Instant::now() + timeout;
+        let mut mqtt = self.lock();
+
+        loop {
+            match f(&mut mqtt.client) {
+                Err(Error::NotReady) => {}
+                result => return result,
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::NotReady);
+            }
+
+            // Note(unwrap): See `lock()`.
+            mqtt = self
+                .shared
+                .progress
+                .wait_timeout(mqtt, remaining)
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Publish a message over MQTT.
+    ///
+    /// # Note
+    /// If the message cannot be published at the moment, the calling thread is blocked until the
+    /// message can be published or the timeout expires. See `MqttClient::publish()`.
+    ///
+    /// # Args
+    /// * `topic` - The topic to publish the message to.
+    /// * `data` - The data to transmit as the message contents.
+    /// * `qos` - The desired quality-of-service level of the message.
+    /// * `retain` - Specifies whether the message should be retained by the broker.
+    /// * `properties` - A list of properties to associate with the message being published. May be
+    ///   empty.
+    /// * `timeout` - The maximum duration to wait for the message to be published.
+    pub fn publish(
+        &self,
+        topic: &str,
+        data: &[u8],
+        qos: QoS,
+        retain: Retain,
+        properties: &[Property],
+        timeout: Duration,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.retry(timeout, |client| {
+            client.publish(topic, data, qos, retain, properties)
+        })
+    }
+
+    /// Subscribe to a topic.
+    ///
+    /// # Note
+    /// If the subscription cannot be requested at the moment, the calling thread is blocked until
+    /// it can be requested or the timeout expires. See `MqttClient::subscribe()`.
+    ///
+    /// # Args
+    /// * `topic` - The topic to subscribe to.
+    /// * `properties` - A list of properties to attach to the subscription request. May be empty.
+    /// * `timeout` - The maximum duration to wait for the subscription to be requested.
+    pub fn subscribe(
+        &self,
+        topic: &str,
+        properties: &[Property],
+        timeout: Duration,
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.retry(timeout, |client| client.subscribe(topic, properties))
+    }
+}
@@ -0,0 +1,178 @@
+//! std TCP Network Stack
+//!
+//! # Design
+//! During development, it is convenient to run the same client code on a desktop against a local
+//! broker such as Mosquitto. On `std` targets, the `StdTcpStack` connects the client to the broker
+//! using a `std::net::TcpStream`, so no embedded network stack is required.
+//!
+//! # Note
+//! Connecting blocks until the connection is established or the connect timeout elapsed. Once
+//! connected, the stream is operated in non-blocking mode. If the broker closes the connection,
+//! the socket is reported as disconnected so that the client reconnects.
+use embedded_nal::{nb, IpAddr, SocketAddr, TcpClientStack};
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{self, TcpStream},
+    time::Duration,
+};
+
+/// The time to wait for a connection to be established by default.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A network stack that connects to a broker using `std::net`.
+#[derive(Debug, Clone)]
+pub struct StdTcpStack {
+    connect_timeout: Duration,
+}
+
+/// The socket of a `StdTcpStack`.
+#[derive(Debug)]
+pub struct StdTcpSocket {
+    stream: Option<TcpStream>,
+}
+
+impl Default for StdTcpStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StdTcpStack {
+    /// Construct a network stack using `std::net`.
+    pub fn new() -> Self {
+        Self {
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+        }
+    }
+
+    /// Configure the time to wait for a connection to be established.
+    ///
+    /// # Args
+    /// * `timeout` - The connect timeout. It must not be zero.
+    pub fn set_connect_timeout(&mut self, timeout: Duration) {
+        self.connect_timeout = timeout;
+    }
+}
+
+/// Convert an embedded-nal socket address into a `std::net` socket address.
+fn std_address(remote: SocketAddr) -> net::SocketAddr {
+    let ip: net::IpAddr = match remote.ip() {
+        IpAddr::V4(ip) => net::Ipv4Addr::from(ip.octets()).into(),
+        IpAddr::V6(ip) => net::Ipv6Addr::from(ip.octets()).into(),
+    };
+
+    net::SocketAddr::new(ip, remote.port())
+}
+
+fn stream(socket: &mut StdTcpSocket) -> Result<&mut TcpStream, io::Error> {
+    socket
+        .stream
+        .as_mut()
+        .ok_or_else(|| io::Error::from(ErrorKind::NotConnected))
+}
+
+fn nb_error(error: io::Error) -> nb::Error<io::Error> {
+    match error.kind() {
+        ErrorKind::WouldBlock => nb::Error::WouldBlock,
+        _ => nb::Error::Other(error),
+    }
+}
+
+impl TcpClientStack for StdTcpStack {
+    type TcpSocket = StdTcpSocket;
+    type Error = io::Error;
+
+    fn socket(&mut self) -> Result<StdTcpSocket, io::Error> {
+        Ok(StdTcpSocket { stream: None })
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut StdTcpSocket,
+        remote: SocketAddr,
+    ) -> nb::Result<(), io::Error> {
+        let stream = TcpStream::connect_timeout(&std_address(remote), self.connect_timeout)?;
+        stream.set_nonblocking(true)?;
+
+        // MQTT packets are small, so they are sent right away instead of being coalesced.
+        stream.set_nodelay(true)?;
+        socket.stream.replace(stream);
+        Ok(())
+    }
+
+    fn is_connected(&mut self, socket: &StdTcpSocket) -> Result<bool, io::Error> {
+        Ok(socket.stream.is_some())
+    }
+
+    fn send(&mut self, socket: &mut StdTcpSocket, buffer: &[u8]) -> nb::Result<usize, io::Error> {
+        stream(socket)?.write(buffer).map_err(nb_error)
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut StdTcpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, io::Error> {
+        match stream(socket)?.read(buffer) {
+            // The broker closed the connection.
+            Ok(0) if !buffer.is_empty() => {
+                socket.stream.take();
+                Err(nb::Error::Other(ErrorKind::ConnectionReset.into()))
+            }
+            result => result.map_err(nb_error),
+        }
+    }
+
+    fn close(&mut self, _socket: StdTcpSocket) -> Result<(), io::Error> {
+        // The stream is closed when the socket is dropped.
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StdTcpStack;
+    use embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, TcpClientStack};
+    use std::{io::Write, net::TcpListener};
+
+    #[test]
+    fn std_stack_transfers_data() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mut stack = StdTcpStack::new();
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), port);
+        let mut socket = stack.socket().unwrap();
+        assert!(!stack.is_connected(&socket).unwrap());
+
+        stack.connect(&mut socket, remote).unwrap();
+        assert!(stack.is_connected(&socket).unwrap());
+        let (mut broker, _) = listener.accept().unwrap();
+
+        let mut buffer = [0u8; 4];
+        assert!(matches!(
+            stack.receive(&mut socket, &mut buffer),
+            Err(nb::Error::WouldBlock)
+        ));
+
+        broker.write_all(&[1, 2]).unwrap();
+        let received = loop {
+            match stack.receive(&mut socket, &mut buffer) {
+                Err(nb::Error::WouldBlock) => std::thread::yield_now(),
+                result => break result.unwrap(),
+            }
+        };
+        assert_eq!(buffer[..received], [1, 2]);
+
+        // The socket is disconnected once the broker closes the connection.
+        drop(broker);
+        loop {
+            match stack.receive(&mut socket, &mut buffer) {
+                Err(nb::Error::WouldBlock) => std::thread::yield_now(),
+                result => break assert!(result.is_err()),
+            }
+        }
+        assert!(!stack.is_connected(&socket).unwrap());
+    }
+}
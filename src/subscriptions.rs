@@ -0,0 +1,854 @@
+//! Subscription Tracking
+//!
+//! # Design
+//! The topic filters requested by the client are tracked as part of the session state. A
+//! subscription is pending from the transmission of the SUBSCRIBE until the broker acknowledges it
+//! with a SUBACK, after which it is active with the quality-of-service granted by the broker.
+//! Active subscriptions are maintained for as long as the broker maintains the session.
+//!
+//! Unsubscribing is tracked in the same manner. The subscription remains active until the broker
+//! acknowledges the UNSUBSCRIBE with an UNSUBACK, after which it is removed.
+//!
+//! If the broker does not maintain the session, subscriptions may be renewed automatically. They
+//! are then requested again with their original options once the client reconnects.
+//!
+//! A subscription may limit the size of the payloads that it accepts. Received messages that only
+//! match subscriptions with a smaller limit are skipped without being buffered completely, so an
+//! occasional large message on a shared topic does not disrupt a client with small buffers.
+//!
+//! # Note
+//! Topic filters are stored inline, so up to `MAX_SUBSCRIPTIONS` filters of up to
+//! `MAX_FILTER_LENGTH` bytes each are supported.
+use crate::QoS;
+use heapless::{String, Vec};
+
+/// The maximum length of a tracked topic filter in bytes.
+pub(crate) const MAX_FILTER_LENGTH: usize = 128;
+
+/// The maximum number of tracked subscriptions, including pending subscriptions.
+pub(crate) const MAX_SUBSCRIPTIONS: usize = 16;
+
+/// A subscription that was acknowledged by the broker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subscription {
+    filter: String<MAX_FILTER_LENGTH>,
+    qos: QoS,
+    options: SubscriptionOptions,
+    max_payload_size: Option<usize>,
+}
+
+impl Subscription {
+    /// The topic filter of the subscription.
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    /// The maximum quality-of-service granted by the broker for the subscription.
+    pub fn qos(&self) -> QoS {
+        self.qos
+    }
+
+    /// The options that the subscription was requested with.
+    pub fn options(&self) -> SubscriptionOptions {
+        self.options
+    }
+
+    /// The maximum size of payloads accepted by the subscription, if limited.
+    pub fn max_payload_size(&self) -> Option<usize> {
+        self.max_payload_size
+    }
+}
+
+/// The handling of retained messages when a subscription is made.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RetainHandling {
+    /// Retained messages are sent whenever the subscription is made.
+    SendAtSubscribe = 0,
+
+    /// Retained messages are only sent if the subscription did not exist before.
+    SendAtNewSubscribe = 1,
+
+    /// Retained messages are not sent.
+    DoNotSend = 2,
+}
+
+/// The options of a subscription request.
+///
+/// # Note
+/// By default, messages are delivered with `QoS::AtMostOnce`, the client's own messages are
+/// delivered, and retained messages are sent whenever the subscription is made.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SubscriptionOptions {
+    qos: QoS,
+    no_local: bool,
+    retain_as_published: bool,
+    retain_handling: RetainHandling,
+}
+
+impl Default for SubscriptionOptions {
+    fn default() -> Self {
+        Self {
+            qos: QoS::AtMostOnce,
+            no_local: false,
+            retain_as_published: false,
+            retain_handling: RetainHandling::SendAtSubscribe,
+        }
+    }
+}
+
+impl SubscriptionOptions {
+    /// Set the maximum quality-of-service that messages are delivered with.
+    ///
+    /// # Args
+    /// * `qos` - The maximum quality-of-service level.
+    pub fn qos(mut self, qos: QoS) -> Self {
+        self.qos = qos;
+        self
+    }
+
+    /// Specify whether the broker may deliver messages that were published by the client itself.
+    ///
+    /// # Args
+    /// * `no_local` - If true, the client's own messages are not delivered.
+    pub fn no_local(mut self, no_local: bool) -> Self {
+        self.no_local = no_local;
+        self
+    }
+
+    /// Specify whether delivered messages keep the retain flag that they were published with.
+    ///
+    /// # Args
+    /// * `retain_as_published` - If true, the retain flag is kept. Otherwise, it is only set for
+    ///   retained messages sent when the subscription is made.
+    pub fn retain_as_published(mut self, retain_as_published: bool) -> Self {
+        self.retain_as_published = retain_as_published;
+        self
+    }
+
+    /// Set the handling of retained messages when the subscription is made.
+    ///
+    /// # Args
+    /// * `retain_handling` - Specifies whether retained messages are sent.
+    pub fn retain_handling(mut self, retain_handling: RetainHandling) -> Self {
+        self.retain_handling = retain_handling;
+        self
+    }
+
+    /// Determine if the client's own messages are not delivered.
+    pub(crate) fn is_no_local(&self) -> bool {
+        self.no_local
+    }
+
+    /// Encode the options into the subscription options byte of a SUBSCRIBE.
+    pub(crate) fn as_byte(&self) -> u8 {
+        self.qos as u8
+            | (self.no_local as u8) << 2
+            | (self.retain_as_published as u8) << 3
+            | (self.retain_handling as u8) << 4
+    }
+}
+
+/// Determine if a topic filter is well-formed.
+///
+/// # Note
+/// Filters must not be empty. The `+` wildcard must occupy an entire level and the `#` wildcard
+/// must occupy the last level. Shared filters of the form `$share/{share}/{filter}` must name a
+/// share that does not contain any wildcards.
+///
+/// # Args
+/// * `filter` - The topic filter to check.
+pub(crate) fn is_valid_filter(filter: &str) -> bool {
+    // Shared subscriptions must name a share that does not contain any wildcards.
+    let filter = match split_shared(filter) {
+        Some((share, _)) if share.is_empty() || share.contains(['+', '#']) => return false,
+        Some((_, filter)) => filter,
+        None if filter.starts_with("$share/") => return false,
+        None => filter,
+    };
+
+    if filter.is_empty() {
+        return false;
+    }
+
+    let mut levels = filter.split('/').peekable();
+    while let Some(level) = levels.next() {
+        let valid = match level {
+            "+" => true,
+            "#" => levels.peek().is_none(),
+            level => !level.contains(['+', '#']),
+        };
+
+        if !valid {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Split a shared subscription filter of the form `$share/{share}/{filter}` into its parts.
+///
+/// # Returns
+/// The name of the share and the topic filter, or `None` if the filter is not shared.
+pub(crate) fn split_shared(filter: &str) -> Option<(&str, &str)> {
+    filter.strip_prefix("$share/")?.split_once('/')
+}
+
+/// Determine if a topic matches a topic filter.
+///
+/// # Args
+/// * `filter` - The topic filter, which may contain the `+` and `#` wildcards.
+/// * `topic` - The topic name.
+pub(crate) fn topic_matches(filter: &str, topic: &str) -> bool {
+    // Messages of shared subscriptions are delivered on the topic matching the shared filter.
+    let filter = split_shared(filter).map_or(filter, |(_, filter)| filter);
+
+    // Topics starting with `$` are not matched by filters starting with a wildcard.
+    if topic.starts_with('$') && filter.starts_with(['+', '#']) {
+        return false;
+    }
+
+    let mut levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(topic)) if level == topic => {}
+            _ => return false,
+        }
+    }
+
+    levels.next().is_none()
+}
+
+/// The pending and active subscriptions of a session.
+pub(crate) struct Subscriptions {
+    pending: Vec<(u16, Subscription), MAX_SUBSCRIPTIONS>,
+    active: Vec<Subscription, MAX_SUBSCRIPTIONS>,
+    removals: Vec<(u16, String<MAX_FILTER_LENGTH>), MAX_SUBSCRIPTIONS>,
+    renewals: Vec<Subscription, MAX_SUBSCRIPTIONS>,
+}
+
+impl Subscriptions {
+    /// Construct an empty set of subscriptions.
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            active: Vec::new(),
+            removals: Vec::new(),
+            renewals: Vec::new(),
+        }
+    }
+
+    /// Determine if a subscription to a topic filter can be requested.
+    ///
+    /// # Args
+    /// * `filter` - The topic filter to subscribe to.
+    pub fn can_request(&self, filter: &str) -> bool {
+        self.can_request_all(core::iter::once(filter))
+    }
+
+    /// Determine if subscriptions to multiple topic filters can be requested at once.
+    ///
+    /// # Args
+    /// * `filters` - The topic filters to subscribe to.
+    pub fn can_request_all<'a>(&self, filters: impl Iterator<Item = &'a str>) -> bool {
+        let mut requested = 0;
+        let mut added = 0;
+        for filter in filters {
+            if filter.len() > MAX_FILTER_LENGTH {
+                return false;
+            }
+
+            // Renewed subscriptions replace the active subscription once acknowledged.
+            requested += 1;
+            if !self
+                .active
+                .iter()
+                .chain(self.renewals.iter())
+                .any(|existing| existing.filter == filter)
+            {
+                added += 1;
+            }
+        }
+
+        self.pending.len() + requested <= MAX_SUBSCRIPTIONS
+            && self.pending.len() + self.active.len() + self.renewals.len() + added
+                <= MAX_SUBSCRIPTIONS
+    }
+
+    /// Register a requested subscription.
+    ///
+    /// # Args
+    /// * `packet_id` - The packet identifier of the SUBSCRIBE packet.
+    /// * `filter` - The requested topic filter.
+    /// * `options` - The options of the subscription.
+    pub fn request(
+        &mut self,
+        packet_id: u16,
+        filter: &str,
+        options: SubscriptionOptions,
+    ) -> Result<(), ()> {
+        if !self.can_request(filter) {
+            return Err(());
+        }
+
+        // A renewed subscription keeps its payload size limit.
+        let max_payload_size = self
+            .active
+            .iter()
+            .chain(self.renewals.iter())
+            .find(|existing| existing.filter == filter)
+            .and_then(|existing| existing.max_payload_size);
+
+        let subscription = Subscription {
+            filter: String::from(filter),
+            qos: QoS::AtMostOnce,
+            options,
+            max_payload_size,
+        };
+
+        self.pending
+            .push((packet_id, subscription))
+            .map_err(|_| ())?;
+
+        // The request supersedes any renewal of the subscription.
+        self.renewals.retain(|renewal| renewal.filter != filter);
+        Ok(())
+    }
+
+    /// Get the topic filters of a pending subscription request.
+    ///
+    /// # Args
+    /// * `packet_id` - The packet identifier of the SUBSCRIBE packet.
+    ///
+    /// # Returns
+    /// The topic filters in the order that they were requested.
+    pub fn requested(&self, packet_id: u16) -> impl Iterator<Item = &str> {
+        self.pending
+            .iter()
+            .filter(move |(id, _)| *id == packet_id)
+            .map(|(_, subscription)| subscription.filter())
+    }
+
+    /// Handle the acknowledgement of a subscription request.
+    ///
+    /// # Args
+    /// * `packet_id` - The packet identifier of the SUBACK packet.
+    /// * `reason_codes` - The reason codes provided by the broker, one for each topic filter of
+    ///   the request.
+    ///
+    /// # Returns
+    /// An error if no subscription was requested with the packet identifier, or if the number of
+    /// reason codes does not match the number of requested topic filters.
+    pub fn acknowledge(&mut self, packet_id: u16, reason_codes: &[u8]) -> Result<(), ()> {
+        let requested = self
+            .pending
+            .iter()
+            .filter(|(id, _)| *id == packet_id)
+            .count();
+        if requested == 0 || requested != reason_codes.len() {
+            return Err(());
+        }
+
+        // The topic filters are acknowledged in the order that they were requested.
+        for reason_code in reason_codes {
+            // Note(unwrap): The number of pending subscriptions was checked above.
+            let index = self
+                .pending
+                .iter()
+                .position(|(id, _)| *id == packet_id)
+                .unwrap();
+            let (_, mut subscription) = self.pending.remove(index);
+
+            // Reason codes below 0x80 indicate success and contain the granted quality-of-service.
+            subscription.qos = match reason_code {
+                0 => QoS::AtMostOnce,
+                1 => QoS::AtLeastOnce,
+                2 => QoS::ExactlyOnce,
+                _ => continue,
+            };
+
+            self.active
+                .retain(|active| active.filter != subscription.filter);
+
+            // Note(unwrap): Space for the subscription was reserved when it was requested.
+            self.active.push(subscription).unwrap();
+        }
+
+        Ok(())
+    }
+
+    /// Determine if the removal of a subscription can be requested.
+    ///
+    /// # Args
+    /// * `filter` - The topic filter to unsubscribe from.
+    pub fn can_request_removal(&self, filter: &str) -> bool {
+        filter.len() <= MAX_FILTER_LENGTH && !self.removals.is_full()
+    }
+
+    /// Register a requested removal of a subscription.
+    ///
+    /// # Args
+    /// * `packet_id` - The packet identifier of the UNSUBSCRIBE packet.
+    /// * `filter` - The topic filter to unsubscribe from.
+    pub fn request_removal(&mut self, packet_id: u16, filter: &str) -> Result<(), ()> {
+        if !self.can_request_removal(filter) {
+            return Err(());
+        }
+
+        self.removals
+            .push((packet_id, String::from(filter)))
+            .map_err(|_| ())?;
+
+        // A subscription that is removed is not renewed anymore.
+        self.cancel_renewal(filter);
+        Ok(())
+    }
+
+    /// Get an active subscription whose removal has not been requested.
+    pub fn next_unrequested_removal(&self) -> Option<&Subscription> {
+        self.active.iter().find(|subscription| {
+            !self
+                .removals
+                .iter()
+                .any(|(_, filter)| *filter == subscription.filter)
+        })
+    }
+
+    /// Determine if any removals are awaiting acknowledgement.
+    pub fn is_removing(&self) -> bool {
+        !self.removals.is_empty()
+    }
+
+    /// Handle the acknowledgement of an unsubscribe request.
+    ///
+    /// # Args
+    /// * `packet_id` - The packet identifier of the UNSUBACK packet.
+    /// * `reason_code` - The reason code provided by the broker.
+    ///
+    /// # Returns
+    /// An error if no removal was requested with the packet identifier.
+    pub fn acknowledge_removal(&mut self, packet_id: u16, reason_code: u8) -> Result<(), ()> {
+        let index = self
+            .removals
+            .iter()
+            .position(|(id, _)| *id == packet_id)
+            .ok_or(())?;
+        let (_, filter) = self.removals.swap_remove(index);
+
+        // The subscription is retained by the broker if the request failed.
+        if reason_code < 0x80 {
+            self.active.retain(|active| active.filter != filter);
+        }
+
+        Ok(())
+    }
+
+    fn all_mut(&mut self) -> impl Iterator<Item = &mut Subscription> {
+        self.active
+            .iter_mut()
+            .chain(
+                self.pending
+                    .iter_mut()
+                    .map(|(_, subscription)| subscription),
+            )
+            .chain(self.renewals.iter_mut())
+    }
+
+    /// Limit the size of payloads accepted by a subscription.
+    ///
+    /// # Args
+    /// * `filter` - The topic filter of the subscription.
+    /// * `limit` - The maximum payload size. If `None`, payloads of any size are accepted.
+    ///
+    /// # Returns
+    /// True if a pending or active subscription with the topic filter exists.
+    pub fn set_max_payload_size(&mut self, filter: &str, limit: Option<usize>) -> bool {
+        let mut found = false;
+        for subscription in self
+            .all_mut()
+            .filter(|subscription| subscription.filter == filter)
+        {
+            subscription.max_payload_size = limit;
+            found = true;
+        }
+
+        found
+    }
+
+    /// Determine if a payload exceeds the limits of all subscriptions matching its topic.
+    ///
+    /// # Args
+    /// * `topic` - The topic that the message was received on.
+    /// * `size` - The size of the payload.
+    pub fn exceeds_payload_limit(&self, topic: &str, size: usize) -> bool {
+        let mut matching = self
+            .active
+            .iter()
+            .chain(self.pending.iter().map(|(_, subscription)| subscription))
+            .filter(|subscription| topic_matches(&subscription.filter, topic))
+            .peekable();
+
+        // Messages that do not match any subscription are always accepted.
+        matching.peek().is_some()
+            && matching.all(
+                |subscription| matches!(subscription.max_payload_size, Some(limit) if size > limit),
+            )
+    }
+
+    /// Determine if any subscriptions or removals are awaiting acknowledgement.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty() || !self.removals.is_empty()
+    }
+
+    /// Abandon all subscription and removal requests that await acknowledgement.
+    ///
+    /// # Note
+    /// The broker does not acknowledge requests sent on a previous connection, even if the
+    /// session is resumed. Active subscriptions are kept.
+    pub fn abandon_requests(&mut self) {
+        self.pending.clear();
+        self.removals.clear();
+    }
+
+    /// Mark subscriptions to be requested again, e.g. on the next connection.
+    ///
+    /// # Note
+    /// Subscriptions that are being removed are not renewed.
+    ///
+    /// # Args
+    /// * `active` - Specifies whether active subscriptions are renewed in addition to pending
+    ///   subscriptions, e.g. because the broker did not maintain the session.
+    pub fn renew(&mut self, active: bool) {
+        let pending = self.pending.iter().map(|(_, subscription)| subscription);
+        let active = self.active.iter().filter(|_| active);
+
+        for subscription in active.chain(pending) {
+            let removed = self
+                .removals
+                .iter()
+                .any(|(_, filter)| *filter == subscription.filter);
+            let renewed = self
+                .renewals
+                .iter()
+                .any(|renewal| renewal.filter == subscription.filter);
+
+            if !removed && !renewed {
+                // Note(unwrap): Renewals are accounted for when subscriptions are requested, so
+                // there are never more renewals than tracked subscriptions.
+                self.renewals.push(subscription.clone()).unwrap();
+            }
+        }
+    }
+
+    /// Get the next subscription that needs to be requested again.
+    pub fn next_renewal(&self) -> Option<&Subscription> {
+        self.renewals.first()
+    }
+
+    /// Stop renewing a subscription, e.g. because it cannot be requested anymore.
+    ///
+    /// # Args
+    /// * `filter` - The topic filter of the subscription.
+    pub fn cancel_renewal(&mut self, filter: &str) {
+        self.renewals.retain(|renewal| renewal.filter != filter);
+    }
+
+    /// Stop renewing all subscriptions.
+    pub fn cancel_renewals(&mut self) {
+        self.renewals.clear();
+    }
+
+    /// Get the active subscriptions.
+    pub fn active(&self) -> impl Iterator<Item = &Subscription> {
+        self.active.iter()
+    }
+
+    /// Remove all pending and active subscriptions.
+    ///
+    /// # Note
+    /// Subscriptions that are marked for renewal are kept.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.active.clear();
+        self.removals.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_valid_filter, topic_matches, RetainHandling, SubscriptionOptions, Subscriptions,
+        MAX_FILTER_LENGTH, MAX_SUBSCRIPTIONS,
+    };
+    use crate::QoS;
+
+    #[test]
+    fn subscriptions_become_active() {
+        let mut subscriptions = Subscriptions::new();
+        subscriptions
+            .request(1, "a/#", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions
+            .request(2, "b", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions
+            .request(3, "c", SubscriptionOptions::default())
+            .unwrap();
+        assert!(subscriptions.is_pending());
+        assert_eq!(subscriptions.active().count(), 0);
+
+        subscriptions.acknowledge(2, &[0x01]).unwrap();
+        subscriptions.acknowledge(1, &[0x00]).unwrap();
+
+        // A rejected subscription does not become active.
+        subscriptions.acknowledge(3, &[0x87]).unwrap();
+        assert!(subscriptions.acknowledge(4, &[0x00]).is_err());
+        assert!(!subscriptions.is_pending());
+
+        let active: heapless::Vec<_, 4> = subscriptions
+            .active()
+            .map(|subscription| (subscription.filter(), subscription.qos()))
+            .collect();
+        assert_eq!(active, [("b", QoS::AtLeastOnce), ("a/#", QoS::AtMostOnce)]);
+    }
+
+    #[test]
+    fn multiple_filters_are_acknowledged() {
+        let mut subscriptions = Subscriptions::new();
+        assert!(subscriptions.can_request_all(["a", "b", "c"].iter().copied()));
+        subscriptions
+            .request(1, "a", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions
+            .request(1, "b", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions
+            .request(1, "c", SubscriptionOptions::default())
+            .unwrap();
+
+        // Each requested topic filter must be acknowledged.
+        assert!(subscriptions.acknowledge(1, &[0x00, 0x01]).is_err());
+        assert!(subscriptions
+            .requested(1)
+            .eq(["a", "b", "c"].iter().copied()));
+        subscriptions.acknowledge(1, &[0x00, 0x87, 0x01]).unwrap();
+        assert_eq!(subscriptions.requested(1).count(), 0);
+        assert!(!subscriptions.is_pending());
+
+        let active: heapless::Vec<_, 4> = subscriptions
+            .active()
+            .map(|subscription| (subscription.filter(), subscription.qos()))
+            .collect();
+        assert_eq!(active, [("a", QoS::AtMostOnce), ("c", QoS::AtLeastOnce)]);
+
+        let filters: heapless::Vec<heapless::String<16>, MAX_SUBSCRIPTIONS> = (0
+            ..MAX_SUBSCRIPTIONS)
+            .map(|id| core::iter::repeat_n('d', id + 1).collect())
+            .collect();
+        assert!(!subscriptions.can_request_all(filters.iter().map(|filter| filter.as_str())));
+        assert!(subscriptions.can_request_all(filters[2..].iter().map(|filter| filter.as_str())));
+    }
+
+    #[test]
+    fn subscriptions_are_bounded() {
+        let mut subscriptions = Subscriptions::new();
+        let filter: heapless::String<{ MAX_FILTER_LENGTH + 1 }> =
+            core::iter::repeat_n('a', MAX_FILTER_LENGTH + 1).collect();
+        assert!(subscriptions
+            .request(1, &filter, SubscriptionOptions::default())
+            .is_err());
+
+        for id in 0..MAX_SUBSCRIPTIONS as u16 {
+            let filter: heapless::String<16> = core::iter::repeat_n('a', id as usize + 1).collect();
+            subscriptions
+                .request(id, &filter, SubscriptionOptions::default())
+                .unwrap();
+            subscriptions.acknowledge(id, &[0x00]).unwrap();
+        }
+
+        assert!(subscriptions
+            .request(100, "b", SubscriptionOptions::default())
+            .is_err());
+
+        // An active subscription may be renewed.
+        subscriptions
+            .request(100, "a", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions.acknowledge(100, &[0x01]).unwrap();
+        assert_eq!(subscriptions.active().count(), MAX_SUBSCRIPTIONS);
+    }
+
+    #[test]
+    fn shared_filters() {
+        assert!(is_valid_filter("$share/group/a/+"));
+        assert!(is_valid_filter("$share/group/#"));
+        assert!(!is_valid_filter("$share/group"));
+        assert!(!is_valid_filter("$share/group/"));
+        assert!(!is_valid_filter("$share//a"));
+        assert!(!is_valid_filter("$share/gr+up/a"));
+        assert!(!is_valid_filter("$share/group/a/#/b"));
+
+        assert!(topic_matches("$share/group/a/+", "a/b"));
+        assert!(!topic_matches("$share/group/a/+", "group/a/b"));
+    }
+
+    #[test]
+    fn topic_filters() {
+        assert!(topic_matches("a/b", "a/b"));
+        assert!(!topic_matches("a/b", "a/b/c"));
+        assert!(topic_matches("a/+/c", "a/b/c"));
+        assert!(!topic_matches("a/+", "a/b/c"));
+        assert!(topic_matches("a/#", "a"));
+        assert!(topic_matches("a/#", "a/b/c"));
+        assert!(topic_matches("#", "a/b"));
+        assert!(!topic_matches("#", "$SYS/broker"));
+        assert!(topic_matches("$SYS/#", "$SYS/broker"));
+    }
+
+    #[test]
+    fn subscriptions_are_removed() {
+        let mut subscriptions = Subscriptions::new();
+        subscriptions
+            .request(1, "a", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions
+            .request(2, "b", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions.acknowledge(1, &[0x00]).unwrap();
+        subscriptions.acknowledge(2, &[0x00]).unwrap();
+
+        // Subscriptions remain active until the removal is acknowledged.
+        subscriptions.request_removal(3, "a").unwrap();
+        subscriptions.request_removal(4, "b").unwrap();
+        assert!(subscriptions.is_pending());
+        assert_eq!(subscriptions.active().count(), 2);
+
+        subscriptions.acknowledge_removal(3, 0x00).unwrap();
+
+        // A failed removal leaves the subscription active.
+        subscriptions.acknowledge_removal(4, 0x87).unwrap();
+        assert!(subscriptions.acknowledge_removal(5, 0x00).is_err());
+        assert!(!subscriptions.is_pending());
+
+        let active: heapless::Vec<_, 4> = subscriptions
+            .active()
+            .map(|subscription| subscription.filter())
+            .collect();
+        assert_eq!(active, ["b"]);
+    }
+
+    #[test]
+    fn options_are_encoded() {
+        assert_eq!(SubscriptionOptions::default().as_byte(), 0);
+
+        let options = SubscriptionOptions::default()
+            .qos(QoS::ExactlyOnce)
+            .no_local(true)
+            .retain_as_published(true)
+            .retain_handling(RetainHandling::DoNotSend);
+        assert_eq!(options.as_byte(), 0b0010_1110);
+    }
+
+    #[test]
+    fn requests_are_abandoned() {
+        let mut subscriptions = Subscriptions::new();
+        subscriptions
+            .request(1, "a", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions.acknowledge(1, &[0x00]).unwrap();
+        subscriptions
+            .request(2, "b", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions.request_removal(3, "a").unwrap();
+
+        subscriptions.abandon_requests();
+        assert!(!subscriptions.is_pending());
+        assert!(subscriptions.acknowledge(2, &[0x00]).is_err());
+        assert_eq!(subscriptions.active().count(), 1);
+    }
+
+    #[test]
+    fn subscriptions_are_renewed() {
+        let mut subscriptions = Subscriptions::new();
+        let options = SubscriptionOptions::default().qos(QoS::AtLeastOnce);
+        subscriptions.request(1, "a", options).unwrap();
+        subscriptions
+            .request(2, "b", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions
+            .request(3, "c", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions.acknowledge(1, &[0x01]).unwrap();
+        subscriptions.acknowledge(2, &[0x00]).unwrap();
+        subscriptions.request_removal(4, "b").unwrap();
+        assert!(subscriptions.set_max_payload_size("a", Some(16)));
+
+        // Subscriptions that are being removed are not renewed.
+        subscriptions.renew(true);
+        subscriptions.clear();
+        assert_eq!(subscriptions.active().count(), 0);
+
+        let renewal = subscriptions.next_renewal().unwrap();
+        assert_eq!(renewal.filter(), "a");
+        assert_eq!(renewal.options(), options);
+
+        // Requesting a subscription completes its renewal, keeping the payload size limit.
+        subscriptions.request(5, "a", options).unwrap();
+        assert_eq!(subscriptions.next_renewal().unwrap().filter(), "c");
+        subscriptions.acknowledge(5, &[0x01]).unwrap();
+        assert_eq!(
+            subscriptions.active().next().unwrap().max_payload_size(),
+            Some(16)
+        );
+
+        subscriptions.cancel_renewal("c");
+        assert!(subscriptions.next_renewal().is_none());
+    }
+
+    #[test]
+    fn filter_validation() {
+        assert!(is_valid_filter("a/b"));
+        assert!(is_valid_filter("a/+/c"));
+        assert!(is_valid_filter("+"));
+        assert!(is_valid_filter("#"));
+        assert!(is_valid_filter("a/#"));
+        assert!(is_valid_filter("a//b"));
+        assert!(!is_valid_filter(""));
+        assert!(!is_valid_filter("a/#/c"));
+        assert!(!is_valid_filter("a/b#"));
+        assert!(!is_valid_filter("a+/b"));
+    }
+
+    #[test]
+    fn payload_limits() {
+        let mut subscriptions = Subscriptions::new();
+        subscriptions
+            .request(1, "a/#", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions
+            .request(2, "a/b", SubscriptionOptions::default())
+            .unwrap();
+        assert!(subscriptions.set_max_payload_size("a/#", Some(16)));
+        assert!(!subscriptions.set_max_payload_size("c", Some(16)));
+
+        // The unlimited subscription accepts the payload.
+        assert!(!subscriptions.exceeds_payload_limit("a/b", 32));
+        assert!(subscriptions.exceeds_payload_limit("a/c", 32));
+        assert!(!subscriptions.exceeds_payload_limit("a/c", 16));
+        assert!(!subscriptions.exceeds_payload_limit("c", 32));
+
+        // The limit is kept once the subscription is acknowledged and renewed.
+        subscriptions.acknowledge(1, &[0x00]).unwrap();
+        subscriptions
+            .request(3, "a/#", SubscriptionOptions::default())
+            .unwrap();
+        subscriptions.acknowledge(3, &[0x00]).unwrap();
+        assert!(subscriptions.exceeds_payload_limit("a/c", 32));
+        assert_eq!(
+            subscriptions.active().next().unwrap().max_payload_size(),
+            Some(16)
+        );
+    }
+}
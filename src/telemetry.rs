@@ -0,0 +1,115 @@
+//! Structured Telemetry
+//!
+//! # Design
+//! Sensor firmware commonly publishes every member of a measurement structure to its own topic.
+//! The `Telemetry` trait captures this pattern, so the publishing boilerplate can be generated using
+//! `#[derive(Telemetry)]` with the `derive` feature:
+//!
+//! ```ignore
+//! #[derive(Telemetry)]
+//! struct Measurement {
+//!     temperature: f32,
+//!     humidity: u8,
+//!     #[telemetry(skip)]
+//!     raw: u16,
+//! }
+//!
+//! // Publishes `20.5` to `sensor/temperature` and `40` to `sensor/humidity`.
+//! measurement.publish_telemetry(&mut mqtt.client, "sensor")?;
+//! ```
+//!
+//! Each field is published to `prefix/field_name` with its `Display` representation as the
+//! payload. Telemetry is published with `QoS::AtMostOnce` and is not retained, as a missed value is
+//! superseded by the next one.
+use crate::{mqtt_client::MqttClient, Error, ProtocolError, QoS, Retain};
+use core::fmt::{Display, Write};
+use embedded_nal::TcpClientStack;
+use heapless::String;
+
+/// A structure that is published field by field.
+pub trait Telemetry {
+    /// Publish every field of the structure.
+    ///
+    /// # Args
+    /// * `client` - The client to publish the fields with.
+    /// * `prefix` - The topic prefix. Each field is published to `prefix/field_name`.
+    fn publish_telemetry<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>(
+        &self,
+        client: &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+        prefix: &str,
+    ) -> Result<(), Error<TcpStack::Error>>
+    where
+        TcpStack: TcpClientStack,
+        Clock: embedded_time::Clock;
+}
+
+/// Publish a single field of a telemetry structure.
+///
+/// # Note
+/// This is used by the implementations generated by `#[derive(Telemetry)]`.
+///
+/// # Args
+/// * `client` - The client to publish the field with.
+/// * `prefix` - The topic prefix of the structure.
+/// * `name` - The name of the field.
+/// * `value` - The value of the field.
+#[doc(hidden)]
+pub fn publish_field<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>(
+    client: &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+    prefix: &str,
+    name: &str,
+    value: &impl Display,
+) -> Result<(), Error<TcpStack::Error>>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    let mut topic: String<MSG_SIZE> = String::new();
+    write_topic(prefix, name, &mut topic)?;
+
+    let mut payload: String<MSG_SIZE> = String::new();
+    write!(payload, "{}", value).map_err(|_| ProtocolError::Failed)?;
+
+    client.publish(
+        &topic,
+        payload.as_bytes(),
+        QoS::AtMostOnce,
+        Retain::NotRetained,
+        &[],
+    )
+}
+
+fn write_topic<const N: usize>(
+    prefix: &str,
+    name: &str,
+    dest: &mut String<N>,
+) -> Result<(), ProtocolError> {
+    let needed = prefix.len() + 1 + name.len();
+    if needed > N {
+        return Err(ProtocolError::BufferTooSmall { needed });
+    }
+
+    // Note(unwrap): The topic was checked to fit into the string above.
+    write!(dest, "{}/{}", prefix, name).unwrap();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_topic;
+    use crate::ProtocolError;
+    use heapless::String;
+
+    #[test]
+    fn telemetry_topic() {
+        let mut topic: String<32> = String::new();
+        write_topic("sensor", "temperature", &mut topic).unwrap();
+        assert_eq!(topic, "sensor/temperature");
+
+        let mut topic: String<8> = String::new();
+        assert_eq!(
+            write_topic("sensor", "temperature", &mut topic),
+            Err(ProtocolError::BufferTooSmall { needed: 18 })
+        );
+    }
+}
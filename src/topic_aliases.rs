@@ -0,0 +1,135 @@
+//! Topic Aliases
+//!
+//! # Design
+//! To reduce the size of PUBLISH packets, the broker may replace the topic of a message by a
+//! numeric alias. The first message using an alias carries both the topic and the alias, after
+//! which messages may carry the alias only. Aliases are only used if the client advertises a Topic
+//! Alias Maximum when connecting, and are valid for a single network connection.
+//!
+//! # Note
+//! Topics are stored in fixed-size buffers, so topics longer than `MAX_TOPIC_LENGTH` bytes cannot
+//! be aliased.
+use crate::ProtocolError;
+use core::str::FromStr;
+use heapless::{String, Vec};
+
+/// The maximum number of topic aliases that can be advertised to the broker.
+pub(crate) const MAX_TOPIC_ALIASES: usize = 8;
+
+/// The maximum length of a topic that can be aliased.
+pub(crate) const MAX_TOPIC_LENGTH: usize = 128;
+
+/// The topic aliases established by the broker on the current connection.
+pub(crate) struct TopicAliases {
+    maximum: u16,
+    topics: Vec<(u16, String<MAX_TOPIC_LENGTH>), MAX_TOPIC_ALIASES>,
+}
+
+impl TopicAliases {
+    /// Construct an empty table of topic aliases that accepts no aliases.
+    pub fn new() -> Self {
+        Self {
+            maximum: 0,
+            topics: Vec::new(),
+        }
+    }
+
+    /// Get the highest alias that the broker may use.
+    pub fn maximum(&self) -> u16 {
+        self.maximum
+    }
+
+    /// Set the highest alias that the broker may use.
+    ///
+    /// # Args
+    /// * `maximum` - The highest accepted alias. If zero, no aliases are accepted.
+    ///
+    /// # Returns
+    /// An error if more than `MAX_TOPIC_ALIASES` aliases were requested.
+    pub fn set_maximum(&mut self, maximum: u16) -> Result<(), ()> {
+        if maximum as usize > MAX_TOPIC_ALIASES {
+            return Err(());
+        }
+
+        self.maximum = maximum;
+        self.topics.clear();
+        Ok(())
+    }
+
+    /// Remove all aliases, e.g. because a new connection was established.
+    pub fn clear(&mut self) {
+        self.topics.clear();
+    }
+
+    /// Resolve the topic of a received message using an alias.
+    ///
+    /// # Args
+    /// * `alias` - The topic alias of the message.
+    /// * `topic` - The topic of the message. If empty, the topic is looked up. Otherwise, the
+    ///   alias is assigned to the topic.
+    ///
+    /// # Returns
+    /// The topic of the message.
+    pub fn resolve(
+        &mut self,
+        alias: u16,
+        topic: &str,
+    ) -> Result<String<MAX_TOPIC_LENGTH>, ProtocolError> {
+        if alias == 0 || alias > self.maximum {
+            return Err(ProtocolError::Invalid);
+        }
+
+        let position = self.topics.iter().position(|(id, _)| *id == alias);
+
+        if topic.is_empty() {
+            return position
+                .map(|index| self.topics[index].1.clone())
+                .ok_or(ProtocolError::Invalid);
+        }
+
+        let topic: String<MAX_TOPIC_LENGTH> =
+            String::from_str(topic).map_err(|_| ProtocolError::DataSize)?;
+
+        match position {
+            Some(index) => self.topics[index].1 = topic.clone(),
+            // Note(unwrap): There are no more aliases than the maximum.
+            None => self.topics.push((alias, topic.clone())).unwrap(),
+        }
+
+        Ok(topic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TopicAliases, MAX_TOPIC_ALIASES};
+    use crate::ProtocolError;
+
+    #[test]
+    fn aliases_are_resolved() {
+        let mut aliases = TopicAliases::new();
+        assert_eq!(aliases.resolve(1, "a"), Err(ProtocolError::Invalid));
+
+        aliases.set_maximum(2).unwrap();
+        assert_eq!(aliases.resolve(1, "a").unwrap(), "a");
+        assert_eq!(aliases.resolve(1, "").unwrap(), "a");
+
+        // Aliases may be reassigned to another topic.
+        assert_eq!(aliases.resolve(1, "b").unwrap(), "b");
+        assert_eq!(aliases.resolve(1, "").unwrap(), "b");
+
+        assert_eq!(aliases.resolve(2, ""), Err(ProtocolError::Invalid));
+        assert_eq!(aliases.resolve(3, "c"), Err(ProtocolError::Invalid));
+        assert_eq!(aliases.resolve(0, "c"), Err(ProtocolError::Invalid));
+
+        aliases.clear();
+        assert_eq!(aliases.resolve(1, ""), Err(ProtocolError::Invalid));
+    }
+
+    #[test]
+    fn aliases_are_bounded() {
+        let mut aliases = TopicAliases::new();
+        assert!(aliases.set_maximum(MAX_TOPIC_ALIASES as u16 + 1).is_err());
+        assert!(aliases.set_maximum(MAX_TOPIC_ALIASES as u16).is_ok());
+    }
+}
@@ -0,0 +1,217 @@
+//! Custom Transports
+//!
+//! # Design
+//! The client communicates with the broker through an embedded-nal `TcpClientStack`, which most
+//! network stacks implement directly. Links without a socket interface, such as cellular modems
+//! driven by AT commands, BLE L2CAP channels or serial tunnels, only need to provide a byte pipe to
+//! the broker. Such a link implements the small `MqttTransport` trait, and the `TransportStack`
+//! adapts it to the interface of the client. With the `embedded-io` feature enabled, the
+//! `IoTransport` implements the trait for `embedded-io` byte streams.
+//!
+//! The transport carries a single connection at a time. The client closes the connection whenever
+//! it is reset and connects again using the same transport, so the link may be re-established
+//! (e.g. by dialing the modem again) within `connect()`.
+//!
+//! # Note
+//! All operations must not block. Operations that cannot complete immediately return
+//! `nb::Error::WouldBlock` and are retried when the client is polled again.
+use embedded_nal::{nb, SocketAddr, TcpClientStack};
+
+/// A byte pipe to the broker.
+pub trait MqttTransport {
+    /// The error type of the transport.
+    type Error: core::fmt::Debug;
+
+    /// Connect to the broker.
+    ///
+    /// # Note
+    /// This is called repeatedly until the connection is established.
+    ///
+    /// # Args
+    /// * `remote` - The address of the broker.
+    fn connect(&mut self, remote: SocketAddr) -> nb::Result<(), Self::Error>;
+
+    /// Determine if the connection with the broker is established.
+    fn is_connected(&mut self) -> Result<bool, Self::Error>;
+
+    /// Write data to the broker.
+    ///
+    /// # Returns
+    /// The number of bytes that were written, which may be less than the length of the data.
+    fn write(&mut self, data: &[u8]) -> nb::Result<usize, Self::Error>;
+
+    /// Read data received from the broker.
+    ///
+    /// # Returns
+    /// The number of bytes stored in the buffer.
+    fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error>;
+
+    /// Close the connection with the broker.
+    fn close(&mut self) -> Result<(), Self::Error>;
+}
+
+/// A network stack that communicates over an `MqttTransport`.
+pub struct TransportStack<T> {
+    transport: T,
+}
+
+/// The socket of a `TransportStack`. Only a single socket is available for each transport.
+#[derive(Debug)]
+pub struct TransportSocket {
+    _private: (),
+}
+
+impl<T> TransportStack<T> {
+    /// Construct a network stack from a transport.
+    ///
+    /// # Args
+    /// * `transport` - The transport to communicate over.
+    pub fn new(transport: T) -> Self {
+        Self { transport }
+    }
+
+    /// Get a reference to the underlying transport.
+    pub fn inner(&self) -> &T {
+        &self.transport
+    }
+
+    /// Get a mutable reference to the underlying transport.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    /// Consume the network stack and return the underlying transport.
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+}
+
+impl<T: MqttTransport> TcpClientStack for TransportStack<T> {
+    type TcpSocket = TransportSocket;
+    type Error = T::Error;
+
+    fn socket(&mut self) -> Result<TransportSocket, T::Error> {
+        Ok(TransportSocket { _private: () })
+    }
+
+    fn connect(
+        &mut self,
+        _socket: &mut TransportSocket,
+        remote: SocketAddr,
+    ) -> nb::Result<(), T::Error> {
+        self.transport.connect(remote)
+    }
+
+    fn is_connected(&mut self, _socket: &TransportSocket) -> Result<bool, T::Error> {
+        self.transport.is_connected()
+    }
+
+    fn send(
+        &mut self,
+        _socket: &mut TransportSocket,
+        buffer: &[u8],
+    ) -> nb::Result<usize, T::Error> {
+        self.transport.write(buffer)
+    }
+
+    fn receive(
+        &mut self,
+        _socket: &mut TransportSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, T::Error> {
+        self.transport.read(buffer)
+    }
+
+    fn close(&mut self, _socket: TransportSocket) -> Result<(), T::Error> {
+        self.transport.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MqttTransport, TransportStack};
+    use embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, TcpClientStack};
+    use heapless::Vec;
+
+    /// A serial link that is dialed on the first connection attempt.
+    #[derive(Default)]
+    struct Serial {
+        dialing: bool,
+        connected: bool,
+        rx: Vec<u8, 32>,
+        tx: Vec<u8, 32>,
+    }
+
+    impl MqttTransport for Serial {
+        type Error = ();
+
+        fn connect(&mut self, _remote: SocketAddr) -> nb::Result<(), ()> {
+            if !self.dialing {
+                self.dialing = true;
+                return Err(nb::Error::WouldBlock);
+            }
+
+            self.connected = true;
+            Ok(())
+        }
+
+        fn is_connected(&mut self) -> Result<bool, ()> {
+            Ok(self.connected)
+        }
+
+        fn write(&mut self, data: &[u8]) -> nb::Result<usize, ()> {
+            self.tx
+                .extend_from_slice(data)
+                .map_err(|_| nb::Error::Other(()))?;
+            Ok(data.len())
+        }
+
+        fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, ()> {
+            if self.rx.is_empty() {
+                return Err(nb::Error::WouldBlock);
+            }
+
+            let len = core::cmp::min(buffer.len(), self.rx.len());
+            buffer[..len].copy_from_slice(&self.rx[..len]);
+            self.rx = Vec::from_slice(&self.rx[len..]).unwrap();
+            Ok(len)
+        }
+
+        fn close(&mut self) -> Result<(), ()> {
+            self.dialing = false;
+            self.connected = false;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn transport_stack_transfers_data() {
+        let mut stack = TransportStack::new(Serial::default());
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 1883);
+
+        let mut socket = stack.socket().unwrap();
+        assert_eq!(
+            stack.connect(&mut socket, remote),
+            Err(nb::Error::WouldBlock)
+        );
+        assert!(!stack.is_connected(&socket).unwrap());
+        stack.connect(&mut socket, remote).unwrap();
+        assert!(stack.is_connected(&socket).unwrap());
+
+        let mut buffer = [0u8; 4];
+        assert_eq!(
+            stack.receive(&mut socket, &mut buffer),
+            Err(nb::Error::WouldBlock)
+        );
+
+        stack.inner_mut().rx.extend_from_slice(&[1, 2]).unwrap();
+        assert_eq!(stack.receive(&mut socket, &mut buffer).unwrap(), 2);
+        assert_eq!(buffer[..2], [1, 2]);
+
+        assert_eq!(stack.send(&mut socket, &[3, 4, 5]).unwrap(), 3);
+        assert_eq!(stack.inner().tx, [3, 4, 5]);
+
+        stack.close(socket).unwrap();
+        assert!(!stack.inner().connected);
+    }
+}
@@ -0,0 +1,236 @@
+//! Type-State Client
+//!
+//! # Design
+//! Operations such as `subscribe()` fail at runtime with `Error::NotConnected` if the client has
+//! not established a connection with the broker yet. Applications that prefer these errors to be
+//! caught at compile time may wrap `Minimq` in a `Client`, whose type encodes the connection phase:
+//!
+//! ```ignore
+//! let mut client = typestate::Client::new(mqtt);
+//!
+//! // Only polling is possible while disconnected.
+//! let mut client = loop {
+//!     client.poll(|_, _, _, _| {})?;
+//!     match client.connected() {
+//!         Ok(client) => break client,
+//!         Err(disconnected) => client = disconnected,
+//!     }
+//! };
+//!
+//! client.subscribe("topic", &[])?;
+//! ```
+//!
+//! The phase is checked whenever the client is transitioned with `connected()` or
+//! `disconnected()`.
+//!
+//! # Note
+//! The connection may still be lost while a `Client<Connected>` is polled. In this case, its
+//! operations return `Error::NotConnected` until it is transitioned back using `disconnected()`.
+use crate::{mqtt_client::MqttClient, Error, Minimq, Property, QoS, Retain, SubscriptionOptions};
+use core::marker::PhantomData;
+use embedded_nal::TcpClientStack;
+
+/// The phase of a client that has not established a connection with the broker.
+#[derive(Debug)]
+pub struct Disconnected;
+
+/// The phase of a client that is connected to the broker.
+#[derive(Debug)]
+pub struct Connected;
+
+/// An MQTT client whose connection phase is tracked in its type.
+pub struct Client<Phase, TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    mqtt: Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+    _phase: PhantomData<Phase>,
+}
+
+impl<Phase, TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+    Client<Phase, TcpStack, Clock, MSG_SIZE, MSG_COUNT>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    fn into_phase<Next>(self) -> Client<Next, TcpStack, Clock, MSG_SIZE, MSG_COUNT> {
+        Client {
+            mqtt: self.mqtt,
+            _phase: PhantomData,
+        }
+    }
+
+    /// Check the MQTT interface for available messages.
+    ///
+    /// # Args
+    /// * `f` - A closure to process any received messages. See `Minimq::poll()`.
+    ///
+    /// # Returns
+    /// The value returned by the handler if a message was handled.
+    pub fn poll<F, R>(&mut self, f: F) -> Result<Option<R>, Error<TcpStack::Error>>
+    where
+        for<'a> F: FnMut(
+            &mut MqttClient<TcpStack, Clock, MSG_SIZE, MSG_COUNT>,
+            &'a str,
+            &[u8],
+            &[Property<'a>],
+        ) -> R,
+    {
+        self.mqtt.poll(f)
+    }
+
+    /// Release the wrapped `Minimq` client.
+    pub fn into_inner(self) -> Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT> {
+        self.mqtt
+    }
+}
+
+impl<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+    Client<Disconnected, TcpStack, Clock, MSG_SIZE, MSG_COUNT>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    /// Track the connection phase of a client.
+    ///
+    /// # Note
+    /// The client starts out as disconnected, even if it already established a connection. It may
+    /// be transitioned using `connected()` immediately.
+    ///
+    /// # Args
+    /// * `mqtt` - The client to wrap. It should be fully configured.
+    pub fn new(mqtt: Minimq<TcpStack, Clock, MSG_SIZE, MSG_COUNT>) -> Self {
+        Self {
+            mqtt,
+            _phase: PhantomData,
+        }
+    }
+
+    /// Transition the client to the connected phase.
+    ///
+    /// # Returns
+    /// The connected client if a connection with the broker was established. Otherwise, the
+    /// disconnected client is returned as the error.
+    // The client is returned in place, so the size of the error is not a concern.
+    #[allow(clippy::result_large_err)]
+    pub fn connected(
+        self,
+    ) -> Result<Client<Connected, TcpStack, Clock, MSG_SIZE, MSG_COUNT>, Self> {
+        if self.mqtt.client.is_connected() {
+            Ok(self.into_phase())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<TcpStack, Clock, const MSG_SIZE: usize, const MSG_COUNT: usize>
+    Client<Connected, TcpStack, Clock, MSG_SIZE, MSG_COUNT>
+where
+    TcpStack: TcpClientStack,
+    Clock: embedded_time::Clock,
+{
+    /// Transition the client to the disconnected phase.
+    ///
+    /// # Returns
+    /// The disconnected client if the connection with the broker was lost. Otherwise, the
+    /// connected client is returned as the error.
+    // The client is returned in place, so the size of the error is not a concern.
+    #[allow(clippy::result_large_err)]
+    pub fn disconnected(
+        self,
+    ) -> Result<Client<Disconnected, TcpStack, Clock, MSG_SIZE, MSG_COUNT>, Self> {
+        if self.mqtt.client.is_connected() {
+            Err(self)
+        } else {
+            Ok(self.into_phase())
+        }
+    }
+
+    /// Subscribe to a topic.
+    ///
+    /// # Args
+    /// * `topic` - The topic to subscribe to.
+    /// * `properties` - A list of properties to attach to the subscription request. May be empty.
+    pub fn subscribe(
+        &mut self,
+        topic: &str,
+        properties: &[Property],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.mqtt.client.subscribe(topic, properties)
+    }
+
+    /// Subscribe to a topic using the provided subscription options.
+    ///
+    /// # Args
+    /// * `topic` - The topic to subscribe to.
+    /// * `options` - The options of the subscription, such as the maximum quality-of-service.
+    /// * `properties` - A list of properties to attach to the subscription request. May be empty.
+    pub fn subscribe_with_options(
+        &mut self,
+        topic: &str,
+        options: SubscriptionOptions,
+        properties: &[Property],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.mqtt
+            .client
+            .subscribe_with_options(topic, options, properties)
+    }
+
+    /// Unsubscribe from a topic.
+    ///
+    /// # Args
+    /// * `topic` - The topic filter to unsubscribe from.
+    /// * `properties` - A list of properties to attach to the unsubscribe request. May be empty.
+    pub fn unsubscribe(
+        &mut self,
+        topic: &str,
+        properties: &[Property],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        self.mqtt.client.unsubscribe(topic, properties)
+    }
+
+    /// Determine if any subscriptions are waiting for completion.
+    pub fn subscriptions_pending(&self) -> bool {
+        self.mqtt.client.subscriptions_pending()
+    }
+
+    /// Publish a message over MQTT.
+    ///
+    /// # Note
+    /// Unlike `MqttClient::publish()`, `Error::NotConnected` is returned if the connection was
+    /// lost instead of silently ignoring the message.
+    ///
+    /// # Args
+    /// * `topic` - The topic to publish the message to.
+    /// * `data` - The data to transmit as the message contents.
+    /// * `qos` - The desired quality-of-service level of the message.
+    /// * `retain` - Specifies whether the message should be retained by the broker.
+    /// * `properties` - A list of properties to associate with the message being published. May be
+    ///   empty.
+    pub fn publish(
+        &mut self,
+        topic: &str,
+        data: &[u8],
+        qos: QoS,
+        retain: Retain,
+        properties: &[Property],
+    ) -> Result<(), Error<TcpStack::Error>> {
+        if !self.mqtt.client.is_connected() {
+            return Err(Error::NotConnected);
+        }
+
+        self.mqtt
+            .client
+            .publish(topic, data, qos, retain, properties)
+    }
+
+    /// Get the count of unacknowledged messages.
+    ///
+    /// # Args
+    /// * `qos` - The quality-of-service level of the messages to count.
+    pub fn pending_messages(&self, qos: QoS) -> usize {
+        self.mqtt.client.pending_messages(qos)
+    }
+}
@@ -0,0 +1,155 @@
+//! Unix Domain Socket Network Stack
+//!
+//! # Design
+//! Brokers such as Mosquitto may listen on a Unix domain socket in addition to TCP. On `std`
+//! targets, the `UnixStack` connects the client to such a socket. This avoids port conflicts and
+//! the overhead of the TCP stack when testing against a broker running on the same host, e.g. in a
+//! container.
+//!
+//! The socket path is provided when constructing the stack. The broker address passed to
+//! `Minimq::new()` is ignored by the stack.
+//!
+//! # Note
+//! The socket is operated in non-blocking mode. If the broker closes the connection, the socket is
+//! reported as disconnected so that the client reconnects.
+use embedded_nal::{nb, SocketAddr, TcpClientStack};
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+/// A network stack that connects to a broker over a Unix domain socket.
+#[derive(Debug, Clone)]
+pub struct UnixStack {
+    path: PathBuf,
+}
+
+/// The socket of a `UnixStack`.
+#[derive(Debug)]
+pub struct UnixSocket {
+    stream: Option<UnixStream>,
+}
+
+impl UnixStack {
+    /// Construct a network stack for a Unix domain socket.
+    ///
+    /// # Args
+    /// * `path` - The path of the socket that the broker listens on.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+fn stream(socket: &mut UnixSocket) -> Result<&mut UnixStream, io::Error> {
+    socket
+        .stream
+        .as_mut()
+        .ok_or_else(|| io::Error::from(ErrorKind::NotConnected))
+}
+
+fn nb_error(error: io::Error) -> nb::Error<io::Error> {
+    match error.kind() {
+        ErrorKind::WouldBlock => nb::Error::WouldBlock,
+        _ => nb::Error::Other(error),
+    }
+}
+
+impl TcpClientStack for UnixStack {
+    type TcpSocket = UnixSocket;
+    type Error = io::Error;
+
+    fn socket(&mut self) -> Result<UnixSocket, io::Error> {
+        Ok(UnixSocket { stream: None })
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut UnixSocket,
+        _remote: SocketAddr,
+    ) -> nb::Result<(), io::Error> {
+        let stream = UnixStream::connect(&self.path)?;
+        stream.set_nonblocking(true)?;
+        socket.stream.replace(stream);
+        Ok(())
+    }
+
+    fn is_connected(&mut self, socket: &UnixSocket) -> Result<bool, io::Error> {
+        Ok(socket.stream.is_some())
+    }
+
+    fn send(&mut self, socket: &mut UnixSocket, buffer: &[u8]) -> nb::Result<usize, io::Error> {
+        stream(socket)?.write(buffer).map_err(nb_error)
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut UnixSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, io::Error> {
+        match stream(socket)?.read(buffer) {
+            // The broker closed the connection.
+            Ok(0) if !buffer.is_empty() => {
+                socket.stream.take();
+                Err(nb::Error::Other(ErrorKind::ConnectionReset.into()))
+            }
+            result => result.map_err(nb_error),
+        }
+    }
+
+    fn close(&mut self, _socket: UnixSocket) -> Result<(), io::Error> {
+        // The stream is closed when the socket is dropped.
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnixStack;
+    use embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, TcpClientStack};
+    use std::{io::Write, os::unix::net::UnixListener};
+
+    #[test]
+    fn unix_stack_transfers_data() {
+        let path = std::env::temp_dir().join(std::format!("minimq-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let mut stack = UnixStack::new(&path);
+        let remote = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 1883);
+        let mut socket = stack.socket().unwrap();
+        assert!(!stack.is_connected(&socket).unwrap());
+
+        stack.connect(&mut socket, remote).unwrap();
+        assert!(stack.is_connected(&socket).unwrap());
+        let (mut broker, _) = listener.accept().unwrap();
+
+        let mut buffer = [0u8; 4];
+        assert!(matches!(
+            stack.receive(&mut socket, &mut buffer),
+            Err(nb::Error::WouldBlock)
+        ));
+
+        broker.write_all(&[1, 2]).unwrap();
+        let received = loop {
+            match stack.receive(&mut socket, &mut buffer) {
+                Err(nb::Error::WouldBlock) => std::thread::yield_now(),
+                result => break result.unwrap(),
+            }
+        };
+        assert_eq!(buffer[..received], [1, 2]);
+
+        // The socket is disconnected once the broker closes the connection.
+        drop(broker);
+        loop {
+            match stack.receive(&mut socket, &mut buffer) {
+                Err(nb::Error::WouldBlock) => std::thread::yield_now(),
+                result => break assert!(result.is_err()),
+            }
+        }
+        assert!(!stack.is_connected(&socket).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,722 @@
+//! WebSocket Network Stack
+//!
+//! # Design
+//! Many managed brokers only accept MQTT over WebSockets, commonly on port 443. The
+//! `WebSocketStack` wraps another network stack and carries the connection to the broker in
+//! WebSocket frames. Once the socket of the underlying stack is connected, the WebSocket opening
+//! handshake is performed by requesting an upgrade to the `mqtt` subprotocol. The socket is only
+//! reported as connected once the broker accepted the upgrade, so the client starts communicating
+//! with the broker afterwards.
+//!
+//! Data sent by the client is transmitted in masked binary frames. Frames received from the broker
+//! are decoded, so that only their payload is provided to the client. Pings of the broker are
+//! answered with a pong, and a close frame is reported as a lost connection.
+//!
+//! For secure WebSockets, the underlying stack is expected to provide the TLS session, e.g. using
+//! an `IoStack`.
+//!
+//! # Note
+//! The `Sec-WebSocket-Accept` header of the response is not verified, as this would require
+//! SHA-1. The broker is authenticated by the transport security beneath the WebSocket.
+use crate::proxy_stack::{parse_status, write_base64, STATUS_LENGTH};
+use core::{convert::TryFrom, fmt::Write};
+use embedded_nal::{nb, SocketAddr, TcpClientStack};
+use heapless::{String, Vec};
+
+/// The maximum length of the upgrade request and of an outgoing frame.
+const MAX_REQUEST_LENGTH: usize = 256;
+
+/// The maximum length of a frame header.
+const MAX_HEADER_LENGTH: usize = 14;
+
+/// The maximum length of the payload of a control frame.
+const MAX_CONTROL_LENGTH: usize = 125;
+
+/// The length of the header of outgoing frames with a 16-bit payload length.
+const FRAME_HEADER_LENGTH: usize = 8;
+
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Errors that may occur when using a `WebSocketStack`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WebSocketError<E> {
+    /// The underlying network stack encountered an error.
+    Network(E),
+
+    /// The broker refused to upgrade the connection with the contained HTTP status code.
+    Refused(u16),
+
+    /// The response of the broker was not a valid HTTP response.
+    MalformedResponse,
+
+    /// A frame received from the broker violated the WebSocket protocol.
+    MalformedFrame,
+
+    /// The upgrade request does not fit into the request buffer.
+    RequestTooLong,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Handshake {
+    Connecting,
+    Requesting,
+    AwaitingResponse,
+    Established,
+}
+
+/// A frame that is being received.
+#[derive(Debug, Copy, Clone)]
+struct Frame {
+    opcode: u8,
+    remaining: usize,
+    mask: Option<[u8; 4]>,
+    offset: usize,
+}
+
+/// A network stack that carries connections in WebSocket frames.
+pub struct WebSocketStack<S> {
+    stack: S,
+    path: &'static str,
+    host: Option<&'static str>,
+    rng: u32,
+}
+
+/// The socket of a `WebSocketStack`.
+#[derive(Debug)]
+pub struct WebSocketSocket<T> {
+    socket: T,
+    handshake: Handshake,
+    outgoing: Vec<u8, MAX_REQUEST_LENGTH>,
+    sent: usize,
+    status: Vec<u8, STATUS_LENGTH>,
+    trailer: [u8; 4],
+    header: Vec<u8, MAX_HEADER_LENGTH>,
+    frame: Option<Frame>,
+    control: Vec<u8, MAX_CONTROL_LENGTH>,
+    pong_pending: bool,
+    closed: bool,
+}
+
+impl<T> WebSocketSocket<T> {
+    /// Handle a completely received control frame.
+    fn handle_control(&mut self, opcode: u8) {
+        match opcode {
+            OPCODE_PING => self.pong_pending = true,
+            OPCODE_CLOSE => self.closed = true,
+            _ => {}
+        }
+    }
+}
+
+/// Get the length of a frame header.
+///
+/// # Args
+/// * `header` - The first two bytes of the header.
+fn header_length(header: &[u8]) -> usize {
+    let extended = match header[1] & 0x7F {
+        126 => 2,
+        127 => 8,
+        _ => 0,
+    };
+    let mask = if header[1] & 0x80 != 0 { 4 } else { 0 };
+
+    2 + extended + mask
+}
+
+/// Parse a complete frame header.
+fn parse_header(header: &[u8]) -> Option<Frame> {
+    let masked = header[1] & 0x80 != 0;
+    let (length, rest) = match header[1] & 0x7F {
+        126 => (
+            u16::from_be_bytes([header[2], header[3]]) as u64,
+            &header[4..],
+        ),
+        127 => {
+            let mut length = [0; 8];
+            length.copy_from_slice(&header[2..10]);
+            (u64::from_be_bytes(length), &header[10..])
+        }
+        length => (length as u64, &header[2..]),
+    };
+
+    let mask = if masked {
+        let mut mask = [0; 4];
+        mask.copy_from_slice(&rest[..4]);
+        Some(mask)
+    } else {
+        None
+    };
+
+    Some(Frame {
+        opcode: header[0] & 0x0F,
+        remaining: usize::try_from(length).ok()?,
+        mask,
+        offset: 0,
+    })
+}
+
+/// Serialize a masked frame.
+///
+/// # Args
+/// * `dest` - The buffer to store the frame in, which is replaced.
+/// * `opcode` - The opcode of the frame.
+/// * `payload` - The payload of the frame. It must fit into the buffer along with the header.
+/// * `mask` - The masking key of the frame.
+fn write_frame<const N: usize>(dest: &mut Vec<u8, N>, opcode: u8, payload: &[u8], mask: [u8; 4]) {
+    dest.clear();
+
+    // Note(unwrap): The caller ensures that the frame fits into the buffer.
+    dest.push(0x80 | opcode).unwrap();
+    if payload.len() > 125 {
+        dest.push(0x80 | 126).unwrap();
+        dest.extend_from_slice(&(payload.len() as u16).to_be_bytes())
+            .unwrap();
+    } else {
+        dest.push(0x80 | payload.len() as u8).unwrap();
+    }
+
+    dest.extend_from_slice(&mask).unwrap();
+    for (index, byte) in payload.iter().enumerate() {
+        dest.push(byte ^ mask[index % 4]).unwrap();
+    }
+}
+
+impl<S: TcpClientStack> WebSocketStack<S> {
+    /// Construct a network stack that carries connections in WebSocket frames.
+    ///
+    /// # Args
+    /// * `stack` - The network stack used to connect to the broker.
+    /// * `path` - The path of the WebSocket endpoint of the broker, e.g. `/mqtt`.
+    /// * `seed` - The seed of the security keys and masking keys. This should differ between
+    ///   devices and restarts, e.g. by deriving it from a hardware random number generator.
+    pub fn new(stack: S, path: &'static str, seed: u32) -> Self {
+        Self {
+            stack,
+            path,
+            host: None,
+            rng: seed | 1,
+        }
+    }
+
+    /// Specify the host name sent to the broker in the upgrade request.
+    ///
+    /// # Note
+    /// Brokers serving multiple domains use the host name to select the endpoint. By default, the
+    /// address of the broker is sent.
+    ///
+    /// # Args
+    /// * `host` - The host name of the broker.
+    pub fn set_host(&mut self, host: &'static str) {
+        self.host.replace(host);
+    }
+
+    /// Get a reference to the underlying network stack.
+    pub fn inner(&self) -> &S {
+        &self.stack
+    }
+
+    /// Get a mutable reference to the underlying network stack.
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.stack
+    }
+
+    /// Get four pseudo-random bytes.
+    fn random(&mut self) -> [u8; 4] {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng.to_be_bytes()
+    }
+
+    fn request_upgrade(
+        &mut self,
+        request: &mut Vec<u8, MAX_REQUEST_LENGTH>,
+        remote: SocketAddr,
+    ) -> Result<(), core::fmt::Error> {
+        let mut nonce = [0; 16];
+        for chunk in nonce.chunks_mut(4) {
+            chunk.copy_from_slice(&self.random());
+        }
+
+        let mut key: String<24> = String::new();
+        write_base64(&nonce, &mut key).map_err(|_| core::fmt::Error)?;
+
+        let mut text: String<MAX_REQUEST_LENGTH> = String::new();
+        write!(text, "GET {} HTTP/1.1\r\n", self.path)?;
+        match self.host {
+            Some(host) => write!(text, "Host: {}\r\n", host)?,
+            None => write!(text, "Host: {}\r\n", remote)?,
+        }
+        write!(
+            text,
+            "Upgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {}\r\n\
+             Sec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: mqtt\r\n\r\n",
+            key
+        )?;
+
+        request.clear();
+        // Note(unwrap): The request has the same capacity as the string.
+        request.extend_from_slice(text.as_bytes()).unwrap();
+        Ok(())
+    }
+
+    /// Receive the response of the broker to the upgrade request.
+    ///
+    /// # Note
+    /// The response is read byte by byte, so no frames sent by the broker are consumed.
+    fn receive_response(
+        &mut self,
+        socket: &mut WebSocketSocket<S::TcpSocket>,
+    ) -> nb::Result<(), WebSocketError<S::Error>> {
+        loop {
+            let mut byte = [0];
+            let received = self
+                .stack
+                .receive(&mut socket.socket, &mut byte)
+                .map_err(|err| err.map(WebSocketError::Network))?;
+            if received == 0 {
+                return Err(nb::Error::WouldBlock);
+            }
+
+            // Only the status code of the response is of interest.
+            socket.status.push(byte[0]).ok();
+            socket.trailer.rotate_left(1);
+            socket.trailer[3] = byte[0];
+
+            // The headers of the response are terminated by an empty line.
+            if &socket.trailer == b"\r\n\r\n" {
+                break;
+            }
+        }
+
+        let status = parse_status(&socket.status)
+            .ok_or(nb::Error::Other(WebSocketError::MalformedResponse))?;
+
+        if status != 101 {
+            return Err(nb::Error::Other(WebSocketError::Refused(status)));
+        }
+
+        Ok(())
+    }
+
+    /// Transmit the outgoing data of a socket, followed by any pending pong.
+    fn flush(
+        &mut self,
+        socket: &mut WebSocketSocket<S::TcpSocket>,
+    ) -> nb::Result<(), WebSocketError<S::Error>> {
+        loop {
+            if socket.sent < socket.outgoing.len() {
+                let written = self
+                    .stack
+                    .send(&mut socket.socket, &socket.outgoing[socket.sent..])
+                    .map_err(|err| err.map(WebSocketError::Network))?;
+                socket.sent += written;
+
+                if socket.sent < socket.outgoing.len() {
+                    return Err(nb::Error::WouldBlock);
+                }
+            }
+
+            if !socket.pong_pending {
+                return Ok(());
+            }
+
+            // The pong carries the payload of the ping.
+            let mask = self.random();
+            write_frame(&mut socket.outgoing, OPCODE_PONG, &socket.control, mask);
+            socket.sent = 0;
+            socket.pong_pending = false;
+        }
+    }
+}
+
+impl<S: TcpClientStack> TcpClientStack for WebSocketStack<S> {
+    type TcpSocket = WebSocketSocket<S::TcpSocket>;
+    type Error = WebSocketError<S::Error>;
+
+    fn socket(&mut self) -> Result<Self::TcpSocket, Self::Error> {
+        Ok(WebSocketSocket {
+            socket: self.stack.socket().map_err(WebSocketError::Network)?,
+            handshake: Handshake::Connecting,
+            outgoing: Vec::new(),
+            sent: 0,
+            status: Vec::new(),
+            trailer: [0; 4],
+            header: Vec::new(),
+            frame: None,
+            control: Vec::new(),
+            pong_pending: false,
+            closed: false,
+        })
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        remote: SocketAddr,
+    ) -> nb::Result<(), Self::Error> {
+        loop {
+            match socket.handshake {
+                Handshake::Connecting => {
+                    self.stack
+                        .connect(&mut socket.socket, remote)
+                        .map_err(|err| err.map(WebSocketError::Network))?;
+
+                    self.request_upgrade(&mut socket.outgoing, remote)
+                        .map_err(|_| WebSocketError::RequestTooLong)?;
+                    socket.sent = 0;
+                    socket.handshake = Handshake::Requesting;
+                }
+
+                Handshake::Requesting => {
+                    self.flush(socket)?;
+                    socket.outgoing.clear();
+                    socket.sent = 0;
+                    socket.handshake = Handshake::AwaitingResponse;
+                }
+
+                Handshake::AwaitingResponse => {
+                    self.receive_response(socket)?;
+                    socket.handshake = Handshake::Established;
+                }
+
+                Handshake::Established => return Ok(()),
+            }
+        }
+    }
+
+    fn is_connected(&mut self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+        if socket.handshake != Handshake::Established || socket.closed {
+            return Ok(false);
+        }
+
+        self.stack
+            .is_connected(&socket.socket)
+            .map_err(WebSocketError::Network)
+    }
+
+    fn send(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &[u8],
+    ) -> nb::Result<usize, Self::Error> {
+        // A frame is only assembled once the previous frame was transmitted.
+        self.flush(socket)?;
+
+        let length = buffer.len().min(MAX_REQUEST_LENGTH - FRAME_HEADER_LENGTH);
+        let mask = self.random();
+        write_frame(&mut socket.outgoing, OPCODE_BINARY, &buffer[..length], mask);
+        socket.sent = 0;
+
+        // The frame is buffered, so the data is accepted even if it cannot be transmitted yet.
+        match self.flush(socket) {
+            Ok(()) | Err(nb::Error::WouldBlock) => Ok(length),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, Self::Error> {
+        // Transmit pongs even if the client has nothing to send.
+        match self.flush(socket) {
+            Ok(()) | Err(nb::Error::WouldBlock) => {}
+            Err(err) => return Err(err),
+        }
+
+        loop {
+            if socket.closed {
+                return Ok(0);
+            }
+
+            let mut frame = match socket.frame {
+                Some(frame) => frame,
+                None => {
+                    let needed = if socket.header.len() < 2 {
+                        2
+                    } else {
+                        header_length(&socket.header)
+                    };
+
+                    if socket.header.len() < needed {
+                        let mut data = [0; MAX_HEADER_LENGTH];
+                        let received = self
+                            .stack
+                            .receive(
+                                &mut socket.socket,
+                                &mut data[..needed - socket.header.len()],
+                            )
+                            .map_err(|err| err.map(WebSocketError::Network))?;
+                        if received == 0 {
+                            return Err(nb::Error::WouldBlock);
+                        }
+
+                        // Note(unwrap): No more than the header length is received.
+                        socket.header.extend_from_slice(&data[..received]).unwrap();
+                        continue;
+                    }
+
+                    let frame = parse_header(&socket.header)
+                        .ok_or(nb::Error::Other(WebSocketError::MalformedFrame))?;
+                    socket.header.clear();
+
+                    if frame.opcode & 0x8 != 0 {
+                        if frame.remaining > MAX_CONTROL_LENGTH {
+                            return Err(nb::Error::Other(WebSocketError::MalformedFrame));
+                        }
+                        socket.control.clear();
+                    }
+
+                    frame
+                }
+            };
+
+            if frame.remaining == 0 {
+                socket.frame = None;
+                if frame.opcode & 0x8 != 0 {
+                    socket.handle_control(frame.opcode);
+                    // The pong is transmitted as soon as possible.
+                    match self.flush(socket) {
+                        Ok(()) | Err(nb::Error::WouldBlock) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                continue;
+            }
+
+            // Control frames are received into their own buffer, data frames into the buffer of
+            // the client.
+            let control = frame.opcode & 0x8 != 0;
+            let mut scratch = [0; MAX_CONTROL_LENGTH];
+            let dest = if control {
+                &mut scratch[..frame.remaining]
+            } else {
+                let length = buffer.len().min(frame.remaining);
+                &mut buffer[..length]
+            };
+
+            let received = match self.stack.receive(&mut socket.socket, dest) {
+                Ok(received) => received,
+                Err(err) => {
+                    socket.frame.replace(frame);
+                    return Err(err.map(WebSocketError::Network));
+                }
+            };
+
+            if let Some(mask) = frame.mask {
+                for (index, byte) in dest[..received].iter_mut().enumerate() {
+                    *byte ^= mask[(frame.offset + index) % 4];
+                }
+            }
+
+            frame.offset += received;
+            frame.remaining -= received;
+            socket.frame.replace(frame);
+
+            if control {
+                // Note(unwrap): Control frames are no longer than the control buffer.
+                socket.control.extend_from_slice(&dest[..received]).unwrap();
+            } else if received > 0 {
+                if frame.remaining == 0 {
+                    socket.frame = None;
+                }
+                return Ok(received);
+            }
+
+            if received == 0 {
+                return Err(nb::Error::WouldBlock);
+            }
+        }
+    }
+
+    fn close(&mut self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+        self.stack
+            .close(socket.socket)
+            .map_err(WebSocketError::Network)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WebSocketError, WebSocketStack};
+    use embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, TcpClientStack};
+    use heapless::Vec;
+
+    #[derive(Default)]
+    struct Pipe {
+        connected: bool,
+        rx: Vec<u8, 256>,
+        tx: Vec<u8, 512>,
+    }
+
+    impl TcpClientStack for Pipe {
+        type TcpSocket = ();
+        type Error = ();
+
+        fn socket(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn connect(&mut self, _socket: &mut (), _remote: SocketAddr) -> nb::Result<(), ()> {
+            self.connected = true;
+            Ok(())
+        }
+
+        fn is_connected(&mut self, _socket: &()) -> Result<bool, ()> {
+            Ok(self.connected)
+        }
+
+        fn send(&mut self, _socket: &mut (), buffer: &[u8]) -> nb::Result<usize, ()> {
+            self.tx.extend_from_slice(buffer).unwrap();
+            Ok(buffer.len())
+        }
+
+        fn receive(&mut self, _socket: &mut (), buffer: &mut [u8]) -> nb::Result<usize, ()> {
+            let len = core::cmp::min(buffer.len(), self.rx.len());
+            buffer[..len].copy_from_slice(&self.rx[..len]);
+            self.rx = Vec::from_slice(&self.rx[len..]).unwrap();
+            Ok(len)
+        }
+
+        fn close(&mut self, _socket: ()) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    fn broker() -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 443)
+    }
+
+    /// Decode a masked frame sent by the client.
+    fn unmask(frame: &[u8]) -> (u8, Vec<u8, 512>) {
+        assert_eq!(frame[1] & 0x80, 0x80);
+        let (length, offset) = match frame[1] & 0x7F {
+            126 => (u16::from_be_bytes([frame[2], frame[3]]) as usize, 4),
+            length => (length as usize, 2),
+        };
+
+        let mask = &frame[offset..offset + 4];
+        let payload = &frame[offset + 4..];
+        assert_eq!(payload.len(), length);
+
+        let payload = payload
+            .iter()
+            .enumerate()
+            .map(|(index, byte)| byte ^ mask[index % 4])
+            .collect();
+        (frame[0], payload)
+    }
+
+    #[test]
+    fn websocket_upgrades_connection() {
+        let mut stack = WebSocketStack::new(Pipe::default(), "/mqtt", 42);
+        stack.set_host("broker.example.com");
+
+        let mut socket = stack.socket().unwrap();
+        assert_eq!(
+            stack.connect(&mut socket, broker()),
+            Err(nb::Error::WouldBlock)
+        );
+        assert!(!stack.is_connected(&socket).unwrap());
+
+        let request = core::str::from_utf8(&stack.inner().tx).unwrap();
+        assert!(request.starts_with("GET /mqtt HTTP/1.1\r\nHost: broker.example.com\r\n"));
+        assert!(request.contains("Upgrade: websocket\r\nConnection: Upgrade\r\n"));
+        assert!(request.contains("Sec-WebSocket-Protocol: mqtt\r\n"));
+        assert!(request.ends_with("\r\n\r\n"));
+
+        // The key consists of 16 bytes encoded using base64.
+        let key = request
+            .lines()
+            .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+            .unwrap();
+        assert_eq!(key.len(), 24);
+        assert!(key.ends_with("=="));
+
+        // A frame following the response carries data of the broker.
+        stack
+            .inner_mut()
+            .rx
+            .extend_from_slice(b"HTTP/1.1 101 Switching Protocols\r\n\r\n\x82\x02\x20\x00")
+            .unwrap();
+        stack.connect(&mut socket, broker()).unwrap();
+        assert!(stack.is_connected(&socket).unwrap());
+
+        let mut buffer = [0; 4];
+        assert_eq!(stack.receive(&mut socket, &mut buffer).unwrap(), 2);
+        assert_eq!(buffer[..2], [0x20, 0x00]);
+    }
+
+    #[test]
+    fn websocket_refuses_upgrade() {
+        let mut stack = WebSocketStack::new(Pipe::default(), "/mqtt", 42);
+        stack
+            .inner_mut()
+            .rx
+            .extend_from_slice(b"HTTP/1.1 404 Not Found\r\n\r\n")
+            .unwrap();
+
+        let mut socket = stack.socket().unwrap();
+        assert_eq!(
+            stack.connect(&mut socket, broker()),
+            Err(nb::Error::Other(WebSocketError::Refused(404)))
+        );
+        assert!(!stack.is_connected(&socket).unwrap());
+    }
+
+    #[test]
+    fn websocket_frames_data() {
+        let mut stack = WebSocketStack::new(Pipe::default(), "/mqtt", 42);
+        stack
+            .inner_mut()
+            .rx
+            .extend_from_slice(b"HTTP/1.1 101 Switching Protocols\r\n\r\n")
+            .unwrap();
+
+        let mut socket = stack.socket().unwrap();
+        stack.connect(&mut socket, broker()).ok();
+        stack.connect(&mut socket, broker()).unwrap();
+        stack.inner_mut().tx.clear();
+
+        // Data is transmitted in masked binary frames.
+        assert_eq!(stack.send(&mut socket, &[0xC0, 0x00]).unwrap(), 2);
+        let (opcode, payload) = unmask(&stack.inner().tx);
+        assert_eq!(opcode, 0x82);
+        assert_eq!(payload, [0xC0, 0x00]);
+        stack.inner_mut().tx.clear();
+
+        // Longer data uses the extended payload length.
+        let data = [0x55; 200];
+        assert_eq!(stack.send(&mut socket, &data).unwrap(), 200);
+        let (_, payload) = unmask(&stack.inner().tx);
+        assert_eq!(payload, data);
+        stack.inner_mut().tx.clear();
+
+        // A ping between fragments of a packet is answered with a pong.
+        stack
+            .inner_mut()
+            .rx
+            .extend_from_slice(b"\x02\x01\xD0\x89\x02hi\x80\x01\x00")
+            .unwrap();
+
+        let mut buffer = [0; 4];
+        assert_eq!(stack.receive(&mut socket, &mut buffer).unwrap(), 1);
+        assert_eq!(buffer[0], 0xD0);
+        assert_eq!(stack.receive(&mut socket, &mut buffer).unwrap(), 1);
+        assert_eq!(buffer[0], 0x00);
+
+        let (opcode, payload) = unmask(&stack.inner().tx);
+        assert_eq!(opcode, 0x8A);
+        assert_eq!(payload, b"hi");
+
+        // A close frame ends the connection.
+        stack.inner_mut().rx.extend_from_slice(b"\x88\x00").unwrap();
+        assert_eq!(stack.receive(&mut socket, &mut buffer).unwrap(), 0);
+        assert!(!stack.is_connected(&socket).unwrap());
+    }
+}
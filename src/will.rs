@@ -41,13 +41,14 @@ impl<const MSG_SIZE: usize> Will<MSG_SIZE> {
         packet.write_binary_data(data)?;
         packet.write_utf8_string(topic)?;
         packet.write_properties(properties)?;
+        let payload = packet.finish()?;
 
         Ok(Self {
             qos: QoS::AtMostOnce,
             retain: Retain::NotRetained,
             // Note(unwrap): The vectro is declared as identical size to the vector, so it will
             // always fit.
-            payload: Vec::from_slice(packet.finish()).unwrap(),
+            payload: Vec::from_slice(payload).unwrap(),
         })
     }
 
@@ -0,0 +1,104 @@
+#![cfg(feature = "async")]
+mod replay;
+
+use minimq::{embedded_time::duration::Milliseconds, AsyncClient, Minimq, QoS, Retain, Timer};
+use replay::{ManualClock, ReplayStack, Session};
+
+use embedded_nal::{IpAddr, Ipv4Addr};
+use std::{
+    cell::RefCell,
+    future::{ready, Future, Ready},
+    pin::Pin,
+    rc::Rc,
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+/// A timer that completes right away and advances the clock by the requested delay.
+struct ReplayTimer {
+    clock: ManualClock,
+    delays: Rc<RefCell<Vec<u32>>>,
+}
+
+impl Timer for ReplayTimer {
+    type Delay = Ready<()>;
+
+    fn delay(&mut self, duration: Milliseconds<u32>) -> Ready<()> {
+        self.clock.advance(duration.0);
+        self.delays.borrow_mut().push(duration.0);
+        ready(())
+    }
+}
+
+/// Run a future to completion by polling it repeatedly.
+fn block_on<F: Future>(mut future: F) -> F::Output {
+    fn raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            raw_waker()
+        }
+        fn noop(_: *const ()) {}
+
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+
+    // Safety: The waker does not use its data pointer.
+    let waker = unsafe { Waker::from_raw(raw_waker()) };
+    let mut cx = Context::from_waker(&waker);
+
+    // Safety: The future is shadowed and is never moved after being pinned.
+    let mut future = unsafe { Pin::new_unchecked(&mut future) };
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+#[test]
+fn async_publish_and_receive() {
+    let session = Session::parse(include_str!("sessions/async.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let delays = Rc::new(RefCell::new(Vec::new()));
+    let timer = ReplayTimer {
+        clock: clock.clone(),
+        delays: delays.clone(),
+    };
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock).unwrap();
+    let mut client = AsyncClient::new(mqtt, timer, |_, topic, _, _| {
+        panic!("Unexpected: {}", topic)
+    });
+
+    block_on(async {
+        client.connect().await.unwrap();
+        client.subscribe("replay/request", &[]).await.unwrap();
+        assert!(!client.inner().client.subscriptions_pending());
+
+        client
+            .publish(
+                "replay/request",
+                b"Hello",
+                QoS::AtLeastOnce,
+                Retain::NotRetained,
+                &[],
+            )
+            .await
+            .unwrap();
+
+        let matched = client
+            .receive(|_, topic, payload, _| topic == "replay/request" && payload == b"Hello")
+            .await
+            .unwrap();
+        assert!(matched);
+    });
+
+    assert!(session.borrow().is_finished());
+
+    // The client waited on the timer instead of polling continuously.
+    let delays = delays.borrow();
+    assert!(!delays.is_empty());
+    assert!(delays.iter().all(|&delay| delay > 0 && delay <= 10));
+}
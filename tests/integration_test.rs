@@ -7,7 +7,7 @@ use std_embedded_time::StandardClock;
 fn main() -> std::io::Result<()> {
     env_logger::init();
 
-    let stack = std_embedded_nal::Stack::default();
+    let stack = std_embedded_nal::Stack;
     let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
     let mut mqtt =
         Minimq::<_, _, 256, 16>::new(localhost, "", stack, StandardClock::default()).unwrap();
@@ -33,21 +33,18 @@ fn main() -> std::io::Result<()> {
         mqtt.poll(|client, topic, payload, properties| {
             println!("{} < {}", topic, core::str::from_utf8(payload).unwrap());
 
-            for property in properties {
-                if let Property::ResponseTopic(topic) = property {
-                    client
-                        .publish(
-                            topic,
-                            "Pong".as_bytes(),
-                            QoS::AtMostOnce,
-                            Retain::NotRetained,
-                            &[],
-                        )
-                        .unwrap();
-                }
-            }
+            // The response is published to the response topic with the correlation data of the
+            // request.
+            client
+                .reply(properties, "Pong".as_bytes(), QoS::AtMostOnce)
+                .unwrap();
 
             if topic == "response" {
+                let correlation_data = properties.iter().find_map(|property| match property {
+                    Property::CorrelationData(data) => Some(*data),
+                    _ => None,
+                });
+                assert_eq!(correlation_data, Some(&b"ping"[..]));
                 responses += 1;
                 if responses == 2 {
                     assert_eq!(0, client.pending_messages(QoS::AtLeastOnce));
@@ -63,37 +60,36 @@ fn main() -> std::io::Result<()> {
                 mqtt.client.subscribe("request", &[]).unwrap();
                 subscribed = true;
             }
-        } else {
-            if mqtt.client.subscriptions_pending() == false {
-                if !published {
-                    println!("PUBLISH request");
-                    let properties = [Property::ResponseTopic("response")];
-                    mqtt.client
-                        .publish(
-                            "request",
-                            "Ping".as_bytes(),
-                            QoS::AtMostOnce,
-                            Retain::NotRetained,
-                            &properties,
-                        )
-                        .unwrap();
+        } else if !mqtt.client.subscriptions_pending() && !published {
+            println!("PUBLISH request");
+            let properties = [
+                Property::ResponseTopic("response"),
+                Property::CorrelationData(b"ping"),
+            ];
+            mqtt.client
+                .publish(
+                    "request",
+                    "Ping".as_bytes(),
+                    QoS::AtMostOnce,
+                    Retain::NotRetained,
+                    &properties,
+                )
+                .unwrap();
 
-                    mqtt.client
-                        .publish(
-                            "request",
-                            "Ping".as_bytes(),
-                            QoS::AtLeastOnce,
-                            Retain::NotRetained,
-                            &properties,
-                        )
-                        .unwrap();
+            mqtt.client
+                .publish(
+                    "request",
+                    "Ping".as_bytes(),
+                    QoS::AtLeastOnce,
+                    Retain::NotRetained,
+                    &properties,
+                )
+                .unwrap();
 
-                    // The message cannot be ack'd until the next poll call
-                    assert_eq!(1, mqtt.client.pending_messages(QoS::AtLeastOnce));
+            // The message cannot be ack'd until the next poll call
+            assert_eq!(1, mqtt.client.pending_messages(QoS::AtLeastOnce));
 
-                    published = true;
-                }
-            }
+            published = true;
         }
     }
 }
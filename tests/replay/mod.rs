@@ -0,0 +1,367 @@
+//! Record and Replay Harness
+//!
+//! # Design
+//! Broker sessions are stored in the hex dump format produced by
+//! `tshark -r capture.pcap -q -z follow,tcp,hex,0`, so a packet capture of a failing session can be
+//! turned into a regression test directly. Lines that are indented by a tab contain data sent by
+//! the broker, all other data lines contain data sent by the client. The `Recorder` produces the
+//! same format from a live session with a broker.
+//!
+//! The `ReplayStack` plays back the broker side of a session. Data sent by the client is compared
+//! against the recorded data, and data from the broker is only made available once the client has
+//! sent everything that preceded it in the recording. Together with the `ManualClock`, this makes
+//! the replayed session fully deterministic.
+#![allow(dead_code)]
+
+use std::{cell::RefCell, collections::VecDeque, fmt::Write, rc::Rc};
+
+use minimq::{
+    embedded_nal::{heapless, nb, AddrType, Dns, IpAddr, Ipv4Addr, SocketAddr, TcpClientStack},
+    embedded_time::{clock, fraction::Fraction, Clock, Instant},
+};
+
+/// The direction of a chunk of data within a session.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    ToBroker,
+    ToClient,
+}
+
+/// A recorded broker session.
+#[derive(Debug, Default)]
+pub struct Session {
+    chunks: VecDeque<(Direction, Vec<u8>)>,
+    stalled: bool,
+}
+
+impl Session {
+    /// Parse a session from a `tshark` TCP stream hex dump.
+    pub fn parse(dump: &str) -> Self {
+        let mut session = Session::default();
+
+        for line in dump.lines() {
+            let (direction, line) = match line.strip_prefix('\t') {
+                Some(line) => (Direction::ToClient, line),
+                None => (Direction::ToBroker, line),
+            };
+
+            // Skip the header and footer of the dump.
+            let offset = line.get(..8).unwrap_or_default();
+            if line.len() < 10 || !offset.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+
+            // The hex column is followed by an ASCII representation of the data, which is ignored.
+            let end = line.len().min(10 + 49);
+            let data = line[10..end]
+                .split_whitespace()
+                .map(|byte| u8::from_str_radix(byte, 16).unwrap());
+
+            session.push(direction, &data.collect::<Vec<_>>());
+        }
+
+        session
+    }
+
+    fn push(&mut self, direction: Direction, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        match self.chunks.back_mut() {
+            Some((last, chunk)) if *last == direction => chunk.extend_from_slice(data),
+            _ => self.chunks.push_back((direction, data.to_vec())),
+        }
+    }
+
+    /// Format the session as a `tshark` TCP stream hex dump.
+    pub fn dump(&self) -> String {
+        let mut dump = String::new();
+        let mut offsets = [0, 0];
+
+        for (direction, chunk) in self.chunks.iter() {
+            let (indent, offset) = match direction {
+                Direction::ToBroker => ("", &mut offsets[0]),
+                Direction::ToClient => ("\t", &mut offsets[1]),
+            };
+
+            for line in chunk.chunks(16) {
+                let mut hex = String::new();
+                for (index, byte) in line.iter().enumerate() {
+                    if index == 8 {
+                        hex.push(' ');
+                    }
+                    write!(hex, "{:02x} ", byte).unwrap();
+                }
+
+                let ascii: String = line
+                    .iter()
+                    .map(|&byte| {
+                        if byte.is_ascii_graphic() {
+                            byte as char
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+
+                writeln!(dump, "{}{:08X}  {:49}  {}", indent, offset, hex, ascii).unwrap();
+                *offset += line.len();
+            }
+        }
+
+        dump
+    }
+
+    /// Stop accepting data from the client, as if the broker silently vanished.
+    pub fn stall(&mut self) {
+        self.stalled = true;
+    }
+
+    /// Determine if the complete session has been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+/// A socket of the `ReplayStack` or `Recorder`.
+#[derive(Debug)]
+pub struct Socket<S> {
+    inner: S,
+}
+
+/// A network stack that replays a recorded session.
+pub struct ReplayStack {
+    session: Rc<RefCell<Session>>,
+    connected: bool,
+    refused: bool,
+    send_limit: usize,
+}
+
+impl ReplayStack {
+    /// Construct a network stack to replay a session.
+    ///
+    /// # Returns
+    /// The stack and a handle to the session to inspect the progress of the replay.
+    pub fn new(session: Session) -> (Self, Rc<RefCell<Session>>) {
+        let session = Rc::new(RefCell::new(session));
+        let stack = Self {
+            session: session.clone(),
+            connected: false,
+            refused: false,
+            send_limit: usize::MAX,
+        };
+
+        (stack, session)
+    }
+
+    /// Refuse all connection attempts, as if the broker was unreachable.
+    pub fn refuse_connections(&mut self) {
+        self.refused = true;
+    }
+
+    /// Accept at most a number of bytes per call to `send()`, as if the socket buffer was full.
+    ///
+    /// # Args
+    /// * `length` - The maximum number of bytes accepted at once.
+    pub fn limit_send(&mut self, length: usize) {
+        self.send_limit = length;
+    }
+}
+
+impl TcpClientStack for ReplayStack {
+    type TcpSocket = Socket<()>;
+    type Error = ();
+
+    fn socket(&mut self) -> Result<Self::TcpSocket, ()> {
+        // Only a single connection is part of a recorded session.
+        assert!(!self.connected, "Client reconnected during replay");
+        Ok(Socket { inner: () })
+    }
+
+    fn connect(&mut self, _: &mut Self::TcpSocket, _: SocketAddr) -> nb::Result<(), ()> {
+        if self.refused {
+            return Err(nb::Error::Other(()));
+        }
+
+        self.connected = true;
+        Ok(())
+    }
+
+    fn is_connected(&mut self, _: &Self::TcpSocket) -> Result<bool, ()> {
+        Ok(self.connected)
+    }
+
+    fn send(&mut self, _: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, ()> {
+        let mut session = self.session.borrow_mut();
+        if session.stalled {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let buffer = &buffer[..buffer.len().min(self.send_limit)];
+        let mut remaining = buffer;
+
+        while !remaining.is_empty() {
+            let expected = match session.chunks.front_mut() {
+                Some((Direction::ToBroker, expected)) => expected,
+                chunk => panic!(
+                    "Client sent {:02x?}, but the recording expects {:02x?}",
+                    remaining, chunk
+                ),
+            };
+
+            let length = expected.len().min(remaining.len());
+            assert_eq!(
+                remaining[..length],
+                expected[..length],
+                "Client data differs from the recording"
+            );
+
+            expected.drain(..length);
+            if expected.is_empty() {
+                session.chunks.pop_front();
+            }
+
+            remaining = &remaining[length..];
+        }
+
+        Ok(buffer.len())
+    }
+
+    fn receive(&mut self, _: &mut Self::TcpSocket, buffer: &mut [u8]) -> nb::Result<usize, ()> {
+        let mut session = self.session.borrow_mut();
+
+        let data = match session.chunks.front_mut() {
+            Some((Direction::ToClient, data)) => data,
+            _ => return Err(nb::Error::WouldBlock),
+        };
+
+        let length = data.len().min(buffer.len());
+        buffer[..length].copy_from_slice(&data[..length]);
+        data.drain(..length);
+
+        if data.is_empty() {
+            session.chunks.pop_front();
+        }
+
+        Ok(length)
+    }
+
+    fn close(&mut self, _: Self::TcpSocket) -> Result<(), ()> {
+        self.connected = false;
+        Ok(())
+    }
+}
+
+/// The hostname of the broker in replayed sessions.
+pub const BROKER_HOSTNAME: &str = "broker.replay";
+
+impl Dns for ReplayStack {
+    type Error = ();
+
+    fn get_host_by_name(&mut self, hostname: &str, _: AddrType) -> nb::Result<IpAddr, ()> {
+        match hostname {
+            BROKER_HOSTNAME => Ok(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+            _ => Err(nb::Error::Other(())),
+        }
+    }
+
+    fn get_host_by_address(&mut self, _: IpAddr) -> nb::Result<heapless::String<256>, ()> {
+        // Reverse lookups are not supported by replayed sessions.
+        Err(nb::Error::Other(()))
+    }
+}
+
+/// A network stack that records the session of another network stack.
+pub struct Recorder<T> {
+    stack: T,
+    session: Rc<RefCell<Session>>,
+}
+
+impl<T> Recorder<T> {
+    /// Construct a network stack that records all data transferred using another stack.
+    ///
+    /// # Returns
+    /// The stack and a handle to the recorded session.
+    pub fn new(stack: T) -> (Self, Rc<RefCell<Session>>) {
+        let session = Rc::new(RefCell::new(Session::default()));
+        let recorder = Self {
+            stack,
+            session: session.clone(),
+        };
+
+        (recorder, session)
+    }
+}
+
+impl<T: TcpClientStack> TcpClientStack for Recorder<T> {
+    type TcpSocket = Socket<T::TcpSocket>;
+    type Error = T::Error;
+
+    fn socket(&mut self) -> Result<Self::TcpSocket, T::Error> {
+        let inner = self.stack.socket()?;
+        Ok(Socket { inner })
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        remote: SocketAddr,
+    ) -> nb::Result<(), T::Error> {
+        self.stack.connect(&mut socket.inner, remote)
+    }
+
+    fn is_connected(&mut self, socket: &Self::TcpSocket) -> Result<bool, T::Error> {
+        self.stack.is_connected(&socket.inner)
+    }
+
+    fn send(&mut self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, T::Error> {
+        let sent = self.stack.send(&mut socket.inner, buffer)?;
+        self.session
+            .borrow_mut()
+            .push(Direction::ToBroker, &buffer[..sent]);
+        Ok(sent)
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, T::Error> {
+        let received = self.stack.receive(&mut socket.inner, buffer)?;
+        self.session
+            .borrow_mut()
+            .push(Direction::ToClient, &buffer[..received]);
+        Ok(received)
+    }
+
+    fn close(&mut self, socket: Self::TcpSocket) -> Result<(), T::Error> {
+        self.stack.close(socket.inner)
+    }
+}
+
+/// A clock that only advances when requested, with millisecond resolution.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    now: Rc<RefCell<u32>>,
+}
+
+impl ManualClock {
+    /// Advance the time of the clock.
+    ///
+    /// # Args
+    /// * `milliseconds` - The number of milliseconds to advance the clock by.
+    pub fn advance(&self, milliseconds: u32) {
+        *self.now.borrow_mut() += milliseconds;
+    }
+}
+
+impl Clock for ManualClock {
+    type T = u32;
+
+    const SCALING_FACTOR: Fraction = Fraction::new(1, 1000);
+
+    fn try_now(&self) -> Result<Instant<Self>, clock::Error> {
+        Ok(Instant::new(*self.now.borrow()))
+    }
+}
@@ -0,0 +1,2459 @@
+mod replay;
+
+use minimq::{
+    embedded_nal::TcpClientStack,
+    embedded_time::{
+        duration::{Extensions, Milliseconds},
+        Clock, Instant,
+    },
+    settings::{self, Settings},
+    typestate, BufferPool, BufferSource, Event, ExponentialBackoff, Minimq, MinimqBuilder,
+    PacketCounts, PollError, QoS, ReconnectPolicy, Retain, SessionStatus, SubscriptionOptions,
+    Telemetry,
+};
+use replay::{ManualClock, Recorder, ReplayStack, Session};
+
+use embedded_nal::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Subscribe to a topic, publish a message to it, and wait for the message to be received.
+///
+/// # Returns
+/// The payload of the received message.
+fn request_response<S: TcpClientStack, C: Clock>(stack: S, clock: C) -> Vec<u8> {
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock).unwrap();
+
+    let mut subscribed = false;
+    let mut published = false;
+
+    for _ in 0..1_000_000 {
+        let received = mqtt
+            .poll(|client, topic, payload, _properties| {
+                assert_eq!(topic, "replay/request");
+                assert_eq!(0, client.pending_messages(QoS::AtLeastOnce));
+
+                let subscription = client.subscriptions().next().unwrap();
+                assert_eq!(subscription.filter(), "replay/request");
+                assert_eq!(subscription.qos(), QoS::AtMostOnce);
+                payload.to_vec()
+            })
+            .unwrap();
+
+        if let Some(payload) = received {
+            return payload;
+        }
+
+        if !mqtt.client.is_connected() {
+            continue;
+        }
+
+        if !subscribed {
+            mqtt.client.subscribe("replay/request", &[]).unwrap();
+            subscribed = true;
+        } else if !mqtt.client.subscriptions_pending() && !published {
+            // The packet consists of the fixed header, topic, packet identifier and properties.
+            let max_payload = mqtt
+                .client
+                .max_payload_size("replay/request", QoS::AtLeastOnce);
+            assert_eq!(max_payload, 256 - 3 - 16 - 2 - 1);
+
+            mqtt.client
+                .publish(
+                    "replay/request",
+                    b"Ping",
+                    QoS::AtLeastOnce,
+                    Retain::NotRetained,
+                    &[],
+                )
+                .unwrap();
+            published = true;
+        }
+
+        std::thread::yield_now();
+    }
+
+    panic!("No message received");
+}
+
+#[test]
+fn replay_request_response() {
+    let session = Session::parse(include_str!("sessions/request_response.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let payload = request_response(stack, ManualClock::default());
+
+    assert_eq!(payload, b"Ping");
+    assert!(session.borrow().is_finished());
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn replay_decryption_failed() {
+    // The broker delivers a message that is tagged as encrypted, but fails authentication.
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    let session = Session::parse(include_str!("sessions/decryption_failed.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    let cipher = minimq::PayloadCipher::new(&[0x42; 32], [0; 4]);
+    mqtt.client.set_payload_cipher(Some(cipher));
+    mqtt.client.set_dead_letter_handler(Some(|topic, reason| {
+        assert_eq!(topic, "replay/request");
+        assert_eq!(reason, minimq::DropReason::DecryptionFailed);
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }));
+
+    // The message is acknowledged without being passed to the handler.
+    let mut subscribed = false;
+    while !session.borrow().is_finished() {
+        mqtt.poll(|_, topic, _, _| panic!("Received message on {}", topic))
+            .unwrap();
+
+        if mqtt.client.is_connected() && !subscribed {
+            mqtt.client.subscribe("replay/request", &[]).unwrap();
+            subscribed = true;
+        }
+    }
+
+    assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+}
+
+/// Connect to the broker and subscribe to the topic that the burst of messages is published to.
+fn burst_client(stack: ReplayStack) -> Minimq<ReplayStack, ManualClock, 256, 16> {
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    mqtt.client.subscribe("replay/request", &[]).unwrap();
+    mqtt
+}
+
+#[test]
+fn replay_burst() {
+    // The broker sends several messages within a single TCP segment.
+    let session = Session::parse(include_str!("sessions/burst.txt"));
+    let (stack, session) = ReplayStack::new(session);
+    let mut mqtt = burst_client(stack);
+
+    // All messages are handled within a single call.
+    let mut received = Vec::new();
+    let last = loop {
+        let last = mqtt
+            .poll(|_, _, payload, _| {
+                received.push(payload[0]);
+                payload[0]
+            })
+            .unwrap();
+
+        if last.is_some() {
+            break last;
+        }
+    };
+
+    assert_eq!(received, b"123");
+    assert_eq!(last, Some(b'3'));
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_burst_poll_one() {
+    let session = Session::parse(include_str!("sessions/burst.txt"));
+    let (stack, session) = ReplayStack::new(session);
+    let mut mqtt = burst_client(stack);
+
+    // The messages are handled over successive calls.
+    let mut received = Vec::new();
+    while received.len() < 3 {
+        if let Some(payload) = mqtt.poll_one(|_, _, payload, _| payload[0]).unwrap() {
+            received.push(payload);
+        }
+    }
+
+    assert_eq!(received, b"123");
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_reconnect_limit_fallback() {
+    // The broker is unreachable over the primary stack.
+    let (mut primary, _) = ReplayStack::new(Session::default());
+    primary.refuse_connections();
+
+    let session = Session::parse(include_str!("sessions/reconnect_limit.txt"));
+    let (fallback, session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", primary, clock.clone()).unwrap();
+    mqtt.client.set_max_reconnect_attempts(Some(2));
+
+    let result = loop {
+        clock.advance(1_000);
+        match mqtt.poll(|_, _, _, _| {}) {
+            Ok(_) | Err(minimq::Error::Network(())) => {}
+            result => break result,
+        }
+    };
+    assert!(matches!(result, Err(minimq::Error::ReconnectLimit)));
+
+    // The client connects again over the fallback stack.
+    mqtt.client.resume_network(Some(fallback)).unwrap();
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_poll_summary() {
+    let session = Session::parse(include_str!("sessions/request_response.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    static SUBSCRIBED: AtomicUsize = AtomicUsize::new(0);
+    mqtt.client.set_event_handler(Some(|event| {
+        if let Event::Subscribed { reason_code, .. } = event {
+            assert_eq!(reason_code, 0);
+            SUBSCRIBED.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+
+    let mut packets = PacketCounts::default();
+    let mut bytes_received = 0;
+    let mut subscribed = false;
+    let mut published = false;
+
+    while !session.borrow().is_finished() {
+        let summary = mqtt
+            .poll_with_summary(|_, _, payload, _| payload.to_vec())
+            .unwrap();
+        packets.conn_ack += summary.packets.conn_ack;
+        packets.publish += summary.packets.publish;
+        packets.pub_ack += summary.packets.pub_ack;
+        packets.sub_ack += summary.packets.sub_ack;
+        bytes_received += summary.bytes_received;
+
+        if let Some(payload) = summary.handled {
+            assert_eq!(payload, b"Ping");
+            assert_eq!(summary.packets.publish, 1);
+        }
+
+        if !mqtt.client.is_connected() {
+            continue;
+        }
+
+        if !subscribed {
+            mqtt.client.subscribe("replay/request", &[]).unwrap();
+            subscribed = true;
+        } else if !mqtt.client.subscriptions_pending() && !published {
+            mqtt.client
+                .publish(
+                    "replay/request",
+                    b"Ping",
+                    QoS::AtLeastOnce,
+                    Retain::NotRetained,
+                    &[],
+                )
+                .unwrap();
+            published = true;
+        }
+    }
+
+    // Process the packets that remain buffered once all data was received.
+    loop {
+        let summary = mqtt.poll_with_summary(|_, _, _, _| ()).unwrap();
+        packets.publish += summary.packets.publish;
+        packets.pub_ack += summary.packets.pub_ack;
+        if summary.packets.total() == 0 {
+            assert!(!summary.pending);
+            break;
+        }
+    }
+
+    assert_eq!(packets.conn_ack, 1);
+    assert_eq!(packets.sub_ack, 1);
+    assert_eq!(packets.pub_ack, 1);
+    assert_eq!(packets.publish, 1);
+    assert!(bytes_received > 0);
+    assert_eq!(SUBSCRIBED.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn replay_poll_all() {
+    let session = Session::parse(include_str!("sessions/request_response.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    let mut received = 0;
+    let mut subscribed = false;
+    let mut published = false;
+
+    while !session.borrow().is_finished() {
+        received += mqtt
+            .poll_all(|_, topic, payload, _| {
+                assert_eq!(topic, "replay/request");
+                assert_eq!(payload, b"Ping");
+            })
+            .unwrap();
+
+        if !mqtt.client.is_connected() {
+            continue;
+        }
+
+        if !subscribed {
+            mqtt.client.subscribe("replay/request", &[]).unwrap();
+            subscribed = true;
+        } else if !mqtt.client.subscriptions_pending() && !published {
+            mqtt.client
+                .publish(
+                    "replay/request",
+                    b"Ping",
+                    QoS::AtLeastOnce,
+                    Retain::NotRetained,
+                    &[],
+                )
+                .unwrap();
+            published = true;
+        }
+    }
+
+    // All received data is processed by the call that reads it, so nothing remains buffered.
+    assert_eq!(received, 1);
+    assert_eq!(mqtt.poll_all(|_, _, _, _| {}).unwrap(), 0);
+    assert_eq!(mqtt.client.pending_messages(QoS::AtLeastOnce), 0);
+}
+
+#[test]
+fn replay_typestate() {
+    let session = Session::parse(include_str!("sessions/request_response.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    let mut client = typestate::Client::new(mqtt);
+
+    let mut client = loop {
+        client.poll(|_, _, _, _| unreachable!()).unwrap();
+        match client.connected() {
+            Ok(client) => break client,
+            Err(disconnected) => client = disconnected,
+        }
+    };
+
+    client.subscribe("replay/request", &[]).unwrap();
+    while client.subscriptions_pending() {
+        client.poll(|_, _, _, _| unreachable!()).unwrap();
+    }
+
+    client
+        .publish(
+            "replay/request",
+            b"Ping",
+            QoS::AtLeastOnce,
+            Retain::NotRetained,
+            &[],
+        )
+        .unwrap();
+
+    let payload = loop {
+        if let Some(payload) = client.poll(|_, _, payload, _| payload.to_vec()).unwrap() {
+            break payload;
+        }
+    };
+
+    assert_eq!(payload, b"Ping");
+    assert!(client.disconnected().is_err());
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_fallible_handler() {
+    let session = Session::parse(include_str!("sessions/request_response.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_redeliver_failed_messages(true);
+
+    let mut subscribed = false;
+    let mut published = false;
+
+    let error = loop {
+        match mqtt.try_poll(|_, _, _, _| Err("Storage full")) {
+            Ok(handled) => assert!(!handled),
+            Err(error) => break error,
+        }
+
+        if !mqtt.client.is_connected() {
+            continue;
+        }
+
+        if !subscribed {
+            mqtt.client.subscribe("replay/request", &[]).unwrap();
+            subscribed = true;
+        } else if !mqtt.client.subscriptions_pending() && !published {
+            mqtt.client
+                .publish(
+                    "replay/request",
+                    b"Ping",
+                    QoS::AtLeastOnce,
+                    Retain::NotRetained,
+                    &[],
+                )
+                .unwrap();
+            published = true;
+        }
+    };
+
+    assert_eq!(error, PollError::Handler("Storage full"));
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_incremental_publish() {
+    let session = Session::parse(include_str!("sessions/request_response.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    let mut subscribed = false;
+    let mut published = false;
+
+    // The payload is published in fragments, which results in the same packet as publishing it at
+    // once.
+    let payload = loop {
+        if let Some(payload) = mqtt.poll(|_, _, payload, _| payload.to_vec()).unwrap() {
+            break payload;
+        }
+
+        if !mqtt.client.is_connected() {
+            continue;
+        }
+
+        if !subscribed {
+            mqtt.client.subscribe("replay/request", &[]).unwrap();
+            subscribed = true;
+        } else if !mqtt.client.subscriptions_pending() && !published {
+            let mut publication = mqtt
+                .client
+                .start_publish("replay/request", QoS::AtLeastOnce, Retain::NotRetained, &[])
+                .unwrap();
+            let remaining = publication.remaining();
+            assert_eq!(remaining, 256 - 3 - 16 - 2 - 1);
+
+            publication.append(b"Pi").unwrap();
+            publication.append(b"ng").unwrap();
+            assert_eq!(publication.remaining(), remaining - 4);
+            assert!(publication.append(&[0; 256]).is_err());
+
+            publication.finish().unwrap();
+            published = true;
+        }
+    };
+
+    assert_eq!(payload, b"Ping");
+    assert!(session.borrow().is_finished());
+}
+
+/// Record the session of `replay_request_response` with a broker running on localhost.
+#[test]
+#[ignore]
+fn record_request_response() {
+    let (stack, session) = Recorder::new(std_embedded_nal::Stack);
+
+    request_response(stack, std_embedded_time::StandardClock::default());
+
+    print!("{}", session.borrow().dump());
+}
+
+/// Connect to the broker with an availability topic and wait for the `online` status to be
+/// received and acknowledged.
+fn availability<S: TcpClientStack, C: Clock>(stack: S, clock: C) {
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock).unwrap();
+
+    mqtt.client
+        .set_availability("replay/status", b"online", b"offline")
+        .unwrap();
+
+    let mut subscribed = false;
+    let mut received = false;
+
+    for _ in 0..1_000_000 {
+        mqtt.poll(|_, topic, payload, _properties| {
+            assert_eq!(topic, "replay/status");
+            received |= payload == b"online";
+        })
+        .unwrap();
+
+        if !mqtt.client.is_connected() {
+            continue;
+        }
+
+        if !subscribed {
+            mqtt.client.subscribe("replay/status", &[]).unwrap();
+            subscribed = true;
+        } else if received && mqtt.client.pending_messages(QoS::AtLeastOnce) == 0 {
+            return;
+        }
+
+        std::thread::yield_now();
+    }
+
+    panic!("Availability not reported");
+}
+
+#[test]
+fn replay_availability() {
+    let session = Session::parse(include_str!("sessions/availability.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    availability(stack, ManualClock::default());
+    assert!(session.borrow().is_finished());
+}
+
+/// Record the session of `replay_availability` with a broker running on localhost.
+#[test]
+#[ignore]
+fn record_availability() {
+    let (stack, session) = Recorder::new(std_embedded_nal::Stack);
+
+    availability(stack, std_embedded_time::StandardClock::default());
+
+    print!("{}", session.borrow().dump());
+}
+
+/// Publish a message and shut down the client before the message was acknowledged.
+fn shutdown<S: TcpClientStack, C: Clock>(stack: S, clock: C) {
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock).unwrap();
+
+    let mut published = false;
+
+    for _ in 0..1_000_000 {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+
+        if mqtt.client.is_shut_down() {
+            // The message was acknowledged before the client disconnected.
+            assert_eq!(0, mqtt.client.pending_messages(QoS::AtLeastOnce));
+            return;
+        }
+
+        if mqtt.client.is_connected() && !published {
+            mqtt.client
+                .publish(
+                    "replay/shutdown",
+                    b"Bye",
+                    QoS::AtLeastOnce,
+                    Retain::NotRetained,
+                    &[],
+                )
+                .unwrap();
+            mqtt.client.shutdown(1_000.milliseconds(), false).unwrap();
+            published = true;
+        }
+
+        std::thread::yield_now();
+    }
+
+    panic!("Client not shut down");
+}
+
+#[test]
+fn replay_shutdown() {
+    let session = Session::parse(include_str!("sessions/shutdown.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    shutdown(stack, ManualClock::default());
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_shutdown_partial_write() {
+    // The socket accepts a single byte at a time, so the DISCONNECT is transmitted over several
+    // polls before the socket is closed.
+    let session = Session::parse(include_str!("sessions/shutdown_partial_write.txt"));
+    let (mut stack, session) = ReplayStack::new(session);
+    stack.limit_send(1);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    mqtt.client.shutdown(1_000.milliseconds(), false).unwrap();
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert!(!mqtt.client.is_shut_down());
+
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert!(mqtt.client.is_shut_down());
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_shutdown_unsubscribe() {
+    // The subscription is removed before disconnecting.
+    let session = Session::parse(include_str!("sessions/shutdown_unsubscribe.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    let mut subscribed = false;
+    while !mqtt.client.is_shut_down() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+
+        if !mqtt.client.is_connected() || mqtt.client.subscriptions_pending() {
+            continue;
+        }
+
+        if !subscribed {
+            mqtt.client.subscribe("replay/unsubscribe", &[]).unwrap();
+            subscribed = true;
+        } else if mqtt.client.subscriptions().count() == 1 {
+            mqtt.client.shutdown(1_000.milliseconds(), true).unwrap();
+        }
+    }
+
+    assert_eq!(mqtt.client.subscriptions().count(), 0);
+    assert!(session.borrow().is_finished());
+}
+
+/// Record the session of `replay_shutdown` with a broker running on localhost.
+#[test]
+#[ignore]
+fn record_shutdown() {
+    let (stack, session) = Recorder::new(std_embedded_nal::Stack);
+
+    shutdown(stack, std_embedded_time::StandardClock::default());
+
+    print!("{}", session.borrow().dump());
+}
+
+#[derive(minimq_derive::Telemetry)]
+struct Measurement {
+    temperature: f32,
+    #[telemetry(rename = "rh")]
+    humidity: u8,
+    #[telemetry(skip)]
+    _raw: u16,
+}
+
+/// Subscribe to a topic prefix, publish telemetry below it, and wait for all fields to be
+/// received.
+fn telemetry<S: TcpClientStack, C: Clock>(stack: S, clock: C) {
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock).unwrap();
+
+    let measurement = Measurement {
+        temperature: 20.5,
+        humidity: 40,
+        _raw: 0x1234,
+    };
+
+    let mut subscribed = false;
+    let mut published = false;
+    let mut received = Vec::new();
+
+    for _ in 0..1_000_000 {
+        mqtt.poll(|_, topic, payload, _properties| {
+            received.push((topic.to_string(), payload.to_vec()));
+        })
+        .unwrap();
+
+        if received.len() == 2 {
+            assert_eq!(
+                received[0],
+                ("replay/sensor/temperature".into(), b"20.5".to_vec())
+            );
+            assert_eq!(received[1], ("replay/sensor/rh".into(), b"40".to_vec()));
+            return;
+        }
+
+        if !mqtt.client.is_connected() {
+            continue;
+        }
+
+        if !subscribed {
+            mqtt.client.subscribe("replay/sensor/#", &[]).unwrap();
+            subscribed = true;
+        } else if !mqtt.client.subscriptions_pending() && !published {
+            measurement
+                .publish_telemetry(&mut mqtt.client, "replay/sensor")
+                .unwrap();
+            published = true;
+        }
+
+        std::thread::yield_now();
+    }
+
+    panic!("Telemetry not received");
+}
+
+#[test]
+fn replay_telemetry() {
+    let session = Session::parse(include_str!("sessions/telemetry.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    telemetry(stack, ManualClock::default());
+    assert!(session.borrow().is_finished());
+}
+
+/// Record the session of `replay_telemetry` with a broker running on localhost.
+#[test]
+#[ignore]
+fn record_telemetry() {
+    let (stack, session) = Recorder::new(std_embedded_nal::Stack);
+
+    telemetry(stack, std_embedded_time::StandardClock::default());
+
+    print!("{}", session.borrow().dump());
+}
+
+/// Subscribe to a topic and unsubscribe from it again. A message published to the topic afterwards
+/// is not received.
+fn unsubscribe<S: TcpClientStack, C: Clock>(stack: S, clock: C) {
+    static UNSUBSCRIBED: AtomicUsize = AtomicUsize::new(0);
+    UNSUBSCRIBED.store(0, Ordering::Relaxed);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock).unwrap();
+    mqtt.client.set_event_handler(Some(|event| {
+        if let Event::Unsubscribed { reason_code, .. } = event {
+            assert_eq!(reason_code, 0);
+            UNSUBSCRIBED.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+
+    let mut subscribed = false;
+    let mut unsubscribed = false;
+    let mut published = false;
+
+    for _ in 0..1_000_000 {
+        mqtt.poll(|_, topic, _, _| panic!("Received message on {}", topic))
+            .unwrap();
+
+        if !mqtt.client.is_connected() || mqtt.client.subscriptions_pending() {
+            continue;
+        }
+
+        if !subscribed {
+            mqtt.client.subscribe("replay/unsubscribe", &[]).unwrap();
+            subscribed = true;
+        } else if !unsubscribed {
+            assert_eq!(mqtt.client.subscriptions().count(), 1);
+            mqtt.client.unsubscribe("replay/unsubscribe", &[]).unwrap();
+            unsubscribed = true;
+        } else if !published {
+            assert_eq!(mqtt.client.subscriptions().count(), 0);
+            assert_eq!(UNSUBSCRIBED.load(Ordering::Relaxed), 1);
+            mqtt.client
+                .publish(
+                    "replay/unsubscribe",
+                    b"Ignored",
+                    QoS::AtLeastOnce,
+                    Retain::NotRetained,
+                    &[],
+                )
+                .unwrap();
+            published = true;
+        } else if mqtt.client.pending_messages(QoS::AtLeastOnce) == 0 {
+            return;
+        }
+
+        std::thread::yield_now();
+    }
+
+    panic!("Unsubscribe did not complete");
+}
+
+#[test]
+fn replay_unsubscribe() {
+    let session = Session::parse(include_str!("sessions/unsubscribe.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    unsubscribe(stack, ManualClock::default());
+    assert!(session.borrow().is_finished());
+}
+
+/// Record the session of `replay_unsubscribe` with a broker running on localhost.
+#[test]
+#[ignore]
+fn record_unsubscribe() {
+    let (stack, session) = Recorder::new(std_embedded_nal::Stack);
+
+    unsubscribe(stack, std_embedded_time::StandardClock::default());
+
+    print!("{}", session.borrow().dump());
+}
+
+/// Subscribe to multiple topics using a single request and wait until all subscriptions are
+/// acknowledged.
+fn subscribe_many<S: TcpClientStack, C: Clock>(stack: S, clock: C) {
+    static SUBSCRIBED: AtomicUsize = AtomicUsize::new(0);
+    SUBSCRIBED.store(0, Ordering::Relaxed);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock).unwrap();
+    mqtt.client.set_event_handler(Some(|event| {
+        if let Event::Subscribed {
+            filter,
+            reason_code,
+            ..
+        } = event
+        {
+            assert!(filter.starts_with("replay/many/"));
+            assert!(reason_code < 0x80);
+            SUBSCRIBED.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+
+    let mut subscribed = false;
+
+    for _ in 0..1_000_000 {
+        mqtt.poll(|_, topic, _, _| panic!("Received message on {}", topic))
+            .unwrap();
+
+        if !mqtt.client.is_connected() || mqtt.client.subscriptions_pending() {
+            continue;
+        }
+
+        if !subscribed {
+            let filters = [
+                ("replay/many/a", SubscriptionOptions::default()),
+                (
+                    "replay/many/b",
+                    SubscriptionOptions::default().qos(QoS::AtLeastOnce),
+                ),
+            ];
+            mqtt.client.subscribe_many(&filters, &[]).unwrap();
+            subscribed = true;
+        } else {
+            let subscriptions: Vec<_> = mqtt
+                .client
+                .subscriptions()
+                .map(|subscription| (subscription.filter(), subscription.qos()))
+                .collect();
+            assert_eq!(
+                subscriptions,
+                [
+                    ("replay/many/a", QoS::AtMostOnce),
+                    ("replay/many/b", QoS::AtLeastOnce)
+                ]
+            );
+            assert_eq!(SUBSCRIBED.load(Ordering::Relaxed), 2);
+            return;
+        }
+
+        std::thread::yield_now();
+    }
+
+    panic!("Subscriptions were not acknowledged");
+}
+
+#[test]
+fn replay_subscribe_many() {
+    let session = Session::parse(include_str!("sessions/subscribe_many.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    subscribe_many(stack, ManualClock::default());
+    assert!(session.borrow().is_finished());
+}
+
+/// Record the session of `replay_subscribe_many` with a broker running on localhost.
+#[test]
+#[ignore]
+fn record_subscribe_many() {
+    let (stack, session) = Recorder::new(std_embedded_nal::Stack);
+
+    subscribe_many(stack, std_embedded_time::StandardClock::default());
+
+    print!("{}", session.borrow().dump());
+}
+
+#[test]
+fn replay_partial_subscription() {
+    static REJECTED: AtomicUsize = AtomicUsize::new(0);
+
+    // The broker rejects the second of three topic filters as the client is not authorized.
+    let session = Session::parse(include_str!("sessions/partial_subscription.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_event_handler(Some(|event| {
+        if let Event::Subscribed {
+            filter,
+            reason_code,
+            ..
+        } = event
+        {
+            match filter {
+                "replay/a" | "replay/c" => assert!(reason_code < 0x80),
+                filter => {
+                    assert_eq!((filter, reason_code), ("replay/b", 0x87));
+                    REJECTED.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }));
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    let filters = [
+        ("replay/a", SubscriptionOptions::default()),
+        ("replay/b", SubscriptionOptions::default()),
+        (
+            "replay/c",
+            SubscriptionOptions::default().qos(QoS::AtLeastOnce),
+        ),
+    ];
+    mqtt.client.subscribe_many(&filters, &[]).unwrap();
+
+    let result = loop {
+        match mqtt.poll(|_, _, _, _| {}) {
+            Ok(_) => assert!(!session.borrow().is_finished()),
+            result => break result,
+        }
+    };
+
+    assert!(matches!(
+        result,
+        Err(minimq::Error::Unacknowledged { reason_code: 0x87 })
+    ));
+    assert_eq!(REJECTED.load(Ordering::Relaxed), 1);
+    assert_eq!(mqtt.client.last_error_detail(), Some("No"));
+
+    // The accepted topic filters are subscribed to nevertheless.
+    let subscriptions: Vec<_> = mqtt
+        .client
+        .subscriptions()
+        .map(|subscription| (subscription.filter(), subscription.qos()))
+        .collect();
+    assert_eq!(
+        subscriptions,
+        [
+            ("replay/a", QoS::AtMostOnce),
+            ("replay/c", QoS::AtLeastOnce)
+        ]
+    );
+    assert!(!mqtt.client.subscriptions_pending());
+}
+
+#[test]
+fn replay_ack_timeout() {
+    // The broker does not acknowledge the published message.
+    let session = Session::parse(include_str!("sessions/ack_timeout.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock.clone()).unwrap();
+    mqtt.client.set_ack_timeout(Some(1_000.milliseconds()));
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    mqtt.client
+        .publish(
+            "replay/ack-timeout",
+            b"Lost",
+            QoS::AtLeastOnce,
+            Retain::NotRetained,
+            &[],
+        )
+        .unwrap();
+    assert!(session.borrow().is_finished());
+
+    clock.advance(1_000);
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert!(mqtt.client.is_connected());
+
+    // The connection is considered failed once the timeout elapses. The message is retained for
+    // retransmission after reconnecting.
+    clock.advance(1);
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert!(!mqtt.client.is_connected());
+    assert_eq!(mqtt.client.pending_messages(QoS::AtLeastOnce), 1);
+}
+
+#[test]
+fn replay_disconnect() {
+    // The broker does not acknowledge the published message before the client disconnects.
+    let session = Session::parse(include_str!("sessions/disconnect.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    mqtt.client
+        .publish(
+            "replay/ack-timeout",
+            b"Lost",
+            QoS::AtLeastOnce,
+            Retain::NotRetained,
+            &[],
+        )
+        .unwrap();
+
+    // The session expires immediately, so the unacknowledged message is discarded.
+    mqtt.client.disconnect(0, Some(0)).unwrap();
+    assert!(session.borrow().is_finished());
+    assert!(mqtt.client.is_shut_down());
+    assert_eq!(mqtt.client.pending_messages(QoS::AtLeastOnce), 0);
+
+    assert!(matches!(
+        mqtt.client.disconnect(0, None),
+        Err(minimq::Error::NotConnected)
+    ));
+}
+
+#[test]
+fn replay_broker_disconnect() {
+    static DISCONNECTED: AtomicUsize = AtomicUsize::new(0);
+
+    // The broker closes the connection as it is shutting down.
+    let session = Session::parse(include_str!("sessions/broker_disconnect.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_event_handler(Some(|event| {
+        if let Event::Disconnected {
+            reason_code,
+            reason,
+        } = event
+        {
+            assert_eq!(reason_code, 0x8b);
+            assert_eq!(reason, Some("Of"));
+            DISCONNECTED.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+
+    let mut packets = PacketCounts::default();
+    while !session.borrow().is_finished() {
+        let summary = mqtt.poll_with_summary(|_, _, _, _| {}).unwrap();
+        packets.conn_ack += summary.packets.conn_ack;
+        packets.disconnect += summary.packets.disconnect;
+    }
+
+    assert_eq!(packets.conn_ack, 1);
+    assert_eq!(packets.disconnect, 1);
+    assert_eq!(DISCONNECTED.load(Ordering::Relaxed), 1);
+    assert_eq!(mqtt.client.last_error_detail(), Some("Of"));
+
+    // The client reconnects with the next poll.
+    assert!(!mqtt.client.is_connected());
+    assert!(!mqtt.client.is_shut_down());
+}
+
+#[test]
+fn replay_connection_refused() {
+    // The broker refuses the connection, providing a reason that exceeds the reason buffer.
+    let session = Session::parse(include_str!("sessions/connection_refused.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    assert_eq!(mqtt.client.last_error_detail(), None);
+
+    let result = loop {
+        match mqtt.poll(|_, _, _, _| {}) {
+            Ok(_) => assert!(!session.borrow().is_finished()),
+            result => break result,
+        }
+    };
+
+    assert!(matches!(
+        result,
+        Err(minimq::Error::ConnectionRefused { reason_code: 0x87 })
+    ));
+    assert_eq!(
+        mqtt.client.last_error_detail(),
+        Some("The client identifier replay is not authorized to connect to thi")
+    );
+}
+
+#[test]
+fn replay_connack_timeout() {
+    // The broker accepts the TCP connection, but does not answer the first CONNECT.
+    let session = Session::parse(include_str!("sessions/connack_timeout.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock.clone()).unwrap();
+    mqtt.client.set_connack_timeout(Some(Milliseconds(5_000)));
+
+    for _ in 0..10 {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+    assert!(!mqtt.client.is_connected());
+
+    // Once the timeout expires, the CONNECT is retried on a new socket.
+    clock.advance(5_001);
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_reconnect_backoff() {
+    // The broker is unavailable on the first connection attempt.
+    static BACKOFF: ExponentialBackoff =
+        ExponentialBackoff::new(Milliseconds(1_000), Milliseconds(10_000), 7);
+
+    let session = Session::parse(include_str!("sessions/reconnect_backoff.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock.clone()).unwrap();
+    mqtt.client.set_reconnect_policy(Some(&BACKOFF));
+
+    let result = loop {
+        match mqtt.poll(|_, _, _, _| {}) {
+            Ok(_) => {}
+            result => break result,
+        }
+    };
+    assert!(matches!(
+        result,
+        Err(minimq::Error::ConnectionRefused { reason_code: 0x88 })
+    ));
+
+    // The next connection attempt is delayed.
+    for _ in 0..10 {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+    assert!(!mqtt.client.is_connected());
+
+    clock.advance(BACKOFF.delay(1).0);
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_connect_throttle() {
+    // The broker drops the connection right after accepting it.
+    let session = Session::parse(include_str!("sessions/connect_throttle.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock.clone()).unwrap();
+    mqtt.client
+        .set_min_connect_interval(Some(2_000.milliseconds()));
+
+    loop {
+        let summary = mqtt.poll_with_summary(|_, _, _, _| {}).unwrap();
+        if summary.packets.disconnect > 0 {
+            break;
+        }
+    }
+
+    // The next connection attempt waits for the interval since the previous attempt.
+    clock.advance(1_999);
+    for _ in 0..10 {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+    assert_eq!(mqtt.client.status(), SessionStatus::Disconnected);
+
+    clock.advance(1);
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_session_taken_over() {
+    // Another client connects with the same client identifier.
+    let session = Session::parse(include_str!("sessions/session_taken_over.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    let result = loop {
+        match mqtt.poll(|_, _, _, _| {}) {
+            Ok(_) => assert!(!session.borrow().is_finished()),
+            result => break result,
+        }
+    };
+
+    assert!(matches!(result, Err(minimq::Error::SessionTakenOver)));
+    assert!(!mqtt.client.is_connected());
+
+    // The client does not take the session over again by reconnecting.
+    assert!(matches!(
+        mqtt.poll(|_, _, _, _| {}),
+        Err(minimq::Error::SessionTakenOver)
+    ));
+    mqtt.client.resume_network(None).unwrap();
+}
+
+#[test]
+fn replay_redirect() {
+    static REDIRECTED: AtomicUsize = AtomicUsize::new(0);
+
+    // The broker refuses the connection as it moved, and then requests another broker to be used.
+    let session = Session::parse(include_str!("sessions/redirect.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_follow_redirects(true);
+    mqtt.client.set_event_handler(Some(|event| {
+        if let Event::Redirected { reference, address } = event {
+            // Host names cannot be resolved, so the first IP address is used.
+            let expected = match reference {
+                "broker.example 127.0.0.2:1884" => "127.0.0.2:1884",
+                "[::1]" => "[::1]:1883",
+                reference => panic!("Unexpected server reference {}", reference),
+            };
+
+            assert_eq!(address, Some(expected.parse().unwrap()));
+            REDIRECTED.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+
+    while !session.borrow().is_finished() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert_eq!(REDIRECTED.load(Ordering::Relaxed), 2);
+
+    // The client connects to the referenced broker with the next poll.
+    assert!(!mqtt.client.is_connected());
+}
+
+#[test]
+fn replay_offline_queue() {
+    // Messages published before connecting are transmitted in order once connected.
+    let session = Session::parse(include_str!("sessions/offline_queue.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_offline_queueing(true);
+
+    for payload in [b"1", b"2"].iter() {
+        mqtt.client
+            .publish(
+                "replay/queue",
+                *payload,
+                QoS::AtMostOnce,
+                Retain::NotRetained,
+                &[],
+            )
+            .unwrap();
+    }
+    assert_eq!(mqtt.client.queued_messages(), 2);
+
+    while mqtt.client.queued_messages() > 0 {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    // Once the queue is empty, messages are published immediately.
+    mqtt.client
+        .publish(
+            "replay/queue",
+            b"3",
+            QoS::AtMostOnce,
+            Retain::NotRetained,
+            &[],
+        )
+        .unwrap();
+    assert_eq!(mqtt.client.queued_messages(), 0);
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_shared_buffer_pool() {
+    // Clients claim their receive, transmit and queue buffers from a shared pool.
+    static POOL: BufferPool<256, 5> = BufferPool::new();
+
+    let session = Session::parse(include_str!("sessions/offline_queue.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.set_buffer_pool(Some(&POOL)).unwrap();
+    mqtt.client.set_offline_queueing(true);
+    assert_eq!(POOL.available(), 3);
+
+    let (stack, _) = ReplayStack::new(Session::default());
+    let mut other =
+        Minimq::<_, _, 256, 16>::new(localhost, "other", stack, ManualClock::default()).unwrap();
+    other.set_buffer_pool(Some(&POOL)).unwrap();
+    assert_eq!(POOL.available(), 1);
+
+    // A client that cannot claim both buffers keeps its inline buffers.
+    let (stack, _) = ReplayStack::new(Session::default());
+    let mut third =
+        Minimq::<_, _, 256, 16>::new(localhost, "third", stack, ManualClock::default()).unwrap();
+    assert!(matches!(
+        third.set_buffer_pool(Some(&POOL)),
+        Err(minimq::Error::NotReady)
+    ));
+    assert_eq!(POOL.available(), 1);
+
+    mqtt.client
+        .publish(
+            "replay/queue",
+            b"1",
+            QoS::AtMostOnce,
+            Retain::NotRetained,
+            &[],
+        )
+        .unwrap();
+
+    // Messages cannot be queued once the pool is exhausted.
+    assert!(matches!(
+        mqtt.client.publish(
+            "replay/queue",
+            b"2",
+            QoS::AtMostOnce,
+            Retain::NotRetained,
+            &[]
+        ),
+        Err(minimq::Error::NotReady)
+    ));
+
+    drop(other);
+    mqtt.client
+        .publish(
+            "replay/queue",
+            b"2",
+            QoS::AtMostOnce,
+            Retain::NotRetained,
+            &[],
+        )
+        .unwrap();
+    assert_eq!(POOL.available(), 1);
+
+    while mqtt.client.queued_messages() > 0 {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    // Transmitted messages return their buffers to the pool.
+    assert_eq!(POOL.available(), 3);
+
+    mqtt.client
+        .publish(
+            "replay/queue",
+            b"3",
+            QoS::AtMostOnce,
+            Retain::NotRetained,
+            &[],
+        )
+        .unwrap();
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_resubscribe() {
+    // The broker loses the session of the client while it is disconnected.
+    let session = Session::parse(include_str!("sessions/resubscribe.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_auto_resubscribe(true);
+
+    let options = SubscriptionOptions::default().qos(QoS::AtLeastOnce);
+    let mut subscribed = false;
+    let mut reset = false;
+
+    while !session.borrow().is_finished() {
+        match mqtt.poll(|_, _, _, _| {}) {
+            Ok(_) => {}
+            Err(minimq::Error::SessionReset { .. }) => reset = true,
+            Err(error) => panic!("{:?}", error),
+        }
+
+        if mqtt.client.is_connected() && !subscribed {
+            mqtt.client
+                .subscribe_with_options("replay/renew", options, &[])
+                .unwrap();
+            subscribed = true;
+        }
+    }
+
+    while mqtt.client.subscriptions_pending() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(reset);
+    let active: Vec<_> = mqtt
+        .client
+        .subscriptions()
+        .map(|subscription| (subscription.filter().to_owned(), subscription.options()))
+        .collect();
+    assert_eq!(active, [("replay/renew".to_owned(), options)]);
+}
+
+#[test]
+fn replay_lifecycle_events() {
+    // The broker disconnects the client, which then resumes its session.
+    let session = Session::parse(include_str!("sessions/lifecycle.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    static CONNECTED: AtomicUsize = AtomicUsize::new(0);
+    static RESUMED: AtomicUsize = AtomicUsize::new(0);
+    static LOST: AtomicUsize = AtomicUsize::new(0);
+    mqtt.client.set_event_handler(Some(|event| match event {
+        Event::Connected { session_present } => {
+            // The connection must have been lost before it is established again.
+            assert_eq!(
+                CONNECTED.load(Ordering::Relaxed),
+                LOST.load(Ordering::Relaxed)
+            );
+            CONNECTED.fetch_add(1, Ordering::Relaxed);
+            if session_present {
+                RESUMED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Event::ConnectionLost => {
+            LOST.fetch_add(1, Ordering::Relaxed);
+        }
+        _ => {}
+    }));
+
+    while !session.borrow().is_finished() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+    assert!(mqtt.client.is_connected());
+    assert_eq!(CONNECTED.load(Ordering::Relaxed), 2);
+    assert_eq!(RESUMED.load(Ordering::Relaxed), 1);
+    assert_eq!(LOST.load(Ordering::Relaxed), 1);
+
+    // Suspending the network closes the connection as well.
+    mqtt.client.suspend_network().unwrap();
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert_eq!(LOST.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn replay_broker_failover() {
+    // The primary broker is unavailable, so the client fails over to the backup broker.
+    static BROKERS: [SocketAddr; 2] = [
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 1883)),
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 2), 1884)),
+    ];
+
+    let session = Session::parse(include_str!("sessions/failover.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let unspecified = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(unspecified, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_broker_failover(&BROKERS, 1);
+    assert_eq!(mqtt.client.broker_address(), BROKERS[0]);
+
+    let result = loop {
+        match mqtt.poll(|_, _, _, _| {}) {
+            Ok(_) => {}
+            result => break result,
+        }
+    };
+    assert!(matches!(
+        result,
+        Err(minimq::Error::ConnectionRefused { reason_code: 0x88 })
+    ));
+    assert_eq!(mqtt.client.broker_address(), BROKERS[0]);
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+    assert_eq!(mqtt.client.broker_address(), BROKERS[1]);
+
+    // After the backup broker disconnects the client, it is reconnected to first.
+    while !session.borrow().is_finished() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+    assert_eq!(mqtt.client.broker_address(), BROKERS[1]);
+}
+
+#[test]
+fn replay_broker_hostname() {
+    let session = Session::parse(include_str!("sessions/broker_disconnect.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let unspecified = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(unspecified, "replay", stack, ManualClock::default()).unwrap();
+
+    // Connection attempts fail while the hostname cannot be resolved.
+    mqtt.client.set_broker_hostname("unknown.replay");
+    assert!(matches!(
+        mqtt.poll(|_, _, _, _| {}),
+        Err(minimq::Error::ResolutionFailed)
+    ));
+
+    // The broker disconnects the client once connected.
+    mqtt.client.set_broker_hostname(replay::BROKER_HOSTNAME);
+    while !session.borrow().is_finished() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    // The hostname is resolved again when reconnecting after the broker disconnected.
+    mqtt.client.set_broker_hostname("unknown.replay");
+    assert!(matches!(
+        mqtt.poll(|_, _, _, _| {}),
+        Err(minimq::Error::ResolutionFailed)
+    ));
+}
+
+#[test]
+fn replay_authentication() {
+    // The broker challenges the client with a nonce, which is answered with a signature.
+    let session = Session::parse(include_str!("sessions/authentication.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client
+        .set_authentication("REPLAY", |challenge, response| match challenge {
+            b"" => Some(0),
+            b"Nonce" => {
+                response[..4].copy_from_slice(b"Sign");
+                Some(4)
+            }
+            _ => None,
+        });
+
+    let mut packets = PacketCounts::default();
+    while !mqtt.client.is_connected() {
+        let summary = mqtt.poll_with_summary(|_, _, _, _| {}).unwrap();
+        packets.auth += summary.packets.auth;
+    }
+
+    assert_eq!(packets.auth, 1);
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_session_expiry() {
+    let session = Session::parse(include_str!("sessions/session_expiry.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_session_expiry(60).unwrap();
+    mqtt.client.set_clean_start(true).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+
+    // The session cannot be reconfigured while connected.
+    assert!(matches!(
+        mqtt.client.set_session_expiry(0),
+        Err(minimq::Error::NotReady)
+    ));
+}
+
+#[test]
+fn replay_builder() {
+    let session = Session::parse(include_str!("sessions/builder.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt: Minimq<_, _, 256, 16> =
+        MinimqBuilder::new(localhost, stack, ManualClock::default())
+            .client_id("replay")
+            .keepalive_interval(30)
+            .session_expiry(60)
+            .clean_start(true)
+            .will(
+                "replay/will",
+                b"Gone",
+                QoS::AtMostOnce,
+                Retain::NotRetained,
+                &[],
+            )
+            .build()
+            .unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+    assert_eq!(mqtt.client.keepalive_interval(), 30);
+
+    // Invalid options are rejected when the client is built.
+    let (stack, _) = ReplayStack::new(Session::parse(include_str!("sessions/builder.txt")));
+    let result = MinimqBuilder::<_, _, 256, 16>::new(localhost, stack, ManualClock::default())
+        .topic_alias_maximum(100)
+        .build();
+    assert!(matches!(result, Err(minimq::Error::Unsupported)));
+}
+
+#[test]
+fn replay_assigned_client_id() {
+    // The client relies on the broker to assign its identifier.
+    let session = Session::parse(include_str!("sessions/assigned_client_id.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "", stack, ManualClock::default()).unwrap();
+    assert_eq!(mqtt.client.client_id(), "");
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+    assert_eq!(mqtt.client.client_id(), "auto-1");
+}
+
+#[test]
+fn replay_response_information() {
+    let session = Session::parse(include_str!("sessions/response_information.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_request_response_information(true).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+    assert_eq!(mqtt.client.response_information(), Some("response/replay"));
+}
+
+#[test]
+fn replay_suspend() {
+    // The broker keeps the session while the client is asleep.
+    let session = Session::parse(include_str!("sessions/suspend.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    let options = SubscriptionOptions::default().qos(QoS::AtLeastOnce);
+    mqtt.client
+        .subscribe_with_options("replay/sleep", options, &[])
+        .unwrap();
+    while mqtt.client.subscriptions_pending() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    // The broker is asked to keep the session for an hour.
+    mqtt.client.suspend(Some(3600)).unwrap();
+    assert_eq!(mqtt.client.status(), SessionStatus::Suspended);
+    for _ in 0..10 {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    mqtt.client.resume().unwrap();
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+    let active: Vec<_> = mqtt
+        .client
+        .subscriptions()
+        .map(|subscription| subscription.filter().to_owned())
+        .collect();
+    assert_eq!(active, ["replay/sleep"]);
+}
+
+#[test]
+fn replay_suspend_expired_session() {
+    // The session ends with the connection, so it cannot be kept while the client is asleep.
+    let session = Session::parse(include_str!("sessions/suspend_expired.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_session_expiry(0).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(matches!(
+        mqtt.client.suspend(Some(3600)),
+        Err(minimq::Error::Protocol(_))
+    ));
+    assert!(mqtt.client.is_connected());
+
+    mqtt.client.suspend(None).unwrap();
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_deferred_connect() {
+    let session = Session::parse(include_str!("sessions/assigned_client_id.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "", stack, ManualClock::default()).unwrap();
+
+    assert_eq!(mqtt.client.status(), SessionStatus::Disconnected);
+
+    // A suspended client does not connect to the broker while polled.
+    mqtt.client.suspend_network().unwrap();
+    for _ in 0..10 {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+    assert!(!mqtt.client.is_connected());
+    assert_eq!(mqtt.client.status(), SessionStatus::Suspended);
+
+    mqtt.client.resume_network(None).unwrap();
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert_eq!(mqtt.client.status(), SessionStatus::Connecting);
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+    assert_eq!(mqtt.client.status(), SessionStatus::Connected);
+
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_capabilities() {
+    // The broker only supports QoS 1, does not retain messages, and accepts four topic aliases.
+    let session = Session::parse(include_str!("sessions/capabilities.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+
+    let capabilities = mqtt.client.capabilities();
+    assert_eq!(capabilities.maximum_qos, QoS::AtLeastOnce);
+    assert!(!capabilities.retain_available);
+    assert!(capabilities.wildcard_subscriptions);
+    assert_eq!(capabilities.topic_alias_maximum, 4);
+    assert_eq!(capabilities.maximum_packet_size, None);
+
+    // Requests using unavailable features are rejected without being sent to the broker.
+    assert!(matches!(
+        mqtt.client
+            .publish("replay", b"", QoS::ExactlyOnce, Retain::NotRetained, &[]),
+        Err(minimq::Error::Unsupported)
+    ));
+    assert!(matches!(
+        mqtt.client
+            .publish("replay", b"", QoS::AtLeastOnce, Retain::Retained, &[]),
+        Err(minimq::Error::Unsupported)
+    ));
+}
+
+#[test]
+fn replay_receive_maximum() {
+    // The broker only accepts a single message in flight.
+    let session = Session::parse(include_str!("sessions/receive_maximum.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert_eq!(mqtt.client.capabilities().receive_maximum, 1);
+    mqtt.client
+        .publish(
+            "replay",
+            b"first",
+            QoS::AtLeastOnce,
+            Retain::NotRetained,
+            &[],
+        )
+        .unwrap();
+
+    // Further messages are not sent until the first message is acknowledged.
+    assert!(!mqtt.client.can_publish(QoS::AtLeastOnce));
+    assert!(matches!(
+        mqtt.client.publish(
+            "replay",
+            b"second",
+            QoS::AtLeastOnce,
+            Retain::NotRetained,
+            &[]
+        ),
+        Err(minimq::Error::NotReady)
+    ));
+
+    while !mqtt.client.can_publish(QoS::AtLeastOnce) {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_publish_rejected() {
+    static ACKNOWLEDGED: AtomicUsize = AtomicUsize::new(0);
+
+    // The broker accepts the first message, but rejects the second as a quota was exceeded.
+    let session = Session::parse(include_str!("sessions/publish_rejected.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_event_handler(Some(|event| {
+        if let Event::Acknowledged {
+            packet_id,
+            topic,
+            reason_code,
+        } = event
+        {
+            let expected = match packet_id {
+                1 => ("replay/a", 0x10),
+                _ => ("replay/b", 0x97),
+            };
+
+            assert_eq!((topic, reason_code), expected);
+            ACKNOWLEDGED.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    for topic in ["replay/a", "replay/b"].iter() {
+        mqtt.client
+            .publish(topic, b"", QoS::AtLeastOnce, Retain::NotRetained, &[])
+            .unwrap();
+    }
+
+    while !session.borrow().is_finished() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert_eq!(ACKNOWLEDGED.load(Ordering::Relaxed), 2);
+    assert_eq!(mqtt.client.pending_messages(QoS::AtLeastOnce), 0);
+}
+
+#[test]
+fn replay_maximum_packet_size() {
+    // The broker accepts packets of up to 16 bytes.
+    let session = Session::parse(include_str!("sessions/maximum_packet_size.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+    assert_eq!(mqtt.client.capabilities().maximum_packet_size, Some(16));
+    assert_eq!(mqtt.client.max_payload_size("replay", QoS::AtMostOnce), 5);
+
+    // The message is rejected without being sent to the broker.
+    assert_eq!(
+        mqtt.client.publish(
+            "replay",
+            b"Hello!",
+            QoS::AtMostOnce,
+            Retain::NotRetained,
+            &[]
+        ),
+        Err(minimq::Error::PacketTooLarge {
+            size: 17,
+            maximum: 16
+        })
+    );
+}
+
+#[test]
+fn replay_topic_alias() {
+    // The broker delivers the second message using the topic alias established by the first.
+    let session = Session::parse(include_str!("sessions/topic_alias.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    mqtt.client.set_topic_alias_maximum(2).unwrap();
+
+    let mut handled = 0;
+    while handled < 2 {
+        handled += mqtt
+            .poll_all(|_, topic, _, _| assert_eq!(topic, "replay/alias"))
+            .unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_topic_alias_maximum() {
+    // The configured Topic Alias Maximum is advertised in the CONNECT and bounds the aliases that
+    // the broker may use.
+    let session = Session::parse(include_str!("sessions/topic_alias_maximum.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+    assert!(matches!(
+        mqtt.client.set_topic_alias_maximum(9),
+        Err(minimq::Error::Unsupported)
+    ));
+    mqtt.client.set_topic_alias_maximum(8).unwrap();
+
+    let mut handled = 0;
+    while handled < 2 {
+        handled += mqtt
+            .poll_all(|_, topic, _, _| assert_eq!(topic, "replay/alias"))
+            .unwrap();
+    }
+
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_keepalive() {
+    // The broker responds to the first ping request only.
+    let session = Session::parse(include_str!("sessions/keepalive.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock.clone()).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    // Pings are sent once half of the keep-alive interval of 59 seconds elapsed.
+    clock.advance(29_500);
+    let summary = mqtt.poll_with_summary(|_, _, _, _| {}).unwrap();
+    assert_eq!(summary.packets.ping_resp, 0);
+    clock.advance(1);
+    let summary = mqtt.poll_with_summary(|_, _, _, _| {}).unwrap();
+    assert_eq!(summary.packets.ping_resp, 1);
+
+    clock.advance(29_501);
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert!(session.borrow().is_finished());
+
+    // The connection is considered lost if the ping response does not arrive in time.
+    clock.advance(5_000);
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert!(mqtt.client.is_connected());
+
+    clock.advance(1);
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert!(!mqtt.client.is_connected());
+}
+
+#[test]
+fn replay_next_deadline() {
+    let session = Session::parse(include_str!("sessions/keepalive.txt"));
+    let (stack, _session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock.clone()).unwrap();
+
+    // The client requires polling while connecting.
+    assert_eq!(mqtt.client.next_deadline(), Ok(Some(Instant::new(0))));
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    // The next ping is due after half of the keep-alive interval of 59 seconds.
+    assert_eq!(mqtt.client.next_deadline(), Ok(Some(Instant::new(29_500))));
+
+    clock.advance(29_501);
+    let summary = mqtt.poll_with_summary(|_, _, _, _| {}).unwrap();
+    assert_eq!(summary.packets.ping_resp, 1);
+    assert_eq!(mqtt.client.next_deadline(), Ok(Some(Instant::new(59_001))));
+
+    mqtt.client.suspend_network().unwrap();
+    assert_eq!(mqtt.client.next_deadline(), Ok(None));
+}
+
+#[test]
+fn replay_half_open() {
+    let session = Session::parse(include_str!("sessions/half_open.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock.clone()).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    // The broker vanishes without closing the connection, so the ping request is never written.
+    session.borrow_mut().stall();
+    clock.advance(29_501);
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert!(mqtt.client.is_connected());
+
+    // The connection is considered lost once no data arrived for 1.5 keep-alive intervals.
+    clock.advance(58_999);
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert!(mqtt.client.is_connected());
+
+    clock.advance(1);
+    mqtt.poll(|_, _, _, _| {}).unwrap();
+    assert!(!mqtt.client.is_connected());
+}
+
+#[test]
+fn replay_keepalive_disabled() {
+    let session = Session::parse(include_str!("sessions/keepalive_disabled.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock.clone()).unwrap();
+    mqtt.client.set_keepalive_interval(0).unwrap();
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert_eq!(mqtt.client.keepalive_interval(), 0);
+    assert_eq!(mqtt.client.next_deadline(), Ok(None));
+
+    // Neither are pings sent, nor is the silence of the broker considered a lost connection.
+    for _ in 0..10 {
+        clock.advance(3_600_000);
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+        assert!(mqtt.client.is_connected());
+    }
+
+    assert!(session.borrow().is_finished());
+}
+
+#[test]
+fn replay_server_keepalive() {
+    // The broker shortens the keep-alive interval to 10 seconds.
+    let session = Session::parse(include_str!("sessions/server_keepalive.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let clock = ManualClock::default();
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock.clone()).unwrap();
+    assert_eq!(mqtt.client.keepalive_interval(), 59);
+
+    while !mqtt.client.is_connected() {
+        mqtt.poll(|_, _, _, _| {}).unwrap();
+    }
+
+    assert_eq!(mqtt.client.keepalive_interval(), 10);
+    assert_eq!(mqtt.client.capabilities().server_keep_alive, Some(10));
+
+    // Pings are sent once half of the interval requested by the broker elapsed.
+    clock.advance(5_000);
+    let summary = mqtt.poll_with_summary(|_, _, _, _| {}).unwrap();
+    assert_eq!(summary.packets.ping_resp, 0);
+    clock.advance(1);
+    let summary = mqtt.poll_with_summary(|_, _, _, _| {}).unwrap();
+    assert_eq!(summary.packets.ping_resp, 1);
+    assert!(session.borrow().is_finished());
+}
+
+/// Publish a message with `QoS::ExactlyOnce` to a subscribed topic and wait for the message to be
+/// received and its delivery to be completed.
+fn exactly_once<S: TcpClientStack, C: Clock>(stack: S, clock: C) {
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock).unwrap();
+
+    let mut packets = PacketCounts::default();
+    let mut received = false;
+    let mut subscribed = false;
+    let mut published = false;
+
+    for _ in 0..1_000_000 {
+        let summary = mqtt
+            .poll_with_summary(|_, topic, payload, _| {
+                assert_eq!(topic, "replay/exactly-once");
+                assert_eq!(payload, b"Once");
+            })
+            .unwrap();
+        received |= summary.handled.is_some();
+        packets.pub_rec += summary.packets.pub_rec;
+        packets.pub_comp += summary.packets.pub_comp;
+
+        if !mqtt.client.is_connected() || mqtt.client.subscriptions_pending() {
+            continue;
+        }
+
+        if !subscribed {
+            mqtt.client.subscribe("replay/exactly-once", &[]).unwrap();
+            subscribed = true;
+        } else if !published {
+            mqtt.client
+                .publish(
+                    "replay/exactly-once",
+                    b"Once",
+                    QoS::ExactlyOnce,
+                    Retain::NotRetained,
+                    &[],
+                )
+                .unwrap();
+            assert_eq!(mqtt.client.pending_messages(QoS::ExactlyOnce), 1);
+            assert_eq!(mqtt.client.pending_messages(QoS::AtLeastOnce), 0);
+            published = true;
+        } else if received && mqtt.client.pending_messages(QoS::ExactlyOnce) == 0 {
+            assert_eq!(packets.pub_rec, 1);
+            assert_eq!(packets.pub_comp, 1);
+            return;
+        }
+
+        std::thread::yield_now();
+    }
+
+    panic!("Delivery not completed");
+}
+
+#[test]
+fn replay_exactly_once() {
+    let session = Session::parse(include_str!("sessions/exactly_once.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    exactly_once(stack, ManualClock::default());
+    assert!(session.borrow().is_finished());
+}
+
+/// Record the session of `replay_exactly_once` with a broker running on localhost.
+#[test]
+#[ignore]
+fn record_exactly_once() {
+    let (stack, session) = Recorder::new(std_embedded_nal::Stack);
+
+    exactly_once(stack, std_embedded_time::StandardClock::default());
+
+    print!("{}", session.borrow().dump());
+}
+
+#[test]
+fn replay_receive_exactly_once() {
+    // The broker delivers a message with `QoS::ExactlyOnce` and redelivers it before releasing it.
+    let session = Session::parse(include_str!("sessions/receive_exactly_once.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    let mut packets = PacketCounts::default();
+    let mut handled = 0;
+
+    while !session.borrow().is_finished() {
+        let summary = mqtt
+            .poll_with_summary(|_, topic, payload, _| {
+                assert_eq!(topic, "replay/receive");
+                assert_eq!(payload, b"Once");
+            })
+            .unwrap();
+
+        handled += summary.handled.iter().count();
+        packets.publish += summary.packets.publish;
+        packets.pub_rel += summary.packets.pub_rel;
+    }
+
+    // The redelivered message is acknowledged without being handled again.
+    assert_eq!(handled, 1);
+    assert_eq!(packets.publish, 2);
+    assert_eq!(packets.pub_rel, 1);
+}
+
+#[test]
+fn replay_receive_quota() {
+    // The broker delivers two messages with `QoS::ExactlyOnce`, but only a single message can await
+    // its release. The second message is rejected without being handled.
+    let session = Session::parse(include_str!("sessions/receive_quota.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt =
+        Minimq::<_, _, 256, 1>::new(localhost, "replay", stack, ManualClock::default()).unwrap();
+
+    let mut handled = 0;
+    while !session.borrow().is_finished() {
+        mqtt.poll(|_, _, payload, _| {
+            assert_eq!(payload, b"Once");
+            handled += 1;
+        })
+        .unwrap();
+    }
+
+    assert_eq!(handled, 1);
+}
+
+/// Subscribe to retained messages with a payload size limit and wait for the message that does not
+/// exceed the limit. The other messages are skipped.
+fn payload_limit<S: TcpClientStack, C: Clock>(stack: S, clock: C) {
+    static SKIPPED: AtomicUsize = AtomicUsize::new(0);
+    SKIPPED.store(0, Ordering::Relaxed);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock).unwrap();
+    mqtt.client.set_event_handler(Some(|event| {
+        if let Event::MessageSkipped { topic, size } = event {
+            assert!(topic != "replay/limit/small");
+            assert!(size > 16);
+            SKIPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }));
+
+    let mut subscribed = false;
+    let mut received = false;
+
+    for _ in 0..1_000_000 {
+        mqtt.poll(|client, topic, payload, _properties| {
+            assert_eq!(topic, "replay/limit/small");
+            assert_eq!(payload, b"Tiny");
+
+            // The message was published before the subscription, so it was retained.
+            let flags = client.message_flags().unwrap();
+            assert_eq!(flags.retain, Retain::Retained);
+            assert!(!flags.duplicate);
+            received = true;
+        })
+        .unwrap();
+
+        if received && SKIPPED.load(Ordering::Relaxed) == 2 {
+            return;
+        }
+
+        if mqtt.client.is_connected() && !subscribed {
+            mqtt.client.subscribe("replay/limit/#", &[]).unwrap();
+            assert!(mqtt.client.set_max_payload_size("replay/limit/#", Some(16)));
+            subscribed = true;
+        }
+
+        std::thread::yield_now();
+    }
+
+    panic!("Message not received");
+}
+
+#[test]
+fn replay_payload_limit() {
+    let session = Session::parse(include_str!("sessions/payload_limit.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    payload_limit(stack, ManualClock::default());
+    assert!(session.borrow().is_finished());
+}
+
+/// Record the session of `replay_payload_limit` with a broker running on localhost.
+#[test]
+#[ignore]
+fn record_payload_limit() {
+    // Publish the retained messages.
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut publisher = Minimq::<_, _, 256, 4>::new(
+        localhost,
+        "publisher",
+        std_embedded_nal::Stack,
+        std_embedded_time::StandardClock::default(),
+    )
+    .unwrap();
+
+    let large = [0xAB; 128];
+    let medium = [0xCD; 32];
+    let mut messages = vec![
+        ("replay/limit/large", &large[..]),
+        ("replay/limit/medium", &medium[..]),
+        ("replay/limit/small", &b"Tiny"[..]),
+    ]
+    .into_iter();
+
+    loop {
+        publisher.poll(|_, _, _, _| {}).unwrap();
+        if !publisher.client.is_connected()
+            || publisher.client.pending_messages(QoS::AtLeastOnce) > 0
+        {
+            continue;
+        }
+
+        match messages.next() {
+            Some((topic, payload)) => publisher
+                .client
+                .publish(topic, payload, QoS::AtLeastOnce, Retain::Retained, &[])
+                .unwrap(),
+            None => break,
+        }
+    }
+
+    let (stack, session) = Recorder::new(std_embedded_nal::Stack);
+
+    payload_limit(stack, std_embedded_time::StandardClock::default());
+
+    print!("{}", session.borrow().dump());
+}
+
+/// Configure a setting remotely using a value retained by the broker, and wait until the current
+/// values of all settings are published.
+fn remote_settings<S: TcpClientStack, C: Clock>(stack: S, clock: C) {
+    static INTERVAL: AtomicUsize = AtomicUsize::new(0);
+    INTERVAL.store(0, Ordering::Relaxed);
+
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut mqtt = Minimq::<_, _, 256, 16>::new(localhost, "replay", stack, clock).unwrap();
+
+    let mut interval = settings::Value::new("interval", 10usize);
+    interval.set_on_change(Some(|value| INTERVAL.store(*value, Ordering::Relaxed)));
+    let mut enabled = settings::Value::new("enabled", false);
+
+    let mut settings: Settings<'_, 4> = Settings::new("replay/settings");
+    settings.register(&mut interval).unwrap();
+    settings.register(&mut enabled).unwrap();
+
+    for _ in 0..1_000_000 {
+        settings.update(&mut mqtt.client).unwrap();
+        mqtt.poll(|_, topic, payload, _| settings.handle(topic, payload))
+            .unwrap()
+            .transpose()
+            .unwrap();
+
+        if INTERVAL.load(Ordering::Relaxed) == 20
+            && !settings.publish_pending()
+            && mqtt.client.pending_messages(QoS::AtLeastOnce) == 0
+        {
+            drop(settings);
+            assert_eq!(*interval.get(), 20);
+            return;
+        }
+
+        std::thread::yield_now();
+    }
+
+    panic!("Setting not applied");
+}
+
+#[test]
+fn replay_remote_settings() {
+    let session = Session::parse(include_str!("sessions/remote_settings.txt"));
+    let (stack, session) = ReplayStack::new(session);
+
+    remote_settings(stack, ManualClock::default());
+    assert!(session.borrow().is_finished());
+}
+
+/// Record the session of `replay_remote_settings` with a broker running on localhost.
+#[test]
+#[ignore]
+fn record_remote_settings() {
+    // Configure the setting remotely before the client connects.
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mut configurator = Minimq::<_, _, 256, 4>::new(
+        localhost,
+        "configurator",
+        std_embedded_nal::Stack,
+        std_embedded_time::StandardClock::default(),
+    )
+    .unwrap();
+
+    let mut published = false;
+    while !published || configurator.client.pending_messages(QoS::AtLeastOnce) > 0 {
+        configurator.poll(|_, _, _, _| {}).unwrap();
+        if configurator.client.is_connected() && !published {
+            configurator
+                .client
+                .publish(
+                    "replay/settings/interval",
+                    b"20",
+                    QoS::AtLeastOnce,
+                    Retain::Retained,
+                    &[],
+                )
+                .unwrap();
+            published = true;
+        }
+    }
+
+    let (stack, session) = Recorder::new(std_embedded_nal::Stack);
+
+    remote_settings(stack, std_embedded_time::StandardClock::default());
+
+    print!("{}", session.borrow().dump());
+}
@@ -0,0 +1,61 @@
+#![cfg(feature = "std")]
+use minimq::{Minimq, QoS, Retain, SharedMqttClient};
+
+use embedded_nal::{IpAddr, Ipv4Addr};
+use std::{thread, time::Duration};
+use std_embedded_time::StandardClock;
+
+#[test]
+fn publish_from_other_thread() {
+    let localhost = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let mqtt = Minimq::<_, _, 256, 16>::new(
+        localhost,
+        "",
+        std_embedded_nal::Stack,
+        StandardClock::default(),
+    )
+    .unwrap();
+    let client = SharedMqttClient::new(mqtt);
+
+    let handle = client.clone();
+    let publisher = thread::spawn(move || {
+        while !handle.with_client(|client| client.is_connected()) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let timeout = Duration::from_secs(1);
+        handle.subscribe("shared", &[], timeout).unwrap();
+        while handle.with_client(|client| client.subscriptions_pending()) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // More messages are published than may be in flight at once.
+        for _ in 0..32 {
+            handle
+                .publish(
+                    "shared",
+                    b"Hello",
+                    QoS::AtLeastOnce,
+                    Retain::NotRetained,
+                    &[],
+                    timeout,
+                )
+                .unwrap();
+        }
+    });
+
+    let mut received = 0;
+    while received < 32 {
+        client
+            .poll(|_, _, payload, _| {
+                if payload == b"Hello" {
+                    received += 1;
+                }
+            })
+            .unwrap();
+
+        thread::yield_now();
+    }
+
+    publisher.join().unwrap();
+}